@@ -15,6 +15,24 @@ mod m20251218_000001_create_ai_engines;
 mod m20251218_000001_create_files;
 mod m20251229_000001_create_sso_providers;
 mod m20250102_000001_add_redirect_url_to_sso_providers;
+mod m20261018_000001_add_fallback_chains_to_ai_engines;
+mod m20261018_000002_add_http_policy_to_ai_engines;
+mod m20261018_000003_add_active_message_id_to_conversations;
+mod m20261018_000004_add_instructions_to_organizations;
+mod m20261018_000005_create_assistants;
+mod m20261018_000006_create_file_chunks;
+mod m20261018_000007_add_extracted_text_to_files;
+mod m20261018_000008_create_collections;
+mod m20261018_000009_add_storage_backend_to_files;
+mod m20261018_000010_add_storage_quotas;
+mod m20261018_000011_add_upload_policy_to_organizations;
+mod m20261018_000012_create_audio_usages;
+mod m20261018_000013_add_password_auth;
+mod m20261018_000014_add_mfa_to_users;
+mod m20261018_000015_create_sessions;
+mod m20261018_000016_add_token_version_to_users;
+mod m20261018_000017_create_api_keys;
+mod m20261018_000018_create_user_identities;
 
 pub struct Migrator;
 
@@ -37,6 +55,24 @@ impl MigratorTrait for Migrator {
           Box::new(m20251218_000001_create_files::Migration),
           Box::new(m20251229_000001_create_sso_providers::Migration),
           Box::new(m20250102_000001_add_redirect_url_to_sso_providers::Migration),
+          Box::new(m20261018_000001_add_fallback_chains_to_ai_engines::Migration),
+          Box::new(m20261018_000002_add_http_policy_to_ai_engines::Migration),
+          Box::new(m20261018_000003_add_active_message_id_to_conversations::Migration),
+          Box::new(m20261018_000004_add_instructions_to_organizations::Migration),
+          Box::new(m20261018_000005_create_assistants::Migration),
+          Box::new(m20261018_000006_create_file_chunks::Migration),
+          Box::new(m20261018_000007_add_extracted_text_to_files::Migration),
+          Box::new(m20261018_000008_create_collections::Migration),
+          Box::new(m20261018_000009_add_storage_backend_to_files::Migration),
+          Box::new(m20261018_000010_add_storage_quotas::Migration),
+          Box::new(m20261018_000011_add_upload_policy_to_organizations::Migration),
+          Box::new(m20261018_000012_create_audio_usages::Migration),
+          Box::new(m20261018_000013_add_password_auth::Migration),
+          Box::new(m20261018_000014_add_mfa_to_users::Migration),
+          Box::new(m20261018_000015_create_sessions::Migration),
+          Box::new(m20261018_000016_add_token_version_to_users::Migration),
+          Box::new(m20261018_000017_create_api_keys::Migration),
+          Box::new(m20261018_000018_create_user_identities::Migration),
          ]
     }
 }
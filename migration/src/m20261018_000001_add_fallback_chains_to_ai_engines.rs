@@ -0,0 +1,42 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AiEngines::Table)
+                    .add_column(ColumnDef::new(AiEngines::FallbackChains).json_binary().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AiEngines::Table)
+                    .drop_column(AiEngines::FallbackChains)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum AiEngines {
+    #[iden = "ai_engines"]
+    Table,
+    // { "<model>": [{ "provider": "...", "model": "..." }] }
+    #[iden = "fallbackChains"]
+    FallbackChains,
+}
@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AiEngines::Table)
+                    .add_column(ColumnDef::new(AiEngines::TimeoutMs).integer().not_null().default(60_000))
+                    .add_column(ColumnDef::new(AiEngines::MaxRetries).integer().not_null().default(2))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AiEngines::Table)
+                    .drop_column(AiEngines::TimeoutMs)
+                    .drop_column(AiEngines::MaxRetries)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum AiEngines {
+    #[iden = "ai_engines"]
+    Table,
+    #[iden = "timeoutMs"]
+    TimeoutMs,
+    #[iden = "maxRetries"]
+    MaxRetries,
+}
@@ -0,0 +1,101 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .add_column(ColumnDef::new(Conversations::ActiveMessageId).uuid().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_messages_previous_message_id")
+                    .table(Messages::Table)
+                    .col(Messages::PreviousMessageId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Every turn used to start a new chain (previousMessageId NULL), link it to the message before it
+        manager
+            .get_connection()
+            .execute_unprepared(r#"
+                UPDATE "messages" m
+                SET "previousMessageId" = (
+                    SELECT p."id" FROM "messages" p
+                    WHERE p."conversationId" = m."conversationId"
+                      AND p."deleted" = false
+                      AND p."createdAt" < m."createdAt"
+                    ORDER BY p."createdAt" DESC
+                    LIMIT 1
+                )
+                WHERE m."previousMessageId" IS NULL
+            "#)
+            .await?;
+
+        // The latest message is the tip of the only branch existing so far
+        manager
+            .get_connection()
+            .execute_unprepared(r#"
+                UPDATE "conversations" c
+                SET "activeMessageId" = (
+                    SELECT m."id" FROM "messages" m
+                    WHERE m."conversationId" = c."id"
+                      AND m."deleted" = false
+                    ORDER BY m."createdAt" DESC
+                    LIMIT 1
+                )
+            "#)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_messages_previous_message_id")
+                    .table(Messages::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .drop_column(Conversations::ActiveMessageId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Conversations {
+    #[iden = "conversations"]
+    Table,
+    // Last message of the branch currently shown to the user
+    #[iden = "activeMessageId"]
+    ActiveMessageId,
+}
+
+#[derive(Iden)]
+enum Messages {
+    #[iden = "messages"]
+    Table,
+    #[iden = "previousMessageId"]
+    PreviousMessageId,
+}
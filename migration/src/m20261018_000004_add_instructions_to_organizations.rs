@@ -0,0 +1,46 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .add_column(ColumnDef::new(Organizations::CustomInstructions).text().null())
+                    .add_column(ColumnDef::new(Organizations::DepartmentInstructions).json_binary().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .drop_column(Organizations::CustomInstructions)
+                    .drop_column(Organizations::DepartmentInstructions)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Organizations {
+    #[iden = "organizations"]
+    Table,
+    #[iden = "customInstructions"]
+    CustomInstructions,
+    // { "<department>": "<instructions>" }
+    #[iden = "departmentInstructions"]
+    DepartmentInstructions,
+}
@@ -0,0 +1,171 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Assistants {
+    #[sea_orm(iden = "assistants")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "name")]
+    Name,
+    #[sea_orm(iden = "description")]
+    Description,
+    #[sea_orm(iden = "modelProvider")]
+    ModelProvider,
+    #[sea_orm(iden = "modelName")]
+    ModelName,
+    #[sea_orm(iden = "systemPrompt")]
+    SystemPrompt,
+    #[sea_orm(iden = "temperature")]
+    Temperature,
+    #[sea_orm(iden = "tools")]
+    Tools,
+    #[sea_orm(iden = "fileIds")]
+    FileIds,
+    #[sea_orm(iden = "visibility")]
+    Visibility,
+    #[sea_orm(iden = "department")]
+    Department,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+    #[sea_orm(iden = "updatedAt")]
+    UpdatedAt,
+    #[sea_orm(iden = "metadata")]
+    Metadata,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "users")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Conversations {
+    #[sea_orm(iden = "conversations")]
+    Table,
+    #[sea_orm(iden = "assistantId")]
+    AssistantId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Assistants::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Assistants::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Assistants::UserId).uuid().null())
+                    .col(ColumnDef::new(Assistants::Name).string().not_null())
+                    .col(ColumnDef::new(Assistants::Description).text().not_null())
+                    .col(ColumnDef::new(Assistants::ModelProvider).string().not_null())
+                    .col(ColumnDef::new(Assistants::ModelName).string().not_null())
+                    .col(ColumnDef::new(Assistants::SystemPrompt).text().not_null())
+                    .col(ColumnDef::new(Assistants::Temperature).float().null())
+                    .col(
+                        ColumnDef::new(Assistants::Tools)
+                            .array(ColumnType::Text)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Assistants::FileIds)
+                            .array(ColumnType::Uuid)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Assistants::Visibility)
+                            .string()
+                            .not_null()
+                            .default("private"),
+                    )
+                    .col(ColumnDef::new(Assistants::Department).string().null())
+                    .col(
+                        ColumnDef::new(Assistants::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Assistants::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Assistants::Metadata).json_binary().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: assistants.userId -> users.id (nullable SET NULL), shared assistants outlive their creator
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Assistants::Table, Assistants::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_assistants_userId")
+                    .table(Assistants::Table)
+                    .col(Assistants::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .add_column(ColumnDef::new(Conversations::AssistantId).uuid().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: conversations.assistantId -> assistants.id (nullable SET NULL)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Conversations::Table, Conversations::AssistantId)
+                    .to(Assistants::Table, Assistants::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Conversations::Table)
+                    .drop_column(Conversations::AssistantId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Assistants::Table).to_owned())
+            .await
+    }
+}
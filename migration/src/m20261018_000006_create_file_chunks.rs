@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum FileChunks {
+    #[sea_orm(iden = "file_chunks")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "fileId")]
+    FileId,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "chunkIndex")]
+    ChunkIndex,
+    #[sea_orm(iden = "content")]
+    Content,
+    #[sea_orm(iden = "embedding")]
+    Embedding,
+    #[sea_orm(iden = "embeddingModel")]
+    EmbeddingModel,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Files {
+    #[sea_orm(iden = "files")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FileChunks::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FileChunks::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(FileChunks::FileId).uuid().not_null())
+                    .col(ColumnDef::new(FileChunks::UserId).uuid().not_null())
+                    .col(ColumnDef::new(FileChunks::ChunkIndex).integer().not_null())
+                    .col(ColumnDef::new(FileChunks::Content).text().not_null())
+                    .col(
+                        ColumnDef::new(FileChunks::Embedding)
+                            .array(ColumnType::Float)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(FileChunks::EmbeddingModel).string().not_null())
+                    .col(
+                        ColumnDef::new(FileChunks::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: file_chunks.fileId -> files.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(FileChunks::Table, FileChunks::FileId)
+                    .to(Files::Table, Files::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_file_chunks_fileId")
+                    .table(FileChunks::Table)
+                    .col(FileChunks::FileId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // pgvector is optional, without it similarity is computed by the api from the `embedding` array
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"DO $$
+                BEGIN
+                    CREATE EXTENSION IF NOT EXISTS vector;
+                    ALTER TABLE "file_chunks" ADD COLUMN IF NOT EXISTS "embeddingVector" vector;
+                EXCEPTION WHEN OTHERS THEN
+                    RAISE NOTICE 'pgvector unavailable, vector search falls back to in-process ranking';
+                END $$;"#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FileChunks::Table).to_owned())
+            .await
+    }
+}
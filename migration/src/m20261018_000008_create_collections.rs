@@ -0,0 +1,188 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Collections {
+    #[sea_orm(iden = "collections")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "name")]
+    Name,
+    #[sea_orm(iden = "description")]
+    Description,
+    #[sea_orm(iden = "scope")]
+    Scope,
+    #[sea_orm(iden = "department")]
+    Department,
+    #[sea_orm(iden = "roles")]
+    Roles,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+    #[sea_orm(iden = "updatedAt")]
+    UpdatedAt,
+    #[sea_orm(iden = "metadata")]
+    Metadata,
+}
+
+#[derive(DeriveIden)]
+enum CollectionFiles {
+    #[sea_orm(iden = "collection_files")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "collectionId")]
+    CollectionId,
+    #[sea_orm(iden = "fileId")]
+    FileId,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "users")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Files {
+    #[sea_orm(iden = "files")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Collections::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Collections::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Collections::UserId).uuid().null())
+                    .col(ColumnDef::new(Collections::Name).string().not_null())
+                    .col(ColumnDef::new(Collections::Description).text().not_null())
+                    .col(
+                        ColumnDef::new(Collections::Scope)
+                            .string()
+                            .not_null()
+                            .default("user"),
+                    )
+                    .col(ColumnDef::new(Collections::Department).string().null())
+                    .col(
+                        ColumnDef::new(Collections::Roles)
+                            .array(ColumnType::Text)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Collections::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Collections::UpdatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Collections::Metadata).json_binary().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: collections.userId -> users.id (nullable SET NULL), shared collections outlive their creator
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Collections::Table, Collections::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CollectionFiles::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CollectionFiles::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(CollectionFiles::CollectionId).uuid().not_null())
+                    .col(ColumnDef::new(CollectionFiles::FileId).uuid().not_null())
+                    .col(
+                        ColumnDef::new(CollectionFiles::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: collection_files.collectionId -> collections.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(CollectionFiles::Table, CollectionFiles::CollectionId)
+                    .to(Collections::Table, Collections::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: collection_files.fileId -> files.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(CollectionFiles::Table, CollectionFiles::FileId)
+                    .to(Files::Table, Files::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_collection_files_collectionId_fileId")
+                    .table(CollectionFiles::Table)
+                    .col(CollectionFiles::CollectionId)
+                    .col(CollectionFiles::FileId)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CollectionFiles::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Collections::Table).to_owned())
+            .await
+    }
+}
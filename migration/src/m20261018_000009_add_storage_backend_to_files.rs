@@ -0,0 +1,81 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .rename_column(Files::LocalPath, Files::StorageKey)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .add_column(
+                        ColumnDef::new(Files::StorageBackend)
+                            .string()
+                            .not_null()
+                            .default("local"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Local paths become keys relative to the local storage root
+        manager
+            .get_connection()
+            .execute_unprepared(r#"UPDATE "files" SET "storageKey" = regexp_replace("storageKey", '^/data/files/', '')"#)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(r#"UPDATE "files" SET "storageKey" = '/data/files/' || "storageKey" WHERE "storageBackend" = 'local'"#)
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .drop_column(Files::StorageBackend)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Files::Table)
+                    .rename_column(Files::StorageKey, Files::LocalPath)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Files {
+    #[iden = "files"]
+    Table,
+    #[iden = "localPath"]
+    LocalPath,
+    // Key of the file in its storage backend
+    #[iden = "storageKey"]
+    StorageKey,
+    // local | s3
+    #[iden = "storageBackend"]
+    StorageBackend,
+}
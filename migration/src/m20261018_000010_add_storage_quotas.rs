@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .add_column(ColumnDef::new(Organizations::StorageQuotaBytes).big_integer().null())
+                    .add_column(ColumnDef::new(Organizations::UserStorageQuotaBytes).big_integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Uploads look up stored files with the same content
+        manager
+            .get_connection()
+            .execute_unprepared(r#"CREATE INDEX IF NOT EXISTS "idx_files_sha256" ON "files" (("metadata" ->> 'sha256'))"#)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(r#"DROP INDEX IF EXISTS "idx_files_sha256""#)
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .drop_column(Organizations::StorageQuotaBytes)
+                    .drop_column(Organizations::UserStorageQuotaBytes)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Organizations {
+    #[iden = "organizations"]
+    Table,
+    // Total bytes of the organization files, unlimited when null
+    #[iden = "storageQuotaBytes"]
+    StorageQuotaBytes,
+    // Bytes per user unless overridden in users.metadata.storageQuotaBytes
+    #[iden = "userStorageQuotaBytes"]
+    UserStorageQuotaBytes,
+}
@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum AudioUsages {
+    #[sea_orm(iden = "audio_usages")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "kind")]
+    Kind,
+    #[sea_orm(iden = "modelProvider")]
+    ModelProvider,
+    #[sea_orm(iden = "modelName")]
+    ModelName,
+    #[sea_orm(iden = "requestTokens")]
+    RequestTokens,
+    #[sea_orm(iden = "responseTokens")]
+    ResponseTokens,
+    #[sea_orm(iden = "totalTokens")]
+    TotalTokens,
+    #[sea_orm(iden = "latency")]
+    Latency,
+    #[sea_orm(iden = "cost")]
+    Cost,
+    #[sea_orm(iden = "conversationId")]
+    ConversationId,
+    #[sea_orm(iden = "messageId")]
+    MessageId,
+    #[sea_orm(iden = "metadata")]
+    Metadata,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "users")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AudioUsages::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AudioUsages::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(AudioUsages::UserId).uuid().not_null())
+                    .col(ColumnDef::new(AudioUsages::Kind).string().not_null())
+                    .col(ColumnDef::new(AudioUsages::ModelProvider).string().not_null())
+                    .col(ColumnDef::new(AudioUsages::ModelName).string().not_null())
+                    .col(ColumnDef::new(AudioUsages::RequestTokens).integer().not_null())
+                    .col(ColumnDef::new(AudioUsages::ResponseTokens).integer().not_null())
+                    .col(ColumnDef::new(AudioUsages::TotalTokens).integer().not_null())
+                    .col(ColumnDef::new(AudioUsages::Latency).integer().not_null())
+                    .col(ColumnDef::new(AudioUsages::Cost).decimal_len(18, 6).not_null())
+                    .col(ColumnDef::new(AudioUsages::ConversationId).uuid().null())
+                    .col(ColumnDef::new(AudioUsages::MessageId).uuid().null())
+                    .col(ColumnDef::new(AudioUsages::Metadata).json_binary().null())
+                    .col(
+                        ColumnDef::new(AudioUsages::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: audio_usages.userId -> users.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(AudioUsages::Table, AudioUsages::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audio_usages_userId")
+                    .table(AudioUsages::Table)
+                    .col(AudioUsages::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AudioUsages::Table).to_owned())
+            .await
+    }
+}
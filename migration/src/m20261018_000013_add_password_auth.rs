@@ -0,0 +1,153 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum PasswordTokens {
+    #[sea_orm(iden = "password_tokens")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "purpose")]
+    Purpose,
+    #[sea_orm(iden = "tokenHash")]
+    TokenHash,
+    #[sea_orm(iden = "expiresAt")]
+    ExpiresAt,
+    #[sea_orm(iden = "usedAt")]
+    UsedAt,
+    #[sea_orm(iden = "createdBy")]
+    CreatedBy,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "users")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "failedLoginAttempts")]
+    FailedLoginAttempts,
+    #[sea_orm(iden = "lockedUntil")]
+    LockedUntil,
+}
+
+#[derive(DeriveIden)]
+enum Organizations {
+    #[sea_orm(iden = "organizations")]
+    Table,
+    // { "min_length": 12, "require_uppercase": true, ..., "max_failed_attempts": 5, "lockout_minutes": 15 }, defaults when null
+    #[sea_orm(iden = "passwordPolicy")]
+    PasswordPolicy,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::FailedLoginAttempts).integer().not_null().default(0))
+                    .add_column(ColumnDef::new(Users::LockedUntil).timestamp_with_time_zone().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .add_column(ColumnDef::new(Organizations::PasswordPolicy).json_binary().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(PasswordTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PasswordTokens::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(PasswordTokens::UserId).uuid().not_null())
+                    .col(ColumnDef::new(PasswordTokens::Purpose).string().not_null())
+                    // SHA-256 of the token, the token itself is only known to its recipient
+                    .col(ColumnDef::new(PasswordTokens::TokenHash).string().not_null().unique_key())
+                    .col(
+                        ColumnDef::new(PasswordTokens::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PasswordTokens::UsedAt).timestamp_with_time_zone().null())
+                    .col(ColumnDef::new(PasswordTokens::CreatedBy).uuid().null())
+                    .col(
+                        ColumnDef::new(PasswordTokens::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: password_tokens.userId -> users.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(PasswordTokens::Table, PasswordTokens::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_password_tokens_userId")
+                    .table(PasswordTokens::Table)
+                    .col(PasswordTokens::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasswordTokens::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Organizations::Table)
+                    .drop_column(Organizations::PasswordPolicy)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::FailedLoginAttempts)
+                    .drop_column(Users::LockedUntil)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
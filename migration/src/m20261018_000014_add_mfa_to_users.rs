@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(
+                        ColumnDef::new(Users::MfaRecoveryCodes)
+                            .array(ColumnType::Text)
+                            .not_null()
+                            .default(Expr::cust("'{}'::text[]")),
+                    )
+                    .add_column(ColumnDef::new(Users::MfaLastStep).big_integer().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::MfaRecoveryCodes)
+                    .drop_column(Users::MfaLastStep)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum Users {
+    #[iden = "users"]
+    Table,
+    // Hex SHA-256 of the unused recovery codes
+    #[iden = "mfaRecoveryCodes"]
+    MfaRecoveryCodes,
+    // Last TOTP time step accepted, codes are not accepted twice
+    #[iden = "mfaLastStep"]
+    MfaLastStep,
+}
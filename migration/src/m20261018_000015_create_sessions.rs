@@ -0,0 +1,112 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum Sessions {
+    #[sea_orm(iden = "sessions")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "refreshJti")]
+    RefreshJti,
+    #[sea_orm(iden = "userAgent")]
+    UserAgent,
+    #[sea_orm(iden = "ipAddress")]
+    IpAddress,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+    #[sea_orm(iden = "lastSeenAt")]
+    LastSeenAt,
+    #[sea_orm(iden = "expiresAt")]
+    ExpiresAt,
+    #[sea_orm(iden = "revokedAt")]
+    RevokedAt,
+    #[sea_orm(iden = "revokedReason")]
+    RevokedReason,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "users")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Sessions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Sessions::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Sessions::UserId).uuid().not_null())
+                    // Id of the only refresh token of the session still accepted, older ones are reuses
+                    .col(ColumnDef::new(Sessions::RefreshJti).uuid().not_null())
+                    .col(ColumnDef::new(Sessions::UserAgent).string().null())
+                    .col(ColumnDef::new(Sessions::IpAddress).string().null())
+                    .col(
+                        ColumnDef::new(Sessions::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Sessions::LastSeenAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Sessions::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Sessions::RevokedAt).timestamp_with_time_zone().null())
+                    .col(ColumnDef::new(Sessions::RevokedReason).string().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: sessions.userId -> users.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(Sessions::Table, Sessions::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_sessions_userId")
+                    .table(Sessions::Table)
+                    .col(Sessions::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Sessions::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
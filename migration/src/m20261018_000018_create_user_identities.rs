@@ -0,0 +1,169 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum UserIdentities {
+    #[sea_orm(iden = "user_identities")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "userId")]
+    UserId,
+    #[sea_orm(iden = "provider")]
+    Provider,
+    #[sea_orm(iden = "subject")]
+    Subject,
+    #[sea_orm(iden = "email")]
+    Email,
+    #[sea_orm(iden = "createdAt")]
+    CreatedAt,
+    #[sea_orm(iden = "lastLoginAt")]
+    LastLoginAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    #[sea_orm(iden = "users")]
+    Table,
+    #[sea_orm(iden = "id")]
+    Id,
+    #[sea_orm(iden = "googleId")]
+    GoogleId,
+    #[sea_orm(iden = "azureId")]
+    AzureId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserIdentities::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(UserIdentities::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(UserIdentities::UserId).uuid().not_null())
+                    // Key of the sso_providers row, e.g. "google", "okta"
+                    .col(ColumnDef::new(UserIdentities::Provider).string().not_null())
+                    // `sub` claim of the ID token
+                    .col(ColumnDef::new(UserIdentities::Subject).text().not_null())
+                    .col(ColumnDef::new(UserIdentities::Email).text().null())
+                    .col(
+                        ColumnDef::new(UserIdentities::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(UserIdentities::LastLoginAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // FK: user_identities.userId -> users.id (CASCADE)
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .from(UserIdentities::Table, UserIdentities::UserId)
+                    .to(Users::Table, Users::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::Cascade)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_identities_provider_subject")
+                    .table(UserIdentities::Table)
+                    .col(UserIdentities::Provider)
+                    .col(UserIdentities::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_user_identities_userId")
+                    .table(UserIdentities::Table)
+                    .col(UserIdentities::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Identities used to live in one column per provider
+        manager
+            .get_connection()
+            .execute_unprepared(r#"
+                INSERT INTO "user_identities" ("id", "userId", "provider", "subject", "email", "createdAt", "lastLoginAt")
+                SELECT gen_random_uuid(), u."id", 'google', u."googleId", u."email", u."createdAt", u."lastLoginAt"
+                FROM "users" u
+                WHERE u."googleId" IS NOT NULL
+                UNION ALL
+                SELECT gen_random_uuid(), u."id", 'azure', u."azureId", u."email", u."createdAt", u."lastLoginAt"
+                FROM "users" u
+                WHERE u."azureId" IS NOT NULL
+            "#)
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .drop_column(Users::GoogleId)
+                    .drop_column(Users::AzureId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Users::Table)
+                    .add_column(ColumnDef::new(Users::GoogleId).text().null().unique_key())
+                    .add_column(ColumnDef::new(Users::AzureId).text().null().unique_key())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Identities of other providers have no column to go back to
+        manager
+            .get_connection()
+            .execute_unprepared(r#"
+                UPDATE "users" u
+                SET "googleId" = (
+                    SELECT i."subject" FROM "user_identities" i
+                    WHERE i."userId" = u."id" AND i."provider" = 'google'
+                    LIMIT 1
+                ),
+                "azureId" = (
+                    SELECT i."subject" FROM "user_identities" i
+                    WHERE i."userId" = u."id" AND i."provider" = 'azure'
+                    LIMIT 1
+                )
+            "#)
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(UserIdentities::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
+use std::net::SocketAddr;
 use axum::{Json, Router, routing::get};
 use reqwest::StatusCode;
 use serde_json::json;
 use anyhow::Error;
 use migration::MigratorTrait;
 use tower_http::cors::{Any, CorsLayer};
-use crate::{config::setting::Settings, routes::{admin::admin_routes, auth::auth_routes, chat::chat_routes, open_error::errors_routes, file::files_routes, message::message_routes, models::models_routes, oidc::oidc_routes, swagger_ui::swagger_ui_routes}, state::AppState};
+use crate::{config::setting::Settings, jobs::{file_scan::run_pending_file_scans, provider_files::run_provider_files_cleanup}, routes::{admin::admin_routes, assistants::assistants_routes, audio::audio_routes, auth::auth_routes, chat::chat_routes, collections::collections_routes, open_error::errors_routes, file::files_routes, instructions::instructions_routes, message::message_routes, models::models_routes, oidc::oidc_routes, prompt_templates::prompt_templates_routes, swagger_ui::swagger_ui_routes}, state::AppState};
 
 async fn sample_root() -> (StatusCode,Json<serde_json::Value>){
     (StatusCode::OK,Json(json!({"status":"Okay","version":env!("CARGO_PKG_VERSION")})))
@@ -16,6 +17,8 @@ pub async fn init_app() -> Result<(),Error>{
     let address = format!("{}:{}",settings.server.host,settings.server.port);
     let app_state = AppState::from_settings(settings).await?;
     migration::Migrator::up(&app_state.database, None).await?; // Auto migration
+    tokio::spawn(run_provider_files_cleanup(app_state.clone()));
+    tokio::spawn(run_pending_file_scans(app_state.clone()));
     let cors = CorsLayer::new()
       .allow_methods(Any)
       .allow_origin(Any)
@@ -25,17 +28,23 @@ pub async fn init_app() -> Result<(),Error>{
       .route("/", get(sample_root))
       .merge(swagger_ui_routes())
       .merge(oidc_routes())
-      .merge(chat_routes())
+      .merge(chat_routes(app_state.clone()))
       .merge(files_routes())
       .merge(message_routes())
+      .merge(prompt_templates_routes(app_state.clone()))
+      .merge(instructions_routes(app_state.clone()))
+      .merge(assistants_routes(app_state.clone()))
+      .merge(collections_routes(app_state.clone()))
+      .merge(audio_routes())
       .merge(admin_routes())
-      .merge(models_routes())
+      .merge(models_routes(app_state.clone()))
       .merge(auth_routes())
       .merge(errors_routes())
       .layer(cors)
       .with_state(app_state);
     let listener = tokio::net::TcpListener::bind(&address).await?;
     println!("Started listening to {}",address);
-    axum::serve(listener, app).await?;
+    // Peer address of the clients, recorded on their sessions
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
  Ok(())
 }
\ No newline at end of file
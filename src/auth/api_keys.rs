@@ -0,0 +1,128 @@
+use axum::http::Method;
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+use sha2::{Digest, Sha256};
+use crate::{auth::{claims::Claims, error::AuthError}, models::{api_keys::{self, ApiKeyScope}, users::{self, UserStatus}}, state::AppState};
+
+/// Start of every API key, tells them apart from JWTs
+pub const API_KEY_PREFIX:&str = "gk_";
+/// Characters after `gk_` kept in the displayed prefix
+const DISPLAYED_KEY_CHARS:usize = 8;
+/// Last use is recorded at most this often, sparing a write on every request
+const LAST_USED_RESOLUTION_SECS:i64 = 60;
+
+/// New key with its displayed prefix and hex SHA-256, only the hash is stored
+pub fn new_api_key() -> (String,String,String) {
+    let mut bytes = [0u8; 24];
+    rand::rng().fill_bytes(&mut bytes);
+    let secret = bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+    let key = format!("{API_KEY_PREFIX}{secret}");
+    let prefix = key[..API_KEY_PREFIX.len() + DISPLAYED_KEY_CHARS].to_string();
+    let hash = api_key_hash(&key);
+    (key, prefix, hash)
+}
+
+pub fn api_key_hash(key:&str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Scope a key needs for the request
+pub fn required_scope(method:&Method,path:&str) -> ApiKeyScope {
+    if path == "/admin" || path.starts_with("/admin/") {
+        ApiKeyScope::Admin
+    } else if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        ApiKeyScope::Read
+    } else {
+        ApiKeyScope::Write
+    }
+}
+
+/// Account management stays with signed in users, a leaked key cannot take the account over
+fn is_account_path(path:&str) -> bool {
+    path.starts_with("/auth/") && path != "/auth/api-keys" && !path.starts_with("/auth/api-keys/")
+}
+
+fn scope_name(scope:ApiKeyScope) -> &'static str {
+    match scope {
+        ApiKeyScope::Read => "read",
+        ApiKeyScope::Write => "write",
+        ApiKeyScope::Admin => "admin",
+    }
+}
+
+/// Claims of the owner of the key, as if they had signed in
+pub async fn resolve_api_key(state:&AppState,key:&str,method:&Method,path:&str) -> Result<Claims,AuthError> {
+    let (api_key, user) = api_keys::Entity::find()
+      .filter(api_keys::Column::KeyHash.eq(api_key_hash(key)))
+      .filter(api_keys::Column::RevokedAt.is_null())
+      .find_also_related(users::Entity)
+      .one(&state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::InvalidToken)?;
+    let user = user.ok_or(AuthError::InvalidToken)?;
+    let now = Utc::now();
+    if api_key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(AuthError::InvalidToken);
+    }
+    match user.status {
+        UserStatus::Deleted => return Err(AuthError::InvalidToken),
+        UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
+        UserStatus::Active => {}
+    }
+    if is_account_path(path) {
+        return Err(AuthError::PermissionDenied);
+    }
+    let scope = required_scope(method, path);
+    if !api_key.scopes.contains(&scope) {
+        return Err(AuthError::InsufficientScope { scope:scope_name(scope) });
+    }
+    if api_key.last_used_at.is_none_or(|last_used_at| now - last_used_at > Duration::seconds(LAST_USED_RESOLUTION_SECS)) {
+        let db = state.database.clone();
+        let api_key_id = api_key.id;
+        tokio::spawn(async move {
+            let _ = api_keys::Entity::update(api_keys::ActiveModel {
+                id:Set(api_key_id),
+                last_used_at:Set(Some(now)),
+                ..Default::default()
+              })
+              .exec(&db)
+              .await
+              .map_err(|e| eprintln!("api key last use update error: {e}"));
+        });
+    }
+    Ok(Claims {
+        sub:user.email,
+        name:user.name,
+        user_id:user.id,
+        org_id:user.org_id,
+        role:user.role,
+        sid:None,
+        ver:user.token_version,
+        exp:api_key.expires_at.map(|expires_at| expires_at.timestamp() as usize).unwrap_or_default(),
+        api_key_id:Some(api_key.id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_scopes() {
+        let (key, prefix, hash) = new_api_key();
+        assert!(key.starts_with(API_KEY_PREFIX) && key.starts_with(&prefix));
+        assert_eq!(prefix.len(), 11);
+        assert_eq!(api_key_hash(&key), hash);
+        assert_eq!(required_scope(&Method::GET, "/chat"), ApiKeyScope::Read);
+        assert_eq!(required_scope(&Method::POST, "/chat/stream"), ApiKeyScope::Write);
+        assert_eq!(required_scope(&Method::GET, "/admin/users"), ApiKeyScope::Admin);
+        assert_eq!(required_scope(&Method::GET, "/administrators"), ApiKeyScope::Read);
+        assert!(is_account_path("/auth/password/change"));
+        assert!(!is_account_path("/auth/api-keys"));
+    }
+}
@@ -5,7 +5,7 @@ use jsonwebtoken::{Validation, decode};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
-use crate::{auth::jwt::KEYS, models::users::UserRole};
+use crate::{auth::{api_keys::{API_KEY_PREFIX, resolve_api_key}, jwt::KEYS}, models::users::{UserRole, UserStatus}, state::SharedState};
 use crate::auth::error::AuthError;
 
 pub trait Claiming:Serialize + for<'a> Deserialize<'a> {
@@ -25,21 +25,62 @@ pub struct RefreshClaims {
     pub refresh:bool,
     pub sub: String, // Email Subject (user identifier)
     pub user_id:Uuid,//user id
+    /// Session the token belongs to
+    pub sid:Uuid,
+    /// Token id, rotated on every refresh
+    pub jti:Uuid,
     pub exp: usize,  // Expiration time
 }
 
 impl Claiming for RefreshClaims {}
 
+/// Lifetime of a refresh token, each rotation extends the session by as much
+pub const REFRESH_TOKEN_TTL_SECS:u64 = 3600 * 24 * 7;
+
 impl RefreshClaims {
-    pub fn new_refresh_token<S: Into<String>>(sub:S,user_id:Uuid) -> Self {
+    pub fn new_refresh_token<S: Into<String>>(sub:S,user_id:Uuid,sid:Uuid,jti:Uuid) -> Self {
         let exp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .expect("Time went backwards")
-            .as_secs()+ 3600 * 24 * 7;
+            .as_secs()+ REFRESH_TOKEN_TTL_SECS;
         Self { 
           sub:sub.into(),
           refresh:true,
           user_id,
+          sid,
+          jti,
+          exp:exp as usize,
+        }
+    }
+}
+
+/// Pending login waiting for the second factor, or for its enrollment when the organization requires MFA
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaClaims {
+    pub mfa:bool,
+    pub sub: String,
+    pub user_id:Uuid,
+    /// The user has no second factor yet and may only enroll one
+    pub enrollment:bool,
+    pub exp: usize,
+}
+
+impl Claiming for MfaClaims {}
+
+/// Lifetime of a pending login, in seconds
+pub const MFA_TOKEN_TTL_SECS:u64 = 300;
+
+impl MfaClaims {
+    pub fn new_mfa_token<S: Into<String>>(sub:S,user_id:Uuid,enrollment:bool) -> Self {
+        let exp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs()+ MFA_TOKEN_TTL_SECS;
+        Self {
+          mfa:true,
+          sub:sub.into(),
+          user_id,
+          enrollment,
           exp:exp as usize,
         }
     }
@@ -52,13 +93,22 @@ pub struct Claims {
     pub user_id:Uuid,//user id
     pub org_id:Option<Uuid>,
     pub role:UserRole,
+    /// Session of the login, absent from tokens issued before sessions were tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid:Option<Uuid>,
+    /// Token version of the user at issuance
+    #[serde(default)]
+    pub ver:i32,
     pub exp: usize,  // Expiration time
+    /// Key authenticating the request, None for signed in users
+    #[serde(skip)]
+    pub api_key_id:Option<Uuid>,
 }
 
 impl Claiming for Claims  {}
 
 impl Claims {
-    pub fn new_access_token<S: Into<String>>(sub:S,name:Option<S>,user_id:Uuid,org_id:Option<Uuid>,role:UserRole) -> Self {
+    pub fn new_access_token<S: Into<String>>(sub:S,name:Option<S>,user_id:Uuid,org_id:Option<Uuid>,role:UserRole,sid:Option<Uuid>,ver:i32) -> Self {
         let exp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .expect("Time went backwards")
@@ -69,7 +119,10 @@ impl Claims {
           user_id,
           org_id,
           role,
+          sid,
+          ver,
           exp:exp as usize,
+          api_key_id:None,
         }
     }
 
@@ -80,24 +133,78 @@ impl Claims {
            user_id:Uuid::new_v4(),
            org_id:None,
            role:UserRole::SuperAdmin, 
+           sid:None,
+           ver:0,
            exp:0,
+           api_key_id:None,
           }
     }
 }
 
-impl<S> FromRequestParts<S> for Claims
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<SharedState> for Claims {
     type Rejection = AuthError;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(parts: &mut Parts, state: &SharedState) -> Result<Self, Self::Rejection> {
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
             .map_err(|_| AuthError::InvalidToken)?;
+        if bearer.token().starts_with(API_KEY_PREFIX) {
+            return resolve_api_key(state, bearer.token(), &parts.method, parts.uri.path()).await;
+        }
         let claims = Self::from_token_string(bearer.token())
             .map_err(|_| AuthError::InvalidToken)?;
+        // The signature only proves who the user was at issuance, their current access is checked too
+        let access = state
+            .user_access
+            .load(&state.database, claims.user_id)
+            .await
+            .map_err(|e| {
+                eprintln!("db get one error: {e}");
+                AuthError::DbTimeout
+            })?
+            .ok_or(AuthError::InvalidToken)?;
+        match access.status {
+            UserStatus::Deleted => return Err(AuthError::InvalidToken),
+            UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
+            UserStatus::Active => {}
+        }
+        // Issued before a status or role change, the client refreshes to get the current role
+        if claims.ver != access.token_version {
+            return Err(AuthError::InvalidToken);
+        }
         Ok(claims)
     }
 }
+
+
+/// User enrolling a second factor, signed in or with the mfa token of a login pending enrollment
+pub struct MfaSubject {
+    pub user_id:Uuid,
+    /// Enrolling during a login, full tokens are issued once enrolled
+    pub pending_login:bool,
+}
+
+impl FromRequestParts<SharedState> for MfaSubject {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &SharedState) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| AuthError::InvalidToken)?;
+        // Logins waiting for the code of an enrolled factor cannot enroll another one
+        if let Ok(claims) = MfaClaims::from_token_string(bearer.token()) {
+            return match claims.mfa && claims.enrollment {
+                true => Ok(Self { user_id:claims.user_id, pending_login:true }),
+                false => Err(AuthError::InvalidToken),
+            };
+        }
+        // Signed in users go through the status and token version checks, keys cannot enroll a factor
+        let claims = Claims::from_request_parts(parts, state).await?;
+        if claims.api_key_id.is_some() {
+            return Err(AuthError::PermissionDenied);
+        }
+        Ok(Self { user_id:claims.user_id, pending_login:false })
+    }
+}
\ No newline at end of file
@@ -0,0 +1,80 @@
+use std::{convert::Infallible, net::{IpAddr, SocketAddr}, sync::OnceLock};
+use axum::{extract::{ConnectInfo, FromRequestParts}, http::{HeaderMap, header::USER_AGENT, request::Parts}};
+
+/// Longest user agent kept, some clients send very long ones
+const MAX_USER_AGENT_CHARS:usize = 512;
+
+/// Proxies whose `X-Forwarded-For` and `X-Real-IP` headers are trusted, from `TRUSTED_PROXIES`
+pub static TRUSTED_PROXIES: OnceLock<Vec<IpAddr>> = OnceLock::new();
+
+/// Device and address of the client signing in
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent:Option<String>,
+    pub ip_address:Option<String>,
+}
+
+/// Address of the client. Forwarding headers are only read when the peer is a trusted proxy,
+/// the rightmost `X-Forwarded-For` hop not added by a trusted proxy is the client
+fn client_ip(headers:&HeaderMap,peer:Option<SocketAddr>,trusted:&[IpAddr]) -> Option<String> {
+    let peer = peer?.ip();
+    if !trusted.contains(&peer) {
+        return Some(peer.to_string());
+    }
+    let forwarded = headers
+      .get("x-forwarded-for")
+      .and_then(|value| value.to_str().ok())
+      .and_then(|value| {
+          value
+            .rsplit(',')
+            .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+            .find(|hop| !trusted.contains(hop))
+      })
+      .or_else(|| {
+          headers
+            .get("x-real-ip")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok())
+      });
+    Some(forwarded.unwrap_or(peer).to_string())
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+          .extensions
+          .get::<ConnectInfo<SocketAddr>>()
+          .map(|ConnectInfo(peer)| *peer);
+        let user_agent = parts
+          .headers
+          .get(USER_AGENT)
+          .and_then(|value| value.to_str().ok())
+          .map(|value| value.chars().take(MAX_USER_AGENT_CHARS).collect());
+        Ok(Self {
+            user_agent,
+            ip_address:client_ip(&parts.headers, peer, TRUSTED_PROXIES.get().map(Vec::as_slice).unwrap_or_default()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_ip_only_trusts_forwarding_from_trusted_proxies() {
+        let peer = Some(SocketAddr::from(([10, 0, 0, 2], 443)));
+        let trusted:Vec<IpAddr> = vec![[10, 0, 0, 1].into(), [10, 0, 0, 2].into()];
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_ip(&headers, peer, &trusted).as_deref(), Some("10.0.0.2"));
+        headers.insert("x-forwarded-for", "198.51.100.1, 203.0.113.7, 10.0.0.1".parse().unwrap());
+        assert_eq!(client_ip(&headers, peer, &trusted).as_deref(), Some("203.0.113.7"));
+        assert_eq!(client_ip(&headers, peer, &[]).as_deref(), Some("10.0.0.2"));
+        assert_eq!(client_ip(&HeaderMap::new(), None, &trusted), None);
+    }
+}
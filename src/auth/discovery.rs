@@ -0,0 +1,49 @@
+use openidconnect::{core::{CoreClient, CoreProviderMetadata}};
+use openidconnect::{ClientId, ClientSecret, IssuerUrl, RedirectUrl};
+use anyhow::Error;
+use reqwest::{Client as ReqwestClient, Url};
+use crate::{auth::azure::build_azure_client, config::setting::{OidcClient, SsoProviderSettings}};
+
+/// Azure multi-tenant issuers do not match their discovery document, their client is built by hand
+fn is_azure_issuer(issuer_url:&str) -> bool {
+    Url::parse(issuer_url)
+      .ok()
+      .and_then(|url| url.host_str().map(|host| host.eq_ignore_ascii_case("login.microsoftonline.com")))
+      .unwrap_or(false)
+}
+
+/// Client of any OpenID provider publishing a discovery document, e.g. Google, Okta, Keycloak, Auth0, Ping
+pub async fn build_discovered_client<S: Into<String>>(req_client:&ReqwestClient,issuer_url:S,client_id:S,client_secret:S,redirect_url:S) -> Result<OidcClient,Error> {
+    let provider_metadata = CoreProviderMetadata::discover_async(
+        IssuerUrl::new(issuer_url.into())?,
+        req_client).await?;
+    let client = CoreClient::from_provider_metadata(
+        provider_metadata,
+        ClientId::new(client_id.into()),
+        Some(ClientSecret::new(client_secret.into())),
+    )
+    .set_redirect_uri(RedirectUrl::new(redirect_url.into())?);
+  Ok(client)
+}
+
+pub async fn build_oidc_client(req_client:&ReqwestClient,settings:&SsoProviderSettings) -> Result<OidcClient,Error> {
+    if is_azure_issuer(&settings.issuer_url) {
+        let tenant_id = settings.tenant_id.clone().unwrap_or("common".to_string());
+        return build_azure_client(req_client,settings.client_id.clone(),settings.client_secret.clone(),settings.redirect_url.clone(),tenant_id).await;
+    }
+    build_discovered_client(req_client,&settings.issuer_url,&settings.client_id,&settings.client_secret,&settings.redirect_url).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn azure_issuers_skip_discovery() {
+        assert!(is_azure_issuer("https://login.microsoftonline.com/common/v2.0"));
+        assert!(is_azure_issuer("https://login.microsoftonline.com/<tenant_id>/v2.0"));
+        assert!(!is_azure_issuer("https://accounts.google.com"));
+        assert!(!is_azure_issuer("https://dev-123.okta.com/oauth2/default"));
+        assert!(!is_azure_issuer("http://localhost:8081/realms/grengin"));
+    }
+}
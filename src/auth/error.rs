@@ -7,6 +7,7 @@ use serde::ser::{Serialize, Serializer};
 use serde::Serialize as SerdeSerialize;
 use std::collections::BTreeMap;
 use utoipa::ToSchema;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
 pub const APP_NAME: &str = "grengin";
@@ -32,6 +33,13 @@ pub enum AuthErrorCode {
     InvalidUserStatus = 6104,
     AccountDeactivated = 6105,
     EmailAlreadyExist = 6106,
+    AccountLocked = 6107,
+    WeakPassword = 6108,
+    InvalidMfaCode = 6109,
+    MfaAlreadyEnabled = 6110,
+    MfaNotEnrolled = 6111,
+    MfaRequired = 6112,
+    SessionNotFound = 6113,
 
     // 6200-6299: provider / oauth / redirect
     InvalidProvider = 6200,
@@ -43,10 +51,13 @@ pub enum AuthErrorCode {
     OrgDoesNotExist = 6301,
     ResourceNotFound = 6302,
     EmailDomainNotAllowed = 6303,
+    InsufficientScope = 6304,
+    InvalidApiKeyRequest = 6305,
 
     // 6400-6499: SSO config / admin controls
     SsoProviderNotConfigured = 6400,
     SsoProviderDisabledByAdmin = 6401,
+    InvalidSsoProviderRequest = 6402,
 }
 
 impl Serialize for AuthErrorCode {
@@ -117,11 +128,25 @@ pub enum AuthError {
 
     InvalidUserStatus,
     AccountDeactivated,
+    AccountLocked { locked_until: DateTime<Utc> },
+    /// `rule` names the unmet rule of the password policy
+    WeakPassword { rule: &'static str, min_length: u32 },
+    InvalidMfaCode,
+    MfaAlreadyEnabled,
+    MfaNotEnrolled,
+    MfaRequired,
+    SessionNotFound,
 
     SsoProviderNotConfigured { provider: Option<String> },
     SsoProviderDisabledByAdmin { provider: Option<String> },
+    /// `field` names the invalid field of the SSO provider request
+    InvalidSsoProviderRequest { field: &'static str },
 
     EmailDomainNotAllowed { domain: Option<String> },
+    /// The API key lacks `scope` for the request
+    InsufficientScope { scope: &'static str },
+    /// `field` names the invalid field of the API key request
+    InvalidApiKeyRequest { field: &'static str },
 }
 
 impl AuthError {
@@ -390,6 +415,166 @@ impl AuthError {
                 )
             }
 
+            AuthError::AccountLocked { locked_until } => {
+                let mut params = Self::base_params();
+                params.insert("locked_until".to_string(), locked_until.to_rfc3339());
+
+                let description_key = "error.auth.account_locked.description".to_string();
+                let solution_key = "error.auth.account_locked.solution".to_string();
+
+                let description_tpl = "This account is locked after repeated failed sign-in attempts.";
+                let solution_tpl = "Try again after {locked_until}, or reset your password.";
+
+                (
+                    StatusCode::LOCKED,
+                    ErrorDetail {
+                        code: AuthErrorCode::AccountLocked,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::WeakPassword { rule, min_length } => {
+                let mut params = Self::base_params();
+                params.insert("rule".to_string(), rule.to_string());
+                params.insert("min_length".to_string(), min_length.to_string());
+
+                let description_key = "error.auth.weak_password.description".to_string();
+                let solution_key = "error.auth.weak_password.solution".to_string();
+
+                let description_tpl = "The password does not meet the password policy of {app} (`{rule}`).";
+                let solution_tpl =
+                    "Use at least {min_length} characters, with the character classes your organization requires.";
+
+                (
+                    StatusCode::BAD_REQUEST,
+                    ErrorDetail {
+                        code: AuthErrorCode::WeakPassword,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::InvalidMfaCode => {
+                let params = Self::base_params();
+                let description_key = "error.auth.invalid_mfa_code.description".to_string();
+                let solution_key = "error.auth.invalid_mfa_code.solution".to_string();
+
+                let description_tpl = "The authentication code is invalid or was already used.";
+                let solution_tpl = "Enter the current code of your authenticator app, or one of your recovery codes.";
+
+                (
+                    StatusCode::UNAUTHORIZED,
+                    ErrorDetail {
+                        code: AuthErrorCode::InvalidMfaCode,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::MfaAlreadyEnabled => {
+                let params = Self::base_params();
+                let description_key = "error.auth.mfa_already_enabled.description".to_string();
+                let solution_key = "error.auth.mfa_already_enabled.solution".to_string();
+
+                let description_tpl = "Multi-factor authentication is already enabled for this account.";
+                let solution_tpl = "Disable it first to enroll a new authenticator.";
+
+                (
+                    StatusCode::CONFLICT,
+                    ErrorDetail {
+                        code: AuthErrorCode::MfaAlreadyEnabled,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::MfaNotEnrolled => {
+                let params = Self::base_params();
+                let description_key = "error.auth.mfa_not_enrolled.description".to_string();
+                let solution_key = "error.auth.mfa_not_enrolled.solution".to_string();
+
+                let description_tpl = "Multi-factor authentication is not set up for this account.";
+                let solution_tpl = "Start the authenticator setup and try again.";
+
+                (
+                    StatusCode::BAD_REQUEST,
+                    ErrorDetail {
+                        code: AuthErrorCode::MfaNotEnrolled,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::MfaRequired => {
+                let params = Self::base_params();
+                let description_key = "error.auth.mfa_required.description".to_string();
+                let solution_key = "error.auth.mfa_required.solution".to_string();
+
+                let description_tpl = "Your organization requires multi-factor authentication in {app}.";
+                let solution_tpl = "Sign in again and set up an authenticator app.";
+
+                (
+                    StatusCode::FORBIDDEN,
+                    ErrorDetail {
+                        code: AuthErrorCode::MfaRequired,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::SessionNotFound => {
+                let params = Self::base_params();
+                let description_key = "error.auth.session_not_found.description".to_string();
+                let solution_key = "error.auth.session_not_found.solution".to_string();
+
+                let description_tpl = "The session does not exist or was already signed out.";
+                let solution_tpl = "Refresh the list of your sessions and try again.";
+
+                (
+                    StatusCode::NOT_FOUND,
+                    ErrorDetail {
+                        code: AuthErrorCode::SessionNotFound,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
             AuthError::EmailAlreadyExist => {
                 let params = Self::base_params();
                 let description_key = "error.auth.email_already_exists.description".to_string();
@@ -569,6 +754,54 @@ impl AuthError {
                 )
             }
 
+            AuthError::InsufficientScope { scope } => {
+                let mut params = Self::base_params();
+                params.insert("scope".to_string(), scope.to_string());
+
+                let description_key = "error.auth.insufficient_scope.description".to_string();
+                let solution_key = "error.auth.insufficient_scope.solution".to_string();
+
+                let description_tpl = "The API key does not have the `{scope}` scope required by this request.";
+                let solution_tpl = "Create an API key with the `{scope}` scope, or sign in to {app}.";
+
+                (
+                    StatusCode::FORBIDDEN,
+                    ErrorDetail {
+                        code: AuthErrorCode::InsufficientScope,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AuthError::InvalidApiKeyRequest { field } => {
+                let mut params = Self::base_params();
+                params.insert("field".to_string(), field.to_string());
+
+                let description_key = "error.auth.invalid_api_key_request.description".to_string();
+                let solution_key = "error.auth.invalid_api_key_request.solution".to_string();
+
+                let description_tpl = "The `{field}` of the API key is invalid.";
+                let solution_tpl = "Give the key a name, at least one scope and an expiry between 1 and 365 days.";
+
+                (
+                    StatusCode::BAD_REQUEST,
+                    ErrorDetail {
+                        code: AuthErrorCode::InvalidApiKeyRequest,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
             AuthError::EmailDomainNotAllowed { domain } => {
                 let mut params = Self::base_params();
                 params.insert(
@@ -653,6 +886,30 @@ impl AuthError {
                     },
                 )
             }
+
+            AuthError::InvalidSsoProviderRequest { field } => {
+                let mut params = Self::base_params();
+                params.insert("field".to_string(), field.to_string());
+
+                let description_key = "error.auth.sso.invalid_request.description".to_string();
+                let solution_key = "error.auth.sso.invalid_request.solution".to_string();
+
+                let description_tpl = "The `{field}` of the SSO provider is invalid.";
+                let solution_tpl = "Use a lowercase provider key, the issuer URL and the client credentials issued by the identity provider.";
+
+                (
+                    StatusCode::BAD_REQUEST,
+                    ErrorDetail {
+                        code: AuthErrorCode::InvalidSsoProviderRequest,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
         }
     }
 }
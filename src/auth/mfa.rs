@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Error, anyhow};
+use rand::seq::IndexedRandom;
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+use crate::auth::error::APP_NAME;
+
+const TOTP_DIGITS:usize = 6;
+const TOTP_STEP_SECS:u64 = 30;
+/// Steps accepted before and after the current one, for clocks running a little off
+const TOTP_SKEW_STEPS:i64 = 1;
+pub const RECOVERY_CODE_COUNT:usize = 10;
+/// Lowercase base32, without the letters and digits read alike
+const RECOVERY_CODE_ALPHABET:&[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+fn totp(secret:&str,account:&str) -> Result<TOTP,Error> {
+    let secret = Secret::Encoded(secret.to_string())
+      .to_bytes()
+      .map_err(|e| anyhow!("totp secret error: {e:?}"))?;
+    // Emails never contain ':' in practice, the label is only shown by authenticator apps
+    TOTP::new(Algorithm::SHA1, TOTP_DIGITS, 0, TOTP_STEP_SECS, secret, Some(APP_NAME.to_string()), account.replace(':', ""))
+      .map_err(|e| anyhow!("totp error: {e}"))
+}
+
+/// New TOTP secret in base32
+pub fn new_totp_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// `otpauth://` URI shown as a QR code to authenticator apps
+pub fn otpauth_uri(secret:&str,account:&str) -> Result<String,Error> {
+    Ok(totp(secret, account)?.get_url())
+}
+
+pub fn current_unix_time() -> u64 {
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|duration| duration.as_secs())
+      .unwrap_or_default()
+}
+
+/// Time step of the code around `now`, None when it does not match or its step was already used
+pub fn verify_totp(secret:&str,code:&str,last_step:Option<i64>,now:u64) -> Result<Option<i64>,Error> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(None);
+    }
+    let totp = totp(secret, "")?;
+    let current_step = (now / TOTP_STEP_SECS) as i64;
+    let step = (current_step - TOTP_SKEW_STEPS..=current_step + TOTP_SKEW_STEPS)
+      .filter(|step| *step >= 0 && last_step.is_none_or(|last_step| *step > last_step))
+      .find(|step| totp.check(code, *step as u64 * TOTP_STEP_SECS));
+    Ok(step)
+}
+
+/// Recovery codes with the hashes to store
+pub fn new_recovery_codes() -> (Vec<String>,Vec<String>) {
+    let mut rng = rand::rng();
+    let codes = (0..RECOVERY_CODE_COUNT)
+      .map(|_| {
+          let chars = (0..10)
+            .map(|_| *RECOVERY_CODE_ALPHABET.choose(&mut rng).unwrap_or(&b'a') as char)
+            .collect::<String>();
+          format!("{}-{}", &chars[..5], &chars[5..])
+      })
+      .collect::<Vec<String>>();
+    let hashes = codes.iter().map(|code| recovery_code_hash(code)).collect();
+    (codes, hashes)
+}
+
+/// Hash of a recovery code, typed with or without its dash and in any case
+pub fn recovery_code_hash(code:&str) -> String {
+    let normalized = code
+      .chars()
+      .filter(|c| c.is_ascii_alphanumeric())
+      .collect::<String>()
+      .to_ascii_lowercase();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_totp_accepts_each_step_once() {
+        let secret = new_totp_secret();
+        let now = 1_760_000_000;
+        let code = totp(&secret, "user@example.com").unwrap().generate(now);
+        let step = verify_totp(&secret, &code, None, now).unwrap();
+        assert_eq!(step, Some((now / TOTP_STEP_SECS) as i64));
+        // A code from the previous step is still accepted, a replayed one is not
+        assert_eq!(verify_totp(&secret, &code, None, now + TOTP_STEP_SECS).unwrap(), step);
+        assert_eq!(verify_totp(&secret, &code, step, now).unwrap(), None);
+        assert_eq!(verify_totp(&secret, &code, None, now + 3 * TOTP_STEP_SECS).unwrap(), None);
+        assert_eq!(verify_totp(&secret, "12345", None, now).unwrap(), None);
+    }
+
+    #[test]
+    fn recovery_code_hash_ignores_dash_and_case() {
+        let (codes, hashes) = new_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(recovery_code_hash(&codes[0].replace('-', "").to_uppercase()), hashes[0]);
+    }
+}
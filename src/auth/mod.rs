@@ -1,7 +1,12 @@
 pub mod jwt;
-pub mod google;
+pub mod discovery;
 pub mod error;
 pub mod claims;
 pub mod azure;
 pub mod encryption;
-pub mod sso_provider;
\ No newline at end of file
+pub mod sso_provider;
+pub mod password;
+pub mod mfa;
+pub mod client;
+pub mod user_access;
+pub mod api_keys;
\ No newline at end of file
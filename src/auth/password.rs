@@ -0,0 +1,103 @@
+use std::sync::OnceLock;
+use anyhow::{Error, anyhow};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::{SaltString, rand_core::OsRng}};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::dto::auth::PasswordPolicy;
+
+/// Longer passwords are refused, hashing them would only cost CPU
+pub const MAX_PASSWORD_CHARS:usize = 128;
+
+/// Argon2id hash in PHC string format, run off the async runtime
+pub async fn hash_password(password:String) -> Result<String,Error> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+          .hash_password(password.as_bytes(), &salt)
+          .map(|hash| hash.to_string())
+          .map_err(|e| anyhow!("password hash error: {e}"))
+    })
+    .await?
+}
+
+/// Checks the password against a stored hash, or against a dummy one when the account has none so that
+/// unknown emails take as long to refuse as wrong passwords
+pub async fn verify_password(password:String,hash:Option<String>) -> Result<bool,Error> {
+    tokio::task::spawn_blocking(move || {
+        static DUMMY_HASH:OnceLock<String> = OnceLock::new();
+        let dummy = DUMMY_HASH.get_or_init(|| {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default()
+              .hash_password(b"dummy password", &salt)
+              .map(|hash| hash.to_string())
+              .unwrap_or_default()
+        });
+        let stored = hash.as_deref().unwrap_or(dummy);
+        let parsed = PasswordHash::new(stored).map_err(|e| anyhow!("password hash parse error: {e}"))?;
+        let matches = Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok();
+        Ok(matches && hash.is_some())
+    })
+    .await?
+}
+
+/// First rule of the policy the password breaks
+pub fn check_password(policy:&PasswordPolicy,password:&str) -> Result<(),&'static str> {
+    let length = password.chars().count();
+    if length < policy.min_length as usize {
+        return Err("min_length");
+    }
+    if length > MAX_PASSWORD_CHARS {
+        return Err("max_length");
+    }
+    if policy.require_uppercase && !password.chars().any(char::is_uppercase) {
+        return Err("require_uppercase");
+    }
+    if policy.require_lowercase && !password.chars().any(char::is_lowercase) {
+        return Err("require_lowercase");
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err("require_digit");
+    }
+    if policy.require_symbol && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+        return Err("require_symbol");
+    }
+    Ok(())
+}
+
+/// Random url-safe token with its hex SHA-256, only the hash is stored
+pub fn new_password_token() -> (String,String) {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    let token = URL_SAFE_NO_PAD.encode(bytes);
+    let hash = password_token_hash(&token);
+    (token, hash)
+}
+
+pub fn password_token_hash(token:&str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_password_reports_the_first_broken_rule() {
+        let policy = PasswordPolicy { min_length:8, require_uppercase:true, require_digit:true, ..PasswordPolicy::default() };
+        assert_eq!(check_password(&policy, "Short1"), Err("min_length"));
+        assert_eq!(check_password(&policy, "longenough1"), Err("require_uppercase"));
+        assert_eq!(check_password(&policy, "LongEnough"), Err("require_digit"));
+        assert_eq!(check_password(&policy, "LongEnough1"), Ok(()));
+        assert_eq!(check_password(&policy, &"A1".repeat(65)), Err("max_length"));
+    }
+
+    #[tokio::test]
+    async fn verify_password_matches_only_the_hashed_password() {
+        let hash = hash_password("correct horse battery".to_string()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse battery".to_string(), Some(hash.clone())).await.unwrap());
+        assert!(!verify_password("wrong horse battery".to_string(), Some(hash)).await.unwrap());
+        assert!(!verify_password("dummy password".to_string(), None).await.unwrap());
+    }
+}
@@ -1,5 +1,8 @@
 use crate::dto::sso_providers::SsoProvider;
 
+/// Path segments of `/auth` routes a provider key would be shadowed by
+const RESERVED_PROVIDER_KEYS:[&str; 7] = ["api-keys","login","logout","mfa","password","refresh","sessions"];
+
 pub fn sso_providers_list() -> Vec<SsoProvider>{
    vec![
       SsoProvider{ 
@@ -15,4 +18,28 @@ pub fn sso_providers_list() -> Vec<SsoProvider>{
         redirect_url:format!("{}/auth/azure/callback",std::env::var("REDIRECT_URL").unwrap_or("http://localhost:8080".to_string())), 
      }
    ]
-}
\ No newline at end of file
+}
+
+/// Provider keys are URL slugs, lowercase letters, digits and dashes
+pub fn is_valid_provider_key(provider:&str) -> bool {
+   !provider.is_empty()
+     && provider.len() <= 32
+     && provider.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+     && !RESERVED_PROVIDER_KEYS.contains(&provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_keys_are_slugs_outside_auth_routes() {
+        assert!(is_valid_provider_key("okta"));
+        assert!(is_valid_provider_key("keycloak-staging"));
+        assert!(!is_valid_provider_key(""));
+        assert!(!is_valid_provider_key("Okta"));
+        assert!(!is_valid_provider_key("okta/default"));
+        assert!(!is_valid_provider_key("login"));
+        assert!(!is_valid_provider_key("refresh"));
+    }
+}
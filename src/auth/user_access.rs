@@ -0,0 +1,100 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+use migration::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use crate::models::users::{self, UserRole, UserStatus};
+
+/// How long a status or role change may take to reach the requests of other instances
+pub const USER_ACCESS_TTL:Duration = Duration::from_secs(30);
+/// Expired entries are dropped once the cache grows past this size
+const MAX_CACHED_USERS:usize = 10_000;
+
+/// What requests of the user are checked against
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserAccess {
+    pub status:UserStatus,
+    pub role:UserRole,
+    pub token_version:i32,
+}
+
+/// Short lived cache of the access of users, sparing a query on every request
+pub struct UserAccessCache {
+    entries:RwLock<HashMap<Uuid,(UserAccess,Instant)>>,
+    ttl:Duration,
+}
+
+impl UserAccessCache {
+    pub fn new(ttl:Duration) -> Self {
+        Self { entries:RwLock::new(HashMap::new()), ttl }
+    }
+
+    async fn get(&self,user_id:Uuid) -> Option<UserAccess> {
+        self.entries
+          .read()
+          .await
+          .get(&user_id)
+          .filter(|(_, cached_at)| cached_at.elapsed() < self.ttl)
+          .map(|(access, _)| access.clone())
+    }
+
+    async fn insert(&self,user_id:Uuid,access:UserAccess) {
+        let mut entries = self.entries.write().await;
+        if entries.len() >= MAX_CACHED_USERS {
+            entries.retain(|_, (_, cached_at)| cached_at.elapsed() < self.ttl);
+        }
+        entries.insert(user_id, (access, Instant::now()));
+    }
+
+    pub async fn invalidate(&self,user_id:Uuid) {
+        self.entries.write().await.remove(&user_id);
+    }
+
+    /// Access of the user, None when the user does not exist
+    pub async fn load(&self,db:&DatabaseConnection,user_id:Uuid) -> Result<Option<UserAccess>,DbErr> {
+        if let Some(access) = self.get(user_id).await {
+            return Ok(Some(access));
+        }
+        let Some(user) = users::Entity::find_by_id(user_id).one(db).await? else {
+            return Ok(None);
+        };
+        let access = UserAccess {
+            status:user.status,
+            role:user.role,
+            token_version:user.token_version,
+        };
+        self.insert(user_id, access.clone()).await;
+        Ok(Some(access))
+    }
+
+    /// Refuses the access tokens already issued to the user, after a change of their status or role
+    pub async fn bump_token_version(&self,db:&DatabaseConnection,user_id:Uuid) -> Result<(),DbErr> {
+        users::Entity::update_many()
+          .col_expr(users::Column::TokenVersion, Expr::col(users::Column::TokenVersion).add(1))
+          .filter(users::Column::Id.eq(user_id))
+          .exec(db)
+          .await?;
+        self.invalidate(user_id).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn cached_access_expires_and_invalidates() {
+        let access = UserAccess { status:UserStatus::Active, role:UserRole::User, token_version:3 };
+        let user_id = Uuid::new_v4();
+        let cache = UserAccessCache::new(USER_ACCESS_TTL);
+        cache.insert(user_id, access.clone()).await;
+        assert_eq!(cache.get(user_id).await, Some(access.clone()));
+        cache.invalidate(user_id).await;
+        assert_eq!(cache.get(user_id).await, None);
+
+        let expired = UserAccessCache::new(Duration::ZERO);
+        expired.insert(user_id, access).await;
+        assert_eq!(expired.get(user_id).await, None);
+    }
+}
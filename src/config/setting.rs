@@ -1,21 +1,29 @@
+use std::{collections::HashMap, net::IpAddr, path::PathBuf, sync::Arc, time::Duration};
 use openidconnect::{core::{CoreClient},EndpointMaybeSet, EndpointNotSet, EndpointSet};
 use reqwest::Url;
 use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
 use thiserror::Error;
 use tokio::sync::RwLock;
 use uuid::Uuid;
-use crate::{auth::{encryption::{decrypt_key, key_from_b64}, jwt::{KEYS, Keys}}, models::{ai_engines, organizations, sso_providers}};
+use crate::{auth::{client::TRUSTED_PROXIES, encryption::{decrypt_key, key_from_b64}, jwt::{KEYS, Keys}}, llm::http_policy::{CircuitBreaker, DEFAULT_MAX_RETRIES, DEFAULT_TIMEOUT_MS, HttpPolicy}, models::{ai_engines, files::StorageBackend, organizations, sso_providers}};
+
+/// Root folder of the local file storage
+pub const LOCAL_STORAGE_ROOT:&str = "/data/files";
+pub const DEFAULT_MAX_UPLOAD_BYTES:u64 = 50 * 1024 * 1024;
 
 pub type OidcClient = CoreClient<EndpointSet, EndpointNotSet, EndpointNotSet, EndpointNotSet, EndpointMaybeSet, EndpointMaybeSet>;
 
 pub struct Settings {
     pub org_id:Option<Uuid>,
     pub auth: AuthSettings,
-    pub google:RwLock<Option<GoogleSettings>>,
-    pub azure:RwLock<Option<AzureSettings>>,
+    /// OIDC settings by provider key, e.g. "google", "okta"
+    pub sso_providers:RwLock<HashMap<String,SsoProviderSettings>>,
     pub server:ServerSettings,
     pub openai:RwLock<Option<OpenaiSettings>>,
     pub anthropic:RwLock<Option<AnthropicSettings>>,
+    pub storage:StorageSettings,
+    /// Account emails are sent when set
+    pub mail:Option<MailSettings>,
 }
 
 pub struct ServerSettings {
@@ -28,22 +36,56 @@ pub struct AuthSettings {
     pub app_key:[u8; 32],
     pub redirect_url:String,
     pub database_url:String,
+    /// Frontend page setting a password, the invite or reset token is appended as `?token=`
+    pub password_reset_url:String,
+}
+
+pub struct MailSettings {
+    pub smtp_url:String,
+    /// Sender, e.g. `Grengin <no-reply@grengin.com>`
+    pub from:String,
+}
+
+pub struct StorageSettings {
+    /// Backend new files are written to
+    pub backend:StorageBackend,
+    pub local_root:String,
+    pub s3:Option<S3Settings>,
+    /// Uploads above are rejected
+    pub max_upload_bytes:u64,
+    /// Uploads are scanned for malware when set
+    pub clamd:Option<ClamdSettings>,
 }
 
 #[derive(Clone)]
-pub struct GoogleSettings {
-    pub client_id:String,
-    pub client_secret:String,
-    pub redirect_url:String,
-    pub is_enabled:bool,
-    pub allowed_domains:Vec<String>,
+pub enum ClamdAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+pub struct ClamdSettings {
+    pub address:ClamdAddress,
+    pub timeout:Duration,
+}
+
+pub struct S3Settings {
+    pub bucket:String,
+    pub region:String,
+    /// Custom endpoint of S3 compatible services, e.g. MinIO
+    pub endpoint:Option<String>,
+    pub access_key_id:Option<String>,
+    pub secret_access_key:Option<String>,
+    pub force_path_style:bool,
 }
 
 #[derive(Clone)]
-pub struct AzureSettings {
+pub struct SsoProviderSettings {
     pub client_id:String,
     pub client_secret:String,
-    pub tenant_id:String,
+    /// Discovery runs against `{issuer_url}/.well-known/openid-configuration`
+    pub issuer_url:String,
+    /// Azure tenant, `common` when unset
+    pub tenant_id:Option<String>,
     pub redirect_url:String,
     pub is_enabled:bool,
     pub allowed_domains:Vec<String>,
@@ -57,12 +99,16 @@ pub struct OpenaiSettings {
     pub timeout_ms:i32,
     pub max_retries:i32,
     pub is_enabled:bool,
+    pub circuit_breaker:Arc<CircuitBreaker>,
 }
 
 #[derive(Clone)]
 pub struct AnthropicSettings {
     pub api_key: String,
+    pub timeout_ms:i32,
+    pub max_retries:i32,
     pub is_enabled:bool,
+    pub circuit_breaker:Arc<CircuitBreaker>,
 }
 
 impl Settings {
@@ -85,7 +131,7 @@ impl Settings {
             let Some(api_key) = decrypt_key(&self.auth.app_key,&encrypted_api_key)
                .ok()
               else { continue }; // fall back for default <empty> string
-            self.load_ai_engine_in_state(engine.engine_key, api_key,true)
+            self.load_ai_engine_in_state(engine.engine_key, api_key,true,engine.timeout_ms,engine.max_retries)
              .await?;
         }
      Ok(())
@@ -113,22 +159,36 @@ impl Settings {
        }
     }
 
-    pub async fn load_ai_engine_in_state<S: Into<String>>(&self,engine_key:S,api_key:S,is_enabled:bool) -> Result<(),ConfigError> {
+    pub async fn load_ai_engine_in_state<S: Into<String>>(&self,engine_key:S,api_key:S,is_enabled:bool,timeout_ms:i32,max_retries:i32) -> Result<(),ConfigError> {
        match engine_key.into().as_str() {
               "openai" => {
               println!("openai api key added successfully from ai_engines Table");
+              // Keep the breaker state across reloads of the same engine
+              let circuit_breaker = self.openai
+                .read()
+                .await
+                .as_ref()
+                .map(|openai| openai.circuit_breaker.clone())
+                .unwrap_or_else(|| Arc::new(CircuitBreaker::new("openai")));
               *self.openai.write().await = Some(OpenaiSettings {
                 api_key:api_key.into(),
                 org_id: None,
                 project_id: None,
-                timeout_ms: 10_000,
-                max_retries: 10,
+                timeout_ms,
+                max_retries,
                 is_enabled,
+                circuit_breaker,
               });
              }
              "anthropic"  => {
               println!("anthropic api key added successfully from ai_engines Table");
-             *self.anthropic.write().await = Some(AnthropicSettings { api_key:api_key.into(),is_enabled });
+              let circuit_breaker = self.anthropic
+                .read()
+                .await
+                .as_ref()
+                .map(|anthropic| anthropic.circuit_breaker.clone())
+                .unwrap_or_else(|| Arc::new(CircuitBreaker::new("anthropic")));
+             *self.anthropic.write().await = Some(AnthropicSettings { api_key:api_key.into(),timeout_ms,max_retries,is_enabled,circuit_breaker });
             }
            _ => {}
           }
@@ -148,68 +208,44 @@ impl Settings {
          .await
          .map_err(|e| ConfigError::DbError(e.to_string()))?;
        for sso_provider in sso_providers {
-            let Ok(client_secret) = decrypt_key(&self.auth.app_key,&sso_provider.client_secret)
+            let Some(settings) = SsoProviderSettings::from_model(&self.auth.app_key,&sso_provider)
              else {
                 continue
              }; // fall back for default <empty> string
-            let Ok(_) = Url::parse(&sso_provider.redirect_url)
-             else{
-                continue;
-            };
-            let Ok(_) = Url::parse(&sso_provider.issuer_url)
-             else{
-                continue;
-            };
-            self.load_sso_provider_in_state(sso_provider.provider, client_secret, sso_provider.client_id, sso_provider.redirect_url, sso_provider.tenant_id,true,sso_provider.allowed_domains)
-              .await?;
+            self.load_sso_provider_in_state(&sso_provider.provider, settings)
+              .await;
        }
        Ok(())
     }
 
-    pub async fn load_sso_provider_in_state<S: Into<String>>(&self,provider:S,client_secret:S,client_id:S,redirect_url:S,tenant_id:Option<S>,is_enabled:bool,allowed_domains:Vec<S>) -> Result<(),ConfigError> {
-       match provider.into().as_str() {
-              "azure" => {
-              println!("azure sso provider added from sso_provider table");
-              *self.azure.write().await = Some(AzureSettings {
-                client_id:client_id.into(),
-                client_secret:client_secret.into(),
-                tenant_id:tenant_id.map(|t| t.into()).unwrap_or("common".into()),
-                redirect_url:redirect_url.into(),
-                is_enabled,
-                allowed_domains:allowed_domains
-                 .into_iter()
-                 .map(|d| d.into())
-                 .collect(),
-              });
-             }
-             "google"  => {
-              println!("google sso provider added from sso_provider table");
-             *self.google.write().await = Some(GoogleSettings { 
-                 client_id:client_id.into(),
-                 client_secret:client_secret.into(),
-                 redirect_url:redirect_url.into(),
-                 is_enabled,
-                 allowed_domains:allowed_domains
-                  .into_iter()
-                  .map(|d| d.into())
-                  .collect()
-                }
-             );
-            }
-           _ => {}
-          }
-      Ok(())
+    pub async fn load_sso_provider_in_state(&self,provider:&str,settings:SsoProviderSettings) {
+       println!("{provider} sso provider added from sso_provider table");
+       self.sso_providers
+         .write()
+         .await
+         .insert(provider.to_lowercase(), settings);
+    }
+
+    pub async fn remove_sso_provider_from_state(&self,provider:&str) {
+       self.sso_providers
+         .write()
+         .await
+         .remove(&provider.to_lowercase());
     }
 
     pub fn from_env() -> Result<Self, ConfigError> {
         Ok(Self {
             org_id:None,
             auth:AuthSettings::from_env()?,
-            google:RwLock::new(GoogleSettings::from_env().ok()),
-            azure:RwLock::new(AzureSettings::from_env().ok()),
+            sso_providers:RwLock::new(SsoProviderSettings::from_env()),
             server:ServerSettings::from_env()?,
             openai:RwLock::new(OpenaiSettings::from_env().ok()),
             anthropic:RwLock::new(AnthropicSettings::from_env().ok()),
+            storage:StorageSettings::from_env()?,
+            mail:std::env::var("SMTP_URL")
+              .ok()
+              .map(|_| MailSettings::from_env())
+              .transpose()?,
         })
     }
 }
@@ -221,6 +257,14 @@ impl ServerSettings {
             .ok()
             .and_then(|s| s.parse::<u16>().ok())
             .unwrap_or(8080); // default
+        let trusted_proxies = std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| proxy.parse::<IpAddr>().map_err(|_| ConfigError::ParseError("TRUSTED_PROXIES")))
+            .collect::<Result<Vec<_>,_>>()?;
+        TRUSTED_PROXIES.set(trusted_proxies).map_err(|_| ConfigError::AlreadyInitilized("TRUSTED_PROXIES"))?;
         Ok(Self { host, port })
     }
 }
@@ -234,28 +278,116 @@ impl AuthSettings {
         KEYS.set(Keys::new(jwt_secret.as_bytes())).map_err(|_| ConfigError::AlreadyInitilized("KEYS"))?;
         let redirect_url = std::env::var("REDIRECT_URL").map_err(|_| ConfigError::Missing("REDIRECT_URL"))?;
         let database_url = std::env::var("DATABASE_URL").map_err(|_| ConfigError::Missing("DATABASE_URL"))?;
-        Ok(Self { jwt_secret,redirect_url,database_url,app_key})
+        let password_reset_url = std::env::var("PASSWORD_RESET_URL").unwrap_or(format!("{}/reset-password", redirect_url.trim_end_matches('/')));
+        Ok(Self { jwt_secret,redirect_url,database_url,app_key,password_reset_url})
+    }
+}
+
+impl MailSettings {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let smtp_url = std::env::var("SMTP_URL").map_err(|_| ConfigError::Missing("SMTP_URL"))?;
+        let from = std::env::var("MAIL_FROM").map_err(|_| ConfigError::Missing("MAIL_FROM"))?;
+        Ok(Self { smtp_url, from })
     }
 }
 
-impl GoogleSettings {
+impl StorageSettings {
     pub fn from_env() -> Result<Self, ConfigError> {
-        let client_id = std::env::var("GOOGLE_CLIENT_ID").map_err(|_| ConfigError::Missing("GOOGLE_CLIENT_ID"))?;
-        let client_secret = std::env::var("GOOGLE_CLIENT_SECRET").map_err(|_| ConfigError::Missing("GOOGLE_CLIENT_SECRET"))?;
-        let app_redirect_url = std::env::var("REDIRECT_URL").map_err(|_| ConfigError::Missing("REDIRECT_URL"))?;
-        let redirect_url = format!("{}/auth/google/callback",app_redirect_url);
-      Ok(Self {client_id,client_secret,redirect_url,is_enabled:true,allowed_domains:Vec::new() })
+        let backend = match std::env::var("FILE_STORAGE_BACKEND").unwrap_or("local".to_string()).to_lowercase().as_str() {
+            "local" => StorageBackend::Local,
+            "s3" => StorageBackend::S3,
+            _ => return Err(ConfigError::ParseError("FILE_STORAGE_BACKEND")),
+        };
+        let local_root = std::env::var("FILE_STORAGE_LOCAL_ROOT").unwrap_or(LOCAL_STORAGE_ROOT.to_string());
+        // Kept when the backend switches back to local, files already in the bucket stay readable
+        let s3 = S3Settings::from_env().ok();
+        let max_upload_bytes = std::env::var("FILE_UPLOAD_MAX_BYTES").unwrap_or(DEFAULT_MAX_UPLOAD_BYTES.to_string()).parse::<u64>().map_err(|_| ConfigError::ParseError("FILE_UPLOAD_MAX_BYTES"))?;
+        let clamd = std::env::var("CLAMD_ADDRESS")
+            .ok()
+            .map(|_| ClamdSettings::from_env())
+            .transpose()?;
+        Ok(Self { backend, local_root, s3, max_upload_bytes, clamd })
     }
 }
 
-impl AzureSettings {
+impl ClamdSettings {
     pub fn from_env() -> Result<Self, ConfigError> {
-        let client_id = std::env::var("AZURE_CLIENT_ID").map_err(|_| ConfigError::Missing("AZURE_CLIENT_ID"))?;
-        let client_secret = std::env::var("AZURE_CLIENT_SECRET").map_err(|_| ConfigError::Missing("AZURE_CLIENT_SECRET"))?;
-        let tenant_id = std::env::var("AZURE_TENANT_ID").map_err(|_| ConfigError::Missing("AZURE_TENANT_ID"))?;
-        let app_redirect_url = std::env::var("REDIRECT_URL").map_err(|_| ConfigError::Missing("REDIRECT_URL"))?;
-        let redirect_url = format!("{}/auth/azure/callback",app_redirect_url);
-      Ok(Self {client_id,client_secret,redirect_url,tenant_id,is_enabled:true,allowed_domains:Vec::new() })
+        let address = std::env::var("CLAMD_ADDRESS").map_err(|_| ConfigError::Missing("CLAMD_ADDRESS"))?;
+        let address = if let Some(path) = address.strip_prefix("unix://") {
+            ClamdAddress::Unix(PathBuf::from(path))
+        } else if let Some(address) = address.strip_prefix("tcp://") {
+            ClamdAddress::Tcp(address.to_string())
+        } else {
+            return Err(ConfigError::ParseError("CLAMD_ADDRESS"));
+        };
+        let timeout_secs = std::env::var("CLAMD_TIMEOUT_SECS").unwrap_or("60".to_string()).parse::<u64>().map_err(|_| ConfigError::ParseError("CLAMD_TIMEOUT_SECS"))?;
+        Ok(Self { address, timeout:Duration::from_secs(timeout_secs) })
+    }
+}
+
+impl S3Settings {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let bucket = std::env::var("S3_BUCKET").map_err(|_| ConfigError::Missing("S3_BUCKET"))?;
+        let region = std::env::var("S3_REGION").unwrap_or("us-east-1".to_string());
+        let endpoint = std::env::var("S3_ENDPOINT").ok();
+        let access_key_id = std::env::var("S3_ACCESS_KEY_ID").ok();
+        let secret_access_key = std::env::var("S3_SECRET_ACCESS_KEY").ok();
+        // MinIO serves buckets on paths rather than subdomains
+        let force_path_style = std::env::var("S3_FORCE_PATH_STYLE")
+            .ok()
+            .map(|value| value.parse::<bool>().map_err(|_| ConfigError::ParseError("S3_FORCE_PATH_STYLE")))
+            .transpose()?
+            .unwrap_or(endpoint.is_some());
+        Ok(Self { bucket, region, endpoint, access_key_id, secret_access_key, force_path_style })
+    }
+}
+
+impl SsoProviderSettings {
+    /// Settings of a configured row, `None` while its secret or URLs are placeholders
+    pub fn from_model(app_key:&[u8; 32],model:&sso_providers::Model) -> Option<Self> {
+        let client_secret = decrypt_key(app_key,&model.client_secret).ok()?;
+        Url::parse(&model.redirect_url).ok()?;
+        Url::parse(&model.issuer_url).ok()?;
+        Some(Self {
+            client_id:model.client_id.clone(),
+            client_secret,
+            issuer_url:model.issuer_url.clone(),
+            tenant_id:model.tenant_id.clone(),
+            redirect_url:model.redirect_url.clone(),
+            is_enabled:model.is_enabled,
+            allowed_domains:model.allowed_domains.clone(),
+        })
+    }
+
+    /// Google and Azure configured through the environment, overridden by the sso_providers table
+    pub fn from_env() -> HashMap<String,Self> {
+        let mut providers = HashMap::new();
+        let Ok(app_redirect_url) = std::env::var("REDIRECT_URL") else {
+            return providers;
+        };
+        if let (Ok(client_id),Ok(client_secret)) = (std::env::var("GOOGLE_CLIENT_ID"),std::env::var("GOOGLE_CLIENT_SECRET")) {
+            providers.insert("google".to_string(), Self {
+                client_id,
+                client_secret,
+                issuer_url:"https://accounts.google.com".to_string(),
+                tenant_id:None,
+                redirect_url:format!("{}/auth/google/callback",app_redirect_url),
+                is_enabled:true,
+                allowed_domains:Vec::new(),
+            });
+        }
+        if let (Ok(client_id),Ok(client_secret),Ok(tenant_id)) = (std::env::var("AZURE_CLIENT_ID"),std::env::var("AZURE_CLIENT_SECRET"),std::env::var("AZURE_TENANT_ID")) {
+            providers.insert("azure".to_string(), Self {
+                client_id,
+                client_secret,
+                issuer_url:format!("https://login.microsoftonline.com/{}/v2.0",tenant_id),
+                tenant_id:Some(tenant_id),
+                redirect_url:format!("{}/auth/azure/callback",app_redirect_url),
+                is_enabled:true,
+                allowed_domains:Vec::new(),
+            });
+        }
+        providers
     }
 }
 
@@ -266,14 +398,24 @@ impl OpenaiSettings {
         let project_id = std::env::var("OPENAI_PROJECT_ID").ok();
         let timeout_ms = std::env::var("OPENAI_TIMEOUT_MS").unwrap_or("60000".to_string()).parse::<i32>().map_err(|_| ConfigError::ParseError("OPENAI_TIMEOUT_MS"))?;
         let max_retries = std::env::var("OPENAI_MAX_TRIES").unwrap_or("1".to_string()).parse::<i32>().map_err(|_| ConfigError::ParseError("OPENAI_MAX_RETRIES"))?;
-      Ok(Self { api_key, org_id, project_id, timeout_ms, max_retries,is_enabled:true })
+      Ok(Self { api_key, org_id, project_id, timeout_ms, max_retries,is_enabled:true,circuit_breaker:Arc::new(CircuitBreaker::new("openai")) })
+    }
+
+    pub fn http_policy(&self) -> HttpPolicy {
+        HttpPolicy::new(self.timeout_ms,self.max_retries,self.circuit_breaker.clone())
     }
 }
 
 impl AnthropicSettings {
     pub fn from_env() -> Result<Self, ConfigError> {
         let api_key = std::env::var("ANTHROPIC_API_KEY").map_err(|_| ConfigError::Missing("ANTHROPIC_API_KEY"))?;
-        Ok(Self { api_key,is_enabled:true })
+        let timeout_ms = std::env::var("ANTHROPIC_TIMEOUT_MS").unwrap_or(DEFAULT_TIMEOUT_MS.to_string()).parse::<i32>().map_err(|_| ConfigError::ParseError("ANTHROPIC_TIMEOUT_MS"))?;
+        let max_retries = std::env::var("ANTHROPIC_MAX_RETRIES").unwrap_or(DEFAULT_MAX_RETRIES.to_string()).parse::<i32>().map_err(|_| ConfigError::ParseError("ANTHROPIC_MAX_RETRIES"))?;
+        Ok(Self { api_key,timeout_ms,max_retries,is_enabled:true,circuit_breaker:Arc::new(CircuitBreaker::new("anthropic")) })
+    }
+
+    pub fn http_policy(&self) -> HttpPolicy {
+        HttpPolicy::new(self.timeout_ms,self.max_retries,self.circuit_breaker.clone())
     }
 }
 
@@ -46,6 +46,9 @@ pub fn build_app_error_catalog() -> Vec<AppErrorCatalogItem> {
     for e in [
         AppError::ServiceTemporarilyUnavailable,
         AppError::ResourceNotFound,
+        AppError::PermissionDenied,
+        AppError::FileQuarantined,
+        AppError::FileScanPending,
         AppError::DbUnavailable,
         AppError::DbTimeout,
         AppError::DbConflict,
@@ -59,6 +62,10 @@ pub fn build_app_error_catalog() -> Vec<AppErrorCatalogItem> {
     for e in [
         AppError::ValidationMissingField { field: "messages" },
         AppError::ValidationEmptyField { field: "messages" },
+        AppError::ValidationInvalidField { field: "message_id" },
+        AppError::ValidationFileTooLarge { max_bytes: 52_428_800 },
+        AppError::ValidationFileTypeNotAllowed { content_type: "application/vnd.microsoft.portable-executable".to_string() },
+        AppError::StorageQuotaExceeded { quota_bytes: 1_073_741_824 },
     ] {
         let (status, detail) = e.to_detail();
         items.push(AppErrorCatalogItem::from_detail(status, detail));
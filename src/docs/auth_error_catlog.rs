@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use utoipa::ToSchema;
+use chrono::Utc;
 use uuid::Uuid;
 use crate::auth::error::{AuthError, AuthErrorCode, ErrorDetail};
 
@@ -58,6 +59,11 @@ pub fn build_auth_error_catalog() -> Vec<AuthErrorCatalogItem> {
         AuthError::ResourceNotFound,
         AuthError::InvalidUserStatus,
         AuthError::AccountDeactivated,
+        AuthError::InvalidMfaCode,
+        AuthError::MfaAlreadyEnabled,
+        AuthError::MfaNotEnrolled,
+        AuthError::MfaRequired,
+        AuthError::SessionNotFound,
     ] {
         let (status, detail) = e.to_detail();
         items.push(AuthErrorCatalogItem::from_detail(status, detail));
@@ -105,6 +111,30 @@ pub fn build_auth_error_catalog() -> Vec<AuthErrorCatalogItem> {
         AuthError::SsoProviderDisabledByAdmin {
             provider: Some("google".to_string()),
         },
+        AuthError::InvalidSsoProviderRequest { field: "issuer_url" },
+    ] {
+        let (status, detail) = e.to_detail();
+        items.push(AuthErrorCatalogItem::from_detail(status, detail));
+    }
+
+    // --- password login params ---
+    for e in [
+        AuthError::AccountLocked {
+            locked_until: Utc::now(),
+        },
+        AuthError::WeakPassword {
+            rule: "min_length",
+            min_length: 12,
+        },
+    ] {
+        let (status, detail) = e.to_detail();
+        items.push(AuthErrorCatalogItem::from_detail(status, detail));
+    }
+
+    // --- API key params ---
+    for e in [
+        AuthError::InsufficientScope { scope: "write" },
+        AuthError::InvalidApiKeyRequest { field: "scopes" },
     ] {
         let (status, detail) = e.to_detail();
         items.push(AuthErrorCatalogItem::from_detail(status, detail));
@@ -1,21 +1,32 @@
 use utoipa::OpenApi;
 use crate::auth::claims::Claims;
 use crate::auth::error::{AuthError,AuthErrorCode,AuthErrorDetailVariant,AuthErrorResponse};
-use crate::dto::admin_ai::{AiEngineResponse, AiEngineUpdateRequest, AiEngineValidationResponse, AiModel,AiEngineModelsResponse, AiModelCapabilities};
+use crate::dto::admin_ai::{AiEngineResponse, AiEngineUpdateRequest, AiEngineValidationResponse, AiModel,AiEngineModelsResponse, AiModelCapabilities, FallbackTarget};
 use crate::dto::admin_department::{Department, DepartmentResponse};
 use crate::dto::admin_org::OrgResponse;
-use crate::dto::admin_sso_providers::{SsoProviderResponse, SsoProviderUpdateRequest};
+use crate::dto::admin_sso_providers::{SsoProviderCreateRequest, SsoProviderResponse, SsoProviderUpdateRequest};
 use crate::dto::admin_user::{UserDetails, UserPatchRequest, UserRequest, UserResponse, UserUpdateRequest};
-use crate::dto::chat::{ArchiveChatRequest, ConversationResponse, MessageParts, MessageResponse, TokenUsage};
-use crate::dto::chat_stream::{ChatInitRequest, ChatStream};
+use crate::dto::audio::{SpeechFormat, SpeechRequest, TranscriptionResponse, TranscriptionUpload};
+use crate::dto::images::{ImageGenerationRequest, ImageGenerationResponse, ImageQuality, ImageSize};
+use crate::dto::api_keys::{ApiKeyCreatedResponse, ApiKeyRequest, ApiKeyResponse, ServiceAccountRequest, ServiceAccountResponse};
+use crate::dto::assistants::{AssistantRequest, AssistantResponse, AssistantUpdateRequest};
+use crate::dto::collections::{CollectionFile, CollectionFilesRequest, CollectionIngestion, CollectionRequest, CollectionResponse, CollectionUpdateRequest, FileIngestionStatus, IngestionStatus};
+use crate::dto::chat::{ArchiveChatRequest, Citation, ConversationResponse, MessageParts, MessageResponse, TokenUsage};
+use crate::dto::chat_stream::{ChatCompareRequest, ChatInitRequest, ChatStream, CompareTarget, RegenerateRequest};
 use crate::dto::common::{PaginationQuery, SortRule};
-use crate::dto::files::{Attachment, File, FileResponse, FileUploadRequest};
+use crate::dto::files::{Attachment, DocumentFormat, DocumentStructure, ExtractionStatus, File, FileExtraction, FileMultipartUpload, FileResponse, FileScan, FileScanStatus, FileUpdateRequest, FileUploadRequest, SheetStructure};
+use crate::dto::storage::{StorageQuotas, StorageReport, StorageUsage, UploadPolicy, UploadRule, UserStorageQuota, UserStorageUsage};
+use crate::dto::instructions::{InstructionLevel, InstructionSource, InstructionsPreviewResponse, OrgInstructions, PersonalInstructions};
 use crate::dto::models::{ModelInfo, ProviderInfo};
 use crate::dto::oauth::OAuthCallback;
+use crate::dto::prompt_templates::{PromptTemplateRequest, PromptTemplateResponse, PromptTemplateUpdateRequest, TemplateScope};
 use crate::error::{AppError, ErrorDetail, ErrorDetailVariant, ErrorResponse};
 use crate::docs::{security::ApiSecurityAddon,app_error_catlog::AppErrorCatalogItem};
-use crate::dto::auth::{AuthInitResponse, AuthTokenResponse, RefreshTokenRequest, TokenType, User};
-use crate::handlers::{auth,oidc,open_error,chat,chat_stream,file,message,admin_users,admin_sso_provider,admin_org,admin_ai,models,admin_department};
+use crate::dto::auth::{AuthInitResponse, AuthTokenResponse, LoginResponse, MfaChallengeResponse, MfaCodeRequest, MfaRecoveryCodesResponse, MfaSetupResponse, MfaVerifyRequest, PasswordChangeRequest, SessionResponse, PasswordForgotRequest, PasswordInviteResponse, PasswordLoginRequest, PasswordPolicy, PasswordResetRequest, RefreshTokenRequest, TokenType, User};
+use crate::handlers::{auth,oidc,open_error,chat,chat_stream,file,message,admin_users,admin_sso_provider,admin_org,admin_ai,models,admin_department,prompt_templates,instructions,assistants,collections,storage,audio,images,password,mfa,sessions,api_keys};
+use crate::models::api_keys::ApiKeyScope;
+use crate::models::assistants::AssistantVisibility;
+use crate::models::collections::CollectionScope;
 use crate::models::messages::ChatRole;
 use crate::models::users::{UserRole, UserStatus};
 
@@ -23,6 +34,34 @@ use crate::models::users::{UserRole, UserStatus};
 #[openapi(
     paths(
         auth::handle_refresh_token,
+        password::password_login,
+        password::change_password,
+        password::forgot_password,
+        password::reset_password,
+        password::invite_user_password,
+        password::unlock_user,
+        password::get_password_policy,
+        password::update_password_policy,
+        mfa::setup_mfa,
+        mfa::enable_mfa,
+        mfa::verify_mfa,
+        mfa::disable_mfa,
+        mfa::regenerate_recovery_codes,
+        mfa::reset_user_mfa,
+        sessions::logout,
+        sessions::logout_all,
+        sessions::get_sessions,
+        sessions::delete_session,
+        sessions::get_user_sessions,
+        sessions::revoke_user_sessions_admin,
+        api_keys::get_api_keys,
+        api_keys::add_api_key,
+        api_keys::delete_api_key,
+        api_keys::get_service_accounts,
+        api_keys::add_service_account,
+        api_keys::get_service_account_api_keys,
+        api_keys::add_service_account_api_key,
+        api_keys::delete_service_account_api_key,
         oidc::oidc_login_start,
         oidc::oidc_oauth_callback_get,
         oidc::oidc_oauth_callback_post,
@@ -32,8 +71,38 @@ use crate::models::users::{UserRole, UserStatus};
         chat::update_chat_by_id,
         chat_stream::handle_chat_stream_doc,
         chat_stream::handle_chat_stream_path_doc,
+        chat_stream::handle_chat_compare,
         message::delete_chat_message_by_id,
         message::edit_chat_message_by_id_and_stream,
+        message::regenerate_chat_message_by_id,
+        message::get_chat_message_siblings,
+        message::select_chat_message_branch,
+        prompt_templates::get_prompt_templates,
+        prompt_templates::get_prompt_template_categories,
+        prompt_templates::get_prompt_template_by_id,
+        prompt_templates::add_prompt_template,
+        prompt_templates::update_prompt_template_by_id,
+        prompt_templates::delete_prompt_template_by_id,
+        instructions::get_personal_instructions,
+        instructions::update_personal_instructions,
+        instructions::preview_instructions,
+        assistants::get_assistants,
+        assistants::get_assistant_by_id,
+        assistants::add_assistant,
+        assistants::update_assistant_by_id,
+        assistants::delete_assistant_by_id,
+        assistants::start_assistant_chat,
+        collections::get_collections,
+        collections::get_collection_by_id,
+        collections::add_collection,
+        collections::update_collection_by_id,
+        collections::delete_collection_by_id,
+        collections::add_collection_files,
+        collections::delete_collection_file,
+        collections::ingest_collection,
+        audio::transcribe_audio,
+        audio::synthesize_speech,
+        images::generate_chat_images,
         admin_users::add_new_user,
         admin_users::get_users,
         admin_users::update_user,
@@ -49,15 +118,30 @@ use crate::models::users::{UserRole, UserStatus};
         admin_ai::delete_ai_engines_api_key_key,
         admin_ai::get_ai_engine_models_by_key,
         admin_department::get_departments,
+        instructions::get_org_instructions,
+        instructions::update_org_instructions,
+        assistants::get_all_assistants,
+        collections::get_all_collections,
+        storage::get_storage_report,
+        storage::update_storage_quotas,
+        storage::update_user_storage_quota,
+        storage::get_upload_policy,
+        storage::update_upload_policy,
         admin_sso_provider::get_sso_providers,
+        admin_sso_provider::add_sso_provider,
         admin_sso_provider::get_sso_provider_by_id,
         admin_sso_provider::update_sso_provider_by_id,
         admin_sso_provider::delete_sso_provider_by_id,
         file::get_file_by_id,
         file::get_files,
         file::delete_file_by_id,
+        file::restore_file,
+        file::update_file,
+        storage::get_storage_usage,
         file::download_file,
+        file::get_file_thumbnail,
         file::upload_file,
+        file::upload_file_multipart,
         models::get_list_models,
         open_error::get_app_error_catalog,
         open_error::get_auth_error_catalog,
@@ -80,9 +164,53 @@ use crate::models::users::{UserRole, UserStatus};
             ConversationResponse,
             File,
             MessageParts,
+            Citation,
             TokenUsage,
             ChatStream,
             ChatInitRequest,
+            ChatCompareRequest,
+            CompareTarget,
+            RegenerateRequest,
+            PromptTemplateRequest,
+            PromptTemplateUpdateRequest,
+            PromptTemplateResponse,
+            TemplateScope,
+            OrgInstructions,
+            FileUpdateRequest,
+            StorageUsage,
+            UserStorageUsage,
+            StorageReport,
+            StorageQuotas,
+            UserStorageQuota,
+            UploadPolicy,
+            UploadRule,
+            FileScan,
+            FileScanStatus,
+            PersonalInstructions,
+            InstructionLevel,
+            InstructionSource,
+            InstructionsPreviewResponse,
+            AssistantRequest,
+            AssistantUpdateRequest,
+            AssistantResponse,
+            AssistantVisibility,
+            CollectionRequest,
+            CollectionUpdateRequest,
+            CollectionFilesRequest,
+            CollectionResponse,
+            CollectionFile,
+            CollectionIngestion,
+            CollectionScope,
+            IngestionStatus,
+            FileIngestionStatus,
+            TranscriptionUpload,
+            TranscriptionResponse,
+            SpeechRequest,
+            SpeechFormat,
+            ImageGenerationRequest,
+            ImageGenerationResponse,
+            ImageSize,
+            ImageQuality,
             Attachment,
             OAuthCallback,
             SortRule,
@@ -97,6 +225,12 @@ use crate::models::users::{UserRole, UserStatus};
             AiEngineUpdateRequest,
             FileResponse,
             FileUploadRequest,
+            FileMultipartUpload,
+            FileExtraction,
+            ExtractionStatus,
+            DocumentFormat,
+            DocumentStructure,
+            SheetStructure,
             ProviderInfo,
             ModelInfo,
             Department,
@@ -105,14 +239,35 @@ use crate::models::users::{UserRole, UserStatus};
             AiEngineModelsResponse,
             AiModel,
             AiModelCapabilities,
+            FallbackTarget,
             SsoProviderResponse,
             SsoProviderUpdateRequest,
+            SsoProviderCreateRequest,
             AuthError,
             AppError,
             AuthErrorCode,
             AuthErrorDetailVariant,
             AuthErrorResponse,
             RefreshTokenRequest,
+            PasswordLoginRequest,
+            PasswordChangeRequest,
+            PasswordForgotRequest,
+            PasswordResetRequest,
+            PasswordInviteResponse,
+            LoginResponse,
+            MfaChallengeResponse,
+            MfaSetupResponse,
+            MfaCodeRequest,
+            MfaVerifyRequest,
+            MfaRecoveryCodesResponse,
+            SessionResponse,
+            ApiKeyScope,
+            ApiKeyRequest,
+            ApiKeyResponse,
+            ApiKeyCreatedResponse,
+            ServiceAccountRequest,
+            ServiceAccountResponse,
+            PasswordPolicy,
             AppErrorCatalogItem,
         )
     ),
@@ -21,6 +21,7 @@ impl Modify for ApiSecurityAddon {
                 HttpBuilder::new()
                     .scheme(HttpAuthScheme::Bearer)
                     .bearer_format("JWT")
+                    .description(Some("Access token, or an API key starting with `gk_`"))
                     .build(),
             ),
         );
@@ -1,7 +1,8 @@
+use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
-use crate::models::ai_engines::ApiKeyStatus;
+use crate::{dto::models::ModelsResponse, models::ai_engines::ApiKeyStatus};
 
 #[derive(Deserialize,ToSchema)]
  pub struct AiEngineUpdateRequest{
@@ -9,6 +10,19 @@ use crate::models::ai_engines::ApiKeyStatus;
     pub api_key:Option<String>,
     pub whitelisted_models:Option<Vec<String>>,
     pub default_model:Option<String>,
+    /// Replaces the fallback chains of this engine, keyed by requested model
+    pub fallback_chains:Option<HashMap<String,Vec<FallbackTarget>>>,
+    /// Timeout of a provider call, time to the first event for streams
+    pub timeout_ms:Option<i32>,
+    /// Retries on timeouts, connection errors, 429 and 5xx before failing over
+    pub max_retries:Option<i32>,
+}
+
+/// Model tried when the requested one is unavailable (outage, overload, 5xx)
+#[derive(Debug,Clone,Serialize,Deserialize,ToSchema)]
+ pub struct FallbackTarget{
+    pub provider:String,
+    pub model:String,
 }
 
 #[derive(Serialize,ToSchema)]
@@ -23,6 +37,9 @@ use crate::models::ai_engines::ApiKeyStatus;
      pub api_key_last_validated_at:Option<DateTime<Utc>>,
      pub whitelisted_models:Vec<String>,
      pub default_model:Option<String>,
+     pub fallback_chains:HashMap<String,Vec<FallbackTarget>>,
+     pub timeout_ms:i32,
+     pub max_retries:i32,
      pub created_at:DateTime<Utc>,
      pub updated_at:DateTime<Utc>,
   }
@@ -54,3 +71,58 @@ pub struct AiModelCapabilities {
     pub function_calling: bool,
     pub streaming: bool,
 }
+
+/// Parses `ai_engines.fallback_chains`, ignoring malformed entries
+pub fn parse_fallback_chains(value:&Option<serde_json::Value>) -> HashMap<String,Vec<FallbackTarget>>{
+    value
+      .clone()
+      .and_then(|json| serde_json::from_value(json).ok())
+      .unwrap_or_default()
+}
+
+/// Fallback chains with trimmed, lowercase providers, `None` when a target is not a model of the catalogue
+pub fn normalize_fallback_chains(chains:HashMap<String,Vec<FallbackTarget>>,catalogue:&ModelsResponse) -> Option<HashMap<String,Vec<FallbackTarget>>>{
+    chains
+      .into_iter()
+      .map(|(requested_model, targets)| {
+          let targets = targets
+            .into_iter()
+            .map(|target| normalize_target(target, catalogue))
+            .collect::<Option<Vec<FallbackTarget>>>()?;
+          Some((requested_model.trim().to_string(), targets))
+      })
+      .collect()
+}
+
+/// Target with a trimmed, lowercased provider and a trimmed model, `None` when the model is not in the catalogue
+pub fn normalize_target(target:FallbackTarget,catalogue:&ModelsResponse) -> Option<FallbackTarget>{
+    let target = FallbackTarget { provider:target.provider.trim().to_lowercase(), model:target.model.trim().to_string() };
+    catalogue.providers
+      .iter()
+      .find(|provider| provider.key == target.provider)?
+      .models
+      .iter()
+      .any(|model| model.key == target.model)
+      .then_some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(provider:&str,model:&str) -> FallbackTarget {
+        FallbackTarget { provider:provider.to_string(), model:model.to_string() }
+    }
+
+    #[test]
+    fn fallback_targets_are_normalized_and_checked() {
+        let catalogue = ModelsResponse::default();
+        let chains = HashMap::from([("gpt-5.2".to_string(), vec![target(" Anthropic ", "claude-sonnet-4-5")])]);
+        let normalized = normalize_fallback_chains(chains, &catalogue).unwrap();
+        assert_eq!(normalized["gpt-5.2"][0].provider, "anthropic");
+        let unknown_provider = HashMap::from([("gpt-5.2".to_string(), vec![target("antrhopic", "claude-sonnet-4-5")])]);
+        assert!(normalize_fallback_chains(unknown_provider, &catalogue).is_none());
+        let unknown_model = HashMap::from([("gpt-5.2".to_string(), vec![target("openai", "claude-sonnet-4-5")])]);
+        assert!(normalize_fallback_chains(unknown_model, &catalogue).is_none());
+    }
+}
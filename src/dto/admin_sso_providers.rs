@@ -33,3 +33,20 @@ pub struct SsoProviderUpdateRequest {
    pub is_enabled:Option<bool>,
 }
 
+
+#[derive(Deserialize,ToSchema)]
+pub struct SsoProviderCreateRequest {
+   /// Key used in `/auth/{provider}`, e.g. "okta", "keycloak"
+   pub provider:String,
+   pub name: String,
+   /// Issuer publishing `/.well-known/openid-configuration`, e.g. "https://dev-123.okta.com/oauth2/default"
+   pub issuer_url:String,
+   pub client_id:String,
+   pub client_secret:String,
+   /// Azure tenant, `common` when unset
+   pub tenant_id:Option<String>,
+   /// Defaults to `{REDIRECT_URL}/auth/{provider}/callback`
+   pub redirect_url:Option<String>,
+   pub allowed_domains:Option<Vec<String>>,
+   pub is_enabled:Option<bool>,
+}
@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::models::{api_keys::ApiKeyScope, users::{UserRole, UserStatus}};
+
+#[derive(Deserialize, ToSchema)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    /// `read` for GET requests, `write` for the others, `admin` for `/admin` endpoints
+    pub scopes: Vec<ApiKeyScope>,
+    /// Days until the key expires, 1 to 365, never when omitted
+    pub expires_in_days: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    /// Start of the key, e.g. `gk_3fa9c1d2`
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiKeyCreatedResponse {
+    /// Sent as `Authorization: Bearer gk_...`, shown only once
+    pub key: String,
+    pub api_key: ApiKeyResponse,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ServiceAccountRequest {
+    pub name: String,
+    /// `user` when omitted, `superadmin` is not allowed
+    pub role: Option<UserRole>,
+    pub department: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ServiceAccountResponse {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub role: UserRole,
+    pub status: UserStatus,
+    pub department: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
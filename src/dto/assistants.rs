@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::models::assistants::AssistantVisibility;
+
+#[derive(Debug,Deserialize,ToSchema,IntoParams)]
+pub struct AssistantQuery {
+   pub limit:Option<u64>,
+   pub offset:Option<u64>,
+   /// Search in names and descriptions
+   pub search:Option<String>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct AssistantRequest {
+   pub name:String,
+   pub description:Option<String>,
+   pub provider:String,
+   pub model_name:String,
+   pub system_prompt:String,
+   /// Default temperature, a chat request may override it
+   pub temperature:Option<f32>,
+   /// Tools the assistant may use, `web_search` included
+   #[serde(default)]
+   pub tools:Vec<String>,
+   /// Uploaded files of the creator attached to every conversation
+   #[serde(default)]
+   pub file_ids:Vec<Uuid>,
+   /// Default value : private
+   pub visibility:Option<AssistantVisibility>,
+   /// Department shared with, defaults to the creator department
+   pub department:Option<String>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct AssistantUpdateRequest {
+   pub name:Option<String>,
+   pub description:Option<String>,
+   pub provider:Option<String>,
+   pub model_name:Option<String>,
+   pub system_prompt:Option<String>,
+   pub temperature:Option<f32>,
+   pub tools:Option<Vec<String>>,
+   pub file_ids:Option<Vec<Uuid>>,
+   pub visibility:Option<AssistantVisibility>,
+   pub department:Option<String>,
+}
+
+#[derive(Serialize,ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AssistantResponse {
+   pub id:Uuid,
+   pub name:String,
+   pub description:String,
+   pub provider:String,
+   pub model_name:String,
+   pub system_prompt:String,
+   pub temperature:Option<f32>,
+   pub tools:Vec<String>,
+   pub file_ids:Vec<Uuid>,
+   pub visibility:AssistantVisibility,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub department:Option<String>,
+   /// Whether the requesting user created the assistant
+   pub owned:bool,
+   pub created_at:DateTime<Utc>,
+   pub updated_at:DateTime<Utc>,
+}
\ No newline at end of file
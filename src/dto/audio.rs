@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::dto::chat::TokenUsage;
+
+/// `multipart/form-data` transcription of a recording
+#[derive(Deserialize, ToSchema)]
+pub struct TranscriptionUpload {
+    #[schema(value_type = String, format = Binary)]
+    pub file:Vec<u8>,
+    /// Defaults to `gpt-4o-mini-transcribe`, `gpt-4o-transcribe` and `whisper-1` are also accepted
+    pub model:Option<String>,
+    /// ISO-639-1 language of the recording, detected when absent
+    pub language:Option<String>,
+    /// Text guiding the transcription, e.g. names and acronyms it contains
+    pub prompt:Option<String>,
+    /// Conversation the transcript is posted to, as a user message on its active branch
+    pub chat_id:Option<Uuid>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TranscriptionResponse {
+    pub text:String,
+    pub model:String,
+    /// Length of the recording, reported by duration priced models
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_seconds:Option<f64>,
+    pub usage:TokenUsage,
+    /// Cost in USD
+    pub cost:f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chat_id:Option<Uuid>,
+    /// User message holding the transcript, when posted to a conversation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id:Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeechFormat {
+    #[default]
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+    Wav,
+    Pcm,
+}
+
+impl SpeechFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpeechFormat::Mp3 => "mp3",
+            SpeechFormat::Opus => "opus",
+            SpeechFormat::Aac => "aac",
+            SpeechFormat::Flac => "flac",
+            SpeechFormat::Wav => "wav",
+            SpeechFormat::Pcm => "pcm",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SpeechFormat::Mp3 => "audio/mpeg",
+            SpeechFormat::Opus => "audio/ogg",
+            SpeechFormat::Aac => "audio/aac",
+            SpeechFormat::Flac => "audio/flac",
+            SpeechFormat::Wav => "audio/wav",
+            // Raw 24kHz 16-bit signed little-endian samples
+            SpeechFormat::Pcm => "audio/L16;rate=24000",
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SpeechRequest {
+    /// Text read aloud, up to 4096 characters
+    pub input:String,
+    /// Defaults to `alloy`
+    pub voice:Option<String>,
+    /// Defaults to `tts-1`, `tts-1-hd` is also accepted
+    pub model:Option<String>,
+    #[serde(default)]
+    pub format:SpeechFormat,
+    /// From 0.25 to 4.0, defaults to 1.0
+    pub speed:Option<f32>,
+}
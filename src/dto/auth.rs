@@ -79,4 +79,138 @@ pub struct User {
     pub created_at: DateTime<Utc>,
     /// User last update timestamp (required)
     pub updated_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+/// Password rules and lockout of local accounts, SSO accounts are not affected
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Fewest characters, from 8 to 128 (default: 12)
+    pub min_length: u32,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Failed logins in a row locking the account (default: 5)
+    pub max_failed_attempts: u32,
+    /// Lockout length in minutes (default: 15)
+    pub lockout_minutes: u32,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 12,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: false,
+            require_symbol: false,
+            max_failed_attempts: 5,
+            lockout_minutes: 15,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordLoginRequest {
+    #[schema(format = "email")]
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordChangeRequest {
+    /// Required when the account already has a password
+    pub current_password: Option<String>,
+    /// Ignored for accounts without a password, they set their first one from an emailed link
+    pub new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordForgotRequest {
+    #[schema(format = "email")]
+    pub email: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PasswordResetRequest {
+    /// Invite or reset token from the emailed link
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PasswordInviteResponse {
+    /// Link setting the password, only returned when it was not emailed, to share with the user
+    #[schema(format = "uri")]
+    pub url: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub emailed: bool,
+}
+
+/// Login waiting for the second factor, completed with `/auth/mfa/verify`, or with `/auth/mfa/setup`
+/// and `/auth/mfa/enable` when the user must enroll one first
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MfaChallengeResponse {
+    /// Always true
+    pub mfa_required: bool,
+    /// The organization requires MFA and the user has no authenticator yet
+    pub enrollment_required: bool,
+    /// Short lived token of the pending login, not an access token
+    pub mfa_token: String,
+    /// Token expiration time in seconds
+    pub expires_in: i32,
+}
+
+/// Tokens of the signed in user, or the challenge of their second factor
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Tokens(Box<AuthTokenResponse>),
+    MfaChallenge(MfaChallengeResponse),
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MfaSetupResponse {
+    /// Base32 secret, for authenticator apps that cannot scan the QR code
+    pub secret: String,
+    /// `otpauth://` URI to render as a QR code
+    pub otpauth_uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MfaCodeRequest {
+    /// Current code of the authenticator app, or a recovery code where accepted
+    pub code: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MfaVerifyRequest {
+    pub mfa_token: String,
+    /// Current code of the authenticator app, or a recovery code
+    pub code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct MfaRecoveryCodesResponse {
+    /// Single use codes replacing the authenticator, shown only once
+    pub recovery_codes: Vec<String>,
+    /// Tokens of the login that was pending enrollment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<AuthTokenResponse>,
+}
+
+/// Signed in device
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResponse {
+    pub id: Uuid,
+    /// User agent of the device at its latest refresh
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Session of the access token making the request
+    pub current: bool,
+}
@@ -46,6 +46,27 @@ pub struct MessageResponse {
   pub tools_results:Vec<serde_json::Value>,
   pub parts:MessageParts,
   pub usage:TokenUsage,
+  pub previous_message_id:Option<Uuid>,
+  /// Number of alternatives (regenerated or edited) of this message, itself included
+  pub sibling_count:usize,
+  /// Position of this message among its alternatives, oldest first
+  pub sibling_index:usize,
+  /// File excerpts the answer was generated from, numbered as cited in the text
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub citations:Option<Vec<Citation>>,
+}
+
+/// File chunk retrieved for an answer, stored in `messages.metadata.citations`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Citation {
+  /// Number of the excerpt in the prompt, `[1]` being the most relevant
+  pub index:usize,
+  pub chunk_id:Uuid,
+  pub file_id:Uuid,
+  pub file_name:String,
+  pub chunk_index:i32,
+  pub score:f32,
 }
 
 
@@ -10,6 +10,9 @@ pub struct ChatStream {
    pub role:Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
    pub content:Option<String>,
+    /// Model that produced the chunk, set when several models stream side by side
+    #[serde(skip_serializing_if = "Option::is_none")]
+   pub model:Option<String>,
 }
 
 impl ChatStream {
@@ -29,6 +32,48 @@ pub struct ChatInitRequest{
   pub conversation_id: Option<Uuid>,
   pub messages: Vec<MessageRequest>,
   pub temperature:Option<f32>,
+  /// Template the messages were written from, counted in its usage
+  pub prompt_template_id: Option<Uuid>,
+  /// Assistant to start a new conversation from, ignored for existing conversations
+  pub assistant_id: Option<Uuid>,
+  /// Knowledge collections searched for this message and the next ones of the branch
+  #[serde(default)]
+  pub collection_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct RegenerateRequest{
+  /// Defaults to the provider of the regenerated message
+  pub provider: Option<String>,
+  /// Defaults to the model of the regenerated message
+  pub model_name: Option<String>,
+  #[serde(default)]
+  pub web_search: bool,
+  pub selected_tools: Option<Vec<String>>,
+  pub temperature:Option<f32>,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct CompareTarget{
+  pub provider: String,
+  pub model_name: String,
+}
+
+#[derive(Deserialize, ToSchema, IntoParams)]
+pub struct ChatCompareRequest{
+  /// 2 to 3 distinct engine/model pairs answering the same messages
+  pub targets: Vec<CompareTarget>,
+  #[serde(default)]
+  pub web_search: bool,
+  pub selected_tools: Option<Vec<String>>,
+  pub conversation_id: Option<Uuid>,
+  pub messages: Vec<MessageRequest>,
+  pub temperature:Option<f32>,
+  /// Template the messages were written from, counted in its usage
+  pub prompt_template_id: Option<Uuid>,
+  /// Knowledge collections searched for this message and the next ones of the branch
+  #[serde(default)]
+  pub collection_ids: Vec<Uuid>,
 }
 
 #[derive(Deserialize, ToSchema, IntoParams)]
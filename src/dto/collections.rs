@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use crate::models::{collections::CollectionScope, users::UserRole};
+
+#[derive(Debug,Deserialize,ToSchema,IntoParams)]
+pub struct CollectionQuery {
+   pub limit:Option<u64>,
+   pub offset:Option<u64>,
+   /// Search in names and descriptions
+   pub search:Option<String>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct CollectionRequest {
+   pub name:String,
+   pub description:Option<String>,
+   /// Default value : user
+   pub scope:Option<CollectionScope>,
+   /// Department shared with, defaults to the creator department
+   pub department:Option<String>,
+   /// Roles allowed in the scope, every role when empty
+   #[serde(default)]
+   pub roles:Vec<UserRole>,
+   /// Uploaded files of the creator
+   #[serde(default)]
+   pub file_ids:Vec<Uuid>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct CollectionUpdateRequest {
+   pub name:Option<String>,
+   pub description:Option<String>,
+   pub scope:Option<CollectionScope>,
+   pub department:Option<String>,
+   pub roles:Option<Vec<UserRole>>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct CollectionFilesRequest {
+   /// Uploaded files of the requesting user
+   pub file_ids:Vec<Uuid>,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize,ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestionStatus {
+   /// The collection has no file
+   Empty,
+   /// Files are waiting to be indexed
+   Pending,
+   /// Every file is searchable
+   Ready,
+   /// Indexing is over, some files have no text to search
+   Partial,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FileIngestionStatus {
+   Pending,
+   Ready,
+   Unsupported,
+}
+
+#[derive(Debug,PartialEq,Eq,Serialize,ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionIngestion {
+   pub status:IngestionStatus,
+   pub total:usize,
+   pub ready:usize,
+   pub pending:usize,
+   pub unsupported:usize,
+}
+
+#[derive(Serialize,ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionFile {
+   pub id:Uuid,
+   pub name:String,
+   pub content_type:String,
+   pub size:i64,
+   pub ingestion:FileIngestionStatus,
+   pub added_at:DateTime<Utc>,
+}
+
+#[derive(Serialize,ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionResponse {
+   pub id:Uuid,
+   pub name:String,
+   pub description:String,
+   pub scope:CollectionScope,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub department:Option<String>,
+   pub roles:Vec<UserRole>,
+   /// Whether the requesting user created the collection
+   pub owned:bool,
+   pub ingestion:CollectionIngestion,
+   pub files:Vec<CollectionFile>,
+   pub created_at:DateTime<Utc>,
+   pub updated_at:DateTime<Utc>,
+}
@@ -19,6 +19,7 @@ pub struct PaginationQuery {
    pub offset:Option<u64>,
    pub search:Option<String>,
    pub archived:Option<bool>,
+   pub deleted:Option<bool>,
    pub ascending:Option<bool>,
    pub role:Option<UserRole>,
    pub status:Option<UserStatus>,
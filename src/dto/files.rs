@@ -4,7 +4,7 @@ use serde_with::{base64::{Base64}, serde_as};
 use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
-use crate::{models::files::FileUploadStatus};
+use crate::{models::files::{FileUploadStatus, StorageBackend}};
 
 #[serde_as]
 #[derive(Deserialize,Serialize, ToSchema, IntoParams)]
@@ -26,7 +26,7 @@ impl Attachment {
     }
 }
 
-#[derive(Debug,Serialize, Deserialize, ToSchema, IntoParams)]
+#[derive(Debug,Clone,Serialize, Deserialize, ToSchema, IntoParams)]
 pub struct File {
     pub id:Uuid,
     pub size: Option<usize>,
@@ -37,6 +37,104 @@ pub struct File {
     pub openai_id:Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub base64:Option<String>,
+    /// Storage owner when not the requesting user (assistant reference files), never read from requests
+    #[serde(skip)]
+    pub owner_id:Option<Uuid>,
+}
+
+/// Provider-side copy of a local file, cached in `files.metadata.providerFiles.<provider>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderFile {
+    pub file_id:String,
+    pub uploaded_at:DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at:Option<DateTime<Utc>>,
+}
+
+impl ProviderFile {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+          .map(|expires_at| expires_at <= Utc::now())
+          .unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileIndexStatus {
+    /// Chunks are embedded and searchable
+    Ready,
+    /// No text could be read from the file
+    Unsupported,
+}
+
+/// Retrieval index of a local file, cached in `files.metadata.fileIndex`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileIndex {
+    pub status:FileIndexStatus,
+    pub chunk_count:usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_model:Option<String>,
+    pub indexed_at:DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    Pdf,
+    Docx,
+    Pptx,
+    Spreadsheet,
+    Csv,
+    Html,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractionStatus {
+    Extracted,
+    /// No extractor for the file type
+    Unsupported,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SheetStructure {
+    pub name:String,
+    pub rows:usize,
+    pub columns:usize,
+}
+
+/// Layout of an extracted document, only the fields of its format are set
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStructure {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages:Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slides:Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tables:Option<usize>,
+    /// Spreadsheet sheets, or the single table of a CSV
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sheets:Vec<SheetStructure>,
+}
+
+/// Text extraction of a local file, cached in `files.metadata.extraction`. The text is in `files.extractedText`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileExtraction {
+    pub status:ExtractionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format:Option<DocumentFormat>,
+    pub characters:usize,
+    #[serde(default)]
+    pub structure:DocumentStructure,
+    pub extracted_at:DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema, IntoParams)]
@@ -46,6 +144,67 @@ pub struct FileUploadRequest{
     pub attachment:Attachment
 }
 
+/// `multipart/form-data` upload, the file part is streamed to the storage
+#[derive(Deserialize, ToSchema)]
+pub struct FileMultipartUpload {
+    #[schema(value_type = String, format = Binary)]
+    pub file:Vec<u8>,
+    pub description:Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FileScanStatus {
+    Clean,
+    Infected,
+}
+
+/// Malware scan verdict of a local file, cached in `files.metadata.scan`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FileScan {
+    pub status:FileScanStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature:Option<String>,
+    pub engine:String,
+    pub scanned_at:DateTime<Utc>,
+}
+
+/// Re-encoded copy of an uploaded image, stored next to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageVariant {
+    pub storage_key:String,
+    pub storage_backend:StorageBackend,
+    pub content_type:String,
+    pub width:u32,
+    pub height:u32,
+    pub size:u64,
+}
+
+/// Normalized variants of an uploaded image, cached in `files.metadata.image`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileImage {
+    pub width:u32,
+    pub height:u32,
+    /// Largest first
+    pub variants:Vec<ImageVariant>,
+    pub thumbnail:ImageVariant,
+}
+
+impl FileImage {
+    /// Variant sent to a model: the largest within its maximum resolution, the
+    /// smallest when none is, the largest for models without a maximum
+    pub fn variant_for(&self,max_dimension:Option<u32>) -> Option<&ImageVariant> {
+        let fitting = self.variants
+          .iter()
+          .filter(|variant| max_dimension.is_none_or(|max_dimension| variant.width.max(variant.height) <= max_dimension))
+          .max_by_key(|variant| variant.width as u64 * variant.height as u64);
+        fitting.or_else(|| self.variants.iter().min_by_key(|variant| variant.width as u64 * variant.height as u64))
+    }
+}
+
 #[derive(Serialize, Deserialize, ToSchema, IntoParams)]
 pub struct FileResponse {
   pub id:Uuid,
@@ -59,6 +218,16 @@ pub struct FileResponse {
   pub created_at:DateTime<Utc>,
   pub updated_at:DateTime<Utc>,
   pub status:FileUploadStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub extraction:Option<FileExtraction>,
+  /// Hex SHA-256 of the content, absent for files uploaded before checksums
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub sha256:Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scan:Option<FileScan>,
+  /// Present for images, once normalized at upload
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub thumbnail_url:Option<String>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema, IntoParams)]
@@ -67,4 +236,16 @@ pub struct FilePaginatedResponse {
    pub limit:u64,
    pub offset:u64,
    pub files:Vec<FileResponse>,
-}
\ No newline at end of file
+}
+#[derive(Deserialize, ToSchema)]
+pub struct FileUpdateRequest {
+    pub name:Option<String>,
+    /// Empty clears the description
+    pub description:Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct FileDeleteQuery {
+    /// Removes the file, its stored content and provider copies instead of moving it to the trash
+    pub permanent:Option<bool>,
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use crate::dto::{chat::TokenUsage, files::FileResponse};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+pub enum ImageSize {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "1024x1024")]
+    Square,
+    #[serde(rename = "1536x1024")]
+    Landscape,
+    #[serde(rename = "1024x1536")]
+    Portrait,
+}
+
+impl ImageSize {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageSize::Auto => "auto",
+            ImageSize::Square => "1024x1024",
+            ImageSize::Landscape => "1536x1024",
+            ImageSize::Portrait => "1024x1536",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageQuality {
+    #[default]
+    Auto,
+    Low,
+    Medium,
+    High,
+}
+
+impl ImageQuality {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageQuality::Auto => "auto",
+            ImageQuality::Low => "low",
+            ImageQuality::Medium => "medium",
+            ImageQuality::High => "high",
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ImageGenerationRequest {
+    pub prompt:String,
+    /// Defaults to `gpt-image-1`, `gpt-image-1-mini` is also accepted
+    pub model:Option<String>,
+    #[serde(default)]
+    pub size:ImageSize,
+    #[serde(default)]
+    pub quality:ImageQuality,
+    /// Number of images, from 1 to 4, defaults to 1
+    pub n:Option<u8>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImageGenerationResponse {
+    pub chat_id:Uuid,
+    /// User message holding the prompt
+    pub user_message_id:Uuid,
+    /// Assistant message the generated images are attached to
+    pub message_id:Uuid,
+    /// Generated images, stored as files of the user
+    pub files:Vec<FileResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revised_prompt:Option<String>,
+    pub usage:TokenUsage,
+    /// Cost in USD
+    pub cost:f32,
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Instructions set by admins, applied to every chat of the organization
+#[derive(Serialize,Deserialize,ToSchema)]
+pub struct OrgInstructions {
+    /// Mandatory wording (compliance, tone), applied first
+    pub organization:Option<String>,
+    /// Keyed by department name, applied after the organization instructions
+    #[serde(default)]
+    pub departments:HashMap<String,String>,
+}
+
+#[derive(Serialize,Deserialize,ToSchema)]
+pub struct PersonalInstructions {
+    /// Applied last, empty or null disables them
+    pub custom_instructions:Option<String>,
+}
+
+/// Level of a composed instruction, in composition order
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InstructionLevel {
+    Organization,
+    Department,
+    Personal,
+}
+
+#[derive(Debug,Clone,Serialize,ToSchema)]
+pub struct InstructionSource {
+    pub level:InstructionLevel,
+    pub text:String,
+}
+
+#[derive(Serialize,ToSchema)]
+pub struct InstructionsPreviewResponse {
+    /// Effective system prompt sent before the conversation, null when no instruction applies
+    pub system_prompt:Option<String>,
+    pub sources:Vec<InstructionSource>,
+}
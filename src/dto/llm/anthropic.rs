@@ -305,11 +305,15 @@ pub struct AnthropicChatResponse {
 impl AnthropicMessage {
     pub fn from_prompts(prompts: Vec<Prompt>) -> (Vec<Self>, Option<String>){
         let mut messages = Vec::new();
-        let mut system_prompt = None;
+        let mut system_prompt:Option<String> = None;
 
         for prompt in prompts {
+            // Several system prompts (instructions, then conversation ones) are joined in order
             if prompt.role == ChatRole::System {
-                system_prompt = Some(prompt.text.clone());
+                system_prompt = Some(match system_prompt {
+                    Some(system_prompt) => format!("{}\n\n{}", system_prompt, prompt.text),
+                    None => prompt.text.clone(),
+                });
                 continue;
             }
             let role = match prompt.role {
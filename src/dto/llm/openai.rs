@@ -426,6 +426,9 @@ pub struct OpenaiContent {
 #[derive(Deserialize)]
 pub struct FileUploadResponse {
     pub id: String,
+    // Unix timestamp, only set when the file was uploaded with an expiration policy
+    #[serde(default)]
+    pub expires_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -441,3 +444,112 @@ pub struct OpenaiListModelsResponse {
     pub object: String,
     pub data: Vec<OpenaiModel>,
 }
+
+#[derive(Debug, Serialize)]
+pub struct OpenaiEmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenaiEmbeddingResponse {
+    pub data: Vec<OpenaiEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenaiEmbedding {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+/// Form fields of an audio transcription (/v1/audio/transcriptions), the audio is sent as a file part
+#[derive(Debug)]
+pub struct OpenaiTranscriptionRequest {
+    pub model: String,
+    pub language: Option<String>,
+    pub prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenaiTranscriptionResponse {
+    pub text: String,
+    #[serde(default)]
+    pub usage: Option<OpenaiTranscriptionUsage>,
+}
+
+/// Token models report tokens, whisper reports the audio duration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OpenaiTranscriptionUsage {
+    Tokens {
+        input_tokens: u32,
+        output_tokens: u32,
+        total_tokens: u32,
+        #[serde(default)]
+        input_token_details: Option<OpenaiTranscriptionInputDetails>,
+    },
+    Duration {
+        seconds: f64,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenaiTranscriptionInputDetails {
+    #[serde(default)]
+    pub text_tokens: u32,
+    #[serde(default)]
+    pub audio_tokens: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenaiSpeechRequest {
+    pub model: String,
+    pub input: String,
+    pub voice: String,
+    pub response_format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+}
+
+/// Image generation (/v1/images/generations), GPT image models always answer base64 images
+#[derive(Debug, Serialize)]
+pub struct OpenaiImageRequest {
+    pub model: String,
+    pub prompt: String,
+    pub n: u8,
+    pub size: String,
+    pub quality: String,
+    pub output_format: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenaiImageResponse {
+    pub data: Vec<OpenaiImage>,
+    #[serde(default)]
+    pub usage: Option<OpenaiImageUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OpenaiImage {
+    #[serde(default)]
+    pub b64_json: Option<String>,
+    #[serde(default)]
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenaiImageUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub total_tokens: u32,
+    #[serde(default)]
+    pub input_tokens_details: Option<OpenaiImageInputDetails>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpenaiImageInputDetails {
+    #[serde(default)]
+    pub text_tokens: u32,
+    #[serde(default)]
+    pub image_tokens: u32,
+}
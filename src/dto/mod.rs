@@ -11,4 +11,12 @@ pub mod admin_sso_providers;
 pub mod admin_ai;
 pub mod models;
 pub mod admin_department;
-pub mod sso_providers;
\ No newline at end of file
+pub mod sso_providers;
+pub mod prompt_templates;
+pub mod instructions;
+pub mod assistants;
+pub mod collections;
+pub mod storage;
+pub mod audio;
+pub mod images;
+pub mod api_keys;
\ No newline at end of file
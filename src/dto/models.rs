@@ -49,4 +49,7 @@ pub struct ModelInfo {
     pub supports_web_search: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_images: Option<i32>,
+    /// Long edge, in pixels, images are downscaled to before being sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_image_dimension: Option<u32>,
 }
\ No newline at end of file
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Who a template is visible to
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize,ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateScope {
+    /// Only its owner
+    Personal,
+    /// Every user of the organization, editable by its owner and admins
+    Organization,
+    /// Provided by admins to every user
+    System,
+}
+
+#[derive(Debug,Deserialize,ToSchema,IntoParams)]
+pub struct PromptTemplateQuery {
+   pub limit:Option<u64>,
+   pub offset:Option<u64>,
+   /// Search in names, descriptions and prompt texts
+   pub search:Option<String>,
+   pub category:Option<String>,
+   pub scope:Option<TemplateScope>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct PromptTemplateRequest {
+   pub name:String,
+   pub description:Option<String>,
+   pub category:Option<String>,
+   /// Text with `{{variable}}` placeholders filled by the client before sending
+   pub prompt_text:String,
+   /// Default value : personal, system templates can only be created by admins
+   pub scope:Option<TemplateScope>,
+   pub model_provider:Option<String>,
+   pub model_name:Option<String>,
+   pub metadata:Option<serde_json::Value>,
+}
+
+#[derive(Deserialize,ToSchema)]
+pub struct PromptTemplateUpdateRequest {
+   pub name:Option<String>,
+   pub description:Option<String>,
+   pub category:Option<String>,
+   pub prompt_text:Option<String>,
+   pub scope:Option<TemplateScope>,
+   pub model_provider:Option<String>,
+   pub model_name:Option<String>,
+   pub metadata:Option<serde_json::Value>,
+}
+
+#[derive(Serialize,ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplateResponse {
+   pub id:Uuid,
+   pub name:String,
+   pub description:String,
+   pub category:String,
+   pub prompt_text:String,
+   /// Placeholder names found in the prompt text, in order of first appearance
+   pub variables:Vec<String>,
+   pub scope:TemplateScope,
+   /// Whether the requesting user owns the template
+   pub owned:bool,
+   pub model_provider:String,
+   pub model_name:String,
+   pub usage_counter:i32,
+   pub created_at:DateTime<Utc>,
+   pub updated_at:DateTime<Utc>,
+   #[serde(skip_serializing_if = "Option::is_none")]
+   pub metadata:Option<serde_json::Value>,
+}
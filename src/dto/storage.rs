@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Storage used by the files of a user, trashed files included until permanently deleted
+#[derive(Serialize,ToSchema)]
+pub struct StorageUsage {
+    pub used_bytes:u64,
+    pub deleted_bytes:u64,
+    pub file_count:u64,
+    /// Effective quota of the user, null when unlimited
+    pub quota_bytes:Option<u64>,
+}
+
+#[derive(Serialize,ToSchema)]
+pub struct UserStorageUsage {
+    pub user_id:Uuid,
+    pub email:Option<String>,
+    pub name:Option<String>,
+    pub used_bytes:u64,
+    pub file_count:u64,
+    pub quota_bytes:Option<u64>,
+}
+
+#[derive(Serialize,ToSchema)]
+pub struct StorageReport {
+    /// Sum of the file sizes, as counted against quotas
+    pub logical_bytes:u64,
+    /// Bytes actually stored, identical contents being stored once
+    pub physical_bytes:u64,
+    pub deleted_bytes:u64,
+    pub file_count:u64,
+    pub quotas:StorageQuotas,
+    pub users:Vec<UserStorageUsage>,
+}
+
+/// Organization quotas, null meaning unlimited
+#[derive(Serialize,Deserialize,ToSchema)]
+pub struct StorageQuotas {
+    pub storage_quota_bytes:Option<u64>,
+    /// Default quota of every user
+    pub user_storage_quota_bytes:Option<u64>,
+}
+
+#[derive(Serialize,Deserialize,ToSchema)]
+pub struct UserStorageQuota {
+    /// Overrides the organization default for the user, null restores it
+    pub quota_bytes:Option<u64>,
+}
+
+#[derive(Debug,Clone,Serialize,Deserialize,ToSchema)]
+pub struct UploadRule {
+    /// Detected type of the content, `type/*` matching a whole family
+    pub content_type:String,
+    /// Tighter than the deployment maximum upload size when set
+    pub max_bytes:Option<u64>,
+}
+
+/// Upload types accepted by the organization, any type when empty. Executables are never accepted.
+#[derive(Debug,Clone,Default,Serialize,Deserialize,ToSchema)]
+pub struct UploadPolicy {
+    #[serde(default)]
+    pub allowed_types:Vec<UploadRule>,
+}
@@ -17,6 +17,10 @@ pub enum ErrorCode {
     // 1000-1999: generic/platform
     ServiceTemporarilyUnavailable = 1000,
     ResourceNotFound = 1001,
+    PermissionDenied = 1002,
+    StorageQuotaExceeded = 1003,
+    FileQuarantined = 1004,
+    FileScanPending = 1005,
 
     // 5000-5999: DB
     DbUnavailable = 5000,
@@ -27,6 +31,9 @@ pub enum ErrorCode {
     // 2000-2999: validation
     ValidationMissingField = 2001,
     ValidationEmptyField = 2002,
+    ValidationInvalidField = 2003,
+    ValidationFileTooLarge = 2004,
+    ValidationFileTypeNotAllowed = 2005,
 
     // 3000-3999: SSO
     SsoSigninBlockedConditionalAccess = 3001,
@@ -84,6 +91,14 @@ pub struct ErrorDetail {
 pub enum AppError {
     ServiceTemporarilyUnavailable,
     ResourceNotFound,
+    /// The resource is visible to the user but not modifiable by them
+    PermissionDenied,
+    /// The upload would exceed the user or organization storage quota
+    StorageQuotaExceeded { quota_bytes: u64 },
+    /// The malware scan flagged the file content
+    FileQuarantined,
+    /// The malware scan of the file has not finished
+    FileScanPending,
 
     // DB errors
     DbUnavailable,
@@ -94,6 +109,10 @@ pub enum AppError {
     /// Generic missing field. Use for things like "message" etc.
     ValidationMissingField { field: &'static str },
     ValidationEmptyField { field: &'static str },
+    ValidationInvalidField { field: &'static str },
+    ValidationFileTooLarge { max_bytes: u64 },
+    /// Type detected from the uploaded content, not the one declared by the client
+    ValidationFileTypeNotAllowed { content_type: String },
 
     /// Microsoft-style conditional access block.
     /// - `external_code`: set to Some("53003") if you want to mirror Microsoft codes
@@ -116,6 +135,10 @@ impl AppError {
         Self::ValidationEmptyField { field }
     }
 
+    pub fn invalid_field(field: &'static str) -> Self {
+        Self::ValidationInvalidField { field }
+    }
+
     pub fn sso_conditional_access(provider: String, external_code: Option<&'static str>) -> Self {
         Self::SsoSigninBlockedConditionalAccess {
             provider,
@@ -185,6 +208,96 @@ impl AppError {
                 )
             }
 
+            AppError::PermissionDenied => {
+                let params = Self::base_params();
+                let description_key = "error.permission_denied.description".to_string();
+                let solution_key = "error.permission_denied.solution".to_string();
+
+                let description_tpl = "You don't have permission to modify this resource.";
+                let solution_tpl = "Ask its owner or an administrator to make the change.";
+
+                (
+                    StatusCode::FORBIDDEN,
+                    ErrorDetail {
+                        code: ErrorCode::PermissionDenied,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AppError::StorageQuotaExceeded { quota_bytes } => {
+                let mut params = Self::base_params();
+                params.insert("quota_bytes".to_string(), quota_bytes.to_string());
+
+                let description_key = "error.storage_quota_exceeded.description".to_string();
+                let solution_key = "error.storage_quota_exceeded.solution".to_string();
+
+                let description_tpl = "The upload would exceed the storage quota of {quota_bytes} bytes.";
+                let solution_tpl = "Permanently delete files you no longer need or ask an administrator to raise the quota.";
+
+                (
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    ErrorDetail {
+                        code: ErrorCode::StorageQuotaExceeded,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AppError::FileQuarantined => {
+                let params = Self::base_params();
+                let description_key = "error.file_quarantined.description".to_string();
+                let solution_key = "error.file_quarantined.solution".to_string();
+
+                let description_tpl = "The file was flagged as malware and is blocked.";
+                let solution_tpl = "Delete the file. If you believe it is safe, contact your administrator.";
+
+                (
+                    StatusCode::FORBIDDEN,
+                    ErrorDetail {
+                        code: ErrorCode::FileQuarantined,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AppError::FileScanPending => {
+                let params = Self::base_params();
+                let description_key = "error.file_scan_pending.description".to_string();
+                let solution_key = "error.file_scan_pending.solution".to_string();
+
+                let description_tpl = "The file is still being scanned for malware.";
+                let solution_tpl = "Try again in a few moments.";
+
+                (
+                    StatusCode::CONFLICT,
+                    ErrorDetail {
+                        code: ErrorCode::FileScanPending,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
             // -------- DB --------
             AppError::DbUnavailable => {
                 let params = Self::base_params();
@@ -328,6 +441,78 @@ impl AppError {
                 )
             }
 
+            AppError::ValidationInvalidField { field } => {
+                let mut params = Self::base_params();
+                params.insert("field".to_string(), (*field).to_string());
+
+                let description_key = "error.validation.invalid_field.description".to_string();
+                let solution_key = "error.validation.invalid_field.solution".to_string();
+
+                let description_tpl = "Invalid value for field: `{field}`.";
+                let solution_tpl = "Correct `{field}` in the request and try again.";
+
+                (
+                    StatusCode::BAD_REQUEST,
+                    ErrorDetail {
+                        code: ErrorCode::ValidationInvalidField,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AppError::ValidationFileTooLarge { max_bytes } => {
+                let mut params = Self::base_params();
+                params.insert("max_bytes".to_string(), max_bytes.to_string());
+
+                let description_key = "error.validation.file_too_large.description".to_string();
+                let solution_key = "error.validation.file_too_large.solution".to_string();
+
+                let description_tpl = "The file exceeds the maximum upload size of {max_bytes} bytes.";
+                let solution_tpl = "Upload a smaller file or split it and try again.";
+
+                (
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    ErrorDetail {
+                        code: ErrorCode::ValidationFileTooLarge,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
+            AppError::ValidationFileTypeNotAllowed { content_type } => {
+                let mut params = Self::base_params();
+                params.insert("content_type".to_string(), content_type.clone());
+
+                let description_key = "error.validation.file_type_not_allowed.description".to_string();
+                let solution_key = "error.validation.file_type_not_allowed.solution".to_string();
+
+                let description_tpl = "Files of type {content_type} are not accepted.";
+                let solution_tpl = "Upload a file of an allowed type or ask an administrator to allow this one.";
+
+                (
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    ErrorDetail {
+                        code: ErrorCode::ValidationFileTypeNotAllowed,
+                        description: Self::render(description_tpl, &params),
+                        solution: Self::render(solution_tpl, &params),
+                        description_key,
+                        solution_key,
+                        params,
+                        external_code: None,
+                    },
+                )
+            }
+
             // -------- SSO --------
             AppError::SsoSigninBlockedConditionalAccess {
                 provider,
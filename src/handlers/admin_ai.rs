@@ -3,7 +3,7 @@ use chrono::Utc;
 use reqwest::StatusCode;
 use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, TryIntoModel};
 use uuid::Uuid;
-use crate::{auth::{claims::Claims, encryption::{decrypt_key, encrypt_key}, error::{AuthError, AuthErrorResponse}}, dto::{admin_ai::{AiEngineModelsResponse, AiEngineResponse, AiEngineUpdateRequest, AiEngineValidationResponse, AiModel, AiModelCapabilities}, models::ModelsResponse}, handlers::admin_org::get_org, llm::provider::{AnthropicApis, OpenaiApis}, models::{ai_engines::{self, ApiKeyStatus}, users::UserRole}, state::SharedState};
+use crate::{auth::{claims::Claims, encryption::{decrypt_key, encrypt_key}, error::{AuthError, AuthErrorResponse}}, error::{AppError, ErrorResponse}, dto::{admin_ai::{AiEngineModelsResponse, AiEngineResponse, AiEngineUpdateRequest, AiEngineValidationResponse, AiModel, AiModelCapabilities, normalize_fallback_chains, parse_fallback_chains}, models::ModelsResponse}, handlers::admin_org::get_org, llm::{http_policy::{DEFAULT_MAX_RETRIES, DEFAULT_TIMEOUT_MS}, provider::{AnthropicApis, OpenaiApis}}, models::{ai_engines::{self, ApiKeyStatus}, users::UserRole}, state::SharedState};
 
 #[utoipa::path(
     get,
@@ -64,6 +64,9 @@ pub async fn get_ai_engines(
                 .collect::<Vec<String>>()),
              default_model:Set(String::from("<empty>")),
              api_key_validated_at:Set(None),
+             fallback_chains:Set(None),
+             timeout_ms:Set(DEFAULT_TIMEOUT_MS),
+             max_retries:Set(DEFAULT_MAX_RETRIES),
              created_at:Set(Utc::now()),
              updated_at:Set(Utc::now()), 
             });
@@ -94,6 +97,9 @@ pub async fn get_ai_engines(
                 .map(|model| model.key)
                 .collect(),
               default_model:None,
+              fallback_chains:Default::default(),
+              timeout_ms:DEFAULT_TIMEOUT_MS,
+              max_retries:DEFAULT_MAX_RETRIES,
               created_at:Utc::now(),
               updated_at:Utc::now(),
             }
@@ -115,6 +121,9 @@ pub async fn get_ai_engines(
             api_key_last_validated_at:model.api_key_validated_at,
             whitelisted_models:model.whitelist_models,
             default_model:Some(model.default_model),
+            fallback_chains:parse_fallback_chains(&model.fallback_chains),
+            timeout_ms:model.timeout_ms,
+            max_retries:model.max_retries,
             created_at:model.created_at,
             updated_at:model.updated_at
         }
@@ -171,6 +180,9 @@ pub async fn get_ai_engines_by_key(
             api_key_last_validated_at:model.api_key_validated_at,
             whitelisted_models:model.whitelist_models,
             default_model:Some(model.default_model),
+            fallback_chains:parse_fallback_chains(&model.fallback_chains),
+            timeout_ms:model.timeout_ms,
+            max_retries:model.max_retries,
             created_at:model.created_at,
             updated_at:model.updated_at
         };
@@ -247,11 +259,14 @@ pub async fn get_ai_engine_models_by_key(
     params(
         ("ai_engine_key" = String, Path, description = "Engine key example 'openai','anthropic'")
     ),
+    request_body = AiEngineUpdateRequest,
     responses(
         (status = 200, body = AiEngineResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Fallback target not in the model catalogue (code=2003)"),
         (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
-        (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Ai Engine not found (code=5003)"),
-        (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Permission denied (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Ai Engine not found (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
 
     )
 )]
@@ -260,10 +275,10 @@ pub async fn update_ai_engines_by_key(
     Path(ai_engine_key):Path<String>,
     State(app_state): State<SharedState>,
     Json(req):Json<AiEngineUpdateRequest>
-) -> Result<(StatusCode,Json<AiEngineResponse>),AuthError>{
+) -> Result<(StatusCode,Json<AiEngineResponse>),AppError>{
    match claims.role {
         UserRole::SuperAdmin | UserRole::Admin => {}
-        _ => return Err(AuthError::PermissionDenied),
+        _ => return Err(AppError::PermissionDenied),
    }
    let ai_models = ModelsResponse::default();
    let mut selector = ai_engines::Entity::find();
@@ -277,9 +292,9 @@ pub async fn update_ai_engines_by_key(
       .await
       .map_err(|e|{
         eprintln!("db error get all {e}");
-        AuthError::DbTimeout
+        AppError::DbTimeout
       })?
-      .ok_or(AuthError::ResourceNotFound)?;
+      .ok_or(AppError::ResourceNotFound)?;
     let mut active_model = ai_engine
       .clone()
       .into_active_model();
@@ -287,7 +302,7 @@ pub async fn update_ai_engines_by_key(
       let encrypted_api_key = encrypt_key(&app_state.settings.auth.app_key,api_key.as_bytes())
        .map_err(|e|{
          eprintln!("Encryption error for api key: {:?}",e);
-         AuthError::DbTimeout
+         AppError::DbTimeout
        })?;
       active_model.api_key = Set(Some(encrypted_api_key));
     }
@@ -301,34 +316,51 @@ pub async fn update_ai_engines_by_key(
     if let Some(is_enabled) = req.is_enabled {
       active_model.is_enabled = Set(is_enabled);
     }
+    if let Some(fallback_chains) = req.fallback_chains {
+      // A typo would only show at runtime as a fallback silently skipped
+      let fallback_chains = normalize_fallback_chains(fallback_chains, &ai_models)
+        .ok_or(AppError::invalid_field("fallback_chains"))?;
+      let fallback_chains = serde_json::to_value(fallback_chains)
+        .map_err(|e|{
+          eprintln!("fallback chains serialize error {e}");
+          AppError::DbTimeout
+        })?;
+      active_model.fallback_chains = Set(Some(fallback_chains));
+    }
+    if let Some(timeout_ms) = req.timeout_ms {
+      active_model.timeout_ms = Set(timeout_ms.max(1));
+    }
+    if let Some(max_retries) = req.max_retries {
+      active_model.max_retries = Set(max_retries.max(0));
+    }
     active_model
      .clone()
      .update(&app_state.database)
      .await
      .map_err(|e|{
         eprintln!("db error update one {e}");
-        AuthError::DbTimeout
+        AppError::DbTimeout
       })?;
     let model = active_model
       .try_into_model()
       .map_err(|e|{
         eprintln!("db error model parse error {e}");
-        AuthError::DbTimeout
+        AppError::DbTimeout
       })?;
      if let Some(api_key) = &model.api_key  {
         let decrypted_api_key = decrypt_key(&app_state.settings.auth.app_key,&api_key)
            .map_err(|e|{
              eprintln!("Decryption api key error {:?}",e);
-             AuthError::DbTimeout
+             AppError::DbTimeout
            }
           )?;
         app_state
           .settings
-          .load_ai_engine_in_state(&ai_engine_key,&decrypted_api_key,model.is_enabled)
+          .load_ai_engine_in_state(&ai_engine_key,&decrypted_api_key,model.is_enabled,model.timeout_ms,model.max_retries)
           .await
           .map_err(|e|{
             eprintln!("Ai engine loading error in state {e}");
-            AuthError::DbTimeout
+            AppError::DbTimeout
       })?;
     }
     let response = AiEngineResponse{
@@ -342,6 +374,9 @@ pub async fn update_ai_engines_by_key(
             api_key_last_validated_at:model.api_key_validated_at,
             whitelisted_models:model.whitelist_models,
             default_model:Some(model.default_model),
+            fallback_chains:parse_fallback_chains(&model.fallback_chains),
+            timeout_ms:model.timeout_ms,
+            max_retries:model.max_retries,
             created_at:model.created_at,
             updated_at:model.updated_at
         };
@@ -417,6 +452,9 @@ pub async fn delete_ai_engines_api_key_key(
             api_key_last_validated_at:model.api_key_validated_at,
             whitelisted_models:model.whitelist_models,
             default_model:Some(model.default_model),
+            fallback_chains:parse_fallback_chains(&model.fallback_chains),
+            timeout_ms:model.timeout_ms,
+            max_retries:model.max_retries,
             created_at:model.created_at,
             updated_at:model.updated_at
         };
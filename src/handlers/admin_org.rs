@@ -46,6 +46,12 @@ pub async fn get_org(
             default_model:"gpt-5.1".into(),
             data_retention_days:90,
             require_mfa:false,
+            custom_instructions:None,
+            department_instructions:None,
+            storage_quota_bytes:None,
+            user_storage_quota_bytes:None,
+            upload_policy:None,
+            password_policy:None,
             created_on:Utc::now(),
             updated_on:Utc::now(), 
         };
@@ -1,9 +1,46 @@
 use axum::{Json, extract::{Path, State}};
 use chrono::Utc;
 use reqwest::StatusCode;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, sea_query::Expr};
 use uuid::Uuid;
-use crate::{auth::{claims::Claims, encryption::{decrypt_key, encrypt_key}, error::{AuthError, AuthErrorResponse}, sso_provider::sso_providers_list}, dto::admin_sso_providers::{SsoProviderResponse, SsoProviderUpdateRequest}, handlers::admin_org::get_org, models::{sso_providers, users::UserRole}, state::SharedState};
+use crate::{auth::{claims::Claims, discovery::build_oidc_client, encryption::encrypt_key, error::{AuthError, AuthErrorResponse}, sso_provider::{is_valid_provider_key, sso_providers_list}}, config::setting::SsoProviderSettings, dto::admin_sso_providers::{SsoProviderCreateRequest, SsoProviderResponse, SsoProviderUpdateRequest}, handlers::admin_org::get_org, models::{sso_providers, user_identities, users::UserRole}, state::{AppState, SharedState}};
+
+fn sso_provider_response(app_state:&AppState,model:sso_providers::Model) -> SsoProviderResponse {
+    SsoProviderResponse{
+      id:model.id,
+      client_secret:app_state.get_decrypted_api_key_preview(&Some(model.client_secret)),
+      provider:model.provider,
+      name:model.name,
+      client_id:model.client_id,
+      issuer_url:model.issuer_url,
+      redirect_url:model.redirect_url,
+      allowed_domains:model.allowed_domains,
+      is_enabled:model.is_enabled,
+      created_at:model.created_at,
+      updated_at:model.updated_at,
+    }
+}
+
+/// Loads the settings of the provider and rebuilds its client, dropping both while it is not configured
+async fn reload_sso_provider(app_state:&AppState,model:&sso_providers::Model) {
+    let Some(settings) = SsoProviderSettings::from_model(&app_state.settings.auth.app_key,model) else {
+        app_state.remove_oidc_client(&model.provider).await;
+        return;
+    };
+    app_state
+      .settings
+      .load_sso_provider_in_state(&model.provider,settings)
+      .await;
+    if let Err(e) = app_state.refresh_oidc_client(&model.provider).await {
+        eprintln!("oidc client refresh error: {e}");
+        // The kept client was built from the replaced settings, the next login builds it again
+        app_state
+          .oidc_clients
+          .write()
+          .await
+          .remove(&model.provider.to_lowercase());
+    }
+}
 
 #[utoipa::path(
     get,
@@ -80,23 +117,8 @@ pub async fn get_sso_providers(
       }
       let response = models
         .into_iter()
-        .map(|model|{
-            let decrypted_client_secret = decrypt_key(&app_state.settings.auth.app_key,&model.client_secret)
-              .ok();
-           SsoProviderResponse{
-             id:model.id,
-             redirect_url:model.redirect_url, 
-             provider:model.provider,
-             name:model.name,
-             client_id:model.client_id,
-             client_secret:app_state.get_decrypted_api_key_preview(&decrypted_client_secret),
-             issuer_url: model.issuer_url,
-             allowed_domains:model.allowed_domains,
-             is_enabled:model.is_enabled,
-             created_at:model.created_at,
-             updated_at:model.updated_at,
-          }
-        }).collect();
+        .map(|model| sso_provider_response(&app_state,model))
+        .collect();
   Ok((StatusCode::OK,Json(response)))
 }
 
@@ -133,23 +155,8 @@ pub async fn get_sso_provider_by_id(
           AuthError::DbTimeout
        })?;
       let response = model
-        .map(|model|{
-           let decrypted_client_secret = decrypt_key(&app_state.settings.auth.app_key,&model.client_secret)
-             .ok();
-            SsoProviderResponse{
-              id:model.id, 
-              provider:model.provider,
-              name:model.name,
-              redirect_url:model.redirect_url,
-              client_id:model.client_id,
-              client_secret:app_state.get_decrypted_api_key_preview(&decrypted_client_secret),
-              issuer_url: model.issuer_url,
-              allowed_domains:model.allowed_domains,
-              is_enabled:model.is_enabled,
-              created_at:model.created_at,
-              updated_at:model.updated_at,
-          }
-        }).ok_or(AuthError::ResourceNotFound)?;
+        .map(|model| sso_provider_response(&app_state,model))
+        .ok_or(AuthError::ResourceNotFound)?;
   Ok((StatusCode::OK,Json(response)))
 }
 
@@ -193,22 +200,27 @@ pub async fn delete_sso_provider_by_id(
       active_model.is_default = Set(false);
       active_model.is_enabled = Set(false);
       active_model.tenant_id = Set(None);
-      active_model
+      let updated_model = active_model
         .update(&app_state.database)
         .await
         .map_err(|e|{
            eprintln!("Db get one error: {}",e);
            AuthError::DbTimeout
        })?;
+      app_state
+        .remove_oidc_client(&updated_model.provider)
+        .await;
   Ok((StatusCode::OK,"Deleted successfully"))
 }
 
 #[utoipa::path(
-    delete,
+    put,
     path = "/admin/sso-providers/{provider_id}",
     tag = "admin",
+    request_body = SsoProviderUpdateRequest,
     responses(
        (status = 200, body = SsoProviderResponse),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid provider key (code=6402)"),
        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
        (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Sso Provider not found (code=5003)"),
        (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
@@ -236,9 +248,13 @@ pub async fn update_sso_provider_by_id(
           AuthError::DbTimeout
        })?
       .ok_or(AuthError::DbNotFound)?;
+     let previous_provider = model.provider.clone();
      let mut active_model = model
        .into_active_model();
      if let Some(provider) = req.provider {
+        if !is_valid_provider_key(&provider) {
+           return Err(AuthError::InvalidSsoProviderRequest { field: "provider" });
+        }
         active_model.provider = Set(provider);
      }
      if let Some(name) = req.name {
@@ -275,32 +291,145 @@ pub async fn update_sso_provider_by_id(
             eprintln!("Db update error {:?}",e);
             AuthError::DbTimeout
      })?;
-     if let Ok(client_secret) = decrypt_key(&app_state.settings.auth.app_key,&updated_model.client_secret)  {
-      let allowed_domains = updated_model
-        .allowed_domains
-        .iter()
-        .map(|d| d.into())
-        .collect();
-      let _ = app_state
-        .settings
-        .load_sso_provider_in_state(&updated_model.provider,&updated_model.client_id,&client_secret,&updated_model.redirect_url, updated_model.tenant_id.as_ref(), updated_model.is_enabled,allowed_domains)
-        .await;
-      let _ = app_state
-        .refresh_oidc_client(&updated_model.provider)
-        .await;
+     if updated_model.provider != previous_provider {
+        // Identities follow the provider key to its new URL
+        user_identities::Entity::update_many()
+          .col_expr(user_identities::Column::Provider, Expr::value(updated_model.provider.clone()))
+          .filter(user_identities::Column::Provider.eq(previous_provider.clone()))
+          .exec(&app_state.database)
+          .await
+          .map_err(|e|{
+              eprintln!("Db update error {:?}",e);
+              AuthError::DbTimeout
+          })?;
+        app_state
+          .remove_oidc_client(&previous_provider)
+          .await;
      }
-     let response = SsoProviderResponse { 
-        id:updated_model.id,
-        provider:updated_model.provider,
-        name:updated_model.name,
-        client_id:updated_model.client_id,
-        client_secret:app_state.get_decrypted_api_key_preview(&Some(updated_model.client_secret)),
-        issuer_url:updated_model.issuer_url,
-        redirect_url:updated_model.redirect_url,
-        allowed_domains:updated_model.allowed_domains,
-        is_enabled:updated_model.is_enabled,
-        created_at:updated_model.created_at,
-        updated_at:updated_model.updated_at,
-    };
+     reload_sso_provider(&app_state,&updated_model)
+       .await;
+     let response = sso_provider_response(&app_state,updated_model);
   Ok((StatusCode::OK,Json(response)))
 }
+
+#[utoipa::path(
+    post,
+    path = "/admin/sso-providers",
+    tag = "admin",
+    request_body = SsoProviderCreateRequest,
+    responses(
+       (status = 201, body = SsoProviderResponse, description = "Provider added, its client is built from the discovery document of the issuer"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid SSO provider field, or no discovery document at the issuer URL (code=6402)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 409, content_type = "application/json", body = AuthErrorResponse, description = "Provider key already used (code=5002)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn add_sso_provider(
+     claims: Claims,
+     State(app_state): State<SharedState>,
+     Json(req):Json<SsoProviderCreateRequest>
+) -> Result<(StatusCode,Json<SsoProviderResponse>), AuthError> {
+     match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+     }
+     let provider = req.provider.trim().to_string();
+     if !is_valid_provider_key(&provider) {
+        return Err(AuthError::InvalidSsoProviderRequest { field: "provider" });
+     }
+     if req.name.trim().is_empty() {
+        return Err(AuthError::InvalidSsoProviderRequest { field: "name" });
+     }
+     if reqwest::Url::parse(&req.issuer_url).is_err() {
+        return Err(AuthError::InvalidSsoProviderRequest { field: "issuer_url" });
+     }
+     if req.client_id.trim().is_empty() {
+        return Err(AuthError::InvalidSsoProviderRequest { field: "client_id" });
+     }
+     if req.client_secret.is_empty() {
+        return Err(AuthError::InvalidSsoProviderRequest { field: "client_secret" });
+     }
+     let redirect_url = req.redirect_url
+       .unwrap_or(format!("{}/auth/{}/callback",app_state.settings.auth.redirect_url.trim_end_matches('/'),provider));
+     if reqwest::Url::parse(&redirect_url).is_err() {
+        return Err(AuthError::InvalidSsoProviderRequest { field: "redirect_url" });
+     }
+     let existing = sso_providers::Entity::find()
+       .filter(sso_providers::Column::Provider.eq(provider.clone()))
+       .one(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("Db get one error: {}",e);
+          AuthError::DbTimeout
+       })?;
+     if existing.is_some() {
+        return Err(AuthError::DbConflict);
+     }
+     let org_id = match claims.org_id {
+        Some(org_id) => org_id,
+        None => {
+           let (_,Json(org)) = get_org(claims,State(app_state.clone()))
+             .await
+             .map_err(|e|{
+               eprintln!("org fetch error: {:?}",e);
+               AuthError::DbTimeout
+           })?;
+           org.id
+        }
+     };
+     let settings = SsoProviderSettings {
+        client_id:req.client_id.trim().to_string(),
+        client_secret:req.client_secret.clone(),
+        issuer_url:req.issuer_url.clone(),
+        tenant_id:req.tenant_id.clone(),
+        redirect_url:redirect_url.clone(),
+        is_enabled:req.is_enabled.unwrap_or(true),
+        allowed_domains:req.allowed_domains.clone().unwrap_or_default(),
+     };
+     // The issuer is checked before saving, a provider without discovery document cannot sign anyone in
+     let oidc_client = build_oidc_client(&app_state.req_client,&settings)
+       .await
+       .map_err(|e|{
+          eprintln!("oidc client build error: {e}");
+          AuthError::InvalidSsoProviderRequest { field: "issuer_url" }
+       })?;
+     let client_secret = encrypt_key(&app_state.settings.auth.app_key,req.client_secret.as_bytes())
+       .map_err(|e|{
+           eprintln!("Sso key encryption error {:?}",e);
+           AuthError::DbTimeout
+       })?;
+     let model = sso_providers::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        org_id:Set(org_id),
+        provider:Set(provider),
+        name:Set(req.name.trim().to_string()),
+        tenant_id:Set(req.tenant_id),
+        client_id:Set(req.client_id.trim().to_string()),
+        client_secret:Set(client_secret),
+        issuer_url:Set(req.issuer_url),
+        redirect_url:Set(redirect_url),
+        allowed_domains:Set(req.allowed_domains.unwrap_or_default()),
+        is_enabled:Set(req.is_enabled.unwrap_or(true)),
+        is_default:Set(false),
+        created_at:Set(Utc::now()),
+        updated_at:Set(Utc::now()),
+     }
+     .insert(&app_state.database)
+     .await
+     .map_err(|e|{
+        eprintln!("Db insert error {:?}",e);
+        AuthError::DbTimeout
+     })?;
+     app_state
+       .settings
+       .load_sso_provider_in_state(&model.provider,settings)
+       .await;
+     app_state
+       .oidc_clients
+       .write()
+       .await
+       .insert(model.provider.to_lowercase(),oidc_client);
+  Ok((StatusCode::CREATED,Json(sso_provider_response(&app_state,model))))
+}
@@ -4,7 +4,7 @@ use migration::extension::postgres::PgExpr;
 use reqwest::StatusCode;
 use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect};
 use uuid::Uuid;
-use crate::{auth::{claims::Claims, error::{AuthError, AuthErrorResponse}}, dto::{admin_user::{UserDetails, UserPatchRequest, UserRequest, UserResponse, UserUpdateRequest}, common::{PaginationQuery, SortRule}}, models::users::{self, UserRole, UserStatus}, state::SharedState};
+use crate::{auth::{claims::Claims, error::{AuthError, AuthErrorResponse}}, dto::{admin_user::{UserDetails, UserPatchRequest, UserRequest, UserResponse, UserUpdateRequest}, common::{PaginationQuery, SortRule}}, handlers::sessions::revoke_user_sessions, models::{sessions::SessionRevokeReason, users::{self, UserRole, UserStatus}}, state::SharedState};
 
 #[utoipa::path(
     get,
@@ -40,7 +40,7 @@ pub async fn get_user_by_id(
      let user_response = UserDetails {
          id: user.id,
          org_id: user.org_id,
-         sub: user.google_id.unwrap_or(user.azure_id.unwrap_or(user.email.clone())),
+         sub: user.email.clone(),
          email: user.email,
          name: user.name,
          picture: user.picture,
@@ -52,7 +52,7 @@ pub async fn get_user_by_id(
          has_password: user.password.is_some(), // SSO-only users don't have password
          mfa_enabled: user.mfa_enabled,
          last_login_at: Some(user.last_login_at),
-         password_changed_at: None,
+         password_changed_at: user.password_changed_at,
          created_at: user.created_at,
          updated_at: user.updated_at,
       };
@@ -150,7 +150,7 @@ pub async fn get_users(
        .map(|user| UserDetails {
          id: user.id,
          org_id:user.org_id,
-         sub: user.google_id.unwrap_or(user.azure_id.unwrap_or(user.email.clone())),
+         sub: user.email.clone(),
          email: user.email,
          name: user.name,
          picture: user.picture,
@@ -162,7 +162,7 @@ pub async fn get_users(
          has_password: user.password.is_some(), // SSO-only users don't have password
          mfa_enabled: user.mfa_enabled,
          last_login_at: Some(user.last_login_at),
-         password_changed_at: None,
+         password_changed_at: user.password_changed_at,
          created_at: user.created_at,
          updated_at: user.updated_at,
       })
@@ -202,14 +202,18 @@ pub async fn add_new_user(
      email_verified: Set(false),
      name: Set(Some(req.name)),
      password: Set(None),
-     google_id: Set(None),
-     azure_id: Set(None),
      mfa_enabled: Set(false),
      mfa_secret: Set(None),
+     mfa_recovery_codes: Set(Vec::new()),
+     mfa_last_step: Set(None),
+     token_version: Set(0),
+     is_service_account: Set(false),
      created_at: Set(Utc::now()),
      updated_at: Set(Utc::now()),
      last_login_at: Set(Utc::now()),
      password_changed_at: Set(None),
+     failed_login_attempts: Set(0),
+     locked_until: Set(None),
      role: Set(req.role),
      hd:Set(req.email.trim().split_once("@").map(|splited| splited.1.to_string())),
      department:Set(Some(req.department)),
@@ -259,6 +263,7 @@ pub async fn update_user(
             AuthError::DbTimeout
         })?
         .ok_or(AuthError::EmailDoesNotExist)?;
+    let role_changed = req.role.as_ref().is_some_and(|role| *role != model.role);
 
     let mut active: users::ActiveModel = model.into();
 
@@ -290,6 +295,16 @@ pub async fn update_user(
                 AuthError::DbTimeout
             }
         })?;
+    if role_changed {
+        app_state
+            .user_access
+            .bump_token_version(&app_state.database, user_id)
+            .await
+            .map_err(|e| {
+                eprintln!("db update error: {e}");
+                AuthError::DbTimeout
+            })?;
+    }
 
     Ok(StatusCode::OK)
 }
@@ -332,6 +347,7 @@ pub async fn patch_user_status(
             AuthError::DbTimeout
         })?
         .ok_or(AuthError::EmailDoesNotExist)?;
+    let revoke_sessions = req.status != UserStatus::Active;
     let mut active: users::ActiveModel = model.into();
     active.status = Set(req.status);
     active.updated_at = Set(Utc::now());
@@ -343,6 +359,22 @@ pub async fn patch_user_status(
               AuthError::DbTimeout
             }
         )?;
+    app_state
+        .user_access
+        .bump_token_version(&app_state.database, user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("db update error: {e}");
+            AuthError::DbTimeout
+        })?;
+    if revoke_sessions {
+        revoke_user_sessions(&app_state.database, user_id, SessionRevokeReason::UserStatus)
+          .await
+          .map_err(|e| {
+              eprintln!("db update error: {e}");
+              AuthError::DbTimeout
+          })?;
+    }
     Ok((StatusCode::OK,"User status updated successfully"))
 }
 
@@ -389,5 +421,19 @@ pub async fn delete_user(
             eprintln!("db find error: {e}");
             AuthError::DbTimeout
         })?;
+    app_state
+      .user_access
+      .bump_token_version(&app_state.database, user_id)
+      .await
+      .map_err(|e| {
+            eprintln!("db update error: {e}");
+            AuthError::DbTimeout
+        })?;
+    revoke_user_sessions(&app_state.database, user_id, SessionRevokeReason::UserStatus)
+      .await
+      .map_err(|e| {
+            eprintln!("db update error: {e}");
+            AuthError::DbTimeout
+        })?;
     Ok(StatusCode::NO_CONTENT)
 }
\ No newline at end of file
@@ -0,0 +1,378 @@
+use axum::{Json, extract::{Path, State}};
+use chrono::{Duration, Utc};
+use migration::Expr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use uuid::Uuid;
+use crate::{auth::{api_keys::new_api_key, claims::Claims, error::{AuthError, AuthErrorResponse}}, dto::api_keys::{ApiKeyCreatedResponse, ApiKeyRequest, ApiKeyResponse, ServiceAccountRequest, ServiceAccountResponse}, models::{api_keys::{self, ApiKeyScope}, users::{self, UserRole, UserStatus}}, state::SharedState};
+
+const MAX_API_KEY_DAYS:u32 = 365;
+
+fn api_key_response(api_key:api_keys::Model) -> ApiKeyResponse {
+    ApiKeyResponse {
+        id:api_key.id,
+        name:api_key.name,
+        prefix:api_key.prefix,
+        scopes:api_key.scopes,
+        expires_at:api_key.expires_at,
+        last_used_at:api_key.last_used_at,
+        created_at:api_key.created_at,
+    }
+}
+
+fn service_account_response(user:users::Model) -> ServiceAccountResponse {
+    ServiceAccountResponse {
+        id:user.id,
+        name:user.name,
+        role:user.role,
+        status:user.status,
+        department:user.department,
+        created_at:user.created_at,
+    }
+}
+
+/// Keys cannot create other keys, a leaked key would otherwise outlive its revocation
+fn ensure_signed_in(claims:&Claims) -> Result<(),AuthError> {
+    match claims.api_key_id {
+        Some(_) => Err(AuthError::PermissionDenied),
+        None => Ok(()),
+    }
+}
+
+fn ensure_admin(claims:&Claims) -> Result<(),AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => Ok(()),
+        _ => Err(AuthError::PermissionDenied),
+    }
+}
+
+async fn create_api_key(db:&DatabaseConnection,user:&users::Model,req:ApiKeyRequest,created_by:Option<Uuid>) -> Result<ApiKeyCreatedResponse,AuthError> {
+    let name = req.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err(AuthError::InvalidApiKeyRequest { field:"name" });
+    }
+    let mut scopes = req.scopes;
+    scopes.sort_by_key(|scope| *scope as u8);
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(AuthError::InvalidApiKeyRequest { field:"scopes" });
+    }
+    // Handlers check the role anyway, refusing the scope keeps the key honest about what it can do
+    if scopes.contains(&ApiKeyScope::Admin) && !matches!(user.role, UserRole::SuperAdmin | UserRole::Admin) {
+        return Err(AuthError::PermissionDenied);
+    }
+    let expires_at = match req.expires_in_days {
+        Some(days) if days == 0 || days > MAX_API_KEY_DAYS => return Err(AuthError::InvalidApiKeyRequest { field:"expires_in_days" }),
+        Some(days) => Some(Utc::now() + Duration::days(days as i64)),
+        None => None,
+    };
+    let (key, prefix, key_hash) = new_api_key();
+    let api_key = api_keys::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        user_id:Set(user.id),
+        name:Set(name),
+        prefix:Set(prefix),
+        key_hash:Set(key_hash),
+        scopes:Set(scopes),
+        expires_at:Set(expires_at),
+        last_used_at:Set(None),
+        created_by:Set(created_by),
+        created_at:Set(Utc::now()),
+        revoked_at:Set(None),
+    }
+    .insert(db)
+    .await
+    .map_err(|e| {
+       eprintln!("db insert error: {e}");
+       AuthError::DbTimeout
+    })?;
+    Ok(ApiKeyCreatedResponse { key, api_key:api_key_response(api_key) })
+}
+
+async fn list_api_keys(db:&DatabaseConnection,user_id:Uuid) -> Result<Vec<ApiKeyResponse>,AuthError> {
+    let api_keys = api_keys::Entity::find()
+      .filter(api_keys::Column::UserId.eq(user_id))
+      .filter(api_keys::Column::RevokedAt.is_null())
+      .order_by_desc(api_keys::Column::CreatedAt)
+      .all(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get all error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(api_keys.into_iter().map(api_key_response).collect())
+}
+
+async fn revoke_api_key(db:&DatabaseConnection,user_id:Uuid,key_id:Uuid) -> Result<(),AuthError> {
+    let result = api_keys::Entity::update_many()
+      .col_expr(api_keys::Column::RevokedAt, Expr::value(Utc::now()))
+      .filter(api_keys::Column::Id.eq(key_id))
+      .filter(api_keys::Column::UserId.eq(user_id))
+      .filter(api_keys::Column::RevokedAt.is_null())
+      .exec(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if result.rows_affected == 0 {
+        return Err(AuthError::ResourceNotFound);
+    }
+    Ok(())
+}
+
+async fn find_user(db:&DatabaseConnection,user_id:Uuid) -> Result<users::Model,AuthError> {
+    users::Entity::find_by_id(user_id)
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .one(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::EmailDoesNotExist)
+}
+
+async fn find_service_account(db:&DatabaseConnection,user_id:Uuid) -> Result<users::Model,AuthError> {
+    let user = find_user(db, user_id).await?;
+    if !user.is_service_account {
+        return Err(AuthError::ResourceNotFound);
+    }
+    Ok(user)
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/api-keys",
+    tag = "auth",
+    responses(
+       (status = 200, body = Vec<ApiKeyResponse>, description = "Active API keys of the user, newest first"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_api_keys(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<ApiKeyResponse>>), AuthError> {
+    let api_keys = list_api_keys(&app_state.database, claims.user_id).await?;
+    Ok((StatusCode::OK,Json(api_keys)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/api-keys",
+    tag = "auth",
+    request_body = ApiKeyRequest,
+    responses(
+       (status = 201, body = ApiKeyCreatedResponse, description = "API key created, the key is shown only in this response"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid name, scopes or expiry (code=6305)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Called with an API key or `admin` scope for a non admin (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn add_api_key(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<ApiKeyRequest>,
+) -> Result<(StatusCode,Json<ApiKeyCreatedResponse>), AuthError> {
+    ensure_signed_in(&claims)?;
+    let user = find_user(&app_state.database, claims.user_id).await?;
+    let api_key = create_api_key(&app_state.database, &user, req, None).await?;
+    Ok((StatusCode::CREATED,Json(api_key)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/api-keys/{key_id}",
+    tag = "auth",
+    params(("key_id" = Uuid, Path, description = "API key ID")),
+    responses(
+       (status = 204, description = "API key revoked"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "API key not found (code=6302)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn delete_api_key(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(key_id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    // A leaked key may revoke itself, never create others
+    revoke_api_key(&app_state.database, claims.user_id, key_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/service-accounts",
+    tag = "admin",
+    responses(
+       (status = 200, body = Vec<ServiceAccountResponse>),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_service_accounts(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<ServiceAccountResponse>>), AuthError> {
+    ensure_admin(&claims)?;
+    let service_accounts = users::Entity::find()
+      .filter(users::Column::IsServiceAccount.eq(true))
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .order_by_asc(users::Column::Name)
+      .all(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get all error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(service_accounts.into_iter().map(service_account_response).collect())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/service-accounts",
+    tag = "admin",
+    request_body = ServiceAccountRequest,
+    responses(
+       (status = 201, body = ServiceAccountResponse, description = "Service account created, it is managed like a user and signs in with its API keys only"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid name (code=6305)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied or super admin role (code=6300)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn add_service_account(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<ServiceAccountRequest>,
+) -> Result<(StatusCode,Json<ServiceAccountResponse>), AuthError> {
+    ensure_admin(&claims)?;
+    let name = req.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AuthError::InvalidApiKeyRequest { field:"name" });
+    }
+    let role = req.role.unwrap_or(UserRole::User);
+    if role == UserRole::SuperAdmin {
+        return Err(AuthError::PermissionDenied);
+    }
+    let id = Uuid::new_v4();
+    let service_account = users::ActiveModel {
+        id:Set(id),
+        org_id:Set(claims.org_id),
+        status:Set(UserStatus::Active),
+        picture:Set(None),
+        // Reserved domain, no mailbox or identity provider can claim it
+        email:Set(format!("{id}@service-accounts.invalid")),
+        email_verified:Set(false),
+        name:Set(Some(name)),
+        password:Set(None),
+        mfa_enabled:Set(false),
+        mfa_secret:Set(None),
+        mfa_recovery_codes:Set(Vec::new()),
+        mfa_last_step:Set(None),
+        token_version:Set(0),
+        is_service_account:Set(true),
+        created_at:Set(Utc::now()),
+        updated_at:Set(Utc::now()),
+        last_login_at:Set(Utc::now()),
+        password_changed_at:Set(None),
+        failed_login_attempts:Set(0),
+        locked_until:Set(None),
+        role:Set(role),
+        hd:Set(None),
+        department:Set(req.department),
+        metadata:Set(None),
+    }
+    .insert(&app_state.database)
+    .await
+    .map_err(|e| {
+       eprintln!("db insert error: {e}");
+       AuthError::DbTimeout
+    })?;
+    Ok((StatusCode::CREATED,Json(service_account_response(service_account))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/service-accounts/{user_id}/api-keys",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "Service account ID")),
+    responses(
+       (status = 200, body = Vec<ApiKeyResponse>, description = "Active API keys of the service account, newest first"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Not a service account (code=6302) or user not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_service_account_api_keys(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<(StatusCode,Json<Vec<ApiKeyResponse>>), AuthError> {
+    ensure_admin(&claims)?;
+    find_service_account(&app_state.database, user_id).await?;
+    let api_keys = list_api_keys(&app_state.database, user_id).await?;
+    Ok((StatusCode::OK,Json(api_keys)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/service-accounts/{user_id}/api-keys",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "Service account ID")),
+    request_body = ApiKeyRequest,
+    responses(
+       (status = 201, body = ApiKeyCreatedResponse, description = "API key created, the key is shown only in this response"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid name, scopes or expiry (code=6305)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied, called with an API key or `admin` scope for a non admin account (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Not a service account (code=6302) or user not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn add_service_account_api_key(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+    Json(req): Json<ApiKeyRequest>,
+) -> Result<(StatusCode,Json<ApiKeyCreatedResponse>), AuthError> {
+    ensure_admin(&claims)?;
+    ensure_signed_in(&claims)?;
+    let service_account = find_service_account(&app_state.database, user_id).await?;
+    let api_key = create_api_key(&app_state.database, &service_account, req, Some(claims.user_id)).await?;
+    Ok((StatusCode::CREATED,Json(api_key)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/service-accounts/{user_id}/api-keys/{key_id}",
+    tag = "admin",
+    params(
+        ("user_id" = Uuid, Path, description = "Service account ID"),
+        ("key_id" = Uuid, Path, description = "API key ID"),
+    ),
+    responses(
+       (status = 204, description = "API key revoked"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "API key not found (code=6302)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn delete_service_account_api_key(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path((user_id, key_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AuthError> {
+    ensure_admin(&claims)?;
+    revoke_api_key(&app_state.database, user_id, key_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,544 @@
+use axum::{Json, extract::{Path, Query, State}, response::{Sse, sse::Event}};
+use chrono::Utc;
+use migration::extension::postgres::PgExpr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect};
+use std::convert::Infallible;
+use uuid::Uuid;
+use crate::{
+    auth::{claims::Claims, error::{AuthError, AuthErrorResponse}},
+    dto::{assistants::{AssistantQuery, AssistantRequest, AssistantResponse, AssistantUpdateRequest}, chat_stream::{ChatInitRequest, ChatStream}, files::File},
+    error::{AppError, ErrorResponse},
+    handlers::{chat_stream::{BranchParent, stream_chat}, sharing::{get_user_department, is_admin, sharing_department}},
+    models::{assistants::{self, AssistantVisibility}, conversations, files::{self, FileUploadStatus}, users::UserRole},
+    state::SharedState,
+};
+
+/// Tool name allowing an assistant to search the web
+pub const WEB_SEARCH_TOOL:&str = "web_search";
+
+/// Assistants a user can use: their own, the organization ones and the ones of their department
+fn visible_to(user_id:Uuid,department:Option<String>) -> Condition {
+    let mut condition = Condition::any()
+        .add(assistants::Column::UserId.eq(user_id))
+        .add(assistants::Column::Visibility.eq(AssistantVisibility::Organization));
+    if let Some(department) = department {
+        condition = condition.add(Condition::all()
+            .add(assistants::Column::Visibility.eq(AssistantVisibility::Department))
+            .add(assistants::Column::Department.eq(department)));
+    }
+    condition
+}
+
+/// Assistant usable by the user, `None` when it does not exist or is not shared with them
+pub async fn find_visible_assistant(db:&DatabaseConnection,user_id:Uuid,assistant_id:Uuid) -> Result<Option<assistants::Model>,DbErr> {
+    let department = get_user_department(db, user_id).await?;
+    assistants::Entity::find_by_id(assistant_id)
+        .filter(visible_to(user_id, department))
+        .one(db)
+        .await
+}
+
+/// Assistant of a conversation, or the one requested to start a new conversation
+pub async fn resolve_chat_assistant(db:&DatabaseConnection,user_id:Uuid,chat_id:Option<Uuid>,assistant_id:Option<Uuid>) -> Result<Option<assistants::Model>,DbErr> {
+    let assistant_id = match chat_id {
+        Some(chat_id) => conversations::Entity::find_by_id(chat_id)
+            .filter(conversations::Column::UserId.eq(user_id))
+            .one(db)
+            .await?
+            .and_then(|conversation| conversation.assistant_id),
+        None => assistant_id,
+    };
+    match assistant_id {
+        // Unsharing an assistant turns its conversations into plain ones
+        Some(assistant_id) => find_visible_assistant(db, user_id, assistant_id).await,
+        None => Ok(None),
+    }
+}
+
+/// Makes the chat use the assistant engine/model and tools, its temperature being the default
+pub fn apply_assistant(assistant:&assistants::Model,req:&mut ChatInitRequest) {
+    req.provider = Some(assistant.model_provider.clone());
+    req.model_name = Some(assistant.model_name.clone());
+    req.temperature = req.temperature.or(assistant.temperature);
+    req.web_search = req.web_search && assistant.tools.iter().any(|tool| tool == WEB_SEARCH_TOOL);
+    let selected_tools = match req.selected_tools.take() {
+        Some(selected_tools) => selected_tools
+            .into_iter()
+            .filter(|tool| assistant.tools.contains(tool))
+            .collect(),
+        None => assistant.tools.clone(),
+    };
+    req.selected_tools = Some(selected_tools);
+}
+
+/// Reference files of an assistant, read from its creator storage
+pub async fn get_assistant_files(db:&DatabaseConnection,assistant:&assistants::Model) -> Result<Vec<File>,DbErr> {
+    let Some(owner_id) = assistant.user_id else {
+        return Ok(Vec::new());
+    };
+    if assistant.file_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let files = files::Entity::find()
+        .filter(files::Column::Id.is_in(assistant.file_ids.clone()))
+        .filter(files::Column::UserId.eq(owner_id))
+        .filter(files::Column::Status.is_in(FileUploadStatus::AVAILABLE))
+        .all(db)
+        .await?;
+    Ok(files
+        .into_iter()
+        .map(|file| File {
+            id:file.id,
+            size:Some(file.size as usize),
+            name:file.name,
+            content_type:file.content_type,
+            openai_id:None,
+            base64:None,
+            owner_id:Some(owner_id),
+        })
+        .collect())
+}
+
+/// Creators edit their assistants, admins edit every assistant
+async fn find_modifiable_assistant(app_state:&SharedState,claims:&Claims,assistant_id:Uuid) -> Result<assistants::Model,AppError> {
+    let assistant = if is_admin(claims) {
+        assistants::Entity::find_by_id(assistant_id)
+            .one(&app_state.database)
+            .await
+    } else {
+        find_visible_assistant(&app_state.database, claims.user_id, assistant_id).await
+    }
+    .map_err(|e| {
+        eprintln!("DB get one error {:?}", e);
+        AppError::DbTimeout})?
+    .ok_or(AppError::DbNotFound)?;
+    if assistant.user_id != Some(claims.user_id) && !is_admin(claims) {
+        return Err(AppError::PermissionDenied);
+    }
+    Ok(assistant)
+}
+
+async fn check_file_ids(app_state:&SharedState,user_id:Option<Uuid>,file_ids:&[Uuid]) -> Result<(),AppError> {
+    if file_ids.is_empty() {
+        return Ok(());
+    }
+    let Some(user_id) = user_id else {
+        return Err(AppError::invalid_field("file_ids"));
+    };
+    let count = files::Entity::find()
+        .filter(files::Column::Id.is_in(file_ids.to_vec()))
+        .filter(files::Column::UserId.eq(user_id))
+        .filter(files::Column::Status.ne(FileUploadStatus::Deleted))
+        .count(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB count error {:?}", e);
+            AppError::DbTimeout})?;
+    if count != file_ids.len() as u64 {
+        return Err(AppError::invalid_field("file_ids"));
+    }
+    Ok(())
+}
+
+fn to_assistant_response(assistant:assistants::Model,user_id:Uuid) -> AssistantResponse {
+    AssistantResponse {
+        id:assistant.id,
+        owned:assistant.user_id == Some(user_id),
+        name:assistant.name,
+        description:assistant.description,
+        provider:assistant.model_provider,
+        model_name:assistant.model_name,
+        system_prompt:assistant.system_prompt,
+        temperature:assistant.temperature,
+        tools:assistant.tools,
+        file_ids:assistant.file_ids,
+        visibility:assistant.visibility,
+        department:assistant.department,
+        created_at:assistant.created_at,
+        updated_at:assistant.updated_at,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/assistants",
+    tag = "assistants",
+    params(
+        ("limit" = Option<u64>, Query, description = "Default value : 30"),
+        ("offset" = Option<u64>, Query, description = "Default value : 0"),
+        ("search" = Option<String>, Query, description = "Search in names and descriptions"),
+    ),
+    responses(
+        (status = 200, body = Vec<AssistantResponse>),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_assistants(
+  claims:Claims,
+  Query(query):Query<AssistantQuery>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<Vec<AssistantResponse>>),AppError>{
+    let department = get_user_department(&app_state.database, claims.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?;
+    let mut select = assistants::Entity::find()
+        .filter(visible_to(claims.user_id, department));
+    if let Some(search) = query.search {
+        let pattern = format!("%{}%",search);
+        select = select.filter(Condition::any()
+            .add(assistants::Column::Name.into_expr().ilike(pattern.clone()))
+            .add(assistants::Column::Description.into_expr().ilike(pattern)));
+    }
+    let assistants = select
+        .order_by_asc(assistants::Column::Name)
+        .limit(query.limit.unwrap_or(30))
+        .offset(query.offset.unwrap_or(0))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?;
+    let response = assistants
+        .into_iter()
+        .map(|assistant| to_assistant_response(assistant, claims.user_id))
+        .collect();
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/assistants",
+    tag = "admin",
+    params(
+        ("limit" = Option<u64>, Query, description = "Default value : 30"),
+        ("offset" = Option<u64>, Query, description = "Default value : 0"),
+        ("search" = Option<String>, Query, description = "Search in names and descriptions"),
+    ),
+    responses(
+       (status = 200, body = Vec<AssistantResponse>),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_all_assistants(
+    claims: Claims,
+    Query(query):Query<AssistantQuery>,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<AssistantResponse>>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let mut select = assistants::Entity::find();
+    if let Some(search) = query.search {
+        let pattern = format!("%{}%",search);
+        select = select.filter(Condition::any()
+            .add(assistants::Column::Name.into_expr().ilike(pattern.clone()))
+            .add(assistants::Column::Description.into_expr().ilike(pattern)));
+    }
+    let assistants = select
+        .order_by_asc(assistants::Column::Name)
+        .limit(query.limit.unwrap_or(30))
+        .offset(query.offset.unwrap_or(0))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("db get many error: {e}");
+            AuthError::DbTimeout
+        })?;
+    let response = assistants
+        .into_iter()
+        .map(|assistant| to_assistant_response(assistant, claims.user_id))
+        .collect();
+    Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/assistants/{assistant_id}",
+    tag = "assistants",
+    params(
+        ("assistant_id" = Uuid, Path, description = "Assistant id"),
+    ),
+    responses(
+        (status = 200, body = AssistantResponse),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Assistant not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_assistant_by_id(
+  claims:Claims,
+  Path(assistant_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<AssistantResponse>),AppError>{
+    let assistant = find_visible_assistant(&app_state.database, claims.user_id, assistant_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?
+        .ok_or(AppError::DbNotFound)?;
+  Ok((StatusCode::OK,Json(to_assistant_response(assistant, claims.user_id))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/assistants",
+    tag = "assistants",
+    request_body = AssistantRequest,
+    responses(
+        (status = 201, body = AssistantResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2001 missing department, code=2002 empty field, code=2003 unknown files)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Sharing with another department (code=1002)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn add_assistant(
+  claims:Claims,
+  State(app_state): State<SharedState>,
+  Json(req):Json<AssistantRequest>
+) -> Result<(StatusCode,Json<AssistantResponse>),AppError>{
+    if req.name.trim().is_empty() {
+        return Err(AppError::empty_field("name"));
+    }
+    if req.model_name.trim().is_empty() {
+        return Err(AppError::empty_field("model_name"));
+    }
+    let visibility = req.visibility.unwrap_or(AssistantVisibility::Private);
+    let user_department = get_user_department(&app_state.database, claims.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?;
+    let department = sharing_department(&claims, visibility == AssistantVisibility::Department, req.department, user_department)?;
+    check_file_ids(&app_state, Some(claims.user_id), &req.file_ids).await?;
+    let new_assistant = assistants::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        user_id:Set(Some(claims.user_id)),
+        name:Set(req.name.trim().to_string()),
+        description:Set(req.description.unwrap_or_default()),
+        model_provider:Set(req.provider.to_lowercase()),
+        model_name:Set(req.model_name),
+        system_prompt:Set(req.system_prompt),
+        temperature:Set(req.temperature),
+        tools:Set(req.tools),
+        file_ids:Set(req.file_ids),
+        visibility:Set(visibility),
+        department:Set(department),
+        created_at:Set(Utc::now()),
+        updated_at:Set(Utc::now()),
+        metadata:Set(None),
+    };
+    let assistant = new_assistant
+        .insert(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db insert one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok((StatusCode::CREATED,Json(to_assistant_response(assistant, claims.user_id))))
+}
+
+#[utoipa::path(
+    put,
+    path = "/assistants/{assistant_id}",
+    tag = "assistants",
+    params(
+        ("assistant_id" = Uuid, Path, description = "Assistant id"),
+    ),
+    request_body = AssistantUpdateRequest,
+    responses(
+        (status = 200, body = AssistantResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2001 missing department, code=2002 empty field, code=2003 unknown files)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Assistant not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Assistant not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn update_assistant_by_id(
+  claims:Claims,
+  Path(assistant_id):Path<Uuid>,
+  State(app_state): State<SharedState>,
+  Json(req):Json<AssistantUpdateRequest>
+) -> Result<(StatusCode,Json<AssistantResponse>),AppError>{
+    let assistant = find_modifiable_assistant(&app_state, &claims, assistant_id).await?;
+    let owner_id = assistant.user_id;
+    let current_visibility = assistant.visibility;
+    let current_department = assistant.department.clone();
+    let mut active_assistant = assistant.into_active_model();
+    if let Some(name) = req.name {
+        if name.trim().is_empty() {
+            return Err(AppError::empty_field("name"));
+        }
+        active_assistant.name = Set(name.trim().to_string());
+    }
+    if let Some(model_name) = req.model_name {
+        if model_name.trim().is_empty() {
+            return Err(AppError::empty_field("model_name"));
+        }
+        active_assistant.model_name = Set(model_name);
+    }
+    if let Some(description) = req.description {
+        active_assistant.description = Set(description);
+    }
+    if let Some(provider) = req.provider {
+        active_assistant.model_provider = Set(provider.to_lowercase());
+    }
+    if let Some(system_prompt) = req.system_prompt {
+        active_assistant.system_prompt = Set(system_prompt);
+    }
+    if req.temperature.is_some() {
+        active_assistant.temperature = Set(req.temperature);
+    }
+    if let Some(tools) = req.tools {
+        active_assistant.tools = Set(tools);
+    }
+    if let Some(file_ids) = req.file_ids {
+        // Files are read from the creator storage
+        check_file_ids(&app_state, owner_id, &file_ids).await?;
+        active_assistant.file_ids = Set(file_ids);
+    }
+    if req.visibility.is_some() || req.department.is_some() {
+        let visibility = req.visibility.unwrap_or(current_visibility);
+        let user_department = get_user_department(&app_state.database, claims.user_id)
+            .await
+            .map_err(|e| {
+                eprintln!("DB get one error {:?}", e);
+                AppError::DbTimeout})?;
+        let department = sharing_department(&claims, visibility == AssistantVisibility::Department, req.department.or(current_department), user_department)?;
+        active_assistant.visibility = Set(visibility);
+        active_assistant.department = Set(department);
+    }
+    active_assistant.updated_at = Set(Utc::now());
+    let assistant = active_assistant
+        .update(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db update one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok((StatusCode::OK,Json(to_assistant_response(assistant, claims.user_id))))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/assistants/{assistant_id}",
+    tag = "assistants",
+    params(
+        ("assistant_id" = Uuid, Path, description = "Assistant id"),
+    ),
+    responses(
+        (status = 204),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Assistant not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Assistant not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn delete_assistant_by_id(
+  claims:Claims,
+  Path(assistant_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<StatusCode,AppError>{
+    let assistant = find_modifiable_assistant(&app_state, &claims, assistant_id).await?;
+    // Conversations keep their history and continue without the assistant (FK set null)
+    assistants::Entity::delete_by_id(assistant.id)
+        .exec(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db delete one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/assistants/{assistant_id}/chat",
+    tag = "assistants",
+    params(
+        ("assistant_id" = Uuid, Path, description = "Assistant to start the conversation from"),
+    ),
+    request_body = ChatInitRequest,
+    responses(
+    (status = 200, content_type = "text/event-stream", body = ChatStream),
+    (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+    (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2002 empty messages)"),
+    (status = 403, content_type = "application/json", body = ErrorResponse, description = "LLM provider disabled by admin (code=4003)"),
+    (status = 404, content_type = "application/json", body = ErrorResponse, description = "Assistant not found (code=5003)"),
+    (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    ),
+)]
+pub async fn start_assistant_chat(
+  claims:Claims,
+  Path(assistant_id):Path<Uuid>,
+  State(app_state): State<SharedState>,
+  Json(mut req):Json<ChatInitRequest>
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,AppError>{
+    find_visible_assistant(&app_state.database, claims.user_id, assistant_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?
+        .ok_or(AppError::DbNotFound)?;
+    req.assistant_id = Some(assistant_id);
+    req.conversation_id = None;
+ stream_chat(claims, None, app_state, req, BranchParent::ActiveMessage).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn assistant(tools:&[&str],temperature:Option<f32>) -> assistants::Model {
+        assistants::Model {
+            id:Uuid::new_v4(),
+            user_id:None,
+            name:"Reviewer".to_string(),
+            description:String::new(),
+            model_provider:"anthropic".to_string(),
+            model_name:"claude-sonnet-4-5".to_string(),
+            system_prompt:String::new(),
+            temperature,
+            tools:tools.iter().map(|tool| tool.to_string()).collect(),
+            file_ids:Vec::new(),
+            visibility:AssistantVisibility::Private,
+            department:None,
+            created_at:Utc::now(),
+            updated_at:Utc::now(),
+            metadata:None,
+        }
+    }
+
+    fn request(body:serde_json::Value) -> ChatInitRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn assistant_sets_the_model_and_default_temperature() {
+        let mut req = request(json!({ "provider":"openai", "model_name":"gpt-5.2", "messages":[] }));
+        apply_assistant(&assistant(&[], Some(0.2)), &mut req);
+        assert_eq!(req.provider.as_deref(), Some("anthropic"));
+        assert_eq!(req.model_name.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(req.temperature, Some(0.2));
+        let mut req = request(json!({ "temperature":0.9, "messages":[] }));
+        apply_assistant(&assistant(&[], Some(0.2)), &mut req);
+        assert_eq!(req.temperature, Some(0.9));
+    }
+
+    #[test]
+    fn assistant_limits_the_tools() {
+        let mut req = request(json!({ "web_search":true, "selected_tools":["web_search", "code"], "messages":[] }));
+        apply_assistant(&assistant(&["code"], None), &mut req);
+        assert!(!req.web_search);
+        assert_eq!(req.selected_tools, Some(vec!["code".to_string()]));
+        let mut req = request(json!({ "web_search":true, "messages":[] }));
+        apply_assistant(&assistant(&[WEB_SEARCH_TOOL, "code"], None), &mut req);
+        assert!(req.web_search);
+        assert_eq!(req.selected_tools, Some(vec![WEB_SEARCH_TOOL.to_string(), "code".to_string()]));
+    }
+}
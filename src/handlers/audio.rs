@@ -0,0 +1,335 @@
+use std::time::Instant;
+use axum::{Json, body::Body, extract::{Multipart, State, multipart::Field}, response::{IntoResponse, Response}};
+use chrono::Utc;
+use num_traits::ToPrimitive;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter, prelude::Decimal};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{auth::{claims::Claims, error::AuthErrorResponse}, dto::{audio::{SpeechRequest, TranscriptionResponse, TranscriptionUpload}, chat::TokenUsage, files::Attachment, llm::openai::{OpenaiSpeechRequest, OpenaiTranscriptionRequest, OpenaiTranscriptionUsage}}, error::{AppError, ErrorResponse}, handlers::{message::set_active_message, models::get_openai_settings}, llm::{pricing::{AudioUsage, audio_cost}, provider::OpenaiApis}, models::{audio_usages::{self, AudioUsageKind}, conversations, messages::{self, ChatRole}}, state::SharedState, storage::upload::{sanitize_file_name, sniff_content_type}};
+
+/// Largest recording the transcription api accepts
+pub const MAX_TRANSCRIPTION_BYTES:u64 = 25 * 1024 * 1024;
+/// Largest text part of a transcription upload, the body limit is off for the recording
+const MAX_TEXT_FIELD_BYTES:usize = 16 * 1024;
+/// Longest text the speech api reads in one request
+const MAX_SPEECH_CHARS:usize = 4096;
+const TRANSCRIPTION_MODELS:&[&str] = &["gpt-4o-mini-transcribe", "gpt-4o-transcribe", "whisper-1"];
+const SPEECH_MODELS:&[&str] = &["tts-1", "tts-1-hd"];
+const SPEECH_VOICES:&[&str] = &["alloy", "ash", "coral", "echo", "fable", "onyx", "nova", "sage", "shimmer"];
+
+/// Recordings come from browsers and phones, webm and mp4 voice notes are detected as video
+fn is_audio_type(content_type:&str) -> bool {
+   content_type.starts_with("audio/") || matches!(content_type, "video/webm" | "video/mp4" | "video/mpeg")
+}
+
+async fn read_audio(mut field:Field<'_>) -> Result<Attachment,AppError> {
+   let name = field
+     .file_name()
+     .and_then(sanitize_file_name)
+     .unwrap_or("audio".to_string());
+   let mut bytes = Vec::new();
+   while let Some(chunk) = field.chunk().await.map_err(|e|{
+      eprintln!("multipart read error : {e}");
+      AppError::invalid_field("file")
+   })? {
+      bytes.extend_from_slice(&chunk);
+      if bytes.len() as u64 > MAX_TRANSCRIPTION_BYTES {
+         return Err(AppError::ValidationFileTooLarge { max_bytes:MAX_TRANSCRIPTION_BYTES });
+      }
+   }
+   // The declared type is ignored, the content decides
+   let content_type = sniff_content_type(&bytes, &name);
+   if !is_audio_type(&content_type) {
+      return Err(AppError::ValidationFileTypeNotAllowed { content_type });
+   }
+   Ok(Attachment { file:Some(bytes), name, content_type })
+}
+
+async fn read_text(mut field:Field<'_>,name:&'static str) -> Result<Option<String>,AppError> {
+   let mut bytes = Vec::new();
+   while let Some(chunk) = field.chunk().await.map_err(|e|{
+      eprintln!("multipart read error : {e}");
+      AppError::invalid_field(name)
+   })? {
+      bytes.extend_from_slice(&chunk);
+      if bytes.len() > MAX_TEXT_FIELD_BYTES {
+         return Err(AppError::invalid_field(name));
+      }
+   }
+   let text = String::from_utf8(bytes).map_err(|_| AppError::invalid_field(name))?;
+   Ok(Some(text.trim().to_string()).filter(|text| !text.is_empty()))
+}
+
+/// Reads the parts of a transcription upload, in any order
+async fn read_transcription_upload(multipart:&mut Multipart) -> Result<(Attachment,OpenaiTranscriptionRequest,Option<Uuid>),AppError> {
+   let mut audio = None;
+   let mut request = OpenaiTranscriptionRequest { model:TRANSCRIPTION_MODELS[0].to_string(), language:None, prompt:None };
+   let mut chat_id = None;
+   while let Some(field) = multipart.next_field().await.map_err(|e|{
+      eprintln!("multipart read error : {e}");
+      AppError::invalid_field("file")
+   })? {
+      match field.name() {
+         Some("file") if audio.is_none() => audio = Some(read_audio(field).await?),
+         Some("file") => return Err(AppError::invalid_field("file")),
+         Some("model") => {
+            if let Some(model) = read_text(field, "model").await? {
+               request.model = model;
+            }
+         },
+         Some("language") => request.language = read_text(field, "language").await?,
+         Some("prompt") => request.prompt = read_text(field, "prompt").await?,
+         Some("chat_id") => {
+            chat_id = read_text(field, "chat_id")
+              .await?
+              .map(|chat_id| chat_id.parse::<Uuid>().map_err(|_| AppError::invalid_field("chat_id")))
+              .transpose()?;
+         },
+         _ => {},
+      }
+   }
+   let audio = audio.ok_or(AppError::missing_field("file"))?;
+   if !TRANSCRIPTION_MODELS.contains(&request.model.as_str()) {
+      return Err(AppError::invalid_field("model"));
+   }
+   Ok((audio, request, chat_id))
+}
+
+/// Appends the transcript as a user message to the active branch of the conversation
+async fn post_transcript(db:&DatabaseConnection,conversation:conversations::Model,text:&str,model:&str) -> Result<Uuid,DbErr> {
+   let message_id = Uuid::new_v4();
+   let conversation_id = conversation.id;
+   let new_message = messages::ActiveModel {
+      id:Set(message_id),
+      conversation_id:Set(conversation_id),
+      previous_message_id:Set(conversation.active_message_id),
+      role:Set(ChatRole::User),
+      deleted:Set(false),
+      message_content:Set(text.to_string()),
+      // Answers to the message keep to the model of the conversation
+      model_provider:Set(conversation.model_provider.clone()),
+      model_name:Set(conversation.model_name.clone()),
+      request_id:Set(None),
+      request_tokens:Set(0),
+      response_tokens:Set(0),
+      tools_calls:Set(Vec::new()),
+      tools_results:Set(Vec::new()),
+      created_at:Set(Utc::now()),
+      updated_at:Set(Utc::now()),
+      total_tokens:Set(0),
+      latency:Set(0),
+      cost:Set(Decimal::from(0)),
+      metadata:Set(Some(json!({"files":[], "transcription":{"model":model}}))),
+   };
+   new_message
+     .insert(db)
+     .await?;
+   set_active_message(db, conversation_id, message_id).await?;
+   let message_count = conversation.message_count + 1;
+   let mut active_model = conversation.into_active_model();
+   active_model.message_count = Set(message_count);
+   active_model.last_message_at = Set(Some(Utc::now()));
+   active_model.updated_at = Set(Utc::now());
+   active_model
+     .update(db)
+     .await?;
+ Ok(message_id)
+}
+
+#[utoipa::path(
+    post,
+    path = "/audio/transcriptions",
+    tag = "audio",
+    request_body(content = TranscriptionUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, body = TranscriptionResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2001 missing file, code=2003 malformed form, text part too long, unknown model or chat id)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Conversation not found or archived (code=1001)"),
+        (status = 413, content_type = "application/json", body = ErrorResponse, description = "Recording above 25 MiB (code=2004)"),
+        (status = 415, content_type = "application/json", body = ErrorResponse, description = "Detected file type is not audio (code=2005)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "OpenAI not configured/disabled (code=4002/4003), transcription failed (code=1000) or database timeout (code=5001)"),
+    ),
+)]
+pub async fn transcribe_audio(
+   claims:Claims,
+   State(app_state):State<SharedState>,
+   mut multipart:Multipart,
+) -> Result<(StatusCode,Json<TranscriptionResponse>),AppError>{
+ let (audio, request, chat_id) = read_transcription_upload(&mut multipart).await?;
+ let settings = get_openai_settings(&app_state).await?;
+ // The conversation is checked before paying for the transcription
+ let conversation = match chat_id {
+    Some(chat_id) => Some(conversations::Entity::find_by_id(chat_id)
+       .filter(conversations::Column::UserId.eq(claims.user_id))
+       .filter(conversations::Column::ArchivedAt.is_null())
+       .one(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("db get one error: {e}");
+          AppError::DbTimeout
+       })?
+       .ok_or(AppError::ResourceNotFound)?),
+    None => None,
+ };
+ let started = Instant::now();
+ let transcription = app_state
+    .req_client
+    .openai_transcribe(&settings, &request, &audio)
+    .await
+    .map_err(|e|{
+       eprintln!("openai transcription error: {e}");
+       AppError::ServiceTemporarilyUnavailable
+    })?;
+ let latency = started.elapsed().as_millis() as i32;
+ let (usage, token_usage, duration_seconds) = match transcription.usage.clone() {
+    Some(OpenaiTranscriptionUsage::Tokens { input_tokens, output_tokens, total_tokens, input_token_details }) => {
+       let (text_input, audio_input) = input_token_details
+          .map(|details| (details.text_tokens, details.audio_tokens))
+          .unwrap_or((0, input_tokens));
+       (
+          AudioUsage::Tokens { text_input, audio_input, output:output_tokens },
+          TokenUsage { input_tokens:input_tokens as i32, output_tokens:output_tokens as i32, total_tokens:total_tokens as i32 },
+          None,
+       )
+    },
+    Some(OpenaiTranscriptionUsage::Duration { seconds }) => (
+       AudioUsage::Seconds(seconds),
+       TokenUsage { input_tokens:0, output_tokens:0, total_tokens:0 },
+       Some(seconds),
+    ),
+    None => (
+       AudioUsage::Seconds(0.0),
+       TokenUsage { input_tokens:0, output_tokens:0, total_tokens:0 },
+       None,
+    ),
+ };
+ let cost = audio_cost(&request.model, usage);
+ let conversation_id = conversation.as_ref().map(|conversation| conversation.id);
+ // Silence transcribes to nothing, there is no message to post
+ let message_id = match conversation {
+    Some(conversation) if !transcription.text.trim().is_empty() => Some(post_transcript(&app_state.database, conversation, &transcription.text, &request.model)
+       .await
+       .map_err(|e|{
+          eprintln!("db insert one error: {e}");
+          AppError::DbTimeout
+       })?),
+    _ => None,
+ };
+ let audio_usage = audio_usages::ActiveModel {
+    id:Set(Uuid::new_v4()),
+    user_id:Set(claims.user_id),
+    kind:Set(AudioUsageKind::Transcription),
+    model_provider:Set("openai".to_string()),
+    model_name:Set(request.model.clone()),
+    request_tokens:Set(token_usage.input_tokens),
+    response_tokens:Set(token_usage.output_tokens),
+    total_tokens:Set(token_usage.total_tokens),
+    latency:Set(latency),
+    cost:Set(cost),
+    conversation_id:Set(conversation_id),
+    message_id:Set(message_id),
+    metadata:Set(Some(json!({"bytes":audio.file.as_ref().map(Vec::len), "contentType":audio.content_type, "durationSeconds":duration_seconds}))),
+    created_at:Set(Utc::now()),
+ };
+ // The transcript is paid for already, it is returned even if its usage cannot be recorded
+ let _ = audio_usage
+    .insert(&app_state.database)
+    .await
+    .map_err(|e| eprintln!("db insert one error: {e}"));
+ Ok((StatusCode::OK, Json(TranscriptionResponse {
+    text:transcription.text,
+    model:request.model,
+    duration_seconds,
+    usage:token_usage,
+    cost:cost.to_f32().unwrap_or_default(),
+    chat_id:conversation_id,
+    message_id,
+ })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/audio/speech",
+    tag = "audio",
+    request_body = SpeechRequest,
+    responses(
+        (status = 200, description = "Audio streamed in the requested format, with its content type"),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2002 empty input, code=2003 input too long, unknown voice, model or speed)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "OpenAI not configured/disabled (code=4002/4003) or speech synthesis failed (code=1000)"),
+    ),
+)]
+pub async fn synthesize_speech(
+   claims:Claims,
+   State(app_state):State<SharedState>,
+   Json(req):Json<SpeechRequest>,
+) -> Result<Response<Body>,AppError>{
+ if req.input.trim().is_empty() {
+    return Err(AppError::empty_field("input"));
+ }
+ let characters = req.input.chars().count();
+ if characters > MAX_SPEECH_CHARS {
+    return Err(AppError::invalid_field("input"));
+ }
+ let voice = req.voice.unwrap_or(SPEECH_VOICES[0].to_string());
+ if !SPEECH_VOICES.contains(&voice.as_str()) {
+    return Err(AppError::invalid_field("voice"));
+ }
+ let model = req.model.unwrap_or(SPEECH_MODELS[0].to_string());
+ if !SPEECH_MODELS.contains(&model.as_str()) {
+    return Err(AppError::invalid_field("model"));
+ }
+ if req.speed.is_some_and(|speed| !(0.25..=4.0).contains(&speed)) {
+    return Err(AppError::invalid_field("speed"));
+ }
+ let settings = get_openai_settings(&app_state).await?;
+ let request = OpenaiSpeechRequest {
+    model,
+    input:req.input,
+    voice,
+    response_format:req.format.as_str().to_string(),
+    speed:req.speed,
+ };
+ let started = Instant::now();
+ let speech = app_state
+    .req_client
+    .openai_speech(&settings, &request)
+    .await
+    .map_err(|e|{
+       eprintln!("openai speech error: {e}");
+       AppError::ServiceTemporarilyUnavailable
+    })?;
+ // Speech is billed per character, known before the audio is streamed
+ let audio_usage = audio_usages::ActiveModel {
+    id:Set(Uuid::new_v4()),
+    user_id:Set(claims.user_id),
+    kind:Set(AudioUsageKind::Speech),
+    model_provider:Set("openai".to_string()),
+    model_name:Set(request.model.clone()),
+    request_tokens:Set(0),
+    response_tokens:Set(0),
+    total_tokens:Set(0),
+    latency:Set(started.elapsed().as_millis() as i32),
+    cost:Set(audio_cost(&request.model, AudioUsage::Characters(characters))),
+    conversation_id:Set(None),
+    message_id:Set(None),
+    metadata:Set(Some(json!({"characters":characters, "voice":request.voice, "format":request.response_format}))),
+    created_at:Set(Utc::now()),
+ };
+ let _ = audio_usage
+    .insert(&app_state.database)
+    .await
+    .map_err(|e| eprintln!("db insert one error: {e}"));
+ let response = Response::builder()
+   .status(StatusCode::OK)
+   .header("Content-Type", req.format.content_type())
+   .body(Body::from_stream(speech.bytes_stream()))
+   .map_err(|e|{
+       eprintln!("Response builder error: {e}");
+       AppError::ServiceTemporarilyUnavailable
+    })?
+   .into_response();
+ Ok(response)
+}
@@ -1,7 +1,49 @@
 use axum::{Json, extract::State};
 use reqwest::StatusCode;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
-use crate::{auth::{claims::{Claiming, Claims, RefreshClaims}, error::{AuthError, AuthErrorResponse}}, dto::auth::{AuthTokenResponse, RefreshTokenRequest, TokenType, User}, models::users::{self, UserRole, UserStatus}, state::SharedState};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use crate::{auth::{claims::{Claiming, Claims, RefreshClaims}, client::ClientInfo, error::{AuthError, AuthErrorResponse}}, dto::auth::{AuthTokenResponse, RefreshTokenRequest, TokenType, User}, handlers::{mfa::ensure_mfa_compliant, sessions::{rotate_session, start_session}}, models::{sessions, users::{self, UserRole, UserStatus}}, state::SharedState};
+
+/// Access and refresh tokens of a session, with the profile of its user
+fn session_tokens(user:users::Model,session:&sessions::Model) -> AuthTokenResponse {
+    let access_token_claims = Claims::new_access_token(user.email.clone(), user.name.clone(), user.id,user.org_id,user.role,Some(session.id),user.token_version);
+    let refresh_token_claims = RefreshClaims::new_refresh_token(user.email.clone(), user.id, session.id, session.refresh_jti);
+    let user_response = User {
+        id: user.id,
+        sub: user.email.clone(),
+        email: user.email,
+        name: user.name,
+        picture: user.picture,
+        hd: user.hd,
+        role: user.role,
+        status: user.status,
+        department: user.department,
+        is_super_admin: user.role == UserRole::SuperAdmin,
+        has_password: user.password.is_some(),
+        mfa_enabled: user.mfa_enabled,
+        last_login_at: Some(user.last_login_at),
+        password_changed_at: user.password_changed_at,
+        created_at: user.created_at,
+        updated_at: user.updated_at,
+    };
+    AuthTokenResponse {
+        access_token:access_token_claims.get_token_string(),
+        token_type: TokenType::Bearer,
+        expires_in: 3600,
+        refresh_token: Some(refresh_token_claims.get_token_string()),
+        user: Some(user_response),
+    }
+}
+
+/// Starts a session for the signed in user, returning its tokens
+pub async fn issue_tokens(db:&DatabaseConnection,user:users::Model,client:&ClientInfo) -> Result<AuthTokenResponse,AuthError> {
+    let session = start_session(db, user.id, client)
+      .await
+      .map_err(|e| {
+         eprintln!("db insert error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(session_tokens(user, &session))
+}
 
 #[utoipa::path(
     post,
@@ -9,14 +51,17 @@ use crate::{auth::{claims::{Claiming, Claims, RefreshClaims}, error::{AuthError,
     tag = "admin",
     request_body = RefreshTokenRequest,
     responses(
+       (status = 200, body = AuthTokenResponse, description = "New access token with the rotated refresh token, the previous refresh token no longer works"),
        (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Missing credentials (code=6102)"),
-       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired refresh token (code=6103)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired refresh token, revoked session or reused refresh token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "The organization requires MFA, sign in again to set it up (code=6112)"),
        (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Email does not exist (code=6101)"),
        (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=5003)"),
        (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
     )
 )]
 pub async fn handle_refresh_token(
+     client: ClientInfo,
      State(app_state): State<SharedState>,
      Json(req): Json<RefreshTokenRequest>
 ) -> Result<(StatusCode,Json<AuthTokenResponse>), AuthError> {
@@ -25,6 +70,7 @@ pub async fn handle_refresh_token(
         eprintln!("Refresh token decoding error: {e}");
         AuthError::InvalidToken
       })?;      
+   let session = rotate_session(&app_state.database, &refresh_claims, &client).await?;
    let user = users::Entity::find_by_id(refresh_claims.user_id)
      .filter(users::Column::Status.ne(UserStatus::Deleted))
      .one(&app_state.database)
@@ -38,31 +84,6 @@ pub async fn handle_refresh_token(
         UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
         _ => ()
     }
-    let access_token_claims = Claims::new_access_token(user.email.clone(), user.name.clone(), user.id,user.org_id,user.role);
-    let user_response = User {
-        id: user.id,
-        sub: user.azure_id.unwrap_or(user.google_id.unwrap_or(user.email.clone())),
-        email: user.email,
-        name: user.name,
-        picture: user.picture,
-        hd: user.hd,
-        role: user.role, // TODO: Map from database if role field exists
-        status: user.status,
-        department: user.department,
-        is_super_admin: user.role == UserRole::SuperAdmin, // Default to false, update based on database field if available
-        has_password: user.password.is_some(), // SSO-only users don't have password
-        mfa_enabled: user.mfa_enabled,
-        last_login_at: Some(user.last_login_at),
-        password_changed_at: None,
-        created_at: user.created_at,
-        updated_at: user.updated_at,
-    };
-    let resp = AuthTokenResponse {
-        access_token:access_token_claims.get_token_string(),
-        token_type: TokenType::Bearer,
-        expires_in: 3600, // 1 hour - match your JWT expiry
-        refresh_token:None,
-        user: Some(user_response),
-    };
- Ok((StatusCode::OK,Json(resp)))
+    ensure_mfa_compliant(&app_state.database, &user).await?;
+ Ok((StatusCode::OK,Json(session_tokens(user, &session))))
 }
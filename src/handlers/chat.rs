@@ -4,7 +4,7 @@ use migration::extension::postgres::PgExpr;
 use reqwest::StatusCode;
 use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, Iterable, PaginatorTrait as _, QueryFilter, QueryOrder, QuerySelect};
 use uuid::Uuid;
-use crate::{auth::{claims::Claims, error::AuthErrorResponse}, dto::{chat::{ArchiveChatRequest, ConversationResponse, MessageParts, MessageResponse, TokenUsage}, common::PaginationQuery, files::File}, error::{AppError, ErrorResponse}, models::{conversations::{self, ConversationWithCount}, messages::{self}}, state::SharedState};
+use crate::{auth::{claims::Claims, error::AuthErrorResponse}, dto::{chat::{ArchiveChatRequest, Citation, ConversationResponse, MessageParts, MessageResponse, TokenUsage}, common::PaginationQuery, files::File}, error::{AppError, ErrorResponse}, handlers::message::MessageTree, models::{conversations::{self, ConversationWithCount}, messages::{self}}, state::SharedState};
 use num_traits::cast::ToPrimitive;
 
 #[utoipa::path(
@@ -125,28 +125,26 @@ pub async fn get_chat_by_id(
 
     let limit = query.limit.unwrap_or(30);
     let offset = query.offset.unwrap_or(0);
-    let page = offset / limit;
 
-    let paginator = messages::Entity::find()
-      .filter(messages::Column::ConversationId.eq(chat_id))
-      .filter(messages::Column::Deleted.eq(false))
-      .order_by(
-        messages::Column::CreatedAt,
-        if query.ascending.unwrap_or(true) {
-            sea_orm::Order::Asc
-        } else {
-            sea_orm::Order::Desc
-        },
-       )
-       .paginate(&app_state.database, limit);
-
-    let message_count = paginator.num_items()
+    // Only the active branch is listed, alternatives are reachable through the sibling counts
+    let tree = MessageTree::load(&app_state.database, chat_id)
       .await
       .map_err(|e|{
         eprintln!("{}",e);
         AppError::DbTimeout})?;
+    let mut path = tree.path(conversation_model.active_message_id);
+    if !query.ascending.unwrap_or(true) {
+        path.reverse();
+    }
+    let message_count = path.len() as u64;
+    let messages = path
+      .into_iter()
+      .skip(offset as usize)
+      .take(limit as usize)
+      .map(|message_model| to_message_response(message_model, &tree))
+      .collect();
 
-    let mut conversation_response = ConversationResponse{
+    let conversation_response = ConversationResponse{
         id: conversation_model.id,
         title: conversation_model.title,
         archived: conversation_model.archived_at.is_some(),
@@ -157,50 +155,9 @@ pub async fn get_chat_by_id(
         created_at: conversation_model.created_at,
         updated_at: conversation_model.updated_at,
         last_message_at: conversation_model.last_message_at,
-        messages:Some(Vec::new()),
+        messages:Some(messages),
         message_count 
     };
-
-    let messages_models = paginator.fetch_page(page)
-     .await
-     .map_err(|e|{
-        eprintln!("{}",e);
-        AppError::DbTimeout})?;
-    messages_models
-      .into_iter()
-      .for_each(|message_model|{
-        let metadata = message_model.metadata.as_ref();
-        let model_params = if let Some(metadata) = metadata{
-          metadata.get("params").cloned()
-        }else{
-            None
-        };
-        let files:Option<Vec<File>> = if let Some(metadata) = metadata{
-          metadata.get("files")
-           .cloned()
-           .map(|value| serde_json::from_value::<Vec<File>>(value).unwrap_or(Vec::new()))
-        }else{
-            None
-        };
-        let message =  MessageResponse {
-            id:message_model.id,
-            role:message_model.role,
-            cost:message_model.cost.to_f32().unwrap_or_default(),
-            created_at: message_model.created_at,
-            updated_at: message_model.updated_at,
-            request_id:message_model.request_id,
-            model:message_model.model_name,
-            model_params: model_params,
-            tool_calls: message_model.tools_calls,
-            tools_results:message_model.tools_results,
-            parts:MessageParts{ text: message_model.message_content, files}, 
-            usage:TokenUsage{input_tokens:message_model.request_tokens,output_tokens:message_model.response_tokens,total_tokens:message_model.total_tokens} 
-        };
-        conversation_response.messages
-          .as_mut()
-          .unwrap()
-          .push(message);
-      });
   Ok((StatusCode::OK,Json(conversation_response)))
 }
 
@@ -307,4 +264,37 @@ pub async fn delete_chat_by_id(
         eprintln!("{}",e);
         AppError::DbTimeout})?;
  Ok(StatusCode::NO_CONTENT)
-}
\ No newline at end of file
+}
+
+pub fn to_message_response(message_model:&messages::Model,tree:&MessageTree) -> MessageResponse {
+    let metadata = message_model.metadata.as_ref();
+    let model_params = metadata.and_then(|metadata| metadata.get("params").cloned());
+    let files:Option<Vec<File>> = metadata
+      .and_then(|metadata| metadata.get("files").cloned())
+      .map(|value| serde_json::from_value::<Vec<File>>(value).unwrap_or(Vec::new()));
+    let citations = metadata
+      .and_then(|metadata| metadata.get("citations").cloned())
+      .and_then(|value| serde_json::from_value::<Vec<Citation>>(value).ok());
+    let siblings = tree.siblings(message_model);
+    MessageResponse {
+        id:message_model.id,
+        role:message_model.role,
+        cost:message_model.cost.to_f32().unwrap_or_default(),
+        created_at: message_model.created_at,
+        updated_at: message_model.updated_at,
+        request_id:message_model.request_id.clone(),
+        model:message_model.model_name.clone(),
+        model_params,
+        tool_calls: message_model.tools_calls.clone(),
+        tools_results:message_model.tools_results.clone(),
+        parts:MessageParts{ text: message_model.message_content.clone(), files},
+        usage:TokenUsage{input_tokens:message_model.request_tokens,output_tokens:message_model.response_tokens,total_tokens:message_model.total_tokens},
+        previous_message_id:message_model.previous_message_id,
+        sibling_count:siblings.len(),
+        sibling_index:siblings
+          .iter()
+          .position(|sibling| sibling.id == message_model.id)
+          .unwrap_or_default(),
+        citations,
+    }
+}
@@ -1,25 +1,40 @@
-use std::convert::Infallible;
+use std::{collections::HashSet, convert::Infallible, time::Duration};
+use anyhow::{Error, anyhow};
 use axum::{Json, extract::{Path, State}, response::{Sse, sse::{Event, KeepAlive}}};
 use chrono::Utc;
-use futures_util::StreamExt;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, prelude::Decimal};
+use futures_util::{StreamExt, stream::{self, BoxStream}};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, prelude::Decimal};
 use serde_json::json;
 use uuid::Uuid;
 use crate::{
     auth::{claims::Claims, error::AuthErrorResponse},
     config::setting::{AnthropicSettings, OpenaiSettings},
     dto::{
-        chat_stream::{ChatInitRequest, ChatStream},
+        admin_ai::{FallbackTarget, normalize_target, parse_fallback_chains},
+        chat::Citation,
+        chat_stream::{ChatCompareRequest, ChatInitRequest, ChatStream, CompareTarget},
         files::File,
         llm::anthropic::ANTHROPIC_DEFAULT_MAX_TOKENS,
+        models::ModelsResponse,
     },
     error::{AppError, ErrorResponse},
-    handlers::llm::{StreamParseResult, StreamParser, anthropic::AnthropicStreamParser, openai::OpenaiStreamParser},
-    llm::{prompt::Prompt, provider::{AnthropicApis, OpenaiApis, get_title_generation_model}},
-    models::{conversations, messages::{self, ChatRole}},
+    handlers::{assistants::{apply_assistant, get_assistant_files, resolve_chat_assistant}, collections::{COLLECTION_IDS_KEY, find_visible_collections, get_visible_collection_files}, file::{get_or_upload_openai_file, get_user_file_binary}, instructions::{compose_system_prompt, load_instructions}, message::{MessageTree, set_active_message}, models::find_model, prompt_templates::increment_template_usage, llm::{StreamParseResult, StreamParser, anthropic::AnthropicStreamParser, openai::OpenaiStreamParser}},
+    llm::{http_policy::{HttpPolicy, is_retryable_status, parse_retry_after}, prompt::Prompt, provider::{AnthropicApis, OpenaiApis, get_title_generation_model}},
+    models::{ai_engines, assistants, conversations, messages::{self, ChatRole}},
+    rag::{extraction::inline_extracted_files, retrieval::{retrieve_context, with_citations}},
     state::SharedState,
 };
-use reqwest_eventsource::Event as ReqwestEvent;
+use reqwest::StatusCode;
+use reqwest_eventsource::{Event as ReqwestEvent, EventSource};
+
+/// Error types streamed by providers when the model is overloaded or failing
+const UNAVAILABLE_ERROR_TYPES: [&str; 2] = ["overloaded_error", "api_error"];
+
+/// Length of the title used when title generation fails
+const FALLBACK_TITLE_LENGTH: usize = 60;
+
+/// Models streamed side by side by the compare endpoint
+const MAX_COMPARE_TARGETS: usize = 3;
 
 /// Provider configuration enum for handling different LLM providers
 enum LlmProviderConfig<'a> {
@@ -63,14 +78,41 @@ pub async fn handle_chat_stream_path_doc(){}
 )]
 pub async fn handle_chat_stream_doc(){}
 
+/// Where the messages of a stream attach in the conversation tree
+pub enum BranchParent {
+    /// After the last message of the active branch
+    ActiveMessage,
+    /// As a new alternative among the replies to this message, `None` for the first message
+    Message(Option<Uuid>),
+}
+
 pub async fn handle_chat_stream(
   claims:Claims,
-  mut chat_id:Option<Path<Uuid>>,
+  chat_id:Option<Path<Uuid>>,
   State(app_state): State<SharedState>,
   Json(req):Json<ChatInitRequest>
 ) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,AppError>{
- let provider = req.provider.clone().unwrap_or_else(|| "openai".to_string());
- let selected_tools = req.selected_tools.clone().unwrap_or_default();
+ stream_chat(claims, chat_id.map(|Path(chat_id)| chat_id), app_state, req, BranchParent::ActiveMessage).await
+}
+
+pub async fn stream_chat(
+  claims:Claims,
+  chat_id:Option<Uuid>,
+  app_state:SharedState,
+  mut req:ChatInitRequest,
+  parent:BranchParent,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,AppError>{
+ req.conversation_id = req.conversation_id.or(chat_id);
+ let assistant = resolve_chat_assistant(&app_state.database, claims.user_id, req.conversation_id, req.assistant_id)
+    .await
+    .map_err(|e| {
+       eprintln!("DB get one error {:?}", e);
+       AppError::DbTimeout})?;
+ if let Some(assistant) = &assistant {
+    apply_assistant(assistant, &mut req);
+ }
+ let provider = req.provider.clone().unwrap_or_else(|| "openai".to_string()).to_lowercase();
+ let temperature = req.temperature;
  let web_search = req.web_search;
  let openai_settings = app_state
     .settings
@@ -84,51 +126,343 @@ pub async fn handle_chat_stream(
     .read()
     .await
     .clone();
- // Select provider configuration and set default model
- let (provider_config, model_name) = match provider.to_lowercase().as_str() {
+ let (provider_config, model_name) = select_provider(&provider, req.model_name.clone(), &openai_settings, &anthropic_settings)?;
+ let requested = FallbackTarget{ provider:provider.clone(), model:model_name.clone() };
+ let prepared = prepare_conversation(&claims, &app_state, req, &requested, &provider_config, parent, assistant.as_ref()).await?;
+ let conversation_id = prepared.conversation_id;
+ let previous_message_id = prepared.previous_message_id;
+ // Requested model first, then the fallback chain configured by admins for it
+ let mut targets = vec![requested.clone()];
+ targets.extend(get_fallback_chain(&app_state, &claims, &provider, &model_name).await?);
+ let mut opened = None;
+ for target in targets {
+    if let Some(provider_stream) = open_target(&app_state, &claims.user_id, &target, temperature, &prepared.prompts, web_search).await {
+        opened = Some((target, provider_stream));
+        break;
+    }
+    eprintln!("llm provider {} model {} unavailable, trying next fallback", &target.provider, &target.model);
+ }
+ let (serving, ProviderStream { mut events, parser }) = opened.ok_or(AppError::ServiceTemporarilyUnavailable)?;
+ let fallback_metadata = (serving.provider != requested.provider || serving.model != requested.model).then(|| json!({
+    "requestedProvider":requested.provider.clone(),
+    "requestedModel":requested.model.clone(),
+ }));
+ let answer_metadata = with_citations(fallback_metadata.clone(), &prepared.citations);
+
+ let sse_stream = async_stream::try_stream! {
+    let mut answer = StreamedAnswer::default();
+
+    if fallback_metadata.is_some() {
+        let fallback = json!({
+            "id":conversation_id,
+            "provider":serving.provider.clone(),
+            "model":serving.model.clone(),
+        });
+        yield Event::default().event("fallback").data(fallback.to_string());
+    }
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(ReqwestEvent::Open) => {
+                println!("SSE connection open for provider: {}", &serving.provider);
+            }
+            Ok(ReqwestEvent::Message(msg)) => {
+                if let Some(text) = answer.apply(parser.parse_event(&msg.data)) {
+                    let chat_stream = ChatStream {
+                        id: conversation_id,
+                        role: None,
+                        content: Some(text),
+                        model: None,
+                    };
+                    yield Event::default().event("chunk").data(chat_stream.to_string());
+                }
+            }
+            Err(reqwest_eventsource::Error::StreamEnded) => {
+                let new_llm_message_id = answer
+                    .save(&app_state, conversation_id, previous_message_id, &serving, answer_metadata.clone())
+                    .await
+                    .expect("failed to insert llm response in table messages");
+                let _ = set_active_message(&app_state.database, conversation_id, new_llm_message_id)
+                    .await
+                    .map_err(|e| eprintln!("Db update many error {:?}", e));
+                yield Event::default().event("chunk").data("[DONE]");
+                break;
+            }
+            Err(e) => {
+                println!("Streaming error for provider:{} error:{}",serving.provider,e.to_string());
+                break;
+            }
+        }
+    }
+ };
+ let sse_response = Sse::new(sse_stream).keep_alive(KeepAlive::new());
+ Ok(sse_response)
+}
+
+/// Models of a comparison: 2 to `MAX_COMPARE_TARGETS` distinct models of the catalogue
+fn compare_targets(targets:&[CompareTarget],catalogue:&ModelsResponse) -> Result<Vec<FallbackTarget>,AppError>{
+ let targets = targets
+   .iter()
+   .map(|target| normalize_target(FallbackTarget{ provider:target.provider.clone(), model:target.model_name.clone() }, catalogue))
+   .collect::<Option<Vec<FallbackTarget>>>()
+   .ok_or(AppError::invalid_field("targets"))?;
+ let has_duplicates = targets
+   .iter()
+   .enumerate()
+   .any(|(i, target)| targets[..i].iter().any(|other| other.provider == target.provider && other.model == target.model));
+ if targets.len() < 2 || targets.len() > MAX_COMPARE_TARGETS || has_duplicates {
+    return Err(AppError::invalid_field("targets"));
+ }
+ Ok(targets)
+}
+
+#[utoipa::path(
+    post,
+    path = "/chat/compare",
+    tag = "chat",
+    request_body = ChatCompareRequest,
+    responses(
+    (status = 200, content_type = "text/event-stream", body = ChatStream, description = "Chunks tagged with their model, a `done` event with the stored message id per model, then `[DONE]`"),
+    (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+    (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2002 empty messages, code=2003 targets not 2 to 3 distinct models of the catalogue)"),
+    (status = 403, content_type = "application/json", body = ErrorResponse, description = "LLM provider disabled by admin (code=4003)"),
+    (status = 404, content_type = "application/json", body = ErrorResponse, description = "Conversation not found / DB not found (code=5003)"),
+    (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    ),
+)]
+pub async fn handle_chat_compare(
+  claims:Claims,
+  State(app_state): State<SharedState>,
+  Json(req):Json<ChatCompareRequest>
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,AppError>{
+ let targets = compare_targets(&req.targets, &ModelsResponse::default())?;
+ let openai_settings = app_state
+    .settings
+    .openai
+    .read()
+    .await
+    .clone();
+ let anthropic_settings = app_state
+    .settings
+    .anthropic
+    .read()
+    .await
+    .clone();
+ let mut provider_configs = Vec::new();
+ for target in &targets {
+    provider_configs.push(select_provider(&target.provider, Some(target.model.clone()), &openai_settings, &anthropic_settings)?.0);
+ }
+ let temperature = req.temperature;
+ let web_search = req.web_search;
+ let chat_req = ChatInitRequest {
+    provider:Some(targets[0].provider.clone()),
+    model_name:Some(targets[0].model.clone()),
+    config:None,
+    web_search,
+    selected_tools:req.selected_tools,
+    conversation_id:req.conversation_id,
+    messages:req.messages,
+    temperature,
+    prompt_template_id:req.prompt_template_id,
+    assistant_id:None,
+    collection_ids:req.collection_ids,
+ };
+ // Compared models answer with the assistant instructions and files, but are not replaced by its model
+ let assistant = resolve_chat_assistant(&app_state.database, claims.user_id, req.conversation_id, None)
+    .await
+    .map_err(|e| {
+       eprintln!("DB get one error {:?}", e);
+       AppError::DbTimeout})?;
+ // Answers are siblings replying to the same message, the title is generated with the first engine
+ let prepared = prepare_conversation(&claims, &app_state, chat_req, &targets[0], &provider_configs[0], BranchParent::ActiveMessage, assistant.as_ref()).await?;
+ let conversation_id = prepared.conversation_id;
+ let previous_message_id = prepared.previous_message_id;
+ let answer_metadata = with_citations(Some(json!({"compare":true})), &prepared.citations);
+ let opened = futures_util::future::join_all(targets
+    .iter()
+    .map(|target| open_target(&app_state, &claims.user_id, target, temperature, &prepared.prompts, web_search)))
+    .await;
+ if opened.iter().all(Option::is_none) {
+    return Err(AppError::ServiceTemporarilyUnavailable);
+ }
+ let mut parsers = Vec::new();
+ let mut unavailable = Vec::new();
+ let mut streams = Vec::new();
+ for (index, provider_stream) in opened.into_iter().enumerate() {
+    match provider_stream {
+        Some(ProviderStream { events, parser }) => {
+            parsers.push(Some(parser));
+            streams.push(events.map(move |event| (index, event)).boxed());
+        },
+        None => {
+            parsers.push(None);
+            unavailable.push(index);
+        }
+    }
+ }
+ let mut events = stream::select_all(streams);
+
+ let sse_stream = async_stream::try_stream! {
+    let mut answers = targets.iter().map(|_| StreamedAnswer::default()).collect::<Vec<StreamedAnswer>>();
+    let mut active_set = false;
+
+    for index in unavailable {
+        let error = json!({
+            "id":conversation_id,
+            "provider":targets[index].provider.clone(),
+            "model":targets[index].model.clone(),
+        });
+        yield Event::default().event("error").data(error.to_string());
+    }
+    while let Some((index, event)) = events.next().await {
+        let target = &targets[index];
+        match event {
+            Ok(ReqwestEvent::Open) => {
+                println!("SSE connection open for provider: {} model: {}", &target.provider, &target.model);
+            }
+            Ok(ReqwestEvent::Message(msg)) => {
+                let Some(parser) = parsers[index].as_ref() else { continue };
+                if let Some(text) = answers[index].apply(parser.parse_event(&msg.data)) {
+                    let chat_stream = ChatStream {
+                        id: conversation_id,
+                        role: None,
+                        content: Some(text),
+                        model: Some(target.model.clone()),
+                    };
+                    yield Event::default().event("chunk").data(chat_stream.to_string());
+                }
+            }
+            Err(reqwest_eventsource::Error::StreamEnded) => {
+                let answer = std::mem::take(&mut answers[index]);
+                let Ok(message_id) = answer
+                    .save(&app_state, conversation_id, previous_message_id, target, answer_metadata.clone())
+                    .await
+                    .map_err(|e| eprintln!("Db insert one error {:?}", e)) else { continue };
+                // The first finished answer continues the conversation until the user picks another one
+                if !active_set {
+                    active_set = set_active_message(&app_state.database, conversation_id, message_id)
+                        .await
+                        .map_err(|e| eprintln!("Db update many error {:?}", e))
+                        .is_ok();
+                }
+                let done = json!({
+                    "id":conversation_id,
+                    "provider":target.provider.clone(),
+                    "model":target.model.clone(),
+                    "messageId":message_id,
+                });
+                yield Event::default().event("done").data(done.to_string());
+            }
+            Err(e) => {
+                println!("Streaming error for provider:{} model:{} error:{}",target.provider,target.model,e);
+                let error = json!({
+                    "id":conversation_id,
+                    "provider":target.provider.clone(),
+                    "model":target.model.clone(),
+                });
+                yield Event::default().event("error").data(error.to_string());
+            }
+        }
+    }
+    yield Event::default().event("chunk").data("[DONE]");
+ };
+ let sse_response = Sse::new(sse_stream).keep_alive(KeepAlive::new());
+ Ok(sse_response)
+}
+
+/// Checks the provider is configured and enabled, and resolves its default model
+fn select_provider<'a>(
+  provider:&str,
+  model_name:Option<String>,
+  openai_settings:&'a Option<OpenaiSettings>,
+  anthropic_settings:&'a Option<AnthropicSettings>,
+) -> Result<(LlmProviderConfig<'a>,String),AppError>{
+ match provider {
      "openai" => {
          let settings = openai_settings
              .as_ref()
-             .ok_or(AppError::LlmProviderNotConfigured { provider:provider.clone() })?;
+             .ok_or(AppError::LlmProviderNotConfigured { provider:provider.to_string() })?;
          if !settings.is_enabled{
-            return Err(AppError::LlmProviderDisabledByAdmin {provider:provider.clone()});
+            return Err(AppError::LlmProviderDisabledByAdmin {provider:provider.to_string()});
          }
-         let model = req.model_name.clone().unwrap_or_else(|| "gpt-5.2".to_string());
-         (LlmProviderConfig::OpenAI(&settings), model)
+         let model = model_name.unwrap_or_else(|| "gpt-5.2".to_string());
+         Ok((LlmProviderConfig::OpenAI(settings), model))
      },
      "anthropic" => {
          let settings = anthropic_settings
            .as_ref()
-           .ok_or(AppError::LlmProviderNotConfigured { provider:provider.clone() })?;
+           .ok_or(AppError::LlmProviderNotConfigured { provider:provider.to_string() })?;
          if !settings.is_enabled{
-            return Err(AppError::LlmProviderDisabledByAdmin {provider:provider.clone()});
+            return Err(AppError::LlmProviderDisabledByAdmin {provider:provider.to_string()});
          }
-         let model = req.model_name.clone().unwrap_or_else(|| "claude-sonnet-4-5".to_string());
-         (LlmProviderConfig::Anthropic(&settings), model)
+         let model = model_name.unwrap_or_else(|| "claude-sonnet-4-5".to_string());
+         Ok((LlmProviderConfig::Anthropic(settings), model))
      },
-     _ => return Err(AppError::InvalidLlmProvider{provider:provider.clone()})
- };
- if let Some(conversation_id) = req.conversation_id{
-    chat_id = Some(Path(conversation_id));
+     _ => Err(AppError::InvalidLlmProvider{provider:provider.to_string()})
  }
+}
+
+/// Conversation ready to be answered, the new user messages are stored
+struct PreparedChat {
+    conversation_id:Uuid,
+    /// Branch sent to the model, new user messages included
+    prompts:Vec<Prompt>,
+    /// Message the answers reply to
+    previous_message_id:Option<Uuid>,
+    /// File chunks injected in the prompts
+    citations:Vec<Citation>,
+}
+
+/// Loads the conversation (or creates it with a generated title) and stores the new user messages
+async fn prepare_conversation(
+  claims:&Claims,
+  app_state:&SharedState,
+  req:ChatInitRequest,
+  requested:&FallbackTarget,
+  provider_config:&LlmProviderConfig<'_>,
+  parent:BranchParent,
+  assistant:Option<&assistants::Model>,
+) -> Result<PreparedChat,AppError>{
+ let selected_tools = req.selected_tools.clone().unwrap_or_default();
+ let chat_id = req.conversation_id;
  let mut metadata = json!({
     "webSearch":req.web_search,
     "selectedTools":selected_tools.clone()
  });
- let (conversation_id,mut previous_prompts) = if let Some(Path(conversation_id)) = chat_id {
-    let (mut conversation, previous_messages) = conversations::Entity::find_by_id(conversation_id.clone())
+ let requested_collection_ids = req.collection_ids
+   .iter()
+   .copied()
+   .collect::<HashSet<Uuid>>();
+ if !requested_collection_ids.is_empty() {
+    let collection_ids = requested_collection_ids.iter().copied().collect::<Vec<Uuid>>();
+    let collections = find_visible_collections(&app_state.database, claims.user_id, claims.role, &collection_ids)
+      .await
+      .map_err(|e| {
+         eprintln!("DB get many error {:?}", e);
+         AppError::DbTimeout})?;
+    if collections.len() != collection_ids.len() {
+       return Err(AppError::invalid_field("collection_ids"));
+    }
+    metadata[COLLECTION_IDS_KEY] = json!(collection_ids);
+ }
+ let (conversation_id,mut previous_prompts,parent_message_id,branch_collection_ids) = if let Some(conversation_id) = chat_id {
+    let mut conversation = conversations::Entity::find_by_id(conversation_id)
+       .filter(conversations::Column::UserId.eq(claims.user_id))
        .filter(conversations::Column::ArchivedAt.is_null())
-       .find_with_related(messages::Entity)
-       .order_by_desc(messages::Column::CreatedAt)
-       .filter(messages::Column::Deleted.eq(false))
-       .all(&app_state.database)
+       .one(&app_state.database)
        .await
        .map_err(|e| {
-          eprintln!("DB get one with many error {:?}", e);
+          eprintln!("DB get one error {:?}", e);
           AppError::DbTimeout})?
-       .into_iter()
-       .next()
        .ok_or(AppError::DbNotFound)?;
+    let parent_message_id = match parent {
+       BranchParent::ActiveMessage => conversation.active_message_id,
+       BranchParent::Message(message_id) => message_id,
+    };
+    let tree = MessageTree::load(&app_state.database, conversation_id)
+       .await
+       .map_err(|e| {
+          eprintln!("DB get many error {:?}", e);
+          AppError::DbTimeout})?;
     if !selected_tools.is_empty(){
       if let Some(json) =  conversation.metadata.as_mut() {
           // Update metadata TODO
@@ -148,53 +482,70 @@ pub async fn handle_chat_stream(
       .map_err(|e| {
           eprintln!("Db update one error {:?}", e);
           AppError::DbTimeout})?;
-   let previous_prompts = previous_messages
+   // Only the branch being answered is sent to the model
+   let path = tree.path(parent_message_id);
+   let branch_collection_ids = path
+     .iter()
+     .filter_map(|message| message.metadata.as_ref().and_then(|json| json.get(COLLECTION_IDS_KEY).cloned()))
+     .filter_map(|value| serde_json::from_value::<Vec<Uuid>>(value).ok())
+     .flatten()
+     .collect::<HashSet<Uuid>>();
+   let previous_prompts = path
      .into_iter()
      .map(|message| Prompt {
-        text: message.message_content,
+        text: message.message_content.clone(),
         role: message.role,
+        // Providers only take files in user messages, generated images stay attached to their answer
         files: message
             .metadata
+            .as_ref()
+            .filter(|_| message.role == ChatRole::User)
             .and_then(|json| json.get("files").cloned())
             .and_then(|files_val| serde_json::from_value::<Vec<File>>(files_val).ok())
             .unwrap_or_default(), // Vec::new()
     })
     .collect::<Vec<Prompt>>();
-  (conversation_id,previous_prompts)
+  (conversation_id,previous_prompts,parent_message_id,branch_collection_ids)
  }else{
   let first_prompt = req.messages
     .first()
     .map(|message| message.content.clone())
     .ok_or(AppError::ValidationEmptyField { field: "messages" })?;
   let new_conversation_id = Uuid::new_v4();
-  let prompt_title_response = match &provider_config {
+  let prompt_title_response = match provider_config {
       LlmProviderConfig::OpenAI(settings) => {
           app_state.req_client
-              .openai_get_title(settings, first_prompt)
+              .openai_get_title(settings, first_prompt.clone())
               .await
       },
       LlmProviderConfig::Anthropic(settings) => {
           app_state.req_client
-              .anthropic_get_title(settings, first_prompt)
+              .anthropic_get_title(settings, first_prompt.clone())
               .await
       },
-  }.map_err(|e| {
-      eprintln!("title generation error {:?}", e);
-      AppError::DbTimeout
-  })?;
-   let title_generation_usage  = json!({
-       "model":get_title_generation_model(&provider),
-       "inputTokens":prompt_title_response.input_tokens,
-       "outputTokens":prompt_title_response.output_tokens,
-  });
+  };
   let mut new_metadata = metadata.clone();
-  new_metadata["titleGenerationUsage"] = title_generation_usage;
+  // A provider outage must not block the chat itself, fallback models may still answer
+  let title = match prompt_title_response {
+      Ok(prompt_title_response) => {
+          new_metadata["titleGenerationUsage"] = json!({
+              "model":get_title_generation_model(&requested.provider),
+              "inputTokens":prompt_title_response.input_tokens,
+              "outputTokens":prompt_title_response.output_tokens,
+          });
+          prompt_title_response.title
+      },
+      Err(e) => {
+          eprintln!("title generation error {:?}", e);
+          first_prompt.chars().take(FALLBACK_TITLE_LENGTH).collect()
+      },
+  };
   let new_conversation = conversations::ActiveModel{ 
     id:Set(new_conversation_id.clone()),
     user_id:Set(claims.user_id),
-    title: Set(Some(prompt_title_response.title)),
-    model_provider:Set(provider.clone()),
-    model_name:Set(model_name.clone()),
+    title: Set(Some(title)),
+    model_provider:Set(requested.provider.clone()),
+    model_name:Set(requested.model.clone()),
     created_at:Set(Utc::now()),
     updated_at: Set(Utc::now()),
     last_message_at:Set(Some(Utc::now())),
@@ -202,7 +553,9 @@ pub async fn handle_chat_stream(
     message_count:Set(req.messages.len() as i32),
     total_tokens: Set(0),
     total_cost:Set(Decimal::from(0)),
-    metadata:Set(Some(new_metadata))
+    metadata:Set(Some(new_metadata)),
+    active_message_id:Set(None),
+    assistant_id:Set(assistant.map(|assistant| assistant.id)),
    };
   new_conversation
     .insert(&app_state.database)
@@ -210,9 +563,9 @@ pub async fn handle_chat_stream(
     .map_err(|e| {
        eprintln!("Db insert one error {:?}", e);
        AppError::DbTimeout})?;
-    (new_conversation_id,Vec::new())
+    (new_conversation_id,Vec::new(),None,HashSet::new())
  };
- let mut previous_message_id = None;
+ let mut previous_message_id = parent_message_id;
  for message in &req.messages {
    let new_message_id = Uuid::new_v4();
    metadata["files"] = message.files
@@ -225,8 +578,8 @@ pub async fn handle_chat_stream(
      role:Set(message.role),
      deleted:Set(false),
      message_content:Set(message.content.clone()),
-     model_provider:Set(provider.clone()),
-     model_name:Set(model_name.clone()),
+     model_provider:Set(requested.provider.clone()),
+     model_name:Set(requested.model.clone()),
      request_id:Set(None),
      request_tokens:Set(0),
      response_tokens:Set(0),
@@ -248,6 +601,18 @@ pub async fn handle_chat_stream(
         eprintln!("Db one insert error {:?}", e);
         AppError::DbTimeout})?;
  }
+ if let Some(message_id) = previous_message_id {
+    set_active_message(&app_state.database, conversation_id, message_id)
+      .await
+      .map_err(|e| {
+          eprintln!("Db update many error {:?}", e);
+          AppError::DbTimeout})?;
+ }
+ if let Some(template_id) = req.prompt_template_id {
+    let _ = increment_template_usage(&app_state.database, claims.user_id, template_id)
+      .await
+      .map_err(|e| eprintln!("Db update many error {:?}", e));
+ }
  
  let current_prompts:Vec<Prompt> = req.messages
    .into_iter()
@@ -256,144 +621,385 @@ pub async fn handle_chat_stream(
     })
    .collect();
  previous_prompts.extend(current_prompts);
- // Create event source based on provider
- let mut event_source = match &provider_config {
-     LlmProviderConfig::OpenAI(settings) => {
-         app_state.req_client
+ // Instructions are not stored with the messages so edits apply to the next turns of every chat
+ let instructions = load_instructions(&app_state.database, claims.user_id)
+   .await
+   .map_err(|e| {
+      eprintln!("DB get one error {:?}", e);
+      AppError::DbTimeout})?;
+ if let Some(system_prompt) = compose_system_prompt(&instructions) {
+    previous_prompts.insert(0, Prompt { text:system_prompt, role:ChatRole::System, files:Vec::new() });
+ }
+ if let Some(assistant) = assistant {
+    let position = previous_prompts
+      .iter()
+      .take_while(|prompt| prompt.role == ChatRole::System)
+      .count();
+    if !assistant.system_prompt.trim().is_empty() {
+       previous_prompts.insert(position, Prompt { text:assistant.system_prompt.clone(), role:ChatRole::System, files:Vec::new() });
+    }
+    let assistant_files = get_assistant_files(&app_state.database, assistant)
+      .await
+      .map_err(|e| {
+         eprintln!("DB get many error {:?}", e);
+         AppError::DbTimeout})?;
+    // Providers only take files in user messages
+    if let Some(prompt) = previous_prompts.iter_mut().find(|prompt| prompt.role == ChatRole::User) {
+       prompt.files.splice(0..0, assistant_files);
+    }
+ }
+ // Collections attached earlier in the branch stay searched, unless no longer shared with the user
+ let collection_ids = branch_collection_ids
+   .union(&requested_collection_ids)
+   .copied()
+   .collect::<Vec<Uuid>>();
+ let collection_files = get_visible_collection_files(&app_state.database, claims.user_id, claims.role, &collection_ids)
+   .await
+   .map_err(|e| {
+      eprintln!("DB get many error {:?}", e);
+      AppError::DbTimeout})?;
+ let citations = retrieve_context(app_state, claims.user_id, &mut previous_prompts, collection_files).await;
+ Ok(PreparedChat { conversation_id, prompts:previous_prompts, previous_message_id, citations })
+}
+
+/// Events of one provider stream, the event consumed while probing the provider is replayed first
+struct ProviderStream {
+    events:BoxStream<'static,Result<ReqwestEvent,reqwest_eventsource::Error>>,
+    parser:Box<dyn StreamParser>,
+}
+
+/// Opens the stream of one model, retrying per the engine http policy. `None` when the model is unavailable.
+async fn open_target(
+  app_state:&SharedState,
+  user_id:&Uuid,
+  target:&FallbackTarget,
+  temperature:Option<f32>,
+  prompts:&[Prompt],
+  web_search:bool,
+) -> Option<ProviderStream>{
+ let mut attempt = 0;
+ // Cached OpenAI file ids are trusted until they expire, a rejected request uploads the files again once
+ let mut reupload_files = false;
+ let has_openai_files = target.provider == "openai" && prompts.iter().any(|prompt| !prompt.files.is_empty());
+ loop {
+    let (mut event_source, parser, http_policy) = match open_event_source(
+        app_state,
+        user_id,
+        target,
+        temperature,
+        prompts.to_vec(),
+        web_search,
+        reupload_files,
+      ).await {
+        Ok(opened) => opened,
+        Err(e) => {
+          eprintln!("event source loading error {} for llm provider {} model {}", e, &target.provider, &target.model);
+          return None;
+        }
+    };
+    match first_event(&mut event_source, parser.as_ref(), &http_policy).await {
+        FirstEvent::Rejected(_) if has_openai_files && !reupload_files => {
+          event_source.close();
+          // The provider answered, a half open breaker lets the retry through
+          http_policy.circuit_breaker.record_success();
+          reupload_files = true;
+        },
+        FirstEvent::Ready(event) | FirstEvent::Rejected(event) => {
+          http_policy.circuit_breaker.record_success();
+          let events = stream::iter(Some(event)).chain(event_source).boxed();
+          return Some(ProviderStream { events, parser });
+        },
+        FirstEvent::Unavailable { retry_after } => {
+          event_source.close();
+          http_policy.circuit_breaker.record_failure();
+          let delay = http_policy.backoff(attempt, retry_after)?;
+          tokio::time::sleep(delay).await;
+          attempt += 1;
+        }
+    }
+ }
+}
+
+/// Answer accumulated from the parsed events of a provider stream
+#[derive(Default)]
+struct StreamedAnswer {
+    content:String,
+    request_tokens:i32,
+    response_tokens:i32,
+    total_tokens:i32,
+    request_id:Option<String>,
+}
+
+impl StreamedAnswer {
+    /// Accumulates the event and returns the text to forward to the client, if any
+    fn apply(&mut self,parse_result:StreamParseResult) -> Option<String> {
+        match parse_result {
+            StreamParseResult::TextDelta { text, request_id } => {
+                self.content.push_str(&text);
+                if request_id.is_some() {
+                    self.request_id = request_id;
+                }
+                return Some(text);
+            }
+            StreamParseResult::TokenUsage{ request_id, input_tokens, output_tokens, total_tokens } => {
+                if let Some(tokens) = input_tokens {
+                    self.request_tokens = tokens as i32;
+                }
+                if let Some(tokens) = output_tokens {
+                    self.response_tokens = tokens as i32;
+                }
+                if let Some(tokens) = total_tokens {
+                    self.total_tokens = tokens as i32;
+                }
+                self.request_id = request_id;
+            }
+            StreamParseResult::MessageStart { request_id, input_tokens, output_tokens } => {
+                if let Some(tokens) = input_tokens {
+                    self.request_tokens = tokens as i32;
+                }
+                if let Some(tokens) = output_tokens {
+                    self.response_tokens = tokens as i32;
+                }
+                self.request_id = Some(request_id);
+            }
+            StreamParseResult::ToolInput { .. } => {
+                // Tool input streaming - accumulate for tool calls (future support)
+            }
+            StreamParseResult::Error { error_type, message } => {
+                eprintln!("Stream error: {} - {}", error_type, message);
+            }
+            StreamParseResult::None => {}
+        }
+        None
+    }
+
+    /// Stores the answer as the assistant reply to `previous_message_id` and returns its id
+    async fn save(
+      self,
+      app_state:&SharedState,
+      conversation_id:Uuid,
+      previous_message_id:Option<Uuid>,
+      serving:&FallbackTarget,
+      metadata:Option<serde_json::Value>,
+    ) -> Result<Uuid,DbErr>{
+        let total_tokens = if self.total_tokens == 0 {
+            self.request_tokens + self.response_tokens
+        } else {
+            self.total_tokens
+        };
+        println!("Stream ended for provider: {} input tokens: {} output_tokens: {} total_tokens: {}", &serving.provider,self.request_tokens,self.response_tokens,total_tokens);
+        let new_llm_message_id = Uuid::new_v4();
+        let new_llm_message = messages::ActiveModel {
+            id: Set(new_llm_message_id),
+            conversation_id: Set(conversation_id),
+            previous_message_id: Set(previous_message_id),
+            deleted: Set(false),
+            role: Set(ChatRole::Assistant),
+            message_content: Set(self.content),
+            model_provider: Set(serving.provider.clone()),
+            model_name: Set(serving.model.clone()),
+            request_id: Set(self.request_id),
+            request_tokens: Set(self.request_tokens),
+            response_tokens: Set(self.response_tokens),
+            tools_calls: Set(Vec::new()),
+            tools_results: Set(Vec::new()),
+            created_at: Set(Utc::now()),
+            updated_at: Set(Utc::now()),
+            total_tokens: Set(total_tokens),
+            latency: Set(0),
+            cost: Set(Decimal::from(0)),
+            metadata: Set(metadata),
+        };
+        new_llm_message
+            .insert(&app_state.database)
+            .await?;
+     Ok(new_llm_message_id)
+    }
+}
+
+/// Fallback chain configured on the engine of the requested provider for this model
+async fn get_fallback_chain(
+  app_state:&SharedState,
+  claims:&Claims,
+  provider:&str,
+  model_name:&str,
+) -> Result<Vec<FallbackTarget>,AppError>{
+ let mut selector = ai_engines::Entity::find()
+    .filter(ai_engines::Column::EngineKey.eq(provider.to_lowercase()));
+ if let Some(org_id) = claims.org_id {
+    selector = selector.filter(ai_engines::Column::OrgId.eq(org_id));
+ }
+ let fallback_chain = selector
+    .order_by_desc(ai_engines::Column::CreatedAt)
+    .one(&app_state.database)
+    .await
+    .map_err(|e| {
+        eprintln!("DB get one error {:?}", e);
+        AppError::DbTimeout})?
+    .and_then(|ai_engine| parse_fallback_chains(&ai_engine.fallback_chains).remove(model_name))
+    .unwrap_or_default();
+ Ok(fallback_chain)
+}
+
+/// Opens the event source, with its stream parser and http policy, for one target of the chain
+async fn open_event_source(
+  app_state:&SharedState,
+  user_id:&Uuid,
+  target:&FallbackTarget,
+  temperature:Option<f32>,
+  mut prompts:Vec<Prompt>,
+  web_search:bool,
+  reupload_files:bool,
+) -> Result<(EventSource,Box<dyn StreamParser>,HttpPolicy),Error>{
+ // Unknown models keep receiving PDFs as files, as before capabilities were checked
+ let model_info = find_model(&target.provider, &target.model);
+ let supports_pdf_native = model_info
+    .as_ref()
+    .map(|model_info| model_info.supports_pdf_native)
+    .unwrap_or(true);
+ let max_image_dimension = model_info.and_then(|model_info| model_info.max_image_dimension);
+ inline_extracted_files(&app_state.database, *user_id, supports_pdf_native, &mut prompts).await?;
+ match target.provider.as_str() {
+     "openai" => {
+         let settings = app_state
+            .settings
+            .openai
+            .read()
+            .await
+            .clone()
+            .filter(|settings| settings.is_enabled)
+            .ok_or(anyhow!("llm provider not configured or disabled"))?;
+         for prompt in &mut prompts {
+            for file in &mut prompt.files {
+               let owner_id = file.owner_id.unwrap_or(*user_id);
+               file.openai_id = get_or_upload_openai_file(app_state, &settings, file, &owner_id, max_image_dimension, reupload_files)
+                 .await
+                 .map_err(|e| eprintln!("openai file upload error {e} for file {}", file.id))
+                 .ok();
+            }
+         }
+         let event_source = app_state.req_client
              .openai_chat_stream(
-                settings, model_name.clone(),
-                req.temperature,
-                previous_prompts,
-                &claims.user_id,
-                req.web_search
+                &settings,
+                target.model.clone(),
+                temperature,
+                prompts,
+                web_search
               )
-             .await
+             .await?;
+         Ok((event_source,Box::new(OpenaiStreamParser::new()),settings.http_policy()))
      },
-     LlmProviderConfig::Anthropic(settings) => {
-         app_state.req_client
+     "anthropic" => {
+         let settings = app_state
+            .settings
+            .anthropic
+            .read()
+            .await
+            .clone()
+            .filter(|settings| settings.is_enabled)
+            .ok_or(anyhow!("llm provider not configured or disabled"))?;
+         // Anthropic takes files inline
+         for prompt in &mut prompts {
+            for file in &mut prompt.files {
+               let owner_id = file.owner_id.unwrap_or(*user_id);
+               match get_user_file_binary(app_state, file.id, &owner_id, max_image_dimension).await {
+                  Ok(attachment) => {
+                     // Normalized images may have been re-encoded to another type
+                     file.content_type = attachment.content_type.clone();
+                     file.base64 = attachment.get_base64();
+                  },
+                  Err(e) => eprintln!("file storage error {e} for file {}", file.id),
+               }
+            }
+         }
+         let event_source = app_state.req_client
              .anthropic_chat_stream(
-                 settings,
-                 model_name.clone(),
+                 &settings,
+                 target.model.clone(),
                  ANTHROPIC_DEFAULT_MAX_TOKENS,
-                 req.temperature,
-                 previous_prompts,
+                 temperature,
+                 prompts,
                  web_search,
-                 &claims.user_id,
               )
-              .await
+              .await?;
+         Ok((event_source,Box::new(AnthropicStreamParser::new()),settings.http_policy()))
      },
- }.map_err(|e| {
-     eprintln!("event source loading error {} for llm provider {}", e, &provider);
-     AppError::ServiceTemporarilyUnavailable
- })?;
- // Create stream parser based on provider
- let stream_parser: Box<dyn StreamParser> = match provider_config {
-     LlmProviderConfig::OpenAI(_) => Box::new(OpenaiStreamParser::new()),
-     LlmProviderConfig::Anthropic(_) => Box::new(AnthropicStreamParser::new()),
- };
+     _ => Err(anyhow!("invalid llm provider")),
+ }
+}
 
- let sse_stream = async_stream::try_stream! {
-    let mut message_content = String::new();
-    let mut request_tokens = 0;
-    let mut response_tokens = 0;
-    let mut total_tokens = 0;
-    let mut request_id: Option<String> = None;
+enum FirstEvent {
+    Ready(Result<ReqwestEvent,reqwest_eventsource::Error>),
+    /// Model unavailable, retry after the delay asked by the provider if any, or fail over
+    Unavailable { retry_after:Option<Duration> },
+    /// Request refused with 400/404, a cached provider file id may no longer exist
+    Rejected(Result<ReqwestEvent,reqwest_eventsource::Error>),
+}
 
-    while let Some(event) = event_source.next().await {
-        match event {
-            Ok(ReqwestEvent::Open) => {
-                println!("SSE connection open for provider: {}", &provider);
+/// Waits for the first meaningful event of the stream, up to the engine timeout.
+/// Connection failures, timeouts, 429/5xx and overload error events make the model unavailable.
+async fn first_event(
+  event_source:&mut EventSource,
+  stream_parser:&dyn StreamParser,
+  http_policy:&HttpPolicy,
+) -> FirstEvent{
+ let unavailable = FirstEvent::Unavailable { retry_after:None };
+ loop {
+    let event = match tokio::time::timeout(http_policy.timeout, event_source.next()).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return unavailable,
+        Err(_) => {
+            eprintln!("Stream first event timeout after {:?}", http_policy.timeout);
+            return unavailable;
+        }
+    };
+    match &event {
+        Ok(ReqwestEvent::Open) => continue,
+        Ok(ReqwestEvent::Message(msg)) => {
+            if let StreamParseResult::Error { error_type, message } = stream_parser.parse_event(&msg.data)
+              && UNAVAILABLE_ERROR_TYPES.contains(&error_type.as_str()) {
+                eprintln!("Stream error: {} - {}", error_type, message);
+                return unavailable;
             }
-            Ok(ReqwestEvent::Message(msg)) => {
-                let parse_result = stream_parser.parse_event(&msg.data);
+            return FirstEvent::Ready(event);
+        },
+        Err(reqwest_eventsource::Error::InvalidStatusCode(status, response)) if is_retryable_status(*status) => {
+            return FirstEvent::Unavailable { retry_after:parse_retry_after(response.headers()) };
+        },
+        Err(reqwest_eventsource::Error::InvalidStatusCode(status, _)) if matches!(*status, StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND) => {
+            return FirstEvent::Rejected(event);
+        },
+        Err(reqwest_eventsource::Error::Transport(e)) => {
+            eprintln!("Stream transport error: {}", e);
+            return unavailable;
+        },
+        Err(_) => return FirstEvent::Ready(event),
+    }
+ }
+}
 
-                match &parse_result {
-                    StreamParseResult::TextDelta { text, request_id: rid } => {
-                        message_content.push_str(text);
-                        if let Some(id) = rid {
-                            request_id = Some(id.clone());
-                        }
-                        let chat_stream = ChatStream {
-                            id: conversation_id.clone(),
-                            role: None,
-                            content: Some(text.clone()),
-                        };
-                        yield Event::default().event("chunk").data(chat_stream.to_string());
-                    }
-                    StreamParseResult::TokenUsage{ request_id:req_id,input_tokens, output_tokens, total_tokens:t_tokens} => {
-                       if let Some(tokens) = input_tokens {
-                         request_tokens = tokens.clone() as i32;
-                       }
-                       if let Some(tokens) = output_tokens {
-                         response_tokens = tokens.clone() as i32;
-                       }
-                       if let Some(tokens) = t_tokens{
-                         total_tokens = tokens.clone() as i32;
-                       }
-                       request_id = req_id.clone();
-                    }
-                    StreamParseResult::MessageStart { request_id:req_id,input_tokens,output_tokens} => {
-                       if let Some(tokens) = input_tokens {
-                         request_tokens = tokens.clone() as i32;
-                       }
-                       if let Some(tokens) = output_tokens {
-                         response_tokens = tokens.clone() as i32;
-                       }
-                      request_id = Some(req_id.clone());
-                    }
-                    StreamParseResult::ToolInput { .. } => {
-                        // Tool input streaming - accumulate for tool calls (future support)
-                    }
-                    StreamParseResult::Error { error_type, message } => {
-                        eprintln!("Stream error: {} - {}", error_type, message);
-                    }
-                    StreamParseResult::None => {}
-                }
-            }
-            Err(e) => {
-                match e {
-                  reqwest_eventsource::Error::StreamEnded => {
-                      if total_tokens == 0 {
-                        total_tokens = request_tokens + response_tokens;
-                      }
-                      println!("Stream ended for provider: {} input tokens: {} output_tokens: {} total_tokens: {}", &provider,request_tokens,response_tokens,total_tokens);
-                      let new_llm_message = messages::ActiveModel {
-                         id: Set(Uuid::new_v4()),
-                         conversation_id: Set(conversation_id.clone()),
-                         previous_message_id: Set(previous_message_id),
-                         deleted: Set(false),
-                         role: Set(ChatRole::Assistant),
-                         message_content: Set(message_content),
-                         model_provider: Set(provider.clone()),
-                         model_name: Set(model_name.clone()),
-                         request_id: Set(request_id),
-                         request_tokens: Set(request_tokens),
-                         response_tokens: Set(response_tokens),
-                         tools_calls: Set(Vec::new()),
-                         tools_results: Set(Vec::new()),
-                         created_at: Set(Utc::now()),
-                         updated_at: Set(Utc::now()),
-                         total_tokens: Set(total_tokens),
-                         latency: Set(0),
-                         cost: Set(Decimal::from(0)),
-                         metadata: Set(None),
-                    };
-                    new_llm_message
-                        .insert(&app_state.database)
-                        .await
-                        .expect("failed to insert llm response in table messages");
-                    yield Event::default().event("chunk").data("[DONE]");
-                    break;
-                    },
-                    _ => {
-                        println!("Streaming error for provider:{} error:{}",provider,e.to_string());
-                        break;
-                    }
-                };
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(provider:&str,model_name:&str) -> CompareTarget {
+        CompareTarget { provider:provider.to_string(), model_name:model_name.to_string() }
+    }
+
+    #[test]
+    fn compare_targets_are_distinct_catalogue_models() {
+        let catalogue = ModelsResponse::default();
+        let targets = compare_targets(&[target("OpenAI", "gpt-5.2"), target("anthropic", " claude-sonnet-4-5 ")], &catalogue).unwrap();
+        assert_eq!(targets[0].provider, "openai");
+        assert_eq!(targets[1].model, "claude-sonnet-4-5");
+        // Fewer than 2 or more than 3 targets
+        assert!(compare_targets(&[target("openai", "gpt-5.2")], &catalogue).is_err());
+        let four = [target("openai", "gpt-5.2"), target("anthropic", "claude-sonnet-4-5"), target("openai", "gpt-5.2"), target("anthropic", "claude-sonnet-4-5")];
+        assert!(compare_targets(&four, &catalogue).is_err());
+        // The same model twice, once differently cased
+        assert!(compare_targets(&[target("openai", "gpt-5.2"), target("OPENAI", "gpt-5.2")], &catalogue).is_err());
+        // Models or providers outside the catalogue
+        assert!(compare_targets(&[target("openai", "gpt-5.2"), target("openai", "gpt-unknown")], &catalogue).is_err());
+        assert!(compare_targets(&[target("openai", "gpt-5.2"), target("mistral", "claude-sonnet-4-5")], &catalogue).is_err());
     }
- };
- let sse_response = Sse::new(sse_stream).keep_alive(KeepAlive::new());
- Ok(sse_response)
 }
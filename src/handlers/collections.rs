@@ -0,0 +1,665 @@
+use std::collections::{HashMap, HashSet};
+use axum::{Json, extract::{Path, Query, State}};
+use chrono::Utc;
+use migration::extension::postgres::PgExpr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveEnum, ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, sea_query::{Alias, Expr, Func, extension::postgres::PgFunc}};
+use uuid::Uuid;
+use crate::{
+    auth::{claims::Claims, error::{AuthError, AuthErrorResponse}},
+    dto::{collections::{CollectionFile, CollectionFilesRequest, CollectionIngestion, CollectionQuery, CollectionRequest, CollectionResponse, CollectionUpdateRequest, FileIngestionStatus, IngestionStatus}, files::{File, FileIndexStatus}},
+    error::{AppError, ErrorResponse},
+    handlers::sharing::{get_user_department, is_admin, sharing_department},
+    models::{collection_files, collections::{self, CollectionScope}, files::{self, FileUploadStatus}, users::UserRole},
+    rag::retrieval::{get_file_index, is_searchable, spawn_index_file},
+    state::SharedState,
+};
+
+/// Key of the collections attached to a message in `messages.metadata`
+pub const COLLECTION_IDS_KEY:&str = "collectionIds";
+
+/// Collections a user can search: their own, and the organization or department ones open to their role
+fn visible_to(user_id:Uuid,department:Option<String>,role:UserRole) -> Condition {
+    let mut shared = Condition::any()
+        .add(collections::Column::Scope.eq(CollectionScope::Organization));
+    if let Some(department) = department {
+        shared = shared.add(Condition::all()
+            .add(collections::Column::Scope.eq(CollectionScope::Department))
+            .add(collections::Column::Department.eq(department)));
+    }
+    let allowed_role = Condition::any()
+        .add(Expr::expr(Func::cust(Alias::new("cardinality")).arg(Expr::col(collections::Column::Roles))).eq(0))
+        .add(Expr::val(role.to_value()).eq(PgFunc::any(Expr::col(collections::Column::Roles))));
+    Condition::any()
+        .add(collections::Column::UserId.eq(user_id))
+        .add(Condition::all().add(shared).add(allowed_role))
+}
+
+/// Collections among `collection_ids` visible to the user
+pub async fn find_visible_collections(db:&DatabaseConnection,user_id:Uuid,role:UserRole,collection_ids:&[Uuid]) -> Result<Vec<collections::Model>,DbErr> {
+    if collection_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let department = get_user_department(db, user_id).await?;
+    collections::Entity::find()
+        .filter(collections::Column::Id.is_in(collection_ids.to_vec()))
+        .filter(visible_to(user_id, department, role))
+        .all(db)
+        .await
+}
+
+/// Files of the collections visible to the user, read from the storage of the users who added them
+pub async fn get_visible_collection_files(db:&DatabaseConnection,user_id:Uuid,role:UserRole,collection_ids:&[Uuid]) -> Result<Vec<File>,DbErr> {
+    let collection_ids = find_visible_collections(db, user_id, role, collection_ids)
+        .await?
+        .into_iter()
+        .map(|collection| collection.id)
+        .collect::<Vec<Uuid>>();
+    let mut seen = HashSet::new();
+    Ok(load_collection_files(db, &collection_ids)
+        .await?
+        .into_values()
+        .flatten()
+        .filter(|(_, file)| seen.insert(file.id))
+        .map(|(_, file)| File {
+            id:file.id,
+            size:Some(file.size as usize),
+            owner_id:Some(file.user_id),
+            name:file.name,
+            content_type:file.content_type,
+            openai_id:None,
+            base64:None,
+        })
+        .collect())
+}
+
+/// Uploaded files of each collection, oldest addition first
+async fn load_collection_files(db:&DatabaseConnection,collection_ids:&[Uuid]) -> Result<HashMap<Uuid,Vec<(collection_files::Model,files::Model)>>,DbErr> {
+    let mut collection_files = HashMap::<Uuid,Vec<(collection_files::Model,files::Model)>>::new();
+    if collection_ids.is_empty() {
+        return Ok(collection_files);
+    }
+    let rows = collection_files::Entity::find()
+        .filter(collection_files::Column::CollectionId.is_in(collection_ids.to_vec()))
+        .find_also_related(files::Entity)
+        .order_by_asc(collection_files::Column::CreatedAt)
+        .all(db)
+        .await?;
+    for (collection_file, file) in rows {
+        if let Some(file) = file.filter(|file| file.status.is_available() || file.status == FileUploadStatus::Scanning) {
+            collection_files
+                .entry(collection_file.collection_id)
+                .or_default()
+                .push((collection_file, file));
+        }
+    }
+    Ok(collection_files)
+}
+
+fn file_ingestion_status(file:&files::Model) -> FileIngestionStatus {
+    match get_file_index(&file.metadata) {
+        Some(file_index) if is_searchable(&file_index) => FileIngestionStatus::Ready,
+        Some(file_index) if file_index.status == FileIndexStatus::Unsupported => FileIngestionStatus::Unsupported,
+        _ => FileIngestionStatus::Pending,
+    }
+}
+
+fn summarize_ingestion(statuses:&[FileIngestionStatus]) -> CollectionIngestion {
+    let count = |status:FileIngestionStatus| statuses.iter().filter(|file_status| **file_status == status).count();
+    let (ready, pending, unsupported) = (count(FileIngestionStatus::Ready), count(FileIngestionStatus::Pending), count(FileIngestionStatus::Unsupported));
+    let status = if statuses.is_empty() {
+        IngestionStatus::Empty
+    } else if pending > 0 {
+        IngestionStatus::Pending
+    } else if unsupported > 0 {
+        IngestionStatus::Partial
+    } else {
+        IngestionStatus::Ready
+    };
+    CollectionIngestion { status, total:statuses.len(), ready, pending, unsupported }
+}
+
+fn to_collection_response(collection:collections::Model,user_id:Uuid,collection_files:Vec<(collection_files::Model,files::Model)>) -> CollectionResponse {
+    let files = collection_files
+        .into_iter()
+        .map(|(collection_file, file)| CollectionFile {
+            ingestion:file_ingestion_status(&file),
+            id:file.id,
+            name:file.name,
+            content_type:file.content_type,
+            size:file.size,
+            added_at:collection_file.created_at,
+        })
+        .collect::<Vec<CollectionFile>>();
+    let statuses = files.iter().map(|file| file.ingestion).collect::<Vec<FileIngestionStatus>>();
+    CollectionResponse {
+        id:collection.id,
+        owned:collection.user_id == Some(user_id),
+        name:collection.name,
+        description:collection.description,
+        scope:collection.scope,
+        department:collection.department,
+        roles:collection.roles
+            .iter()
+            .filter_map(|role| UserRole::try_from_value(role).ok())
+            .collect(),
+        ingestion:summarize_ingestion(&statuses),
+        files,
+        created_at:collection.created_at,
+        updated_at:collection.updated_at,
+    }
+}
+
+async fn to_collection_responses(db:&DatabaseConnection,collections:Vec<collections::Model>,user_id:Uuid) -> Result<Vec<CollectionResponse>,DbErr> {
+    let collection_ids = collections.iter().map(|collection| collection.id).collect::<Vec<Uuid>>();
+    let mut collection_files = load_collection_files(db, &collection_ids).await?;
+    Ok(collections
+        .into_iter()
+        .map(|collection| {
+            let files = collection_files.remove(&collection.id).unwrap_or_default();
+            to_collection_response(collection, user_id, files)
+        })
+        .collect())
+}
+
+async fn get_collection_response(app_state:&SharedState,collection:collections::Model,user_id:Uuid) -> Result<CollectionResponse,AppError> {
+    to_collection_responses(&app_state.database, vec![collection], user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?
+        .pop()
+        .ok_or(AppError::DbNotFound)
+}
+
+async fn find_visible_collection(app_state:&SharedState,claims:&Claims,collection_id:Uuid) -> Result<collections::Model,AppError> {
+    find_visible_collections(&app_state.database, claims.user_id, claims.role, &[collection_id])
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?
+        .pop()
+        .ok_or(AppError::DbNotFound)
+}
+
+/// Creators curate their collections, admins curate every collection
+async fn find_modifiable_collection(app_state:&SharedState,claims:&Claims,collection_id:Uuid) -> Result<collections::Model,AppError> {
+    let collection = if is_admin(claims) {
+        collections::Entity::find_by_id(collection_id)
+            .one(&app_state.database)
+            .await
+            .map_err(|e| {
+                eprintln!("DB get one error {:?}", e);
+                AppError::DbTimeout})?
+            .ok_or(AppError::DbNotFound)?
+    } else {
+        find_visible_collection(app_state, claims, collection_id).await?
+    };
+    if collection.user_id != Some(claims.user_id) && !is_admin(claims) {
+        return Err(AppError::PermissionDenied);
+    }
+    Ok(collection)
+}
+
+fn role_names(roles:Vec<UserRole>) -> Vec<String> {
+    let mut role_names = roles
+        .into_iter()
+        .map(|role| role.to_value())
+        .collect::<Vec<String>>();
+    role_names.sort();
+    role_names.dedup();
+    role_names
+}
+
+/// Uploaded files of the user, all of them must exist
+async fn get_user_files(app_state:&SharedState,user_id:Uuid,file_ids:&[Uuid]) -> Result<Vec<files::Model>,AppError> {
+    if file_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let file_models = files::Entity::find()
+        .filter(files::Column::Id.is_in(file_ids.to_vec()))
+        .filter(files::Column::UserId.eq(user_id))
+        .filter(files::Column::Status.ne(FileUploadStatus::Deleted))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?;
+    if file_models.len() != file_ids.iter().collect::<HashSet<&Uuid>>().len() {
+        return Err(AppError::invalid_field("file_ids"));
+    }
+    Ok(file_models)
+}
+
+/// Links the files to the collection and indexes the ones never indexed
+async fn add_files(app_state:&SharedState,collection_id:Uuid,file_models:Vec<files::Model>) -> Result<(),AppError> {
+    if file_models.is_empty() {
+        return Ok(());
+    }
+    let linked = collection_files::Entity::find()
+        .filter(collection_files::Column::CollectionId.eq(collection_id))
+        .filter(collection_files::Column::FileId.is_in(file_models.iter().map(|file| file.id)))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?
+        .into_iter()
+        .map(|collection_file| collection_file.file_id)
+        .collect::<HashSet<Uuid>>();
+    let new_files = file_models
+        .into_iter()
+        .filter(|file| !linked.contains(&file.id))
+        .collect::<Vec<files::Model>>();
+    if new_files.is_empty() {
+        return Ok(());
+    }
+    let new_collection_files = new_files
+        .iter()
+        .map(|file| collection_files::ActiveModel {
+            id:Set(Uuid::new_v4()),
+            collection_id:Set(collection_id),
+            file_id:Set(file.id),
+            created_at:Set(Utc::now()),
+        });
+    collection_files::Entity::insert_many(new_collection_files)
+        .exec(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db insert many error {:?}", e);
+            AppError::DbTimeout})?;
+    for file in new_files {
+        if file_ingestion_status(&file) == FileIngestionStatus::Pending {
+            spawn_index_file(app_state.clone(), file);
+        }
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections",
+    tag = "collections",
+    params(
+        ("limit" = Option<u64>, Query, description = "Default value : 30"),
+        ("offset" = Option<u64>, Query, description = "Default value : 0"),
+        ("search" = Option<String>, Query, description = "Search in names and descriptions"),
+    ),
+    responses(
+        (status = 200, body = Vec<CollectionResponse>),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_collections(
+  claims:Claims,
+  Query(query):Query<CollectionQuery>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<Vec<CollectionResponse>>),AppError>{
+    let department = get_user_department(&app_state.database, claims.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?;
+    let mut select = collections::Entity::find()
+        .filter(visible_to(claims.user_id, department, claims.role));
+    if let Some(search) = query.search {
+        let pattern = format!("%{}%",search);
+        select = select.filter(Condition::any()
+            .add(collections::Column::Name.into_expr().ilike(pattern.clone()))
+            .add(collections::Column::Description.into_expr().ilike(pattern)));
+    }
+    let collections = select
+        .order_by_asc(collections::Column::Name)
+        .limit(query.limit.unwrap_or(30))
+        .offset(query.offset.unwrap_or(0))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?;
+    let response = to_collection_responses(&app_state.database, collections, claims.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/collections",
+    tag = "admin",
+    params(
+        ("limit" = Option<u64>, Query, description = "Default value : 30"),
+        ("offset" = Option<u64>, Query, description = "Default value : 0"),
+        ("search" = Option<String>, Query, description = "Search in names and descriptions"),
+    ),
+    responses(
+       (status = 200, body = Vec<CollectionResponse>),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_all_collections(
+    claims: Claims,
+    Query(query):Query<CollectionQuery>,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<CollectionResponse>>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let mut select = collections::Entity::find();
+    if let Some(search) = query.search {
+        let pattern = format!("%{}%",search);
+        select = select.filter(Condition::any()
+            .add(collections::Column::Name.into_expr().ilike(pattern.clone()))
+            .add(collections::Column::Description.into_expr().ilike(pattern)));
+    }
+    let collections = select
+        .order_by_asc(collections::Column::Name)
+        .limit(query.limit.unwrap_or(30))
+        .offset(query.offset.unwrap_or(0))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("db get many error: {e}");
+            AuthError::DbTimeout
+        })?;
+    let response = to_collection_responses(&app_state.database, collections, claims.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("db get many error: {e}");
+            AuthError::DbTimeout
+        })?;
+    Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/collections/{collection_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = Uuid, Path, description = "Collection id"),
+    ),
+    responses(
+        (status = 200, body = CollectionResponse),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Collection not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_collection_by_id(
+  claims:Claims,
+  Path(collection_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<CollectionResponse>),AppError>{
+    let collection = find_visible_collection(&app_state, &claims, collection_id).await?;
+    let response = get_collection_response(&app_state, collection, claims.user_id).await?;
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/collections",
+    tag = "collections",
+    request_body = CollectionRequest,
+    responses(
+        (status = 201, body = CollectionResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2001 missing department, code=2002 empty field, code=2003 unknown files)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Sharing with another department (code=1002)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn add_collection(
+  claims:Claims,
+  State(app_state): State<SharedState>,
+  Json(req):Json<CollectionRequest>
+) -> Result<(StatusCode,Json<CollectionResponse>),AppError>{
+    if req.name.trim().is_empty() {
+        return Err(AppError::empty_field("name"));
+    }
+    let scope = req.scope.unwrap_or(CollectionScope::User);
+    let user_department = get_user_department(&app_state.database, claims.user_id)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get one error {:?}", e);
+            AppError::DbTimeout})?;
+    let department = sharing_department(&claims, scope == CollectionScope::Department, req.department, user_department)?;
+    let file_models = get_user_files(&app_state, claims.user_id, &req.file_ids).await?;
+    let new_collection = collections::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        user_id:Set(Some(claims.user_id)),
+        name:Set(req.name.trim().to_string()),
+        description:Set(req.description.unwrap_or_default()),
+        scope:Set(scope),
+        department:Set(department),
+        roles:Set(role_names(req.roles)),
+        created_at:Set(Utc::now()),
+        updated_at:Set(Utc::now()),
+        metadata:Set(None),
+    };
+    let collection = new_collection
+        .insert(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db insert one error {:?}", e);
+            AppError::DbTimeout})?;
+    add_files(&app_state, collection.id, file_models).await?;
+    let response = get_collection_response(&app_state, collection, claims.user_id).await?;
+  Ok((StatusCode::CREATED,Json(response)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/collections/{collection_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = Uuid, Path, description = "Collection id"),
+    ),
+    request_body = CollectionUpdateRequest,
+    responses(
+        (status = 200, body = CollectionResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2001 missing department, code=2002 empty field)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Collection not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Collection not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn update_collection_by_id(
+  claims:Claims,
+  Path(collection_id):Path<Uuid>,
+  State(app_state): State<SharedState>,
+  Json(req):Json<CollectionUpdateRequest>
+) -> Result<(StatusCode,Json<CollectionResponse>),AppError>{
+    let collection = find_modifiable_collection(&app_state, &claims, collection_id).await?;
+    let current_scope = collection.scope;
+    let current_department = collection.department.clone();
+    let mut active_collection = collection.into_active_model();
+    if let Some(name) = req.name {
+        if name.trim().is_empty() {
+            return Err(AppError::empty_field("name"));
+        }
+        active_collection.name = Set(name.trim().to_string());
+    }
+    if let Some(description) = req.description {
+        active_collection.description = Set(description);
+    }
+    if let Some(roles) = req.roles {
+        active_collection.roles = Set(role_names(roles));
+    }
+    if req.scope.is_some() || req.department.is_some() {
+        let scope = req.scope.unwrap_or(current_scope);
+        let user_department = get_user_department(&app_state.database, claims.user_id)
+            .await
+            .map_err(|e| {
+                eprintln!("DB get one error {:?}", e);
+                AppError::DbTimeout})?;
+        let department = sharing_department(&claims, scope == CollectionScope::Department, req.department.or(current_department), user_department)?;
+        active_collection.scope = Set(scope);
+        active_collection.department = Set(department);
+    }
+    active_collection.updated_at = Set(Utc::now());
+    let collection = active_collection
+        .update(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db update one error {:?}", e);
+            AppError::DbTimeout})?;
+    let response = get_collection_response(&app_state, collection, claims.user_id).await?;
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/collections/{collection_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = Uuid, Path, description = "Collection id"),
+    ),
+    responses(
+        (status = 204),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Collection not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Collection not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn delete_collection_by_id(
+  claims:Claims,
+  Path(collection_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<StatusCode,AppError>{
+    let collection = find_modifiable_collection(&app_state, &claims, collection_id).await?;
+    // The files stay in the storage of their owners
+    collections::Entity::delete_by_id(collection.id)
+        .exec(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db delete one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/collections/{collection_id}/files",
+    tag = "collections",
+    params(
+        ("collection_id" = Uuid, Path, description = "Collection id"),
+    ),
+    request_body = CollectionFilesRequest,
+    responses(
+        (status = 200, body = CollectionResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2003 unknown files)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Collection not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Collection not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn add_collection_files(
+  claims:Claims,
+  Path(collection_id):Path<Uuid>,
+  State(app_state): State<SharedState>,
+  Json(req):Json<CollectionFilesRequest>
+) -> Result<(StatusCode,Json<CollectionResponse>),AppError>{
+    let collection = find_modifiable_collection(&app_state, &claims, collection_id).await?;
+    let file_models = get_user_files(&app_state, claims.user_id, &req.file_ids).await?;
+    add_files(&app_state, collection.id, file_models).await?;
+    let response = get_collection_response(&app_state, collection, claims.user_id).await?;
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/collections/{collection_id}/files/{file_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = Uuid, Path, description = "Collection id"),
+        ("file_id" = Uuid, Path, description = "File to remove from the collection"),
+    ),
+    responses(
+        (status = 204),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Collection not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Collection or file not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn delete_collection_file(
+  claims:Claims,
+  Path((collection_id,file_id)):Path<(Uuid,Uuid)>,
+  State(app_state): State<SharedState>
+) -> Result<StatusCode,AppError>{
+    let collection = find_modifiable_collection(&app_state, &claims, collection_id).await?;
+    let result = collection_files::Entity::delete_many()
+        .filter(collection_files::Column::CollectionId.eq(collection.id))
+        .filter(collection_files::Column::FileId.eq(file_id))
+        .exec(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db delete many error {:?}", e);
+            AppError::DbTimeout})?;
+    if result.rows_affected == 0 {
+        return Err(AppError::DbNotFound);
+    }
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/collections/{collection_id}/ingest",
+    tag = "collections",
+    params(
+        ("collection_id" = Uuid, Path, description = "Collection id"),
+    ),
+    responses(
+        (status = 202, body = CollectionResponse, description = "Indexing of the pending files started"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Collection not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Collection not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn ingest_collection(
+  claims:Claims,
+  Path(collection_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<CollectionResponse>),AppError>{
+    let collection = find_modifiable_collection(&app_state, &claims, collection_id).await?;
+    // Failed indexing is not recorded, pending files are the ones to retry
+    let collection_files = load_collection_files(&app_state.database, &[collection.id])
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?
+        .remove(&collection.id)
+        .unwrap_or_default();
+    for (_, file) in &collection_files {
+        if file_ingestion_status(file) == FileIngestionStatus::Pending {
+            spawn_index_file(app_state.clone(), file.clone());
+        }
+    }
+  Ok((StatusCode::ACCEPTED,Json(to_collection_response(collection, claims.user_id, collection_files))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ingestion_summary_reports_pending_before_partial() {
+        use FileIngestionStatus::*;
+        assert_eq!(summarize_ingestion(&[]).status, IngestionStatus::Empty);
+        assert_eq!(summarize_ingestion(&[Ready, Ready]).status, IngestionStatus::Ready);
+        assert_eq!(summarize_ingestion(&[Ready, Unsupported]).status, IngestionStatus::Partial);
+        assert_eq!(
+            summarize_ingestion(&[Ready, Pending, Unsupported]),
+            CollectionIngestion { status:IngestionStatus::Pending, total:3, ready:1, pending:1, unsupported:1 },
+        );
+    }
+}
@@ -1,31 +1,412 @@
-use std::{fs::{self, File}, io::Write, path::PathBuf};
-use anyhow::Error;
-use axum::{Json, body::Body, extract::{Path, Query, State}, response::{IntoResponse, Response}};
-use chrono::Utc;
+use anyhow::{Error, anyhow};
+use axum::{Json, body::Body, extract::{Multipart, Path, Query, State, multipart::Field}, response::{IntoResponse, Response}};
+use chrono::{DateTime, Utc};
 use migration::extension::postgres::PgExpr;
 use reqwest::StatusCode;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TryIntoModel};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, Order, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait, sea_query::Expr};
+use sha2::{Digest, Sha256};
+use serde_json::json;
 use uuid::Uuid;
-use crate::{auth::{claims::Claims, error::AuthErrorResponse}, dto::{common::{PaginationQuery, SortRule}, files::{Attachment, File as FileLocal, FilePaginatedResponse, FileResponse, FileUploadRequest}}, error::{AppError, ErrorResponse}, models::files::{self, FileUploadStatus}, state::SharedState};
+use crate::{auth::{claims::Claims, error::AuthErrorResponse}, config::setting::OpenaiSettings, dto::{common::{PaginationQuery, SortRule}, files::{Attachment, File as FileLocal, FileDeleteQuery, FileMultipartUpload, FilePaginatedResponse, FileResponse, FileImage, FileUpdateRequest, FileUploadRequest, ImageVariant, ProviderFile}}, error::{AppError, ErrorResponse}, handlers::{models::image_dimensions, storage::{limit_error, load_upload_policy, remaining_quota}}, jobs::{file_scan::{SCAN_KEY, get_file_scan, initial_status, spawn_process_file}, provider_files::delete_provider_files}, llm::provider::OpenaiApis, models::files::{self, FileUploadStatus, StorageBackend}, rag::extraction::{document_format, extract_file, extraction_metadata, get_file_extraction}, state::SharedState, storage::{file_key, variant_key, images::{is_normalized_type, normalize_image, variant_file_name}, upload::{SNIFF_LEN, allowed_max_bytes, sanitize_file_name, sniff_content_type}}};
 
-pub const LOCAL_FOLDER:&str = "/data/files";
+pub const PROVIDER_FILES_KEY:&str = "providerFiles";
+/// Key of the hex SHA-256 of the content in `files.metadata`
+pub const CHECKSUM_KEY:&str = "sha256";
+/// Key of the normalized variants of an image in `files.metadata`
+pub const IMAGE_KEY:&str = "image";
 
-pub fn get_local_path(file:&FileLocal,user_id: &Uuid) -> PathBuf {
-   let user_folder = format!("{}/{}/file/{}", LOCAL_FOLDER, user_id.to_string(),file.id.to_string());
-   let local_path = format!("{}/{}", user_folder,&file.name);
-   PathBuf::from(local_path)
+pub fn get_file_image(metadata:&Option<serde_json::Value>) -> Option<FileImage> {
+   metadata
+     .as_ref()
+     .and_then(|json| json.get(IMAGE_KEY))
+     .and_then(|value| serde_json::from_value::<FileImage>(value.clone()).ok())
 }
 
-pub fn get_file_binary(file:&FileLocal,user_id: &Uuid) -> Result<Attachment,Error> {
-   let path = get_local_path(file,user_id);
-   let buff = fs::read(&path)?;
+/// Content of a file for a provider. Images are sent as their normalized variant
+/// within `max_image_dimension`, without metadata.
+pub async fn get_file_binary(app_state:&SharedState,file_model:&files::Model,max_image_dimension:Option<u32>) -> Result<Attachment,Error> {
+   // Content waiting for or failing its malware scan never leaves the storage
+   if !file_model.status.is_available() {
+      return Err(anyhow!("file {} is {:?}",file_model.id,file_model.status));
+   }
+   let variant = get_file_image(&file_model.metadata)
+     .and_then(|image| image.variant_for(max_image_dimension).cloned());
+   let Some(variant) = variant else {
+      let buff = app_state
+        .file_storage
+        .get(file_model.storage_backend, &file_model.storage_key)
+        .await?;
+      return Ok(Attachment{
+        file:Some(buff),
+        name:file_model.name.clone(),
+        content_type:file_model.content_type.clone(),
+      });
+   };
+   let buff = app_state
+     .file_storage
+     .get(variant.storage_backend, &variant.storage_key)
+     .await?;
    Ok(Attachment{
     file:Some(buff),
-    name:file.name.clone(),
-    content_type:file.content_type.clone(),
+    name:variant_file_name(&file_model.name, &variant.content_type),
+    content_type:variant.content_type,
   })
 }
 
+/// Content of an uploaded file of the user, for providers taking files inline
+pub async fn get_user_file_binary(app_state:&SharedState,file_id:Uuid,user_id:&Uuid,max_image_dimension:Option<u32>) -> Result<Attachment,Error> {
+   let file_model = files::Entity::find_by_id(file_id)
+     .filter(files::Column::UserId.eq(*user_id))
+     .filter(files::Column::Status.is_in(FileUploadStatus::AVAILABLE))
+     .one(&app_state.database)
+     .await?
+     .ok_or(anyhow!("file {} not found for user {}",file_id,user_id))?;
+   get_file_binary(app_state, &file_model, max_image_dimension).await
+}
+
+pub fn get_provider_file(metadata:&Option<serde_json::Value>,provider:&str) -> Option<ProviderFile> {
+   metadata
+     .as_ref()
+     .and_then(|json| json.get(PROVIDER_FILES_KEY))
+     .and_then(|provider_files| provider_files.get(provider))
+     .and_then(|value| serde_json::from_value::<ProviderFile>(value.clone()).ok())
+}
+
+/// Returns the metadata with the provider entry replaced, or removed when `provider_file` is None
+pub fn set_provider_file(metadata:Option<serde_json::Value>,provider:&str,provider_file:Option<&ProviderFile>) -> serde_json::Value {
+   let mut metadata = metadata
+     .filter(|json| json.is_object())
+     .unwrap_or_else(|| json!({}));
+   let provider_files = &mut metadata[PROVIDER_FILES_KEY];
+   if !provider_files.is_object() {
+      *provider_files = json!({});
+   }
+   if let Some(provider_files) = provider_files.as_object_mut() {
+      match provider_file {
+         Some(provider_file) => {
+            provider_files.insert(provider.to_string(), json!(provider_file));
+         }
+         None => {
+            provider_files.remove(provider);
+         }
+      }
+   }
+   if metadata[PROVIDER_FILES_KEY].as_object().is_some_and(|provider_files| provider_files.is_empty())
+      && let Some(json) = metadata.as_object_mut() {
+      json.remove(PROVIDER_FILES_KEY);
+   }
+   metadata
+}
+
+/// Reuses the OpenAI file id cached on the file row until it expires, without asking OpenAI.
+/// `reupload` uploads again after OpenAI rejected the cached id.
+pub async fn get_or_upload_openai_file(app_state:&SharedState,openai_settings:&OpenaiSettings,file:&FileLocal,user_id:&Uuid,max_image_dimension:Option<u32>,reupload:bool) -> Result<String,Error> {
+   let file_model = files::Entity::find_by_id(file.id)
+     .filter(files::Column::UserId.eq(*user_id))
+     .filter(files::Column::Status.is_in(FileUploadStatus::AVAILABLE))
+     .one(&app_state.database)
+     .await?
+     .ok_or(anyhow!("file {} not found for user {}",file.id,user_id))?;
+   if let Some(provider_file) = get_provider_file(&file_model.metadata,"openai")
+      && !provider_file.is_expired() {
+      if !reupload {
+         return Ok(provider_file.file_id);
+      }
+      println!("openai file {} rejected, uploading {} again",provider_file.file_id,file.id);
+   }
+   let attachment = get_file_binary(app_state,&file_model,max_image_dimension).await?;
+   let uploaded = app_state
+     .req_client
+     .openai_upload_file(openai_settings,&attachment)
+     .await?;
+   let provider_file = ProviderFile {
+     file_id:uploaded.id,
+     uploaded_at:Utc::now(),
+     expires_at:uploaded.expires_at.and_then(|timestamp| DateTime::from_timestamp(timestamp,0)),
+   };
+   let metadata = set_provider_file(file_model.metadata.clone(),"openai",Some(&provider_file));
+   let mut active_model = file_model.into_active_model();
+   active_model.metadata = Set(Some(metadata));
+   active_model
+     .update(&app_state.database)
+     .await?;
+ Ok(provider_file.file_id)
+}
+
+pub fn get_file_checksum(metadata:&Option<serde_json::Value>) -> Option<String> {
+   metadata
+     .as_ref()
+     .and_then(|json| json.get(CHECKSUM_KEY))
+     .and_then(|value| value.as_str())
+     .map(str::to_string)
+}
+
+pub fn to_file_response(file_model:files::Model) -> FileResponse {
+   FileResponse {
+      download_url:format!("/files/{}/download",file_model.id),
+      extraction:get_file_extraction(&file_model.metadata),
+      sha256:get_file_checksum(&file_model.metadata),
+      scan:get_file_scan(&file_model.metadata),
+      thumbnail_url:get_file_image(&file_model.metadata).map(|_| format!("/files/{}/thumbnail",file_model.id)),
+      id:file_model.id,
+      name:file_model.name,
+      size:file_model.size,
+      content_type:file_model.content_type,
+      description:file_model.description,
+      url:file_model.url,
+      created_at:file_model.created_at,
+      updated_at:file_model.updated_at,
+      status:file_model.status,
+   }
+}
+
+/// Available file of the user with the same content, whose stored copy can be shared
+async fn find_duplicate(db:&DatabaseConnection,user_id:Uuid,sha256:&str,size:u64) -> Result<Option<files::Model>,DbErr> {
+   files::Entity::find()
+     .filter(files::Column::UserId.eq(user_id))
+     .filter(files::Column::Status.is_in(FileUploadStatus::AVAILABLE))
+     .filter(Expr::cust_with_values(format!(r#""metadata" ->> '{CHECKSUM_KEY}' = $1"#), [sha256]))
+     .filter(files::Column::Size.eq(size as i64))
+     .one(db)
+     .await
+}
+
+/// Records the file. The `duplicate` whose stored content it shares is locked first, so a purge
+/// running concurrently either sees the new row or removes the duplicate before, then `None` is returned.
+async fn insert_file(db:&DatabaseConnection,new_file:files::ActiveModel,duplicate_id:Option<Uuid>) -> Result<Option<files::Model>,DbErr> {
+   let transaction = db.begin().await?;
+   if let Some(duplicate_id) = duplicate_id {
+      let duplicate = files::Entity::find_by_id(duplicate_id)
+        .lock_shared()
+        .one(&transaction)
+        .await?;
+      if duplicate.is_none() {
+         return Ok(None);
+      }
+   }
+   let file_model = new_file.insert(&transaction).await?;
+   transaction.commit().await?;
+   Ok(Some(file_model))
+}
+
+/// Deletes the file row and tells whether another file still points at its stored content.
+/// Rows sharing the content are locked so no file can start sharing it meanwhile.
+async fn delete_file_row(db:&DatabaseConnection,file_model:&files::Model) -> Result<bool,DbErr> {
+   let transaction = db.begin().await?;
+   files::Entity::find()
+     .filter(files::Column::StorageBackend.eq(file_model.storage_backend))
+     .filter(files::Column::StorageKey.eq(&file_model.storage_key))
+     .lock_exclusive()
+     .all(&transaction)
+     .await?;
+   files::Entity::delete_by_id(file_model.id)
+     .exec(&transaction)
+     .await?;
+   let count = files::Entity::find()
+     .filter(files::Column::StorageBackend.eq(file_model.storage_backend))
+     .filter(files::Column::StorageKey.eq(&file_model.storage_key))
+     .count(&transaction)
+     .await?;
+   transaction.commit().await?;
+   Ok(count > 0)
+}
+
+async fn get_remaining_quota(app_state:&SharedState,user_id:Uuid) -> Result<Option<(u64,u64)>,AppError> {
+   remaining_quota(&app_state.database, user_id)
+     .await
+     .map_err(|e|{
+        eprintln!("db get one error: {e}");
+        AppError::DbTimeout
+     })
+}
+
+/// Maximum size of an upload of the detected type, when the upload policy accepts it
+async fn get_upload_max_bytes(app_state:&SharedState,content_type:&str) -> Result<u64,AppError> {
+   let policy = load_upload_policy(&app_state.database)
+     .await
+     .map_err(|e|{
+        eprintln!("db get one error: {e}");
+        AppError::DbTimeout
+     })?;
+   allowed_max_bytes(&policy, content_type, app_state.settings.storage.max_upload_bytes)
+}
+
+async fn delete_image_variants(app_state:&SharedState,variants:&[ImageVariant]) {
+   for variant in variants {
+      let _ = app_state
+         .file_storage
+         .delete(variant.storage_backend, &variant.storage_key)
+         .await
+         .map_err(|e| eprintln!("file storage delete error : {e}"));
+   }
+}
+
+/// Stores the variants of an uploaded image at each model resolution, and its
+/// thumbnail, next to it. Images that cannot be decoded are kept as uploaded.
+async fn store_image_variants(app_state:&SharedState,stored:&StoredFile,content:&[u8]) -> Option<FileImage> {
+   let content = content.to_vec();
+   let normalized = tokio::task::spawn_blocking(move || normalize_image(&content, &image_dimensions()))
+      .await
+      .map_err(Error::from)
+      .and_then(|normalized| normalized)
+      .map_err(|e| eprintln!("file {} image normalization error: {e}", stored.id))
+      .ok()?;
+   let encoded = normalized.variants
+      .into_iter()
+      .map(|variant| (variant.width.max(variant.height).to_string(), variant))
+      .chain([("thumbnail".to_string(), normalized.thumbnail)]);
+   let mut variants = Vec::new();
+   for (name, image) in encoded {
+      let storage_key = variant_key(&stored.storage_key, &name);
+      let size = image.bytes.len() as u64;
+      match app_state.file_storage.put(&storage_key, image.bytes, image.content_type).await {
+         Ok(storage_backend) => variants.push(ImageVariant {
+            storage_key,
+            storage_backend,
+            content_type:image.content_type.to_string(),
+            width:image.width,
+            height:image.height,
+            size,
+         }),
+         Err(e) => {
+            eprintln!("file storage error : {e}");
+            delete_image_variants(app_state, &variants).await;
+            return None;
+         },
+      }
+   }
+   let thumbnail = variants.pop()?;
+   Some(FileImage { width:normalized.width, height:normalized.height, variants, thumbnail })
+}
+
+/// File written to the storage, not recorded yet
+struct StoredFile {
+   id:Uuid,
+   name:String,
+   content_type:String,
+   size:u64,
+   sha256:String,
+   storage_key:String,
+   storage_backend:StorageBackend,
+}
+
+/// Extracts the text of the stored file, normalizes images, and records it, its scan and indexing
+/// run in the background. The extraction, image variants and scan verdict of a `duplicate` with the
+/// same content are reused. Fails when the duplicate was purged meanwhile, its content is gone.
+async fn save_stored_file(app_state:&SharedState,user_id:Uuid,stored:StoredFile,content:Option<Vec<u8>>,description:Option<String>,duplicate:Option<&files::Model>) -> Result<files::Model,AppError> {
+ let extracted = duplicate.and_then(|duplicate| {
+    get_file_extraction(&duplicate.metadata).map(|extraction| (duplicate.extracted_text.clone(), extraction))
+ });
+ let duplicate_image = duplicate.and_then(|duplicate| get_file_image(&duplicate.metadata));
+ let normalize = duplicate_image.is_none() && is_normalized_type(&stored.content_type);
+ let extract = extracted.is_none() && document_format(&stored.content_type, &stored.name).is_some();
+ // Streamed files are read back only when their text can be extracted or their image normalized
+ let content = match content {
+    Some(content) => content,
+    None if normalize || extract => app_state
+       .file_storage
+       .get(stored.storage_backend, &stored.storage_key)
+       .await
+       .map_err(|e|{
+          eprintln!("file storage error : {e}");
+          AppError::ServiceTemporarilyUnavailable
+       })?,
+    None => Vec::new(),
+ };
+ let image = match duplicate_image {
+    Some(image) => Some(image),
+    None if normalize => store_image_variants(app_state, &stored, &content).await,
+    None => None,
+ };
+ let (extracted_text, extraction) = match extracted {
+    Some(extracted) => extracted,
+    None => extract_file(content, &stored.content_type, &stored.name).await,
+ };
+ let mut metadata = extraction_metadata(&extraction);
+ metadata[CHECKSUM_KEY] = json!(stored.sha256);
+ if let Some(image) = &image {
+    metadata[IMAGE_KEY] = json!(image);
+ }
+ // Identical content keeps its verdict
+ let scan = duplicate.and_then(|duplicate| get_file_scan(&duplicate.metadata));
+ let status = initial_status(app_state, scan.as_ref());
+ if let Some(scan) = &scan {
+    metadata[SCAN_KEY] = json!(scan);
+ }
+ let new_file = files::ActiveModel{
+    id:Set(stored.id),
+    user_id:Set(user_id),
+    name: Set(stored.name),
+    content_type:Set(stored.content_type),
+    size:Set(stored.size as i64),
+    storage_key:Set(stored.storage_key),
+    storage_backend:Set(stored.storage_backend),
+    description:Set(description),
+    url:Set(None),
+    status:Set(status),
+    created_at:Set(Utc::now()),
+    updated_at:Set(Utc::now()),
+    metadata:Set(Some(metadata)),
+    extracted_text:Set(extracted_text),
+ };
+ let file_model = insert_file(&app_state.database, new_file, duplicate.map(|duplicate| duplicate.id))
+   .await
+   .map_err(|e|{
+      eprintln!("db insert one error: {e}");
+      AppError::DbTimeout
+    })?
+   .ok_or(AppError::ServiceTemporarilyUnavailable)?;
+ spawn_process_file(app_state.clone(), file_model.clone());
+ Ok(file_model)
+}
+
+/// Stores the content as a file of the user, with the checks and processing of an upload
+pub async fn store_file_content(app_state:&SharedState,user_id:Uuid,name:String,content:Vec<u8>,description:Option<String>) -> Result<files::Model,AppError> {
+ let size = content.len() as u64;
+ // The declared type is ignored, the content decides
+ let content_type = sniff_content_type(&content, &name);
+ let max_bytes = get_upload_max_bytes(app_state, &content_type).await?;
+ let quota = get_remaining_quota(app_state, user_id).await?;
+ if let Some(e) = limit_error(size, max_bytes, quota) {
+    return Err(e);
+ }
+ let sha256 = format!("{:x}", Sha256::digest(&content));
+ let duplicate = find_duplicate(&app_state.database, user_id, &sha256, size)
+    .await
+    .map_err(|e|{
+       eprintln!("db get one error: {e}");
+       AppError::DbTimeout
+    })?;
+ // Generate a unique local file ID
+ let local_file_id = Uuid::new_v4();
+ let (storage_key, storage_backend) = match &duplicate {
+    // Identical content is stored already
+    Some(duplicate) => (duplicate.storage_key.clone(), duplicate.storage_backend),
+    None => {
+       let storage_key = file_key(&user_id, &local_file_id);
+       let storage_backend = app_state
+          .file_storage
+          .put(&storage_key, content.clone(), &content_type)
+          .await
+          .map_err(|e|{
+             eprintln!("file storage error : {e}");
+             AppError::ServiceTemporarilyUnavailable
+          })?;
+       (storage_key, storage_backend)
+    },
+ };
+ let stored = StoredFile {
+    id:local_file_id,
+    name,
+    content_type,
+    size,
+    sha256,
+    storage_key,
+    storage_backend,
+ };
+ save_stored_file(app_state, user_id, stored, Some(content), description, duplicate.as_ref()).await
+}
+
 #[utoipa::path(
     post,
     path = "/files",
@@ -33,8 +414,11 @@ pub fn get_file_binary(file:&FileLocal,user_id: &Uuid) -> Result<Attachment,Erro
     request_body = FileUploadRequest,
     responses(
         (status = 200, body = FileResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "File name without any usable character (code=2003)"),
         (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
-        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000)"),
+        (status = 413, content_type = "application/json", body = ErrorResponse, description = "File above the maximum upload size (code=2004) or storage quota exceeded (code=1003)"),
+        (status = 415, content_type = "application/json", body = ErrorResponse, description = "Detected file type not allowed (code=2005)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or file storage unavailable (code=1000)"),
     ),
 )]
 pub async fn upload_file(
@@ -42,66 +426,167 @@ pub async fn upload_file(
    State(app_state):State<SharedState>,
    Json(req):Json<FileUploadRequest>
 ) -> Result<(StatusCode,Json<FileResponse>),AppError>{
- // Generate a unique local file ID
- let local_file_id = Uuid::new_v4();
- let user_folder = format!("{}/{}/file/{}", LOCAL_FOLDER, claims.user_id,local_file_id.to_string());
- // Ensure user folder exists
- let _ = fs::create_dir_all(&user_folder);
- let local_path = format!("{}/{}", user_folder,&req.attachment.name);
- // Save file locally with unique ID
- if let Ok(mut file_handle) = File::create(&local_path) {
-      if let Some(buffer) = &req.attachment.file {
-         if file_handle.write_all(buffer).is_ok() {
-          println!("Saved file {} locally as {}", &req.attachment.name, &user_folder)
-        }
-      }
- }
- let size = req.attachment
-    .file
-    .as_ref()
-    .map(|f| f.len())
-    .unwrap_or(0) as i64;
- let new_file = files::ActiveModel{
-    id:Set(local_file_id),
-    user_id:Set(claims.user_id),
-    name: Set(req.attachment.name.clone()),
-    content_type:Set(req.attachment.content_type.clone()),
-    size:Set(size),
-    local_path:Set(local_path),
-    description:Set(req.description),
-    url:Set(None),
-    status:Set(FileUploadStatus::Uploaded),
-    created_at:Set(Utc::now()),
-    updated_at:Set(Utc::now()),
-    metadata:Set(None), 
+ let name = sanitize_file_name(&req.attachment.name).ok_or(AppError::invalid_field("name"))?;
+ let content = req.attachment.file.unwrap_or_default();
+ let file_model = store_file_content(&app_state, claims.user_id, name, content, req.description).await?;
+ Ok((StatusCode::OK, Json(to_file_response(file_model))))
+}
+
+/// Streams the file part to the storage, hashing it and enforcing the type policy,
+/// size limit and quota on the way
+async fn stream_to_storage(app_state:&SharedState,user_id:Uuid,mut field:Field<'_>) -> Result<StoredFile,AppError> {
+ let name = field
+    .file_name()
+    .and_then(sanitize_file_name)
+    .ok_or(AppError::missing_field("file"))?;
+ let read_error = |e| {
+    eprintln!("multipart read error : {e}");
+    AppError::invalid_field("file")
  };
- new_file
-   .clone()
-   .insert(&app_state.database)
-   .await
-   .map_err(|e|{
-      eprintln!("db insert one error: {e}");
-      AppError::DbTimeout
+ // The type is detected from the first bytes, before anything is stored
+ let mut head = Vec::new();
+ while head.len() < SNIFF_LEN {
+    match field.chunk().await.map_err(read_error)? {
+       Some(chunk) => head.extend_from_slice(&chunk),
+       None => break,
+    }
+ }
+ let content_type = sniff_content_type(&head, &name);
+ let max_bytes = get_upload_max_bytes(app_state, &content_type).await?;
+ let quota = get_remaining_quota(app_state, user_id).await?;
+ let mut size = head.len() as u64;
+ if let Some(e) = limit_error(size, max_bytes, quota) {
+    return Err(e);
+ }
+ let id = Uuid::new_v4();
+ let storage_key = file_key(&user_id, &id);
+ let (mut writer, storage_backend) = app_state
+    .file_storage
+    .writer(&storage_key, &content_type)
+    .await
+    .map_err(|e|{
+       eprintln!("file storage error : {e}");
+       AppError::ServiceTemporarilyUnavailable
     })?;
- let file_model = new_file
-   .try_into_model()
-   .map_err(|e|{
-       eprintln!("file model parse error : {e}");
+ let mut hasher = Sha256::new();
+ let streamed = async {
+    let mut chunk = Some(axum::body::Bytes::from(head));
+    while let Some(bytes) = chunk {
+       hasher.update(&bytes);
+       writer.write(&bytes).await.map_err(|e|{
+          eprintln!("file storage error : {e}");
+          AppError::ServiceTemporarilyUnavailable
+       })?;
+       chunk = field.chunk().await.map_err(read_error)?;
+       if let Some(bytes) = &chunk {
+          size += bytes.len() as u64;
+          if let Some(e) = limit_error(size, max_bytes, quota) {
+             return Err(e);
+          }
+       }
+    }
+    Ok(())
+ }.await;
+ if let Err(e) = streamed {
+    let _ = writer
+       .abort()
+       .await
+       .map_err(|e| eprintln!("file storage abort error : {e}"));
+    return Err(e);
+ }
+ writer
+    .finish()
+    .await
+    .map_err(|e|{
+       eprintln!("file storage error : {e}");
+       AppError::ServiceTemporarilyUnavailable
+    })?;
+ Ok(StoredFile { id, name, content_type, size, sha256:format!("{:x}", hasher.finalize()), storage_key, storage_backend })
+}
+
+/// Reads the `file` and `description` parts, in any order
+async fn read_multipart_upload(app_state:&SharedState,user_id:Uuid,multipart:&mut Multipart,stored:&mut Option<StoredFile>) -> Result<Option<String>,AppError> {
+ let mut description = None;
+ while let Some(field) = multipart.next_field().await.map_err(|e|{
+    eprintln!("multipart read error : {e}");
+    AppError::invalid_field("file")
+ })? {
+    match field.name() {
+       Some("file") if stored.is_none() => *stored = Some(stream_to_storage(app_state, user_id, field).await?),
+       Some("file") => return Err(AppError::invalid_field("file")),
+       Some("description") => description = Some(field.text().await.map_err(|e|{
+          eprintln!("multipart read error : {e}");
+          AppError::invalid_field("description")
+       })?),
+       _ => {},
+    }
+ }
+ Ok(description)
+}
+
+/// Points the streamed file at an identical stored content of the user, returning the duplicate
+/// and the location of the new copy to drop once the file is recorded
+async fn dedupe_stored_file(app_state:&SharedState,user_id:Uuid,stored:&mut StoredFile) -> Result<Option<(files::Model,StorageBackend,String)>,AppError> {
+ let duplicate = find_duplicate(&app_state.database, user_id, &stored.sha256, stored.size)
+    .await
+    .map_err(|e|{
+       eprintln!("db get one error: {e}");
        AppError::DbTimeout
-    })?;  
- let response = FileResponse { 
-        id:file_model.id,
-        name:file_model.name,
-        size:file_model.size,
-        content_type:file_model.content_type,
-        description:file_model.description,
-        url:file_model.url,
-        download_url:format!("/files/{}/download",file_model.id.to_string()),
-        created_at:file_model.created_at,
-        updated_at:file_model.updated_at,
-        status:file_model.status 
-    };
- Ok((StatusCode::OK, Json(response)))
+    })?;
+ Ok(duplicate.map(|duplicate| {
+    let storage_key = std::mem::replace(&mut stored.storage_key, duplicate.storage_key.clone());
+    let storage_backend = std::mem::replace(&mut stored.storage_backend, duplicate.storage_backend);
+    (duplicate, storage_backend, storage_key)
+ }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/files/upload",
+    tag = "files",
+    request_body(content = FileMultipartUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, body = FileResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2001 missing file, code=2003 malformed form or several files)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 413, content_type = "application/json", body = ErrorResponse, description = "File above the maximum upload size (code=2004) or storage quota exceeded (code=1003)"),
+        (status = 415, content_type = "application/json", body = ErrorResponse, description = "Detected file type not allowed (code=2005)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or file storage unavailable (code=1000)"),
+    ),
+)]
+pub async fn upload_file_multipart(
+   claims:Claims,
+   State(app_state):State<SharedState>,
+   mut multipart:Multipart,
+) -> Result<(StatusCode,Json<FileResponse>),AppError>{
+ let mut stored = None;
+ let description = read_multipart_upload(&app_state, claims.user_id, &mut multipart, &mut stored).await;
+ let (mut stored, description) = match (stored, description) {
+    (Some(stored), Ok(description)) => (stored, description),
+    (None, Ok(_)) => return Err(AppError::missing_field("file")),
+    (stored, Err(e)) => {
+       // A part after the file failed, the stored file would be orphaned
+       if let Some(stored) = stored {
+          let _ = app_state
+             .file_storage
+             .delete(stored.storage_backend, &stored.storage_key)
+             .await
+             .map_err(|e| eprintln!("file storage delete error : {e}"));
+       }
+       return Err(e);
+    },
+ };
+ let duplicate = dedupe_stored_file(&app_state, claims.user_id, &mut stored).await?;
+ let saved = save_stored_file(&app_state, claims.user_id, stored, None, description, duplicate.as_ref().map(|(duplicate, _, _)| duplicate)).await;
+ if let Some((_, storage_backend, storage_key)) = duplicate {
+    let _ = app_state
+       .file_storage
+       .delete(storage_backend, &storage_key)
+       .await
+       .map_err(|e| eprintln!("file storage delete error : {e}"));
+ }
+ let file_model = saved?;
+ Ok((StatusCode::OK, Json(to_file_response(file_model))))
 }
 
 #[utoipa::path(
@@ -111,8 +596,10 @@ pub async fn upload_file(
     responses(
         (status = 200, description = "file binary with content_type"),
         (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "File flagged by the malware scan (code=1004)"),
         (status = 404, content_type = "application/json", body = ErrorResponse, description = "File not found in database (code=5003)"),
-        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000)"),
+        (status = 409, content_type = "application/json", body = ErrorResponse, description = "Malware scan not finished (code=1005)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or file storage unavailable (code=1000)"),
     ),
 )]
 pub async fn download_file(
@@ -122,7 +609,7 @@ pub async fn download_file(
 ) -> Result<Response<Body>,AppError>{
     let file_model = files::Entity::find_by_id(file_id)
        .filter(files::Column::UserId.eq(claims.user_id))
-       .filter(files::Column::Status.eq(FileUploadStatus::Uploaded))
+       .filter(files::Column::Status.ne(FileUploadStatus::Deleted))
        .one(&app_state.database)
        .await
        .map_err(|e|{
@@ -130,10 +617,18 @@ pub async fn download_file(
           AppError::DbTimeout
        })?
        .ok_or(AppError::ResourceNotFound)?;
-    let file_binary =  fs::read(file_model.local_path)
+    match file_model.status {
+       FileUploadStatus::Infected => return Err(AppError::FileQuarantined),
+       FileUploadStatus::Scanning => return Err(AppError::FileScanPending),
+       _ => {},
+    }
+    let file_binary = app_state
+        .file_storage
+        .get(file_model.storage_backend, &file_model.storage_key)
+        .await
         .map_err(|e|{
-           eprintln!("local storage error : {e}");
-           AppError::DbTimeout
+           eprintln!("file storage error : {e}");
+           AppError::ServiceTemporarilyUnavailable
        })?;
     let response = Response::builder()
       .status(StatusCode::OK)
@@ -147,6 +642,62 @@ pub async fn download_file(
  Ok(response)
 }
 
+#[utoipa::path(
+    get,
+    path = "/files/{file_id}/thumbnail",
+    tag = "files",
+    responses(
+        (status = 200, description = "thumbnail binary with content_type"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "File flagged by the malware scan (code=1004)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "File not found in database or without thumbnail (code=5003)"),
+        (status = 409, content_type = "application/json", body = ErrorResponse, description = "Malware scan not finished (code=1005)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or file storage unavailable (code=1000)"),
+    ),
+)]
+pub async fn get_file_thumbnail(
+   claims:Claims,
+   Path(file_id):Path<Uuid>,
+   State(app_state):State<SharedState>,
+) -> Result<Response<Body>,AppError>{
+    let file_model = files::Entity::find_by_id(file_id)
+       .filter(files::Column::UserId.eq(claims.user_id))
+       .filter(files::Column::Status.ne(FileUploadStatus::Deleted))
+       .one(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("db get one error: {e}");
+          AppError::DbTimeout
+       })?
+       .ok_or(AppError::ResourceNotFound)?;
+    match file_model.status {
+       FileUploadStatus::Infected => return Err(AppError::FileQuarantined),
+       FileUploadStatus::Scanning => return Err(AppError::FileScanPending),
+       _ => {},
+    }
+    let thumbnail = get_file_image(&file_model.metadata)
+       .map(|image| image.thumbnail)
+       .ok_or(AppError::ResourceNotFound)?;
+    let thumbnail_binary = app_state
+        .file_storage
+        .get(thumbnail.storage_backend, &thumbnail.storage_key)
+        .await
+        .map_err(|e|{
+           eprintln!("file storage error : {e}");
+           AppError::ServiceTemporarilyUnavailable
+       })?;
+    let response = Response::builder()
+      .status(StatusCode::OK)
+      .header("Content-Type",thumbnail.content_type)
+      .body(Body::from(thumbnail_binary))
+      .map_err(|e|{
+          eprintln!("Response builder error: {e}");
+          AppError::DbTimeout
+       })?
+      .into_response();
+ Ok(response)
+}
+
 #[utoipa::path(
     get,
     path = "/files/{file_id}",
@@ -165,7 +716,7 @@ pub async fn get_file_by_id(
 ) -> Result<Json<FileResponse>,AppError>{
     let file_model = files::Entity::find_by_id(file_id)
        .filter(files::Column::UserId.eq(claims.user_id))
-       .filter(files::Column::Status.eq(FileUploadStatus::Uploaded))
+       .filter(files::Column::Status.ne(FileUploadStatus::Deleted))
        .one(&app_state.database)
        .await
        .map_err(|e|{
@@ -173,40 +724,71 @@ pub async fn get_file_by_id(
           AppError::DbTimeout
        })?
        .ok_or(AppError::ResourceNotFound)?;
-    let response = FileResponse { 
-        id:file_model.id,
-        name:file_model.name,
-        size:file_model.size,
-        content_type:file_model.content_type,
-        description:file_model.description,
-        url:file_model.url,
-        download_url:format!("/files/{}/download",file_model.id.to_string()),
-        created_at:file_model.created_at,
-        updated_at:file_model.updated_at,
-        status:file_model.status 
-    };
- Ok(Json(response))
+ Ok(Json(to_file_response(file_model)))
+}
+
+/// Removes the file row with its chunks and provider copies, then its stored
+/// content unless another file shares it
+async fn purge_file(app_state:&SharedState,file_model:files::Model) -> Result<(),AppError> {
+    let file_id = file_model.id;
+    let image = get_file_image(&file_model.metadata);
+    let metadata = delete_provider_files(app_state, file_model.metadata.clone()).await;
+    if metadata.as_ref().and_then(|json| json.get(PROVIDER_FILES_KEY)).is_some() {
+       eprintln!("file {file_id} provider copies could not be deleted");
+    }
+    let _ = app_state
+       .vector_store
+       .delete_file(file_id)
+       .await
+       .map_err(|e| eprintln!("file {file_id} chunks delete error: {e}"));
+    let used = delete_file_row(&app_state.database, &file_model)
+       .await
+       .map_err(|e|{
+          eprintln!("db delete one error: {e}");
+          AppError::DbTimeout
+        })?;
+    if !used {
+       let _ = app_state
+          .file_storage
+          .delete(file_model.storage_backend, &file_model.storage_key)
+          .await
+          .map_err(|e| eprintln!("file storage delete error : {e}"));
+       // Variants go with the content they were made from
+       if let Some(image) = image {
+          let mut variants = image.variants;
+          variants.push(image.thumbnail);
+          delete_image_variants(app_state, &variants).await;
+       }
+    }
+ Ok(())
 }
 
 #[utoipa::path(
     delete,
     path = "/files/{file_id}",
     tag = "files",
+    params(FileDeleteQuery),
     responses(
-        (status = 200, description = "Deleted successfully"),
+        (status = 200, description = "Deleted successfully, trashed files can be restored unless deleted permanently"),
         (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
-        (status = 404, content_type = "application/json", body = ErrorResponse, description = "File not found in database (code=5003)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "File not found in database (code=1001)"),
         (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000)"),
     ),
 )]
 pub async fn delete_file_by_id(
    claims:Claims,
    Path(file_id):Path<Uuid>,
+   Query(query):Query<FileDeleteQuery>,
    State(app_state):State<SharedState>,
 ) -> Result<(StatusCode,&'static str),AppError>{
-    let file_model = files::Entity::find_by_id(file_id)
-       .filter(files::Column::UserId.eq(claims.user_id))
-       .filter(files::Column::Status.eq(FileUploadStatus::Uploaded))
+    let permanent = query.permanent.unwrap_or(false);
+    let mut select = files::Entity::find_by_id(file_id)
+       .filter(files::Column::UserId.eq(claims.user_id));
+    // Trashed files can only be deleted permanently
+    if !permanent {
+       select = select.filter(files::Column::Status.ne(FileUploadStatus::Deleted));
+    }
+    let file_model = select
        .one(&app_state.database)
        .await
        .map_err(|e|{
@@ -214,6 +796,10 @@ pub async fn delete_file_by_id(
           AppError::DbTimeout
         })?
        .ok_or(AppError::ResourceNotFound)?;
+    if permanent {
+       purge_file(&app_state, file_model).await?;
+       return Ok((StatusCode::OK,"Delete successfully"));
+    }
     let mut active_model = file_model
        .into_active_model();
     active_model.status = Set(FileUploadStatus::Deleted);
@@ -225,9 +811,112 @@ pub async fn delete_file_by_id(
           eprintln!("db get one error: {e}");
           AppError::DbTimeout
         })?;
+    // The row is kept as deleted, its chunks must not be retrieved anymore
+    let _ = app_state
+       .vector_store
+       .delete_file(file_id)
+       .await
+       .map_err(|e| eprintln!("file {file_id} chunks delete error: {e}"));
  Ok((StatusCode::OK,"Delete successfully"))
 }
 
+#[utoipa::path(
+    post,
+    path = "/files/{file_id}/restore",
+    tag = "files",
+    responses(
+        (status = 200, body = FileResponse),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "File not found in the trash (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000)"),
+    ),
+)]
+pub async fn restore_file(
+   claims:Claims,
+   Path(file_id):Path<Uuid>,
+   State(app_state):State<SharedState>,
+) -> Result<(StatusCode,Json<FileResponse>),AppError>{
+    let file_model = files::Entity::find_by_id(file_id)
+       .filter(files::Column::UserId.eq(claims.user_id))
+       .filter(files::Column::Status.eq(FileUploadStatus::Deleted))
+       .one(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("db get one error: {e}");
+          AppError::DbTimeout
+        })?
+       .ok_or(AppError::ResourceNotFound)?;
+    let status = initial_status(&app_state, get_file_scan(&file_model.metadata).as_ref());
+    let mut active_model = file_model
+       .into_active_model();
+    active_model.status = Set(status);
+    active_model.updated_at = Set(Utc::now());
+    let file_model = active_model
+       .update(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("db update one error: {e}");
+          AppError::DbTimeout
+        })?;
+    // Its chunks were dropped when trashed
+    spawn_process_file(app_state.clone(), file_model.clone());
+ Ok((StatusCode::OK,Json(to_file_response(file_model))))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/files/{file_id}",
+    tag = "files",
+    request_body = FileUpdateRequest,
+    responses(
+        (status = 200, body = FileResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Empty name (code=2002)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "File not found in database (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000)"),
+    ),
+)]
+pub async fn update_file(
+   claims:Claims,
+   Path(file_id):Path<Uuid>,
+   State(app_state):State<SharedState>,
+   Json(req):Json<FileUpdateRequest>,
+) -> Result<(StatusCode,Json<FileResponse>),AppError>{
+    let file_model = files::Entity::find_by_id(file_id)
+       .filter(files::Column::UserId.eq(claims.user_id))
+       .filter(files::Column::Status.ne(FileUploadStatus::Deleted))
+       .one(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("db get one error: {e}");
+          AppError::DbTimeout
+        })?
+       .ok_or(AppError::ResourceNotFound)?;
+    let mut active_model = file_model
+       .into_active_model();
+    // The storage key keeps the uploaded name
+    if let Some(name) = req.name {
+       let name = name.trim();
+       if name.is_empty() {
+          return Err(AppError::empty_field("name"));
+       }
+       active_model.name = Set(name.to_string());
+    }
+    if let Some(description) = req.description {
+       let description = description.trim();
+       active_model.description = Set((!description.is_empty()).then(|| description.to_string()));
+    }
+    active_model.updated_at = Set(Utc::now());
+    let file_model = active_model
+       .update(&app_state.database)
+       .await
+       .map_err(|e|{
+          eprintln!("db update one error: {e}");
+          AppError::DbTimeout
+        })?;
+ Ok((StatusCode::OK,Json(to_file_response(file_model))))
+}
+
 #[utoipa::path(
     get,
     path = "/files",
@@ -238,6 +927,7 @@ pub async fn delete_file_by_id(
         ("offset" = Option<u64>, Query, description = "Default value : 0"),
         ("search" = Option<String>, Query, description = "Search by file name"),
         ("type" = Option<String>, Query, description = "Search by content_type of file"),
+        ("deleted" = Option<bool>, Query, description = "List the trashed files instead, default false"),
         ("sort" = Option<SortRule>, Query, description = "Sorting by column 'created_at','size','name"),
         ("ascending" = Option<bool>, Query, description = "Sort by ascending order default false"),
     ),
@@ -265,6 +955,11 @@ pub async fn get_files(
      .offset(offset)
      .limit(limit)
      .filter(files::Column::UserId.eq(claims.user_id));
+   if query.deleted.unwrap_or(false){
+      select = select.filter(files::Column::Status.eq(FileUploadStatus::Deleted));
+   }else{
+      select = select.filter(files::Column::Status.ne(FileUploadStatus::Deleted));
+   }
    if let Some(search) = query.search{
       select = select.filter(files::Column::Name.into_expr().ilike(format!("%{}%", search)));
    }
@@ -301,18 +996,38 @@ pub async fn get_files(
        })?;
     response.files = file_models
        .into_iter()
-       .map(|file_model|FileResponse{
-         id:file_model.id,
-         name:file_model.name,
-         size:file_model.size,
-         content_type:file_model.content_type,
-         description:file_model.description,
-         url:file_model.url,
-         download_url:format!("/files/{}/download",file_model.id.to_string()),
-         created_at:file_model.created_at,
-         updated_at:file_model.updated_at,
-         status:file_model.status 
-       })
+       .map(to_file_response)
        .collect::<Vec<_>>();
  Ok(Json(response))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider_file(file_id:&str) -> ProviderFile {
+        ProviderFile { file_id:file_id.to_string(), uploaded_at:Utc::now(), expires_at:None }
+    }
+
+    #[test]
+    fn provider_files_are_set_and_removed_in_metadata() {
+        let metadata = Some(json!({ CHECKSUM_KEY: "abc" }));
+        let metadata = Some(set_provider_file(metadata, "openai", Some(&provider_file("file-1"))));
+        assert_eq!(get_provider_file(&metadata, "openai").map(|file| file.file_id).as_deref(), Some("file-1"));
+        assert!(get_provider_file(&metadata, "anthropic").is_none());
+        let metadata = Some(set_provider_file(metadata, "openai", Some(&provider_file("file-2"))));
+        assert_eq!(get_provider_file(&metadata, "openai").map(|file| file.file_id).as_deref(), Some("file-2"));
+        // The last provider removed drops the key, other metadata is kept
+        let metadata = set_provider_file(metadata, "openai", None);
+        assert_eq!(metadata, json!({ CHECKSUM_KEY: "abc" }));
+    }
+
+    #[test]
+    fn provider_files_tolerate_missing_or_malformed_metadata() {
+        assert!(get_provider_file(&None, "openai").is_none());
+        assert!(get_provider_file(&Some(json!({ PROVIDER_FILES_KEY: { "openai": "file-1" } })), "openai").is_none());
+        let metadata = Some(set_provider_file(Some(json!("not an object")), "openai", Some(&provider_file("file-1"))));
+        assert_eq!(get_provider_file(&metadata, "openai").map(|file| file.file_id).as_deref(), Some("file-1"));
+        assert_eq!(set_provider_file(None, "openai", None), json!({}));
+    }
+}
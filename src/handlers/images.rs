@@ -0,0 +1,255 @@
+use std::time::Instant;
+use axum::{Json, extract::{Path, State}};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use chrono::Utc;
+use num_traits::ToPrimitive;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter, prelude::Decimal};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{auth::{claims::Claims, error::AuthErrorResponse}, dto::{chat::TokenUsage, files::File, images::{ImageGenerationRequest, ImageGenerationResponse}, llm::openai::OpenaiImageRequest}, error::{AppError, ErrorResponse}, handlers::{file::{store_file_content, to_file_response}, message::set_active_message, models::get_openai_settings, storage::remaining_quota}, llm::{pricing::{ImageGenerationUsage, image_generation_cost}, provider::OpenaiApis}, models::{conversations, files, messages::{self, ChatRole}}, state::SharedState};
+
+const IMAGE_MODELS:&[&str] = &["gpt-image-1", "gpt-image-1-mini"];
+/// Longest prompt the GPT image models accept
+const MAX_IMAGE_PROMPT_CHARS:usize = 32000;
+const MAX_IMAGES:u8 = 4;
+
+struct GeneratedImages<'a> {
+   prompt:&'a str,
+   revised_prompt:Option<&'a str>,
+   model:&'a str,
+   files:&'a [files::Model],
+   usage:&'a TokenUsage,
+   latency:i32,
+   cost:Decimal,
+   options:serde_json::Value,
+}
+
+/// Appends the prompt and an assistant message holding the images to the active branch of the conversation
+async fn post_generated_images(db:&DatabaseConnection,conversation:conversations::Model,images:GeneratedImages<'_>) -> Result<(Uuid,Uuid),DbErr> {
+   let conversation_id = conversation.id;
+   let user_message_id = Uuid::new_v4();
+   let message_id = Uuid::new_v4();
+   let user_message = messages::ActiveModel {
+      id:Set(user_message_id),
+      conversation_id:Set(conversation_id),
+      previous_message_id:Set(conversation.active_message_id),
+      role:Set(ChatRole::User),
+      deleted:Set(false),
+      message_content:Set(images.prompt.to_string()),
+      model_provider:Set("openai".to_string()),
+      model_name:Set(images.model.to_string()),
+      request_id:Set(None),
+      request_tokens:Set(0),
+      response_tokens:Set(0),
+      tools_calls:Set(Vec::new()),
+      tools_results:Set(Vec::new()),
+      created_at:Set(Utc::now()),
+      updated_at:Set(Utc::now()),
+      total_tokens:Set(0),
+      latency:Set(0),
+      cost:Set(Decimal::from(0)),
+      metadata:Set(Some(json!({"files":[]}))),
+   };
+   user_message
+     .insert(db)
+     .await?;
+   let attachments = images.files
+     .iter()
+     .map(|file| File {
+        id:file.id,
+        size:Some(file.size as usize),
+        name:file.name.clone(),
+        content_type:file.content_type.clone(),
+        openai_id:None,
+        base64:None,
+        owner_id:None,
+     })
+     .collect::<Vec<File>>();
+   let assistant_message = messages::ActiveModel {
+      id:Set(message_id),
+      conversation_id:Set(conversation_id),
+      previous_message_id:Set(Some(user_message_id)),
+      role:Set(ChatRole::Assistant),
+      deleted:Set(false),
+      message_content:Set(images.revised_prompt.unwrap_or(images.prompt).to_string()),
+      model_provider:Set("openai".to_string()),
+      model_name:Set(images.model.to_string()),
+      request_id:Set(None),
+      request_tokens:Set(images.usage.input_tokens),
+      response_tokens:Set(images.usage.output_tokens),
+      tools_calls:Set(Vec::new()),
+      tools_results:Set(Vec::new()),
+      created_at:Set(Utc::now()),
+      updated_at:Set(Utc::now()),
+      total_tokens:Set(images.usage.total_tokens),
+      latency:Set(images.latency),
+      cost:Set(images.cost),
+      metadata:Set(Some(json!({"files":attachments, "imageGeneration":images.options}))),
+   };
+   assistant_message
+     .insert(db)
+     .await?;
+   set_active_message(db, conversation_id, message_id).await?;
+   let message_count = conversation.message_count + 2;
+   let total_tokens = conversation.total_tokens + images.usage.total_tokens as i64;
+   let total_cost = conversation.total_cost + images.cost;
+   let mut active_model = conversation.into_active_model();
+   active_model.message_count = Set(message_count);
+   active_model.total_tokens = Set(total_tokens);
+   active_model.total_cost = Set(total_cost);
+   active_model.last_message_at = Set(Some(Utc::now()));
+   active_model.updated_at = Set(Utc::now());
+   active_model
+     .update(db)
+     .await?;
+ Ok((user_message_id, message_id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/chat/{chat_id}/images",
+    tag = "chat",
+    params(("chat_id" = Uuid, Path, description = "Conversation the images are generated in")),
+    request_body = ImageGenerationRequest,
+    responses(
+        (status = 200, body = ImageGenerationResponse, description = "Generated images, fewer than `n` when storing one failed, the generation cost is recorded either way"),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2002 empty prompt, code=2003 prompt too long, unknown model or image count)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Conversation not found or archived (code=1001)"),
+        (status = 413, content_type = "application/json", body = ErrorResponse, description = "Storage quota exceeded (code=1003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "OpenAI not configured/disabled (code=4002/4003), generation failed (code=1000) or database timeout (code=5001)"),
+    ),
+)]
+pub async fn generate_chat_images(
+   claims:Claims,
+   State(app_state):State<SharedState>,
+   Path(chat_id):Path<Uuid>,
+   Json(req):Json<ImageGenerationRequest>,
+) -> Result<(StatusCode,Json<ImageGenerationResponse>),AppError>{
+ let prompt = req.prompt.trim();
+ if prompt.is_empty() {
+    return Err(AppError::empty_field("prompt"));
+ }
+ if prompt.chars().count() > MAX_IMAGE_PROMPT_CHARS {
+    return Err(AppError::invalid_field("prompt"));
+ }
+ let model = req.model.unwrap_or(IMAGE_MODELS[0].to_string());
+ if !IMAGE_MODELS.contains(&model.as_str()) {
+    return Err(AppError::invalid_field("model"));
+ }
+ let n = req.n.unwrap_or(1);
+ if !(1..=MAX_IMAGES).contains(&n) {
+    return Err(AppError::invalid_field("n"));
+ }
+ let settings = get_openai_settings(&app_state).await?;
+ let conversation = conversations::Entity::find_by_id(chat_id)
+    .filter(conversations::Column::UserId.eq(claims.user_id))
+    .filter(conversations::Column::ArchivedAt.is_null())
+    .one(&app_state.database)
+    .await
+    .map_err(|e|{
+       eprintln!("db get one error: {e}");
+       AppError::DbTimeout
+    })?
+    .ok_or(AppError::ResourceNotFound)?;
+ // Images are stored as files of the user, a full storage is refused before paying for the generation
+ let quota = remaining_quota(&app_state.database, claims.user_id)
+    .await
+    .map_err(|e|{
+       eprintln!("db get one error: {e}");
+       AppError::DbTimeout
+    })?;
+ if let Some((0, quota_bytes)) = quota {
+    return Err(AppError::StorageQuotaExceeded { quota_bytes });
+ }
+ let request = OpenaiImageRequest {
+    model:model.clone(),
+    prompt:prompt.to_string(),
+    n,
+    size:req.size.as_str().to_string(),
+    quality:req.quality.as_str().to_string(),
+    output_format:"png".to_string(),
+ };
+ let started = Instant::now();
+ let generation = app_state
+    .req_client
+    .openai_generate_images(&settings, &request)
+    .await
+    .map_err(|e|{
+       eprintln!("openai image generation error: {e}");
+       AppError::ServiceTemporarilyUnavailable
+    })?;
+ let latency = started.elapsed().as_millis() as i32;
+ let (usage, token_usage) = match generation.usage.clone() {
+    Some(usage) => {
+       let (text_input, image_input) = usage.input_tokens_details
+          .map(|details| (details.text_tokens, details.image_tokens))
+          .unwrap_or((usage.input_tokens, 0));
+       (
+          ImageGenerationUsage { text_input, image_input, output:usage.output_tokens },
+          TokenUsage { input_tokens:usage.input_tokens as i32, output_tokens:usage.output_tokens as i32, total_tokens:usage.total_tokens as i32 },
+       )
+    },
+    None => (
+       ImageGenerationUsage { text_input:0, image_input:0, output:0 },
+       TokenUsage { input_tokens:0, output_tokens:0, total_tokens:0 },
+    ),
+ };
+ let cost = image_generation_cost(&model, usage);
+ let revised_prompt = generation.data
+    .iter()
+    .find_map(|image| image.revised_prompt.clone());
+ // The generation is billed already, the images stored before a failure are kept and the cost is recorded either way
+ let mut stored_files = Vec::new();
+ let mut store_error = None;
+ for (index, image) in generation.data.into_iter().enumerate() {
+    let Some(b64_json) = image.b64_json else {
+       continue;
+    };
+    let content = match BASE64.decode(b64_json) {
+       Ok(content) => content,
+       Err(e) => {
+          eprintln!("openai image decode error: {e}");
+          store_error = Some(AppError::ServiceTemporarilyUnavailable);
+          break;
+       }
+    };
+    match store_file_content(&app_state, claims.user_id, format!("generated-image-{}.png", index + 1), content, Some(prompt.to_string())).await {
+       Ok(file) => stored_files.push(file),
+       Err(e) => {
+          store_error = Some(e);
+          break;
+       }
+    }
+ }
+ let images = GeneratedImages {
+    prompt,
+    revised_prompt:revised_prompt.as_deref(),
+    model:&model,
+    files:&stored_files,
+    usage:&token_usage,
+    latency,
+    cost,
+    options:json!({"size":req.size.as_str(), "quality":req.quality.as_str(), "n":n}),
+ };
+ let (user_message_id, message_id) = post_generated_images(&app_state.database, conversation, images)
+    .await
+    .map_err(|e|{
+       eprintln!("db insert one error: {e}");
+       AppError::DbTimeout
+    })?;
+ if stored_files.is_empty() {
+    eprintln!("openai image generation returned no stored image");
+    return Err(store_error.unwrap_or(AppError::ServiceTemporarilyUnavailable));
+ }
+ Ok((StatusCode::OK, Json(ImageGenerationResponse {
+    chat_id,
+    user_message_id,
+    message_id,
+    files:stored_files.into_iter().map(to_file_response).collect(),
+    revised_prompt,
+    usage:token_usage,
+    cost:cost.to_f32().unwrap_or_default(),
+ })))
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use axum::{Json, extract::State};
+use chrono::Utc;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{
+    auth::{claims::Claims, error::{AuthError, AuthErrorResponse}},
+    dto::instructions::{InstructionLevel, InstructionSource, InstructionsPreviewResponse, OrgInstructions, PersonalInstructions},
+    error::{AppError, ErrorResponse},
+    models::{organizations, users::{self, UserRole}},
+    state::SharedState,
+};
+
+/// Key of the personal instructions in `users.metadata`
+pub const CUSTOM_INSTRUCTIONS_KEY:&str = "customInstructions";
+
+fn non_empty(text:Option<&str>) -> Option<String> {
+    text
+      .map(str::trim)
+      .filter(|text| !text.is_empty())
+      .map(str::to_string)
+}
+
+fn parse_department_instructions(value:&Option<serde_json::Value>) -> HashMap<String,String> {
+    value
+      .as_ref()
+      .and_then(|json| serde_json::from_value(json.clone()).ok())
+      .unwrap_or_default()
+}
+
+/// Joins the instructions in composition order: organization, department, personal
+pub fn compose_system_prompt(sources:&[InstructionSource]) -> Option<String> {
+    let mut ordered = sources.iter().collect::<Vec<&InstructionSource>>();
+    ordered.sort_by_key(|source| source.level as u8);
+    let system_prompt = ordered
+      .into_iter()
+      .map(|source| source.text.as_str())
+      .collect::<Vec<&str>>()
+      .join("\n\n");
+    (!system_prompt.is_empty()).then_some(system_prompt)
+}
+
+/// Instructions applying to the chats of a user
+pub async fn load_instructions(db:&DatabaseConnection,user_id:Uuid) -> Result<Vec<InstructionSource>,DbErr> {
+    let mut sources = Vec::new();
+    let Some(user) = users::Entity::find_by_id(user_id)
+      .one(db)
+      .await? else {
+        return Ok(sources);
+    };
+    // Single organization deployments may have users without org id
+    let org = match user.org_id {
+        Some(org_id) => organizations::Entity::find_by_id(org_id).one(db).await?,
+        None => organizations::Entity::find().one(db).await?,
+    };
+    if let Some(org) = org {
+        if let Some(text) = non_empty(org.custom_instructions.as_deref()) {
+            sources.push(InstructionSource { level:InstructionLevel::Organization, text });
+        }
+        if let Some(department) = user.department.as_ref()
+          && let Some(text) = non_empty(parse_department_instructions(&org.department_instructions).get(department).map(String::as_str)) {
+            sources.push(InstructionSource { level:InstructionLevel::Department, text });
+        }
+    }
+    let personal = user.metadata
+      .as_ref()
+      .and_then(|json| json.get(CUSTOM_INSTRUCTIONS_KEY))
+      .and_then(|value| value.as_str());
+    if let Some(text) = non_empty(personal) {
+        sources.push(InstructionSource { level:InstructionLevel::Personal, text });
+    }
+ Ok(sources)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/instructions",
+    tag = "admin",
+    responses(
+       (status = 200, body = OrgInstructions),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_org_instructions(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<OrgInstructions>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let org = organizations::Entity::find()
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let response = OrgInstructions {
+        organization:org.as_ref().and_then(|org| org.custom_instructions.clone()),
+        departments:org
+          .map(|org| parse_department_instructions(&org.department_instructions))
+          .unwrap_or_default(),
+    };
+    Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/instructions",
+    tag = "admin",
+    request_body = OrgInstructions,
+    responses(
+       (status = 200, body = OrgInstructions),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Organization not found (code=5003)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn update_org_instructions(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<OrgInstructions>
+) -> Result<(StatusCode,Json<OrgInstructions>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let org = organizations::Entity::find()
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::OrgDoesNotExist { org_id:claims.org_id })?;
+    let organization = non_empty(req.organization.as_deref());
+    let departments = req.departments
+      .into_iter()
+      .filter_map(|(department, text)| non_empty(Some(&text)).map(|text| (department, text)))
+      .collect::<HashMap<String,String>>();
+    let mut active_model = org.into_active_model();
+    active_model.custom_instructions = Set(organization.clone());
+    active_model.department_instructions = Set((!departments.is_empty()).then(|| json!(departments)));
+    active_model.updated_on = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+          eprintln!("update error: {e}");
+          AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(OrgInstructions { organization, departments })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/instructions",
+    tag = "instructions",
+    responses(
+        (status = 200, body = PersonalInstructions),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "User not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_personal_instructions(
+  claims:Claims,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<PersonalInstructions>),AppError>{
+    let user = users::Entity::find_by_id(claims.user_id)
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("DB get one error {:?}", e);
+         AppError::DbTimeout})?
+      .ok_or(AppError::DbNotFound)?;
+    let custom_instructions = user.metadata
+      .as_ref()
+      .and_then(|json| json.get(CUSTOM_INSTRUCTIONS_KEY))
+      .and_then(|value| value.as_str())
+      .map(str::to_string);
+  Ok((StatusCode::OK,Json(PersonalInstructions { custom_instructions })))
+}
+
+#[utoipa::path(
+    put,
+    path = "/instructions",
+    tag = "instructions",
+    request_body = PersonalInstructions,
+    responses(
+        (status = 200, body = PersonalInstructions),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "User not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn update_personal_instructions(
+  claims:Claims,
+  State(app_state): State<SharedState>,
+  Json(req):Json<PersonalInstructions>
+) -> Result<(StatusCode,Json<PersonalInstructions>),AppError>{
+    let user = users::Entity::find_by_id(claims.user_id)
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("DB get one error {:?}", e);
+         AppError::DbTimeout})?
+      .ok_or(AppError::DbNotFound)?;
+    let custom_instructions = non_empty(req.custom_instructions.as_deref());
+    let mut metadata = user.metadata
+      .clone()
+      .filter(|json| json.is_object())
+      .unwrap_or_else(|| json!({}));
+    if let Some(json) = metadata.as_object_mut() {
+        match &custom_instructions {
+            Some(text) => json.insert(CUSTOM_INSTRUCTIONS_KEY.to_string(), json!(text)),
+            None => json.remove(CUSTOM_INSTRUCTIONS_KEY),
+        };
+    }
+    let mut active_model = user.into_active_model();
+    active_model.metadata = Set(Some(metadata));
+    active_model.updated_at = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("Db update one error {:?}", e);
+         AppError::DbTimeout})?;
+  Ok((StatusCode::OK,Json(PersonalInstructions { custom_instructions })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/instructions/preview",
+    tag = "instructions",
+    responses(
+        (status = 200, body = InstructionsPreviewResponse),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn preview_instructions(
+  claims:Claims,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<InstructionsPreviewResponse>),AppError>{
+    let sources = load_instructions(&app_state.database, claims.user_id)
+      .await
+      .map_err(|e| {
+         eprintln!("DB get one error {:?}", e);
+         AppError::DbTimeout})?;
+    let response = InstructionsPreviewResponse {
+        system_prompt:compose_system_prompt(&sources),
+        sources,
+    };
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_prompt_follows_level_order() {
+        let sources = vec![
+            InstructionSource { level:InstructionLevel::Personal, text:"Answer in French.".into() },
+            InstructionSource { level:InstructionLevel::Organization, text:"Never share customer data.".into() },
+            InstructionSource { level:InstructionLevel::Department, text:"Cite the legal basis.".into() },
+        ];
+        assert_eq!(
+            compose_system_prompt(&sources).as_deref(),
+            Some("Never share customer data.\n\nCite the legal basis.\n\nAnswer in French."),
+        );
+        assert_eq!(compose_system_prompt(&[]), None);
+    }
+}
@@ -53,6 +53,7 @@ impl StreamParseResult {
                 id: conversation_id,
                 role: None,
                 content: Some(text.clone()),
+                model: None,
             }),
             _ => None,
         }
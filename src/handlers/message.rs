@@ -1,10 +1,132 @@
-use std::convert::Infallible;
+use std::{collections::HashMap, convert::Infallible};
 use axum::{Json, extract::{Path, State}, response::{Sse, sse::Event}};
 use chrono::Utc;
 use reqwest::StatusCode;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, sea_query};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, sea_query};
 use uuid::Uuid;
-use crate::{auth::claims::Claims, dto::chat_stream::{ChatInitRequest, ChatStream}, error::AppError, handlers::chat_stream::handle_chat_stream, models::{conversations, messages}, state::SharedState};
+use crate::{auth::{claims::Claims, error::AuthErrorResponse}, dto::{chat::MessageResponse, chat_stream::{ChatInitRequest, ChatStream, RegenerateRequest}}, error::{AppError, ErrorResponse}, handlers::{chat::to_message_response, chat_stream::{BranchParent, stream_chat}}, models::{conversations, messages::{self, ChatRole}}, state::SharedState};
+
+/// Messages of a conversation as a tree, alternatives of a message share its `previous_message_id`
+pub struct MessageTree {
+    messages:HashMap<Uuid,messages::Model>,
+    // Children ids by previous message id, oldest first
+    children:HashMap<Option<Uuid>,Vec<Uuid>>,
+}
+
+impl MessageTree {
+    pub async fn load(database:&DatabaseConnection,conversation_id:Uuid) -> Result<Self,DbErr> {
+        let models = messages::Entity::find()
+          .filter(messages::Column::ConversationId.eq(conversation_id))
+          .order_by_asc(messages::Column::CreatedAt)
+          .all(database)
+          .await?;
+     Ok(Self::from_messages(models))
+    }
+
+    /// Builds the tree of messages sorted oldest first
+    fn from_messages(models:Vec<messages::Model>) -> Self {
+        let mut children:HashMap<Option<Uuid>,Vec<Uuid>> = HashMap::new();
+        for message in &models {
+           children
+             .entry(message.previous_message_id)
+             .or_default()
+             .push(message.id);
+        }
+        let messages = models
+          .into_iter()
+          .map(|message| (message.id,message))
+          .collect();
+        Self { messages, children }
+    }
+
+    pub fn get(&self,message_id:&Uuid) -> Option<&messages::Model> {
+        self.messages.get(message_id)
+    }
+
+    /// Non deleted messages from the root down to `leaf` included.
+    /// Deleted messages are skipped but still link the branch together.
+    pub fn path(&self,leaf:Option<Uuid>) -> Vec<&messages::Model> {
+        let mut path = Vec::new();
+        let mut current = leaf.and_then(|id| self.messages.get(&id));
+        // Bounded walk, a corrupted chain must not loop forever
+        for _ in 0..self.messages.len() {
+            let Some(message) = current else { break };
+            if !message.deleted {
+                path.push(message);
+            }
+            current = message
+              .previous_message_id
+              .and_then(|id| self.messages.get(&id));
+        }
+        path.reverse();
+        path
+    }
+
+    /// Non deleted alternatives of the message, itself included, oldest first
+    pub fn siblings(&self,message:&messages::Model) -> Vec<&messages::Model> {
+        self.children
+          .get(&message.previous_message_id)
+          .map(|ids| ids
+             .iter()
+             .filter_map(|id| self.messages.get(id))
+             .filter(|sibling| !sibling.deleted || sibling.id == message.id)
+             .collect())
+          .unwrap_or_default()
+    }
+
+    /// Follows the most recent non deleted reply down to the tip of the branch starting at `message_id`.
+    /// Deleted replies never become the tip, they are only followed when no other reply is left
+    /// and a non deleted message comes after them.
+    pub fn branch_tip(&self,message_id:Uuid) -> Uuid {
+        self.visible_tip_below(message_id, self.messages.len())
+          .unwrap_or(message_id)
+    }
+
+    // `depth` bounds the walk, a corrupted chain must not loop forever
+    fn visible_tip_below(&self,message_id:Uuid,depth:usize) -> Option<Uuid> {
+        let ids = self.children.get(&Some(message_id)).filter(|_| depth > 0)?;
+        let visible = ids
+          .iter()
+          .rev()
+          .find(|id| self.messages.get(id).is_some_and(|child| !child.deleted));
+        if let Some(child) = visible {
+            return Some(self.visible_tip_below(*child, depth - 1).unwrap_or(*child));
+        }
+        ids
+          .iter()
+          .rev()
+          .find_map(|child| self.visible_tip_below(*child, depth - 1))
+    }
+}
+
+pub async fn set_active_message(database:&DatabaseConnection,conversation_id:Uuid,message_id:Uuid) -> Result<(),DbErr> {
+    conversations::Entity::update_many()
+      .filter(conversations::Column::Id.eq(conversation_id))
+      .col_expr(conversations::Column::ActiveMessageId,sea_query::Expr::value(message_id))
+      .exec(database)
+      .await?;
+  Ok(())
+}
+
+async fn find_chat_message(app_state:&SharedState,user_id:Uuid,chat_id:Uuid,message_id:Uuid) -> Result<messages::Model,AppError> {
+  let message = conversations::Entity::find()
+    .filter(conversations::Column::Id.eq(chat_id))
+    .filter(conversations::Column::UserId.eq(user_id))
+    .inner_join(messages::Entity)
+    .filter(messages::Column::Id.eq(message_id))
+    .filter(messages::Column::Deleted.eq(false))
+    .select_also(messages::Entity)
+    .one(&app_state.database)
+    .await
+    .map_err(|e|{
+      eprintln!("db error :{}",e);
+      AppError::DbTimeout
+     })?
+    .ok_or(AppError::ResourceNotFound)?
+    .1
+    .ok_or(AppError::ResourceNotFound)?;
+ Ok(message)
+}
 
 #[utoipa::path(
     delete,
@@ -73,31 +195,213 @@ pub async fn edit_chat_message_by_id_and_stream(
   State(app_state): State<SharedState>,
   Json(req):Json<ChatInitRequest>,
 ) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,AppError> {
-     let message = conversations::Entity::find()
-       .filter(conversations::Column::Id.eq(chat_id))
-       .filter(conversations::Column::UserId.eq(claims.user_id.clone()))
-       .inner_join(messages::Entity)
-       .filter(messages::Column::Id.eq(message_id))
-       .select_also(messages::Entity)
-       .one(&app_state.database)
+     let message = find_chat_message(&app_state,claims.user_id,chat_id,message_id)
+       .await?;
+     // The edited messages become an alternative of `message`, the original branch is kept
+ Ok(stream_chat(claims, Some(chat_id), app_state, req, BranchParent::Message(message.previous_message_id)).await?)
+}
+
+#[utoipa::path(
+    post,
+    path = "/chat/{chat_id}/message/{message_id}/regenerate",
+    tag = "chat",
+    params(
+        ("chat_id" = Uuid, Path, description = "Unique identifier for the conversation"),
+        ("message_id" = Uuid, Path, description = "Assistant message to regenerate"),
+    ),
+    request_body = RegenerateRequest,
+    responses(
+        (status = 200, content_type = "text/event-stream", body = ChatStream),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Message is not an assistant reply (code=2003)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Message not found (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn regenerate_chat_message_by_id(
+  claims:Claims,
+  Path((chat_id,message_id)):Path<(Uuid,Uuid)>,
+  State(app_state): State<SharedState>,
+  Json(req):Json<RegenerateRequest>,
+) -> Result<Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>>,AppError> {
+     let message = find_chat_message(&app_state,claims.user_id,chat_id,message_id)
+       .await?;
+     if message.role != ChatRole::Assistant {
+        return Err(AppError::invalid_field("message_id"));
+     }
+     let (provider,model_name) = match req.provider {
+        Some(provider) => (provider,req.model_name),
+        None => (message.model_provider,req.model_name.or(Some(message.model_name))),
+     };
+     let chat_req = ChatInitRequest {
+        provider:Some(provider),
+        model_name,
+        config:None,
+        web_search:req.web_search,
+        selected_tools:req.selected_tools,
+        conversation_id:None,
+        messages:Vec::new(),
+        temperature:req.temperature,
+        prompt_template_id:None,
+        assistant_id:None,
+        collection_ids:Vec::new(),
+     };
+ stream_chat(claims, Some(chat_id), app_state, chat_req, BranchParent::Message(message.previous_message_id)).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/chat/{chat_id}/message/{message_id}/siblings",
+    tag = "chat",
+    params(
+        ("chat_id" = Uuid, Path, description = "Unique identifier for the conversation"),
+        ("message_id" = Uuid, Path, description = "Unique identifier for the message"),
+    ),
+    responses(
+        (status = 200, body = Vec<MessageResponse>, description = "Alternatives of the message, itself included, oldest first"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Message not found (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_chat_message_siblings(
+  claims:Claims,
+  Path((chat_id,message_id)):Path<(Uuid,Uuid)>,
+  State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<MessageResponse>>),AppError> {
+     find_chat_message(&app_state,claims.user_id,chat_id,message_id)
+       .await?;
+     let tree = MessageTree::load(&app_state.database,chat_id)
        .await
        .map_err(|e|{
           eprintln!("db error :{}",e);
           AppError::DbTimeout
-        })?
-       .ok_or(AppError::ResourceNotFound)?
-       .1
+        })?;
+     let message = tree
+       .get(&message_id)
        .ok_or(AppError::ResourceNotFound)?;
-     messages::Entity::update_many()
-       .filter(messages::Column::ConversationId.eq(chat_id))
-       .filter(messages::Column::Deleted.eq(false))
-       .filter(messages::Column::CreatedAt.gte(message.created_at))
-       .col_expr(messages::Column::Deleted,sea_query::Expr::value(true))
-       .exec(&app_state.database)
+     let response = tree
+       .siblings(message)
+       .into_iter()
+       .map(|sibling| to_message_response(sibling,&tree))
+       .collect();
+ Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/chat/{chat_id}/message/{message_id}/active",
+    tag = "chat",
+    params(
+        ("chat_id" = Uuid, Path, description = "Unique identifier for the conversation"),
+        ("message_id" = Uuid, Path, description = "Alternative to show, its latest replies are shown after it"),
+    ),
+    responses(
+        (status = 200, body = Vec<MessageResponse>, description = "New active path of the conversation"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Message not found (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn select_chat_message_branch(
+  claims:Claims,
+  Path((chat_id,message_id)):Path<(Uuid,Uuid)>,
+  State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<MessageResponse>>),AppError> {
+     find_chat_message(&app_state,claims.user_id,chat_id,message_id)
+       .await?;
+     let tree = MessageTree::load(&app_state.database,chat_id)
        .await
-          .map_err(|e|{
-           eprintln!("db update many error :{}",e);
-            AppError::DbTimeout
+       .map_err(|e|{
+          eprintln!("db error :{}",e);
+          AppError::DbTimeout
         })?;
- Ok(handle_chat_stream(claims, Some(Path(chat_id)), State(app_state), Json(req)).await?)
-}
\ No newline at end of file
+     let branch_tip = tree.branch_tip(message_id);
+     set_active_message(&app_state.database,chat_id,branch_tip)
+       .await
+       .map_err(|e|{
+          eprintln!("db update many error :{}",e);
+          AppError::DbTimeout
+        })?;
+     let response = tree
+       .path(Some(branch_tip))
+       .into_iter()
+       .map(|message| to_message_response(message,&tree))
+       .collect();
+ Ok((StatusCode::OK,Json(response)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use sea_orm::prelude::Decimal;
+
+    fn message(id:u128,previous:Option<u128>,deleted:bool) -> messages::Model {
+        messages::Model {
+            id:Uuid::from_u128(id),
+            conversation_id:Uuid::nil(),
+            previous_message_id:previous.map(Uuid::from_u128),
+            deleted,
+            role:ChatRole::User,
+            message_content:String::new(),
+            model_provider:String::new(),
+            model_name:String::new(),
+            request_tokens:0,
+            response_tokens:0,
+            request_id:None,
+            tools_calls:Vec::new(),
+            tools_results:Vec::new(),
+            created_at:Utc::now() + Duration::seconds(id as i64),
+            updated_at:Utc::now(),
+            total_tokens:0,
+            latency:0,
+            cost:Decimal::ZERO,
+            metadata:None,
+        }
+    }
+
+    fn ids(messages:Vec<&messages::Model>) -> Vec<u128> {
+        messages.iter().map(|message| message.id.as_u128()).collect()
+    }
+
+    // 1 -> 2 -> 3 (deleted) -> 4
+    //        -> 5 (deleted)
+    //   -> 6 -> 7 (deleted)
+    fn tree() -> MessageTree {
+        MessageTree::from_messages(vec![
+            message(1, None, false),
+            message(2, Some(1), false),
+            message(3, Some(2), true),
+            message(4, Some(3), false),
+            message(5, Some(2), true),
+            message(6, Some(1), false),
+            message(7, Some(6), true),
+        ])
+    }
+
+    #[test]
+    fn path_skips_deleted_links() {
+        let tree = tree();
+        assert_eq!(ids(tree.path(Some(Uuid::from_u128(4)))), vec![1, 2, 4]);
+        assert_eq!(ids(tree.path(Some(Uuid::from_u128(7)))), vec![1, 6]);
+        assert!(tree.path(None).is_empty());
+    }
+
+    #[test]
+    fn siblings_are_oldest_first_without_deleted_ones() {
+        let tree = tree();
+        assert_eq!(ids(tree.siblings(tree.get(&Uuid::from_u128(6)).unwrap())), vec![2, 6]);
+        assert_eq!(ids(tree.siblings(tree.get(&Uuid::from_u128(3)).unwrap())), vec![3]);
+    }
+
+    #[test]
+    fn branch_tip_never_stops_on_deleted_messages() {
+        let tree = tree();
+        // The deleted 5 is the latest reply to 2, the branch goes on through the deleted 3
+        assert_eq!(tree.branch_tip(Uuid::from_u128(2)), Uuid::from_u128(4));
+        assert_eq!(tree.branch_tip(Uuid::from_u128(6)), Uuid::from_u128(6));
+        assert_eq!(tree.branch_tip(Uuid::from_u128(1)), Uuid::from_u128(6));
+        assert_eq!(tree.branch_tip(Uuid::from_u128(4)), Uuid::from_u128(4));
+    }
+}
@@ -0,0 +1,477 @@
+use axum::{Json, extract::{Path, State}};
+use chrono::Utc;
+use migration::Expr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter};
+use uuid::Uuid;
+use crate::{auth::{claims::{Claiming, Claims, MFA_TOKEN_TTL_SECS, MfaClaims, MfaSubject}, client::ClientInfo, encryption::{decrypt_key, encrypt_key}, error::{AuthError, AuthErrorResponse}, mfa::{current_unix_time, new_recovery_codes, new_totp_secret, otpauth_uri, recovery_code_hash, verify_totp}}, dto::auth::{AuthTokenResponse, MfaChallengeResponse, MfaCodeRequest, MfaRecoveryCodesResponse, MfaSetupResponse, MfaVerifyRequest}, handlers::{auth::issue_tokens, password::{load_password_policy, register_failed_login}, sessions::{revoke_other_sessions, revoke_user_sessions}}, models::{organizations, sessions::SessionRevokeReason, users::{self, UserRole, UserStatus}}, state::SharedState};
+
+/// Second factor presented by the user
+enum SecondFactor {
+    /// Time step of an authenticator code
+    Totp(i64),
+    /// Hash of a recovery code
+    RecoveryCode(String),
+}
+
+async fn org_requires_mfa(db:&DatabaseConnection) -> Result<bool,DbErr> {
+    let org = organizations::Entity::find().one(db).await?;
+    Ok(org.is_some_and(|org| org.require_mfa))
+}
+
+/// Challenge replacing the tokens of a login whose second factor is missing, None when the password or SSO login is enough
+pub async fn mfa_challenge(db:&DatabaseConnection,user:&users::Model) -> Result<Option<MfaChallengeResponse>,AuthError> {
+    let enrollment_required = if user.mfa_enabled {
+        false
+    } else {
+        let require_mfa = org_requires_mfa(db)
+          .await
+          .map_err(|e| {
+             eprintln!("db get one error: {e}");
+             AuthError::DbTimeout
+          })?;
+        if !require_mfa {
+            return Ok(None);
+        }
+        true
+    };
+    let mfa_claims = MfaClaims::new_mfa_token(user.email.clone(), user.id, enrollment_required);
+    Ok(Some(MfaChallengeResponse {
+        mfa_required:true,
+        enrollment_required,
+        mfa_token:mfa_claims.get_token_string(),
+        expires_in:MFA_TOKEN_TTL_SECS as i32,
+    }))
+}
+
+async fn find_user(db:&DatabaseConnection,user_id:Uuid) -> Result<users::Model,AuthError> {
+    let user = users::Entity::find_by_id(user_id)
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .one(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::EmailDoesNotExist)?;
+    match user.status {
+        UserStatus::Deactivated | UserStatus::Suspended => Err(AuthError::AccountDeactivated),
+        _ => Ok(user),
+    }
+}
+
+fn totp_secret(app_state:&SharedState,user:&users::Model) -> Result<String,AuthError> {
+    let encrypted_secret = user.mfa_secret.as_ref().ok_or(AuthError::MfaNotEnrolled)?;
+    decrypt_key(&app_state.settings.auth.app_key, encrypted_secret)
+      .map_err(|e| {
+         eprintln!("mfa secret decrypt error: {e:?}");
+         AuthError::ServiceTemporarilyUnavailable
+      })
+}
+
+/// Matches the code against the authenticator, then against the recovery codes when allowed
+fn check_second_factor(app_state:&SharedState,user:&users::Model,code:&str,allow_recovery_code:bool) -> Result<Option<SecondFactor>,AuthError> {
+    let secret = totp_secret(app_state, user)?;
+    let step = verify_totp(&secret, code, user.mfa_last_step, current_unix_time())
+      .map_err(|e| {
+         eprintln!("{e}");
+         AuthError::ServiceTemporarilyUnavailable
+      })?;
+    if let Some(step) = step {
+        return Ok(Some(SecondFactor::Totp(step)));
+    }
+    if !allow_recovery_code {
+        return Ok(None);
+    }
+    let code_hash = recovery_code_hash(code);
+    Ok(user.mfa_recovery_codes
+      .contains(&code_hash)
+      .then_some(SecondFactor::RecoveryCode(code_hash)))
+}
+
+/// Marks the factor as used, false when a concurrent request used it first
+async fn consume_second_factor(db:&DatabaseConnection,user_id:Uuid,factor:SecondFactor) -> Result<bool,DbErr> {
+    let update = users::Entity::update_many().filter(users::Column::Id.eq(user_id));
+    let update = match factor {
+        SecondFactor::Totp(step) => update
+          .col_expr(users::Column::MfaLastStep, Expr::value(step))
+          .filter(
+              Condition::any()
+                .add(users::Column::MfaLastStep.is_null())
+                .add(users::Column::MfaLastStep.lt(step))
+          ),
+        SecondFactor::RecoveryCode(code_hash) => update
+          .col_expr(users::Column::MfaRecoveryCodes, Expr::cust_with_values(r#"array_remove("mfaRecoveryCodes", $1)"#, [code_hash.clone()]))
+          .filter(Expr::cust_with_values(r#"$1 = ANY("mfaRecoveryCodes")"#, [code_hash])),
+    };
+    let result = update.exec(db).await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Refuses a wrong code, counting it toward the lockout like a wrong password
+async fn reject_code(db:&DatabaseConnection,user_id:Uuid) -> AuthError {
+    let locked_until = match load_password_policy(db).await {
+        Ok(policy) => register_failed_login(db, user_id, &policy).await,
+        Err(e) => Err(e),
+    };
+    match locked_until {
+        Ok(Some(locked_until)) => AuthError::AccountLocked { locked_until },
+        Ok(None) => AuthError::InvalidMfaCode,
+        Err(e) => {
+            eprintln!("db update error: {e}");
+            AuthError::DbTimeout
+        }
+    }
+}
+
+/// Checks the code and marks it as used
+async fn accept_code(app_state:&SharedState,user:&users::Model,code:&str,allow_recovery_code:bool) -> Result<(),AuthError> {
+    let Some(factor) = check_second_factor(app_state, user, code, allow_recovery_code)? else {
+        return Err(reject_code(&app_state.database, user.id).await);
+    };
+    let consumed = consume_second_factor(&app_state.database, user.id, factor)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if !consumed {
+        return Err(AuthError::InvalidMfaCode);
+    }
+    Ok(())
+}
+
+fn ensure_not_locked(user:&users::Model) -> Result<(),AuthError> {
+    if let Some(locked_until) = user.locked_until
+      && locked_until > Utc::now() {
+        return Err(AuthError::AccountLocked { locked_until });
+    }
+    Ok(())
+}
+
+/// Completes the login, clearing the failures counted by its password and codes
+async fn complete_login(db:&DatabaseConnection,user_id:Uuid) -> Result<users::Model,AuthError> {
+    let user = find_user(db, user_id).await?;
+    let mut active_model = user.into_active_model();
+    active_model.failed_login_attempts = Set(0);
+    active_model.locked_until = Set(None);
+    active_model.last_login_at = Set(Utc::now());
+    active_model
+      .update(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/setup",
+    tag = "auth",
+    responses(
+       (status = 200, body = MfaSetupResponse, description = "New authenticator secret, active once confirmed with /auth/mfa/enable"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired access or enrollment token (code=6103) or account deactivated (code=6105)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "API keys cannot enroll a second factor (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 409, content_type = "application/json", body = AuthErrorResponse, description = "MFA already enabled (code=6110)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn setup_mfa(
+    subject: MfaSubject,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<MfaSetupResponse>), AuthError> {
+    let user = find_user(&app_state.database, subject.user_id).await?;
+    if user.mfa_enabled {
+        return Err(AuthError::MfaAlreadyEnabled);
+    }
+    let secret = new_totp_secret();
+    let otpauth_uri = otpauth_uri(&secret, &user.email)
+      .map_err(|e| {
+         eprintln!("{e}");
+         AuthError::ServiceTemporarilyUnavailable
+      })?;
+    let encrypted_secret = encrypt_key(&app_state.settings.auth.app_key, secret.as_bytes())
+      .map_err(|e| {
+         eprintln!("mfa secret encrypt error: {e:?}");
+         AuthError::ServiceTemporarilyUnavailable
+      })?;
+    let mut active_model = user.into_active_model();
+    active_model.mfa_secret = Set(Some(encrypted_secret));
+    active_model.mfa_last_step = Set(None);
+    active_model.updated_at = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(MfaSetupResponse { secret, otpauth_uri })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/enable",
+    tag = "auth",
+    request_body = MfaCodeRequest,
+    responses(
+       (status = 200, body = MfaRecoveryCodesResponse, description = "MFA enabled, with the tokens of the login when it was pending enrollment"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Setup not started (code=6111)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired access or enrollment token (code=6103), wrong code (code=6109) or account deactivated (code=6105)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "API keys cannot enroll a second factor (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 409, content_type = "application/json", body = AuthErrorResponse, description = "MFA already enabled (code=6110)"),
+       (status = 423, content_type = "application/json", body = AuthErrorResponse, description = "Account locked after repeated failures (code=6107)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn enable_mfa(
+    subject: MfaSubject,
+    client: ClientInfo,
+    State(app_state): State<SharedState>,
+    Json(req): Json<MfaCodeRequest>,
+) -> Result<(StatusCode,Json<MfaRecoveryCodesResponse>), AuthError> {
+    let user = find_user(&app_state.database, subject.user_id).await?;
+    if user.mfa_enabled {
+        return Err(AuthError::MfaAlreadyEnabled);
+    }
+    ensure_not_locked(&user)?;
+    accept_code(&app_state, &user, &req.code, false).await?;
+    let (recovery_codes, recovery_code_hashes) = new_recovery_codes();
+    users::Entity::update_many()
+      .col_expr(users::Column::MfaEnabled, Expr::value(true))
+      .col_expr(users::Column::MfaRecoveryCodes, Expr::value(recovery_code_hashes))
+      .col_expr(users::Column::UpdatedAt, Expr::value(Utc::now()))
+      .filter(users::Column::Id.eq(user.id))
+      .exec(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let session = if subject.pending_login {
+        let user = complete_login(&app_state.database, user.id).await?;
+        Some(issue_tokens(&app_state.database, user, &client).await?)
+    } else {
+        None
+    };
+    Ok((StatusCode::OK,Json(MfaRecoveryCodesResponse { recovery_codes, session })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/verify",
+    tag = "auth",
+    request_body = MfaVerifyRequest,
+    responses(
+       (status = 200, body = AuthTokenResponse),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "MFA not set up (code=6111)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired mfa token (code=6103), wrong code (code=6109) or account deactivated (code=6105)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 423, content_type = "application/json", body = AuthErrorResponse, description = "Account locked after repeated failures (code=6107)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn verify_mfa(
+    client: ClientInfo,
+    State(app_state): State<SharedState>,
+    Json(req): Json<MfaVerifyRequest>,
+) -> Result<(StatusCode,Json<AuthTokenResponse>), AuthError> {
+    let mfa_claims = MfaClaims::from_token_string(&req.mfa_token)
+      .map_err(|_| AuthError::InvalidToken)?;
+    if !mfa_claims.mfa || mfa_claims.enrollment {
+        return Err(AuthError::InvalidToken);
+    }
+    let user = find_user(&app_state.database, mfa_claims.user_id).await?;
+    if !user.mfa_enabled {
+        return Err(AuthError::MfaNotEnrolled);
+    }
+    ensure_not_locked(&user)?;
+    accept_code(&app_state, &user, &req.code, true).await?;
+    let user = complete_login(&app_state.database, user.id).await?;
+    Ok((StatusCode::OK,Json(issue_tokens(&app_state.database, user, &client).await?)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/disable",
+    tag = "auth",
+    request_body = MfaCodeRequest,
+    responses(
+       (status = 204, description = "MFA disabled, the other sessions are signed out"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "MFA not set up (code=6111)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103), wrong code (code=6109) or account deactivated (code=6105)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "The organization requires MFA (code=6112)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 423, content_type = "application/json", body = AuthErrorResponse, description = "Account locked after repeated failures (code=6107)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn disable_mfa(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<MfaCodeRequest>,
+) -> Result<StatusCode, AuthError> {
+    let require_mfa = org_requires_mfa(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if require_mfa {
+        return Err(AuthError::MfaRequired);
+    }
+    let user = find_user(&app_state.database, claims.user_id).await?;
+    if !user.mfa_enabled {
+        return Err(AuthError::MfaNotEnrolled);
+    }
+    ensure_not_locked(&user)?;
+    accept_code(&app_state, &user, &req.code, true).await?;
+    clear_mfa(&app_state.database, user.id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    // Devices signed in with the second factor no longer need it, only this one stays signed in
+    revoke_other_sessions(&app_state.database, user.id, claims.sid, SessionRevokeReason::MfaChange)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/recovery-codes",
+    tag = "auth",
+    request_body = MfaCodeRequest,
+    responses(
+       (status = 200, body = MfaRecoveryCodesResponse, description = "New recovery codes, the previous ones no longer work"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "MFA not set up (code=6111)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103), wrong code (code=6109) or account deactivated (code=6105)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 423, content_type = "application/json", body = AuthErrorResponse, description = "Account locked after repeated failures (code=6107)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn regenerate_recovery_codes(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<MfaCodeRequest>,
+) -> Result<(StatusCode,Json<MfaRecoveryCodesResponse>), AuthError> {
+    let user = find_user(&app_state.database, claims.user_id).await?;
+    if !user.mfa_enabled {
+        return Err(AuthError::MfaNotEnrolled);
+    }
+    ensure_not_locked(&user)?;
+    accept_code(&app_state, &user, &req.code, false).await?;
+    let (recovery_codes, recovery_code_hashes) = new_recovery_codes();
+    users::Entity::update_many()
+      .col_expr(users::Column::MfaRecoveryCodes, Expr::value(recovery_code_hashes))
+      .col_expr(users::Column::UpdatedAt, Expr::value(Utc::now()))
+      .filter(users::Column::Id.eq(user.id))
+      .exec(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(MfaRecoveryCodesResponse { recovery_codes, session:None })))
+}
+
+async fn clear_mfa(db:&DatabaseConnection,user_id:Uuid) -> Result<u64,DbErr> {
+    let result = users::Entity::update_many()
+      .col_expr(users::Column::MfaEnabled, Expr::value(false))
+      .col_expr(users::Column::MfaSecret, Expr::value(None::<String>))
+      .col_expr(users::Column::MfaRecoveryCodes, Expr::value(Vec::<String>::new()))
+      .col_expr(users::Column::MfaLastStep, Expr::value(None::<i64>))
+      .col_expr(users::Column::UpdatedAt, Expr::value(Utc::now()))
+      .filter(users::Column::Id.eq(user_id))
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .exec(db)
+      .await?;
+    Ok(result.rows_affected)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{user_id}/mfa",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses(
+       (status = 204, description = "MFA reset and the user signed out everywhere, they enroll again at their next login when the organization requires it"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied, or an admin resetting a super admin (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn reset_user_mfa(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let user = users::Entity::find_by_id(user_id)
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::EmailDoesNotExist)?;
+    // An admin clearing the second factor of a super admin could take over their account
+    if user.role == UserRole::SuperAdmin && claims.role != UserRole::SuperAdmin {
+        return Err(AuthError::PermissionDenied);
+    }
+    let rows_affected = clear_mfa(&app_state.database, user.id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if rows_affected == 0 {
+        return Err(AuthError::EmailDoesNotExist);
+    }
+    revoke_user_sessions(&app_state.database, user.id, SessionRevokeReason::MfaChange)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    app_state
+      .user_access
+      .bump_token_version(&app_state.database, user.id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Refresh tokens issued before the organization required MFA do not bypass it
+pub async fn ensure_mfa_compliant(db:&DatabaseConnection,user:&users::Model) -> Result<(),AuthError> {
+    if user.mfa_enabled {
+        return Ok(());
+    }
+    let require_mfa = org_requires_mfa(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if require_mfa {
+        return Err(AuthError::MfaRequired);
+    }
+    Ok(())
+}
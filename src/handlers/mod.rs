@@ -12,3 +12,15 @@ pub mod models;
 pub mod admin_department;
 pub mod admin_sso_provider;
 pub mod open_error;
+pub mod prompt_templates;
+pub mod instructions;
+pub mod assistants;
+pub mod collections;
+pub mod sharing;
+pub mod storage;
+pub mod audio;
+pub mod images;
+pub mod password;
+pub mod mfa;
+pub mod sessions;
+pub mod api_keys;
\ No newline at end of file
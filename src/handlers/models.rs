@@ -1,6 +1,6 @@
 use axum::{Json, extract::State};
 use reqwest::StatusCode;
-use crate::{dto::models::{ModelInfo, ModelsResponse, ProviderInfo}, state::SharedState};
+use crate::{config::setting::OpenaiSettings, dto::models::{ModelInfo, ModelsResponse, ProviderInfo}, error::AppError, state::SharedState};
 
 pub fn list_models() -> Vec<ProviderInfo> {
    vec![
@@ -21,6 +21,7 @@ pub fn list_models() -> Vec<ProviderInfo> {
                     supports_pdf_native: true,
                     supports_web_search: false,
                     max_images: Some(50),
+                    max_image_dimension: Some(2048),
                 },
                 ModelInfo {
                     key: "gpt-5.2-mini".to_string(),
@@ -33,6 +34,7 @@ pub fn list_models() -> Vec<ProviderInfo> {
                     supports_pdf_native: true,
                     supports_web_search: false,
                     max_images: Some(50),
+                    max_image_dimension: Some(2048),
                 },
             ],
         },
@@ -53,6 +55,7 @@ pub fn list_models() -> Vec<ProviderInfo> {
                     supports_pdf_native: true,
                     supports_web_search: true,
                     max_images: Some(20),
+                    max_image_dimension: Some(1568),
                 },
                 ModelInfo {
                     key: "claude-opus-4-5".to_string(),
@@ -65,6 +68,7 @@ pub fn list_models() -> Vec<ProviderInfo> {
                     supports_pdf_native: true,
                     supports_web_search: true,
                     max_images: Some(20),
+                    max_image_dimension: Some(1568),
                 },
                 ModelInfo {
                     key: "claude-haiku-4-5".to_string(),
@@ -77,6 +81,7 @@ pub fn list_models() -> Vec<ProviderInfo> {
                     supports_pdf_native: false,
                     supports_web_search: false,
                     max_images: None,
+                    max_image_dimension: None,
                 },
             ],
         },
@@ -171,6 +176,43 @@ pub fn list_models() -> Vec<ProviderInfo> {
       ]
 }
 
+/// Resolutions uploaded images are normalized to, one per vision model maximum
+pub fn image_dimensions() -> Vec<u32> {
+    let mut dimensions = list_models()
+      .into_iter()
+      .flat_map(|provider_info| provider_info.models)
+      .filter(|model_info| model_info.supports_vision)
+      .filter_map(|model_info| model_info.max_image_dimension)
+      .collect::<Vec<_>>();
+    dimensions.sort_unstable();
+    dimensions.dedup();
+    dimensions
+}
+
+/// Settings of OpenAI when configured and enabled, for the endpoints only it serves
+pub async fn get_openai_settings(app_state:&SharedState) -> Result<OpenaiSettings,AppError> {
+    let settings = app_state
+      .settings
+      .openai
+      .read()
+      .await
+      .clone()
+      .ok_or(AppError::LlmProviderNotConfigured { provider:"openai".to_string() })?;
+    if !settings.is_enabled {
+        return Err(AppError::LlmProviderDisabledByAdmin { provider:"openai".to_string() });
+    }
+    Ok(settings)
+}
+
+/// Capabilities of a listed model, `None` for models the api does not know about
+pub fn find_model(provider:&str,model_name:&str) -> Option<ModelInfo> {
+    list_models()
+      .into_iter()
+      .filter(|provider_info| provider_info.key == provider)
+      .flat_map(|provider_info| provider_info.models)
+      .find(|model_info| model_info.key == model_name)
+}
+
 #[utoipa::path(
     get,
     path = "/models",
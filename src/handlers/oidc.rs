@@ -4,10 +4,41 @@ use chrono::Utc;
 use openidconnect::{AuthorizationCode, ClaimsVerificationError, CsrfToken, Nonce, OAuth2TokenResponse, PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, core::{CoreAuthenticationFlow, CoreUserInfoClaims}};
 use openidconnect::{TokenResponse as OidcTokenResponse};
 use axum::http::StatusCode;
-use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, TryIntoModel};
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, TryIntoModel};
 use uuid::Uuid;
-use crate::{auth::{claims::{Claiming as _, Claims, RefreshClaims}, error::AuthErrorResponse}, dto::oauth::AuthProvider, models::{oauth_sessions, users::{self, UserRole, UserStatus}}};
-use crate::{auth::error::{AuthError}, dto::{auth::{AuthTokenResponse, TokenType, User}, oauth::{OAuthCallback, StartParams}}, state::SharedState};
+use crate::{auth::{claims::{Claiming as _, Claims, RefreshClaims}, error::AuthErrorResponse}, dto::oauth::AuthProvider, models::{oauth_sessions, user_identities, users::{self, UserRole, UserStatus}}};
+use crate::{auth::{client::ClientInfo, error::AuthError}, handlers::{mfa::mfa_challenge, sessions::start_session}, dto::{auth::{AuthTokenResponse, LoginResponse, TokenType, User}, oauth::{OAuthCallback, StartParams}}, state::SharedState};
+
+/// Providers where users set their own email, e.g. a Keycloak realm, must vouch for it before it claims an existing account
+fn can_link_by_email(email_verified:Option<bool>) -> bool {
+    email_verified == Some(true)
+}
+
+/// Records the provider account of the user, moving it over when it belonged to a deleted user
+async fn link_identity(db:&DatabaseConnection,identity:Option<user_identities::Model>,user_id:Uuid,provider:&AuthProvider,subject:&str,email:Option<String>) -> Result<(),AuthError> {
+    let result = match identity {
+        Some(identity) => {
+            let mut active:user_identities::ActiveModel = identity.into();
+            active.user_id = Set(user_id);
+            active.email = Set(email);
+            active.last_login_at = Set(Utc::now());
+            active.update(db).await.map(|_| ())
+        }
+        None => user_identities::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            user_id: Set(user_id),
+            provider: Set(provider.to_lowercase()),
+            subject: Set(subject.to_string()),
+            email: Set(email),
+            created_at: Set(Utc::now()),
+            last_login_at: Set(Utc::now()),
+        }.insert(db).await.map(|_| ()),
+    };
+    result.map_err(|e| {
+        eprintln!("db error while saving identity: {e:?}");
+        AuthError::ServiceTemporarilyUnavailable
+    })
+}
 
 #[utoipa::path(
     get,
@@ -43,20 +74,18 @@ pub async fn oidc_login_start(
         return Err(AuthError::SsoProviderDisabledByAdmin{provider:Some(provider.clone())});
     }   
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
-    let (oidc_client, _,default_redirect_uri) = app_state
-        .get_oidc_client_and_column_and_redirect_uri(&provider)
+    let default_redirect_uri = app_state
+        .sso_provider_settings(&provider)
         .await
-        .map_err(|_| AuthError::InvalidProvider{provider:Some(provider.clone())})?;
+        .ok_or(AuthError::InvalidProvider{provider:Some(provider.clone())})?
+        .redirect_url;
     let redirect_uri = RedirectUrl::new(query.redirect_uri
         .clone()
-        .unwrap_or(default_redirect_uri
-            .ok_or(AuthError::SsoProviderNotConfigured{provider:Some(provider.clone())})?
-          )
+        .unwrap_or(default_redirect_uri)
         ).map_err(|_| AuthError::InvalidRedirectUri{redirect_uri:query.redirect_uri.clone()})?;
-    let (auth_url, csrf_state, nonce) = oidc_client
-        .read()
+    let (auth_url, csrf_state, nonce) = app_state
+        .get_oidc_client(&provider)
         .await
-        .as_ref()
         .ok_or(AuthError::SsoProviderNotConfigured{provider:Some(provider.clone())})?
         .authorize_url(
             CoreAuthenticationFlow::AuthorizationCode,
@@ -99,6 +128,7 @@ pub async fn oidc_login_start(
         ("error_description" = Option<String>, Query, description = "Error description from provider")
     ),
     responses(
+        (status = 200, body = LoginResponse, description = "Tokens, or the MFA challenge to complete with /auth/mfa/verify or enroll with /auth/mfa/setup"),
         (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Missing credentials (code=6102)"),
         (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid auth provider (code=6200)"),
         (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid callback parameters (code=6201)"),
@@ -118,16 +148,18 @@ pub async fn oidc_login_start(
 pub async fn oidc_oauth_callback_get(
     Path(provider): Path<AuthProvider>,
     Query(cb): Query<OAuthCallback>,
+    client: ClientInfo,
     State(app_state): State<SharedState>
-) -> Result<(StatusCode, Json<AuthTokenResponse>), AuthError> {
-    oidc_oauth_callback(provider, cb, app_state).await
+) -> Result<(StatusCode, Json<LoginResponse>), AuthError> {
+    oidc_oauth_callback(provider, cb, client, app_state).await
 }
 
 async fn oidc_oauth_callback(
     provider: AuthProvider,
     cb: OAuthCallback,
+    client: ClientInfo,
     app_state: SharedState,
-) -> Result<(StatusCode, Json<AuthTokenResponse>), AuthError> {
+) -> Result<(StatusCode, Json<LoginResponse>), AuthError> {
     // Check for OAuth error responses
     if let Some(error) = cb.error {
         eprintln!("OAuth error: {} - {:?}", error, cb.error_description);
@@ -136,14 +168,14 @@ async fn oidc_oauth_callback(
     let code = cb
      .code
      .ok_or(AuthError::InvalidCallbackParameters)?;
-    let (oidc_client_configured, column,default_redirect_uri) = app_state
-        .get_oidc_client_and_column_and_redirect_uri(&provider)
+    let default_redirect_uri = app_state
+        .sso_provider_settings(&provider)
         .await
-        .map_err(|_| AuthError::InvalidProvider{provider:Some(provider.clone())})?;
-    let mut oidc_client = oidc_client_configured
-        .read()
+        .ok_or(AuthError::InvalidProvider{provider:Some(provider.clone())})?
+        .redirect_url;
+    let mut oidc_client = app_state
+        .get_oidc_client(&provider)
         .await
-        .clone()
         .ok_or(AuthError::SsoProviderNotConfigured{provider:Some(provider.clone())})?;
     let sess = oauth_sessions::Entity::find()
         .filter(oauth_sessions::Column::State.eq(Some(cb.state.to_owned())))
@@ -156,9 +188,7 @@ async fn oidc_oauth_callback(
         .ok_or(AuthError::InvalidToken)?;
     let redirect_uri = RedirectUrl::new(sess.redirect_uri
          .clone()
-         .unwrap_or(default_redirect_uri
-            .ok_or(AuthError::SsoProviderNotConfigured{provider:Some(provider.clone())})?
-         ))
+         .unwrap_or(default_redirect_uri))
         .map_err(|_| AuthError::InvalidRedirectUri{redirect_uri:sess.redirect_uri.clone()})?;
     let active: oauth_sessions::ActiveModel = sess.clone().into();
     active.delete(&app_state.database)
@@ -203,10 +233,9 @@ async fn oidc_oauth_callback(
                 AuthError::ServiceTemporarilyUnavailable
             })?;
 
-        oidc_client = oidc_client_configured
-            .read()
+        oidc_client = app_state
+            .get_oidc_client(&provider)
             .await
-            .clone()
             .ok_or(AuthError::SsoProviderNotConfigured{provider:Some(provider.clone())})?;
 
         let verifier2 = oidc_client.id_token_verifier();
@@ -223,6 +252,7 @@ async fn oidc_oauth_callback(
        .to_string();
     let mut email = claims.email()
        .map(|e| e.as_str().to_string());
+    let mut email_verified = claims.email_verified();
     let mut display_name: Option<String> = None;
     let picture = claims.picture()
        .and_then(|pic_claim| pic_claim.get(None))       // default locale
@@ -230,8 +260,6 @@ async fn oidc_oauth_callback(
     let hd = claims.website()
        .and_then(|website_claim| website_claim.get(None))       // default locale
        .map(|url| url.as_str().to_owned());
-    let google_id = if provider == "google" {Some(sub.clone())}else{None};
-    let azure_id = if provider == "azure" {Some(sub.clone())}else{None};
     if email.is_none() {
         let info: CoreUserInfoClaims = oidc_client
             .user_info(token_resp.access_token().to_owned(), None)
@@ -240,6 +268,7 @@ async fn oidc_oauth_callback(
             .await
             .map_err(|_| AuthError::ServiceTemporarilyUnavailable)?;
         email = info.email().map(|e| e.as_str().to_string());
+        email_verified = info.email_verified();
         if display_name.is_none(){
           display_name = info.name().and_then(|n| n.get(None).map(|s| s.to_string()));
         }
@@ -254,15 +283,38 @@ async fn oidc_oauth_callback(
           return Err(AuthError::EmailDomainNotAllowed{domain});
        } 
     }
-    let mut user = users::Entity::find()
-        .filter(column.eq(Some(sub.clone())))
-        .filter(users::Column::Status.ne(UserStatus::Deleted))
-        .order_by_desc(users::Column::CreatedAt)
+    let identity = user_identities::Entity::find()
+        .filter(user_identities::Column::Provider.eq(provider.to_lowercase()))
+        .filter(user_identities::Column::Subject.eq(sub.clone()))
         .one(&app_state.database)
         .await
         .map_err(|e| {
-            eprintln!("db error while fetching user: {e:?}");
+            eprintln!("db error while fetching identity: {e:?}");
             AuthError::ServiceTemporarilyUnavailable})?;
+    let mut user = match &identity {
+        Some(identity) => users::Entity::find_by_id(identity.user_id)
+            .filter(users::Column::Status.ne(UserStatus::Deleted))
+            .one(&app_state.database)
+            .await
+            .map_err(|e| {
+                eprintln!("db error while fetching user: {e:?}");
+                AuthError::ServiceTemporarilyUnavailable})?,
+        None => None,
+    };
+    if user.is_none() && let Some(ref em) = email {
+        let existing = users::Entity::find()
+            .filter(users::Column::Email.eq(em))
+            .filter(users::Column::Status.ne(UserStatus::Deleted))
+            .one(&app_state.database)
+            .await
+            .map_err(|e|{
+                  eprintln!("{:?}",e);
+                  AuthError::ServiceTemporarilyUnavailable})?;
+        if existing.is_some() && !can_link_by_email(email_verified) {
+            return Err(AuthError::EmailAlreadyExist);
+        }
+        user = existing;
+    }
     if let Some(u) = &user {
       match &u.status {
          UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
@@ -276,52 +328,27 @@ async fn oidc_oauth_callback(
             eprintln!("db error while updating user {:?}",e);
             AuthError::ServiceTemporarilyUnavailable})?;
     }
-    if user.is_none() {
-        if let Some(ref em) = email {
-            user = users::Entity::find()
-                .filter(users::Column::Email.eq(em))
-                .filter(users::Column::Status.ne(UserStatus::Deleted))
-                .one(&app_state.database)
-                .await
-                .map_err(|e|{
-                      eprintln!("{:?}",e);
-                      AuthError::ServiceTemporarilyUnavailable})?;
-
-            if let Some(u) = &user {
-                match &u.status {
-                   UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
-                   _ => ()
-                }
-                let mut active_user:users::ActiveModel = u.clone().into();
-                active_user.google_id = Set(google_id.clone());
-                active_user.azure_id = Set(azure_id.clone());
-                active_user.updated_at = Set(Utc::now());
-                active_user.last_login_at = Set(Utc::now());
-                active_user.update(&app_state.database)
-                  .await
-                  .map_err(|e|{
-                      eprintln!("db error while updating user {:?}",e);
-                      AuthError::ServiceTemporarilyUnavailable})?;
-            }
-        }
-    }
     if user.is_none() {
         let new_user = users::ActiveModel{
             id: Set(Uuid::new_v4()),
             org_id:Set(None),
             email: Set(email.clone().unwrap_or_else(|| format!("{sub}@users.noreply.oidc"))),
             name: Set(display_name.into()),
-            google_id: Set(google_id),
-            azure_id:Set(azure_id),
-            email_verified:Set(true),
+            email_verified:Set(email_verified == Some(true)),
             created_at:Set(Utc::now()),
             updated_at:Set(Utc::now()),
             last_login_at:Set(Utc::now()),
             password_changed_at:Set(None),
+            failed_login_attempts:Set(0),
+            locked_until:Set(None),
             department:Set(None),
             status:Set(UserStatus::Active),
             mfa_enabled:Set(false),
             mfa_secret:Set(None),
+            mfa_recovery_codes:Set(Vec::new()),
+            mfa_last_step:Set(None),
+            token_version:Set(0),
+            is_service_account:Set(false),
             picture:Set(picture.clone()),
             password:Set(None),
             role:Set(UserRole::SuperAdmin),
@@ -341,9 +368,20 @@ async fn oidc_oauth_callback(
     };
     let user = user
       .ok_or(AuthError::EmailDoesNotExist)?;
+    link_identity(&app_state.database, identity, user.id, &provider, &sub, email)
+      .await?;
+    if let Some(challenge) = mfa_challenge(&app_state.database, &user).await? {
+        return Ok((StatusCode::OK, Json(LoginResponse::MfaChallenge(challenge))));
+    }
 
-    let access_token_claims = Claims::new_access_token(user.email.clone(), user.name.clone(), user.id,user.org_id,user.role);
-    let refresh_token_claims = RefreshClaims::new_refresh_token(user.email.clone(), user.id);
+    let session = start_session(&app_state.database, user.id, &client)
+      .await
+      .map_err(|e| {
+         eprintln!("db insert error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let access_token_claims = Claims::new_access_token(user.email.clone(), user.name.clone(), user.id,user.org_id,user.role,Some(session.id),user.token_version);
+    let refresh_token_claims = RefreshClaims::new_refresh_token(user.email.clone(), user.id, session.id, session.refresh_jti);
     let user_response = User {
         id: user.id,
         sub: sub.clone(),
@@ -358,7 +396,7 @@ async fn oidc_oauth_callback(
         has_password: user.password.is_some(), // SSO-only users don't have password
         mfa_enabled: user.mfa_enabled,
         last_login_at: Some(user.last_login_at),
-        password_changed_at: None,
+        password_changed_at: user.password_changed_at,
         created_at: user.created_at,
         updated_at: user.updated_at,
     };
@@ -367,10 +405,10 @@ async fn oidc_oauth_callback(
         access_token:access_token_claims.get_token_string(),
         token_type: TokenType::Bearer,
         expires_in: 3600, // 1 hour - match your JWT expiry
-        refresh_token: Some(refresh_token_claims.get_token_string()), // TODO: Implement refresh token logic if needed
+        refresh_token: Some(refresh_token_claims.get_token_string()),
         user: Some(user_response),
     };
-  Ok((StatusCode::OK, Json(resp)))
+  Ok((StatusCode::OK, Json(LoginResponse::Tokens(Box::new(resp)))))
 }
 
 #[utoipa::path(
@@ -383,6 +421,7 @@ async fn oidc_oauth_callback(
     ),
     request_body(content = OAuthCallback, description = "OAuth callback parameters"),
     responses(
+        (status = 200, body = LoginResponse, description = "Tokens, or the MFA challenge to complete with /auth/mfa/verify or enroll with /auth/mfa/setup"),
         (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Missing credentials (code=6102)"),
         (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid auth provider (code=6200)"),
         (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Invalid callback parameters (code=6201)"),
@@ -401,8 +440,21 @@ async fn oidc_oauth_callback(
 )]
 pub async fn oidc_oauth_callback_post(
     Path(provider): Path<AuthProvider>,
+    client: ClientInfo,
     State(app_state): State<SharedState>,
     Json(cb): Json<OAuthCallback>,
-) -> Result<(StatusCode, Json<AuthTokenResponse>), AuthError> {
-    oidc_oauth_callback(provider, cb, app_state).await
-}
\ No newline at end of file
+) -> Result<(StatusCode, Json<LoginResponse>), AuthError> {
+    oidc_oauth_callback(provider, cb, client, app_state).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unverified_emails_do_not_link_existing_accounts() {
+        assert!(can_link_by_email(Some(true)));
+        assert!(!can_link_by_email(Some(false)));
+        assert!(!can_link_by_email(None));
+    }
+}
@@ -0,0 +1,620 @@
+use axum::{Json, extract::{Path, State}};
+use chrono::{DateTime, Duration, Utc};
+use migration::Expr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{auth::{claims::Claims, client::ClientInfo, error::{AuthError, AuthErrorResponse}, password::{check_password, hash_password, new_password_token, password_token_hash, verify_password}}, dto::auth::{LoginResponse, PasswordChangeRequest, PasswordForgotRequest, PasswordInviteResponse, PasswordLoginRequest, PasswordPolicy, PasswordResetRequest}, error::{AppError, ErrorResponse}, handlers::{auth::issue_tokens, mfa::mfa_challenge, sessions::{revoke_other_sessions, revoke_user_sessions}}, models::{organizations, password_tokens::{self, PasswordTokenPurpose}, sessions::SessionRevokeReason, users::{self, UserRole, UserStatus}}, state::SharedState};
+
+/// Invites give the user time to read their email, resets are used right away
+const INVITE_TTL_DAYS:i64 = 7;
+const RESET_TTL_MINUTES:i64 = 60;
+
+fn parse_password_policy(value:&Option<serde_json::Value>) -> PasswordPolicy {
+    value
+      .as_ref()
+      .and_then(|json| serde_json::from_value(json.clone()).ok())
+      .unwrap_or_default()
+}
+
+/// Password policy of the organization, the defaults when none is set
+pub async fn load_password_policy(db:&DatabaseConnection) -> Result<PasswordPolicy,DbErr> {
+    let org = organizations::Entity::find().one(db).await?;
+    Ok(org.map(|org| parse_password_policy(&org.password_policy)).unwrap_or_default())
+}
+
+async fn new_password_hash(db:&DatabaseConnection,password:String) -> Result<String,AuthError> {
+    let policy = load_password_policy(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    check_password(&policy, &password).map_err(|rule| AuthError::WeakPassword { rule, min_length:policy.min_length })?;
+    hash_password(password)
+      .await
+      .map_err(|e| {
+         eprintln!("{e}");
+         AuthError::ServiceTemporarilyUnavailable
+      })
+}
+
+/// Sets the password of the user, clearing its lockout and the tokens still pending
+async fn set_password(db:&DatabaseConnection,user:users::Model,password_hash:String,email_verified:bool) -> Result<(),DbErr> {
+    let user_id = user.id;
+    let email_verified = user.email_verified || email_verified;
+    let mut active_model = user.into_active_model();
+    active_model.password = Set(Some(password_hash));
+    active_model.password_changed_at = Set(Some(Utc::now()));
+    active_model.failed_login_attempts = Set(0);
+    active_model.locked_until = Set(None);
+    active_model.email_verified = Set(email_verified);
+    active_model.updated_at = Set(Utc::now());
+    active_model
+      .update(db)
+      .await?;
+    delete_pending_tokens(db, user_id).await
+}
+
+async fn delete_pending_tokens(db:&DatabaseConnection,user_id:Uuid) -> Result<(),DbErr> {
+    password_tokens::Entity::delete_many()
+      .filter(password_tokens::Column::UserId.eq(user_id))
+      .filter(password_tokens::Column::UsedAt.is_null())
+      .exec(db)
+      .await?;
+    Ok(())
+}
+
+/// Replaces the pending tokens of the user with a new one, returning the token to send
+async fn create_password_token(db:&DatabaseConnection,user_id:Uuid,purpose:PasswordTokenPurpose,created_by:Option<Uuid>) -> Result<(String,password_tokens::Model),DbErr> {
+    delete_pending_tokens(db, user_id).await?;
+    let (token, token_hash) = new_password_token();
+    let ttl = match purpose {
+        PasswordTokenPurpose::Invite => Duration::days(INVITE_TTL_DAYS),
+        PasswordTokenPurpose::Reset => Duration::minutes(RESET_TTL_MINUTES),
+    };
+    let password_token = password_tokens::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        user_id:Set(user_id),
+        purpose:Set(purpose),
+        token_hash:Set(token_hash),
+        expires_at:Set(Utc::now() + ttl),
+        used_at:Set(None),
+        created_by:Set(created_by),
+        created_at:Set(Utc::now()),
+    }
+    .insert(db)
+    .await?;
+    Ok((token, password_token))
+}
+
+fn password_link(app_state:&SharedState,token:&str) -> String {
+    format!("{}?token={token}", app_state.settings.auth.password_reset_url)
+}
+
+/// Counts a failed login, locking the account once the policy limit is reached
+pub async fn register_failed_login(db:&DatabaseConnection,user_id:Uuid,policy:&PasswordPolicy) -> Result<Option<DateTime<Utc>>,DbErr> {
+    // Incremented in the database, concurrent attempts are all counted
+    let attempts = users::Entity::update_many()
+      .col_expr(users::Column::FailedLoginAttempts, Expr::col(users::Column::FailedLoginAttempts).add(1))
+      .filter(users::Column::Id.eq(user_id))
+      .exec_with_returning(db)
+      .await?
+      .first()
+      .map(|user| user.failed_login_attempts)
+      .unwrap_or_default();
+    if (attempts as u32) < policy.max_failed_attempts {
+        return Ok(None);
+    }
+    let locked_until = Utc::now() + Duration::minutes(policy.lockout_minutes as i64);
+    users::Entity::update_many()
+      .col_expr(users::Column::FailedLoginAttempts, Expr::value(0))
+      .col_expr(users::Column::LockedUntil, Expr::value(Some(locked_until)))
+      .filter(users::Column::Id.eq(user_id))
+      .exec(db)
+      .await?;
+    Ok(Some(locked_until))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = PasswordLoginRequest,
+    responses(
+       (status = 200, body = LoginResponse, description = "Tokens, or the MFA challenge to complete with /auth/mfa/verify or enroll with /auth/mfa/setup"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Missing credentials (code=6102)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid email or password (code=6100) or account deactivated (code=6105)"),
+       (status = 423, content_type = "application/json", body = AuthErrorResponse, description = "Account locked after repeated failures (code=6107)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn password_login(
+    client: ClientInfo,
+    State(app_state): State<SharedState>,
+    Json(req): Json<PasswordLoginRequest>,
+) -> Result<(StatusCode,Json<LoginResponse>), AuthError> {
+    let email = req.email.trim();
+    if email.is_empty() || req.password.is_empty() {
+        return Err(AuthError::MissingCredentials);
+    }
+    // SSO-only accounts have no password, they are refused like unknown emails
+    let user = users::Entity::find()
+      .filter(users::Column::Email.eq(email))
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .filter(users::Column::Password.is_not_null())
+      .order_by_desc(users::Column::CreatedAt)
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if let Some(locked_until) = user.as_ref().and_then(|user| user.locked_until)
+      && locked_until > Utc::now() {
+        return Err(AuthError::AccountLocked { locked_until });
+    }
+    let is_valid = verify_password(req.password, user.as_ref().and_then(|user| user.password.clone()))
+      .await
+      .map_err(|e| {
+         eprintln!("password verify error: {e}");
+         AuthError::ServiceTemporarilyUnavailable
+      })?;
+    let user = user.ok_or(AuthError::InvalidCredentials)?;
+    if !is_valid {
+        let policy = load_password_policy(&app_state.database)
+          .await
+          .map_err(|e| {
+             eprintln!("db get one error: {e}");
+             AuthError::DbTimeout
+          })?;
+        let locked_until = register_failed_login(&app_state.database, user.id, &policy)
+          .await
+          .map_err(|e| {
+             eprintln!("db update error: {e}");
+             AuthError::DbTimeout
+          })?;
+        return Err(match locked_until {
+            Some(locked_until) => AuthError::AccountLocked { locked_until },
+            None => AuthError::InvalidCredentials,
+        });
+    }
+    match user.status {
+        UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
+        _ => ()
+    }
+    // Failures stay counted until the second factor completes the login
+    if let Some(challenge) = mfa_challenge(&app_state.database, &user).await? {
+        return Ok((StatusCode::OK,Json(LoginResponse::MfaChallenge(challenge))));
+    }
+    let mut active_model = user.into_active_model();
+    active_model.failed_login_attempts = Set(0);
+    active_model.locked_until = Set(None);
+    active_model.last_login_at = Set(Utc::now());
+    let user = active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(LoginResponse::Tokens(Box::new(issue_tokens(&app_state.database, user, &client).await?)))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password/change",
+    tag = "auth",
+    request_body = PasswordChangeRequest,
+    responses(
+       (status = 202, description = "The account has no password yet, a link setting the first one was emailed to the user"),
+       (status = 204, description = "Password changed"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Missing current password (code=6102) or password not meeting the policy (code=6108)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103) or wrong current password (code=6100)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000), also when no mailer is configured for a first password"),
+    )
+)]
+pub async fn change_password(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<PasswordChangeRequest>,
+) -> Result<StatusCode, AuthError> {
+    let user = users::Entity::find_by_id(claims.user_id)
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::EmailDoesNotExist)?;
+    // A stolen access token alone must not add a lasting login, SSO accounts prove they own the mailbox
+    let Some(password_hash) = user.password.clone() else {
+        return email_first_password_link(&app_state, user).await;
+    };
+    let current_password = req.current_password.ok_or(AuthError::MissingCredentials)?;
+    let is_valid = verify_password(current_password, Some(password_hash))
+      .await
+      .map_err(|e| {
+         eprintln!("password verify error: {e}");
+         AuthError::ServiceTemporarilyUnavailable
+      })?;
+    if !is_valid {
+        return Err(AuthError::InvalidCredentials);
+    }
+    let password_hash = new_password_hash(&app_state.database, req.new_password).await?;
+    let user_id = user.id;
+    set_password(&app_state.database, user, password_hash, false)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    // Other devices are signed out, this one gets new tokens with its refresh token
+    revoke_other_sessions(&app_state.database, user_id, claims.sid, SessionRevokeReason::PasswordChange)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    app_state
+      .user_access
+      .bump_token_version(&app_state.database, user_id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Emails an SSO account the link setting its first password
+async fn email_first_password_link(app_state:&SharedState,user:users::Model) -> Result<StatusCode,AuthError> {
+    let Some(mailer) = app_state.mailer.clone() else {
+        eprintln!("first password requested but no mailer is configured");
+        return Err(AuthError::ServiceTemporarilyUnavailable);
+    };
+    let (token, _) = create_password_token(&app_state.database, user.id, PasswordTokenPurpose::Reset, None)
+      .await
+      .map_err(|e| {
+         eprintln!("db insert one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let body = format!(
+        "A password was requested for your account.\n\nSet it within {RESET_TTL_MINUTES} minutes:\n{}\n\nIgnore this email if you did not request it.",
+        password_link(app_state, &token),
+    );
+    mailer
+      .send(&user.email, "Set your password", &body)
+      .await
+      .map_err(|e| {
+         eprintln!("first password email error: {e}");
+         AuthError::ServiceTemporarilyUnavailable
+      })?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password/forgot",
+    tag = "auth",
+    request_body = PasswordForgotRequest,
+    responses(
+       (status = 202, description = "Reset link emailed when the email belongs to a password account, the answer is the same otherwise"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Missing email (code=6102)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn forgot_password(
+    State(app_state): State<SharedState>,
+    Json(req): Json<PasswordForgotRequest>,
+) -> Result<StatusCode, AuthError> {
+    let email = req.email.trim();
+    if email.is_empty() {
+        return Err(AuthError::MissingCredentials);
+    }
+    let Some(mailer) = app_state.mailer.clone() else {
+        eprintln!("password reset requested but no mailer is configured");
+        return Ok(StatusCode::ACCEPTED);
+    };
+    let user = users::Entity::find()
+      .filter(users::Column::Email.eq(email))
+      .filter(users::Column::Status.eq(UserStatus::Active))
+      .filter(users::Column::Password.is_not_null())
+      .order_by_desc(users::Column::CreatedAt)
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let Some(user) = user else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+    let (token, _) = create_password_token(&app_state.database, user.id, PasswordTokenPurpose::Reset, None)
+      .await
+      .map_err(|e| {
+         eprintln!("db insert one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let body = format!(
+        "A password reset was requested for your account.\n\nSet a new password within {RESET_TTL_MINUTES} minutes:\n{}\n\nIgnore this email if you did not request it.",
+        password_link(&app_state, &token),
+    );
+    // Sent in the background, the answer time does not tell which emails have an account
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&user.email, "Reset your password", &body).await {
+            eprintln!("password reset email error: {e}");
+        }
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password/reset",
+    tag = "auth",
+    request_body = PasswordResetRequest,
+    responses(
+       (status = 204, description = "Password set, the user signs in with it"),
+       (status = 400, content_type = "application/json", body = AuthErrorResponse, description = "Password not meeting the policy (code=6108)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid, used or expired token (code=6103) or account deactivated (code=6105)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000) or auth service temporarily unavailable (code=6000)"),
+    )
+)]
+pub async fn reset_password(
+    State(app_state): State<SharedState>,
+    Json(req): Json<PasswordResetRequest>,
+) -> Result<StatusCode, AuthError> {
+    let password_token = password_tokens::Entity::find()
+      .filter(password_tokens::Column::TokenHash.eq(password_token_hash(req.token.trim())))
+      .filter(password_tokens::Column::UsedAt.is_null())
+      .filter(password_tokens::Column::ExpiresAt.gt(Utc::now()))
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::InvalidToken)?;
+    let user = users::Entity::find_by_id(password_token.user_id)
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::InvalidToken)?;
+    match user.status {
+        UserStatus::Deactivated | UserStatus::Suspended => return Err(AuthError::AccountDeactivated),
+        _ => ()
+    }
+    let password_hash = new_password_hash(&app_state.database, req.new_password).await?;
+    // Consumed atomically, of concurrent requests with the same link only one goes through
+    let consumed = password_tokens::Entity::update_many()
+      .col_expr(password_tokens::Column::UsedAt, Expr::value(Utc::now()))
+      .filter(password_tokens::Column::Id.eq(password_token.id))
+      .filter(password_tokens::Column::UsedAt.is_null())
+      .exec(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if consumed.rows_affected != 1 {
+        return Err(AuthError::InvalidToken);
+    }
+    let user_id = user.id;
+    // The link reached the inbox of the user, their email is verified
+    set_password(&app_state.database, user, password_hash, true)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    // Whoever knew the previous password is signed out
+    revoke_user_sessions(&app_state.database, user_id, SessionRevokeReason::PasswordReset)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    app_state
+      .user_access
+      .bump_token_version(&app_state.database, user_id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{user_id}/password-invite",
+    tag = "admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User id")
+    ),
+    responses(
+       (status = 201, body = PasswordInviteResponse),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn invite_user_password(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<(StatusCode,Json<PasswordInviteResponse>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    // Service accounts never sign in, they use their API keys
+    let user = users::Entity::find_by_id(user_id)
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .filter(users::Column::IsServiceAccount.eq(false))
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::EmailDoesNotExist)?;
+    // An admin setting the password of a super admin could sign in as them
+    if user.role == UserRole::SuperAdmin && claims.role != UserRole::SuperAdmin {
+        return Err(AuthError::PermissionDenied);
+    }
+    let (token, password_token) = create_password_token(&app_state.database, user.id, PasswordTokenPurpose::Invite, Some(claims.user_id))
+      .await
+      .map_err(|e| {
+         eprintln!("db insert one error: {e}");
+         AuthError::DbTimeout
+      })?;
+    let url = password_link(&app_state, &token);
+    let emailed = match app_state.mailer.as_ref() {
+        Some(mailer) => {
+            let body = format!(
+                "You were invited to sign in with a password.\n\nSet your password within {INVITE_TTL_DAYS} days:\n{url}",
+            );
+            mailer
+              .send(&user.email, "Set your password", &body)
+              .await
+              .map_err(|e| eprintln!("password invite email error: {e}"))
+              .is_ok()
+        },
+        None => false,
+    };
+    // The link only reaches the admin when they have to share it themselves
+    let url = (!emailed).then_some(url);
+    Ok((StatusCode::CREATED,Json(PasswordInviteResponse { url, expires_at:password_token.expires_at, emailed })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{user_id}/lockout",
+    tag = "admin",
+    params(
+        ("user_id" = Uuid, Path, description = "User id")
+    ),
+    responses(
+       (status = 204, description = "Lockout cleared"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6101)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn unlock_user(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let result = users::Entity::update_many()
+      .col_expr(users::Column::FailedLoginAttempts, Expr::value(0))
+      .col_expr(users::Column::LockedUntil, Expr::value(None::<DateTime<Utc>>))
+      .filter(users::Column::Id.eq(user_id))
+      .filter(users::Column::Status.ne(UserStatus::Deleted))
+      .exec(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if result.rows_affected == 0 {
+        return Err(AuthError::EmailDoesNotExist);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/auth/password-policy",
+    tag = "admin",
+    responses(
+       (status = 200, body = PasswordPolicy),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = ErrorResponse, description = "Permission denied (code=1002)"),
+       (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_password_policy(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<PasswordPolicy>), AppError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AppError::PermissionDenied),
+    }
+    let policy = load_password_policy(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AppError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(policy)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/auth/password-policy",
+    tag = "admin",
+    request_body = PasswordPolicy,
+    responses(
+       (status = 200, body = PasswordPolicy),
+       (status = 400, content_type = "application/json", body = ErrorResponse, description = "Minimum length outside 8 to 128, or no failed attempts or lockout minutes (code=2003)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = ErrorResponse, description = "Permission denied (code=1002)"),
+       (status = 404, content_type = "application/json", body = ErrorResponse, description = "Organization not found (code=1001)"),
+       (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn update_password_policy(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<PasswordPolicy>
+) -> Result<(StatusCode,Json<PasswordPolicy>), AppError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AppError::PermissionDenied),
+    }
+    if !(8..=128).contains(&req.min_length) {
+        return Err(AppError::invalid_field("min_length"));
+    }
+    if req.max_failed_attempts == 0 {
+        return Err(AppError::invalid_field("max_failed_attempts"));
+    }
+    if req.lockout_minutes == 0 {
+        return Err(AppError::invalid_field("lockout_minutes"));
+    }
+    let org = organizations::Entity::find()
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AppError::DbTimeout
+      })?
+      .ok_or(AppError::ResourceNotFound)?;
+    let mut active_model = org.into_active_model();
+    active_model.password_policy = Set(Some(json!(req)));
+    active_model.updated_on = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+          eprintln!("update error: {e}");
+          AppError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(req)))
+}
@@ -0,0 +1,400 @@
+use axum::{Json, extract::{Path, Query, State}};
+use chrono::Utc;
+use migration::extension::postgres::PgExpr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, sea_query::Expr};
+use uuid::Uuid;
+use crate::{
+    auth::{claims::Claims, error::AuthErrorResponse},
+    dto::prompt_templates::{PromptTemplateQuery, PromptTemplateRequest, PromptTemplateResponse, PromptTemplateUpdateRequest, TemplateScope},
+    error::{AppError, ErrorResponse},
+    handlers::sharing::is_admin,
+    models::prompt_templates::{self, FLAG_SET, FLAG_UNSET},
+    state::SharedState,
+};
+
+const DEFAULT_CATEGORY:&str = "general";
+
+/// Names of the `{{variable}}` placeholders of a template, in order of first appearance
+pub fn template_variables(prompt_text:&str) -> Vec<String> {
+    let mut variables:Vec<String> = Vec::new();
+    let mut rest = prompt_text;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else { break };
+        let name = rest[..end].trim();
+        if !name.is_empty() && !name.contains('{') && !variables.iter().any(|variable| variable == name) {
+            variables.push(name.to_string());
+        }
+        rest = &rest[end + 2..];
+    }
+    variables
+}
+
+/// Templates a user can see: their own, the organization ones and the system ones
+fn visible_to(user_id:Uuid) -> Condition {
+    Condition::any()
+        .add(prompt_templates::Column::UserId.eq(user_id))
+        .add(prompt_templates::Column::PublicFlag.eq(FLAG_SET))
+        .add(prompt_templates::Column::SystemFlagTemplate.eq(FLAG_SET))
+}
+
+fn template_scope(template:&prompt_templates::Model) -> TemplateScope {
+    if template.is_system() {
+        TemplateScope::System
+    } else if template.is_public() {
+        TemplateScope::Organization
+    } else {
+        TemplateScope::Personal
+    }
+}
+
+/// Values of the `publicFlag` and `systemFlagTemplate` columns for a scope
+fn scope_flags(scope:TemplateScope) -> (&'static str,&'static str) {
+    match scope {
+        TemplateScope::Personal => (FLAG_UNSET, FLAG_UNSET),
+        TemplateScope::Organization => (FLAG_SET, FLAG_UNSET),
+        TemplateScope::System => (FLAG_UNSET, FLAG_SET),
+    }
+}
+
+/// Owners edit their templates, admins also edit organization and system ones
+fn can_modify(claims:&Claims,template:&prompt_templates::Model) -> bool {
+    match template_scope(template) {
+        TemplateScope::Personal => template.user_id == Some(claims.user_id),
+        TemplateScope::Organization => template.user_id == Some(claims.user_id) || is_admin(claims),
+        TemplateScope::System => is_admin(claims),
+    }
+}
+
+fn to_template_response(template:prompt_templates::Model,user_id:Uuid) -> PromptTemplateResponse {
+    PromptTemplateResponse {
+        id:template.id,
+        variables:template_variables(&template.prompt_text),
+        scope:template_scope(&template),
+        owned:template.user_id == Some(user_id),
+        name:template.name,
+        description:template.description,
+        category:template.category,
+        prompt_text:template.prompt_text,
+        model_provider:template.model_provider,
+        model_name:template.model_name,
+        usage_counter:template.usage_counter,
+        created_at:template.created_at,
+        updated_at:template.updated_at,
+        metadata:template.metadata,
+    }
+}
+
+async fn find_visible_template(app_state:&SharedState,claims:&Claims,template_id:Uuid) -> Result<prompt_templates::Model,AppError> {
+    prompt_templates::Entity::find_by_id(template_id)
+      .filter(visible_to(claims.user_id))
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("DB get one error {:?}", e);
+         AppError::DbTimeout})?
+      .ok_or(AppError::DbNotFound)
+}
+
+/// Counts a chat seeded from a template visible to the user
+pub async fn increment_template_usage(db:&DatabaseConnection,user_id:Uuid,template_id:Uuid) -> Result<(),DbErr> {
+    prompt_templates::Entity::update_many()
+      .col_expr(prompt_templates::Column::UsageCounter, Expr::col(prompt_templates::Column::UsageCounter).add(1))
+      .filter(prompt_templates::Column::Id.eq(template_id))
+      .filter(visible_to(user_id))
+      .exec(db)
+      .await?;
+ Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/prompt-templates",
+    tag = "prompt-templates",
+    params(
+        ("limit" = Option<u64>, Query, description = "Default value : 30"),
+        ("offset" = Option<u64>, Query, description = "Default value : 0"),
+        ("search" = Option<String>, Query, description = "Search in names, descriptions and prompt texts"),
+        ("category" = Option<String>, Query, description = "Exact category"),
+        ("scope" = Option<TemplateScope>, Query, description = "personal, organization or system"),
+    ),
+    responses(
+        (status = 200, body = Vec<PromptTemplateResponse>),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_prompt_templates(
+  claims:Claims,
+  Query(query):Query<PromptTemplateQuery>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<Vec<PromptTemplateResponse>>),AppError>{
+    let mut select = prompt_templates::Entity::find()
+        .filter(visible_to(claims.user_id));
+    if let Some(search) = query.search {
+        let pattern = format!("%{}%",search);
+        select = select.filter(Condition::any()
+            .add(prompt_templates::Column::Name.into_expr().ilike(pattern.clone()))
+            .add(prompt_templates::Column::Description.into_expr().ilike(pattern.clone()))
+            .add(prompt_templates::Column::PromptText.into_expr().ilike(pattern)));
+    }
+    if let Some(category) = query.category {
+        select = select.filter(prompt_templates::Column::Category.eq(category));
+    }
+    if let Some(scope) = query.scope {
+        let (public_flag, system_flag) = scope_flags(scope);
+        select = select
+            .filter(prompt_templates::Column::PublicFlag.eq(public_flag))
+            .filter(prompt_templates::Column::SystemFlagTemplate.eq(system_flag));
+        if scope == TemplateScope::Personal {
+            select = select.filter(prompt_templates::Column::UserId.eq(claims.user_id));
+        }
+    }
+    let templates = select
+        .order_by_desc(prompt_templates::Column::UsageCounter)
+        .order_by_desc(prompt_templates::Column::UpdatedAt)
+        .limit(query.limit.unwrap_or(30))
+        .offset(query.offset.unwrap_or(0))
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?;
+    let response = templates
+        .into_iter()
+        .map(|template| to_template_response(template, claims.user_id))
+        .collect();
+  Ok((StatusCode::OK,Json(response)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/prompt-templates/categories",
+    tag = "prompt-templates",
+    responses(
+        (status = 200, body = Vec<String>),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_prompt_template_categories(
+  claims:Claims,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<Vec<String>>),AppError>{
+    let categories = prompt_templates::Entity::find()
+        .select_only()
+        .column(prompt_templates::Column::Category)
+        .distinct()
+        .filter(visible_to(claims.user_id))
+        .order_by_asc(prompt_templates::Column::Category)
+        .into_tuple::<String>()
+        .all(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("DB get many error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok((StatusCode::OK,Json(categories)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/prompt-templates/{template_id}",
+    tag = "prompt-templates",
+    params(
+        ("template_id" = Uuid, Path, description = "Prompt template id"),
+    ),
+    responses(
+        (status = 200, body = PromptTemplateResponse),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Template not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn get_prompt_template_by_id(
+  claims:Claims,
+  Path(template_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<(StatusCode,Json<PromptTemplateResponse>),AppError>{
+    let template = find_visible_template(&app_state, &claims, template_id).await?;
+  Ok((StatusCode::OK,Json(to_template_response(template, claims.user_id))))
+}
+
+#[utoipa::path(
+    post,
+    path = "/prompt-templates",
+    tag = "prompt-templates",
+    request_body = PromptTemplateRequest,
+    responses(
+        (status = 201, body = PromptTemplateResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2002 empty name or prompt text)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "System templates are reserved to admins (code=1002)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn add_prompt_template(
+  claims:Claims,
+  State(app_state): State<SharedState>,
+  Json(req):Json<PromptTemplateRequest>
+) -> Result<(StatusCode,Json<PromptTemplateResponse>),AppError>{
+    if req.name.trim().is_empty() {
+        return Err(AppError::empty_field("name"));
+    }
+    if req.prompt_text.trim().is_empty() {
+        return Err(AppError::empty_field("prompt_text"));
+    }
+    let scope = req.scope.unwrap_or(TemplateScope::Personal);
+    if scope == TemplateScope::System && !is_admin(&claims) {
+        return Err(AppError::PermissionDenied);
+    }
+    let (public_flag, system_flag) = scope_flags(scope);
+    let new_template = prompt_templates::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        // System templates belong to nobody, they outlive the admin who wrote them
+        user_id:Set((scope != TemplateScope::System).then_some(claims.user_id)),
+        name:Set(req.name.trim().to_string()),
+        model_provider:Set(req.model_provider.unwrap_or_default()),
+        model_name:Set(req.model_name.unwrap_or_default()),
+        created_at:Set(Utc::now()),
+        updated_at:Set(Utc::now()),
+        usage_counter:Set(0),
+        description:Set(req.description.unwrap_or_default()),
+        category:Set(req.category.unwrap_or_else(|| DEFAULT_CATEGORY.to_string())),
+        prompt_text:Set(req.prompt_text),
+        public_flag:Set(public_flag.to_string()),
+        system_flag_template:Set(system_flag.to_string()),
+        metadata:Set(req.metadata),
+    };
+    let template = new_template
+        .insert(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db insert one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok((StatusCode::CREATED,Json(to_template_response(template, claims.user_id))))
+}
+
+#[utoipa::path(
+    put,
+    path = "/prompt-templates/{template_id}",
+    tag = "prompt-templates",
+    params(
+        ("template_id" = Uuid, Path, description = "Prompt template id"),
+    ),
+    request_body = PromptTemplateUpdateRequest,
+    responses(
+        (status = 200, body = PromptTemplateResponse),
+        (status = 400, content_type = "application/json", body = ErrorResponse, description = "Validation error (code=2002 empty name or prompt text)"),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Template not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Template not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn update_prompt_template_by_id(
+  claims:Claims,
+  Path(template_id):Path<Uuid>,
+  State(app_state): State<SharedState>,
+  Json(req):Json<PromptTemplateUpdateRequest>
+) -> Result<(StatusCode,Json<PromptTemplateResponse>),AppError>{
+    let template = find_visible_template(&app_state, &claims, template_id).await?;
+    if !can_modify(&claims, &template) {
+        return Err(AppError::PermissionDenied);
+    }
+    let current_scope = template_scope(&template);
+    let mut active_template = template.into_active_model();
+    if let Some(name) = req.name {
+        if name.trim().is_empty() {
+            return Err(AppError::empty_field("name"));
+        }
+        active_template.name = Set(name.trim().to_string());
+    }
+    if let Some(prompt_text) = req.prompt_text {
+        if prompt_text.trim().is_empty() {
+            return Err(AppError::empty_field("prompt_text"));
+        }
+        active_template.prompt_text = Set(prompt_text);
+    }
+    if let Some(description) = req.description {
+        active_template.description = Set(description);
+    }
+    if let Some(category) = req.category {
+        active_template.category = Set(category);
+    }
+    if let Some(model_provider) = req.model_provider {
+        active_template.model_provider = Set(model_provider);
+    }
+    if let Some(model_name) = req.model_name {
+        active_template.model_name = Set(model_name);
+    }
+    if req.metadata.is_some() {
+        active_template.metadata = Set(req.metadata);
+    }
+    if let Some(scope) = req.scope.filter(|scope| *scope != current_scope) {
+        if (scope == TemplateScope::System || current_scope == TemplateScope::System) && !is_admin(&claims) {
+            return Err(AppError::PermissionDenied);
+        }
+        let (public_flag, system_flag) = scope_flags(scope);
+        active_template.public_flag = Set(public_flag.to_string());
+        active_template.system_flag_template = Set(system_flag.to_string());
+        if scope == TemplateScope::System {
+            active_template.user_id = Set(None);
+        } else if current_scope == TemplateScope::System {
+            active_template.user_id = Set(Some(claims.user_id));
+        }
+    }
+    active_template.updated_at = Set(Utc::now());
+    let template = active_template
+        .update(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db update one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok((StatusCode::OK,Json(to_template_response(template, claims.user_id))))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/prompt-templates/{template_id}",
+    tag = "prompt-templates",
+    params(
+        ("template_id" = Uuid, Path, description = "Prompt template id"),
+    ),
+    responses(
+        (status = 204),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 403, content_type = "application/json", body = ErrorResponse, description = "Template not modifiable by the user (code=1002)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "Template not found (code=5003)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000) or service temporarily unavailable (code=1000)"),
+    )
+)]
+pub async fn delete_prompt_template_by_id(
+  claims:Claims,
+  Path(template_id):Path<Uuid>,
+  State(app_state): State<SharedState>
+) -> Result<StatusCode,AppError>{
+    let template = find_visible_template(&app_state, &claims, template_id).await?;
+    if !can_modify(&claims, &template) {
+        return Err(AppError::PermissionDenied);
+    }
+    prompt_templates::Entity::delete_by_id(template.id)
+        .exec(&app_state.database)
+        .await
+        .map_err(|e| {
+            eprintln!("Db delete one error {:?}", e);
+            AppError::DbTimeout})?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables_in_order_without_duplicates() {
+        let prompt_text = "Summarize {{ document }} for {{audience}}, keep {{document}} citations. {{}} {{unclosed";
+        assert_eq!(template_variables(prompt_text), vec!["document", "audience"]);
+        assert!(template_variables("No placeholders").is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use axum::{Json, extract::{Path, State}};
+use chrono::{Duration, Utc};
+use migration::Expr;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder};
+use uuid::Uuid;
+use crate::{auth::{claims::{Claiming, Claims, REFRESH_TOKEN_TTL_SECS, RefreshClaims}, client::ClientInfo, error::{AuthError, AuthErrorResponse}}, dto::auth::{RefreshTokenRequest, SessionResponse}, models::{sessions::{self, SessionRevokeReason}, users::UserRole}, state::SharedState};
+
+fn session_expiry() -> chrono::DateTime<Utc> {
+    Utc::now() + Duration::seconds(REFRESH_TOKEN_TTL_SECS as i64)
+}
+
+/// Records the login of a device, its first refresh token carries the returned `refresh_jti`
+pub async fn start_session(db:&DatabaseConnection,user_id:Uuid,client:&ClientInfo) -> Result<sessions::Model,DbErr> {
+    let now = Utc::now();
+    sessions::ActiveModel {
+        id:Set(Uuid::new_v4()),
+        user_id:Set(user_id),
+        refresh_jti:Set(Uuid::new_v4()),
+        user_agent:Set(client.user_agent.clone()),
+        ip_address:Set(client.ip_address.clone()),
+        created_at:Set(now),
+        last_seen_at:Set(now),
+        expires_at:Set(session_expiry()),
+        revoked_at:Set(None),
+        revoked_reason:Set(None),
+    }
+    .insert(db)
+    .await
+}
+
+/// Replaces the refresh token of the session, revoking the whole session when a rotated token comes back
+pub async fn rotate_session(db:&DatabaseConnection,refresh_claims:&RefreshClaims,client:&ClientInfo) -> Result<sessions::Model,AuthError> {
+    let session = sessions::Entity::find_by_id(refresh_claims.sid)
+      .filter(sessions::Column::UserId.eq(refresh_claims.user_id))
+      .one(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::InvalidToken)?;
+    if session.revoked_at.is_some() || session.expires_at <= Utc::now() {
+        return Err(AuthError::InvalidToken);
+    }
+    // Matching on the current token id, of two concurrent refreshes only one rotates
+    let rotated = sessions::Entity::update_many()
+      .col_expr(sessions::Column::RefreshJti, Expr::value(Uuid::new_v4()))
+      .col_expr(sessions::Column::LastSeenAt, Expr::value(Utc::now()))
+      .col_expr(sessions::Column::ExpiresAt, Expr::value(session_expiry()))
+      .col_expr(sessions::Column::UserAgent, Expr::value(client.user_agent.clone()))
+      .col_expr(sessions::Column::IpAddress, Expr::value(client.ip_address.clone()))
+      .filter(sessions::Column::Id.eq(session.id))
+      .filter(sessions::Column::RefreshJti.eq(refresh_claims.jti))
+      .filter(sessions::Column::RevokedAt.is_null())
+      .exec_with_returning(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?
+      .pop();
+    match rotated {
+        Some(session) => Ok(session),
+        None => {
+            eprintln!("refresh token reused for session {}, revoking it", session.id);
+            revoke_session_by_id(db, refresh_claims.user_id, session.id, SessionRevokeReason::Reuse)
+              .await
+              .map_err(|e| {
+                 eprintln!("db update error: {e}");
+                 AuthError::DbTimeout
+              })?;
+            Err(AuthError::InvalidToken)
+        }
+    }
+}
+
+async fn revoke_session_by_id(db:&DatabaseConnection,user_id:Uuid,session_id:Uuid,reason:SessionRevokeReason) -> Result<u64,DbErr> {
+    let result = sessions::Entity::update_many()
+      .col_expr(sessions::Column::RevokedAt, Expr::value(Utc::now()))
+      .col_expr(sessions::Column::RevokedReason, Expr::value(reason))
+      .filter(sessions::Column::Id.eq(session_id))
+      .filter(sessions::Column::UserId.eq(user_id))
+      .filter(sessions::Column::RevokedAt.is_null())
+      .exec(db)
+      .await?;
+    Ok(result.rows_affected)
+}
+
+/// Revokes every session of the user, their refresh tokens stop working right away
+pub async fn revoke_user_sessions(db:&DatabaseConnection,user_id:Uuid,reason:SessionRevokeReason) -> Result<u64,DbErr> {
+    revoke_other_sessions(db, user_id, None, reason).await
+}
+
+/// Revokes the sessions of the user but `current`, the one the request was made from
+pub async fn revoke_other_sessions(db:&DatabaseConnection,user_id:Uuid,current:Option<Uuid>,reason:SessionRevokeReason) -> Result<u64,DbErr> {
+    let mut update = sessions::Entity::update_many()
+      .col_expr(sessions::Column::RevokedAt, Expr::value(Utc::now()))
+      .col_expr(sessions::Column::RevokedReason, Expr::value(reason))
+      .filter(sessions::Column::UserId.eq(user_id))
+      .filter(sessions::Column::RevokedAt.is_null());
+    if let Some(current) = current {
+        update = update.filter(sessions::Column::Id.ne(current));
+    }
+    let result = update
+      .exec(db)
+      .await?;
+    Ok(result.rows_affected)
+}
+
+async fn active_sessions(db:&DatabaseConnection,user_id:Uuid,current:Option<Uuid>) -> Result<Vec<SessionResponse>,AuthError> {
+    let sessions = sessions::Entity::find()
+      .filter(sessions::Column::UserId.eq(user_id))
+      .filter(sessions::Column::RevokedAt.is_null())
+      .filter(sessions::Column::ExpiresAt.gt(Utc::now()))
+      .order_by_desc(sessions::Column::LastSeenAt)
+      .all(db)
+      .await
+      .map_err(|e| {
+         eprintln!("db get all error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(sessions
+      .into_iter()
+      .map(|session| SessionResponse {
+          id:session.id,
+          current:current == Some(session.id),
+          user_agent:session.user_agent,
+          ip_address:session.ip_address,
+          created_at:session.created_at,
+          last_seen_at:session.last_seen_at,
+          expires_at:session.expires_at,
+      })
+      .collect())
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    responses(
+       (status = 204, description = "Session signed out, its refresh token no longer works"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired refresh token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn logout(
+    State(app_state): State<SharedState>,
+    Json(req): Json<RefreshTokenRequest>,
+) -> Result<StatusCode, AuthError> {
+    let refresh_claims = RefreshClaims::from_token_string(&req.refresh_token)
+      .map_err(|_| AuthError::InvalidToken)?;
+    // Signing out twice is not an error
+    revoke_session_by_id(&app_state.database, refresh_claims.user_id, refresh_claims.sid, SessionRevokeReason::Logout)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/logout/all",
+    tag = "auth",
+    responses(
+       (status = 204, description = "All sessions of the user signed out, their access tokens included"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn logout_all(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<StatusCode, AuthError> {
+    revoke_user_sessions(&app_state.database, claims.user_id, SessionRevokeReason::LogoutAll)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    app_state
+      .user_access
+      .bump_token_version(&app_state.database, claims.user_id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/sessions",
+    tag = "auth",
+    responses(
+       (status = 200, body = Vec<SessionResponse>, description = "Signed in devices, most recently seen first"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_sessions(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<Vec<SessionResponse>>), AuthError> {
+    let sessions = active_sessions(&app_state.database, claims.user_id, claims.sid).await?;
+    Ok((StatusCode::OK,Json(sessions)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions/{session_id}",
+    tag = "auth",
+    params(("session_id" = Uuid, Path, description = "Session ID")),
+    responses(
+       (status = 204, description = "Session signed out"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Session not found (code=6113)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn delete_session(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    let rows_affected = revoke_session_by_id(&app_state.database, claims.user_id, session_id, SessionRevokeReason::Logout)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    if rows_affected == 0 {
+        return Err(AuthError::SessionNotFound);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users/{user_id}/sessions",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses(
+       (status = 200, body = Vec<SessionResponse>, description = "Signed in devices of the user, most recently seen first"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_user_sessions(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<(StatusCode,Json<Vec<SessionResponse>>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let sessions = active_sessions(&app_state.database, user_id, claims.sid).await?;
+    Ok((StatusCode::OK,Json(sessions)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/users/{user_id}/sessions",
+    tag = "admin",
+    params(("user_id" = Uuid, Path, description = "User ID")),
+    responses(
+       (status = 204, description = "All sessions of the user signed out, their access tokens included"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn revoke_user_sessions_admin(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    revoke_user_sessions(&app_state.database, user_id, SessionRevokeReason::Admin)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    app_state
+      .user_access
+      .bump_token_version(&app_state.database, user_id)
+      .await
+      .map_err(|e| {
+         eprintln!("db update error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok(StatusCode::NO_CONTENT)
+}
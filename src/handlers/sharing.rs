@@ -0,0 +1,49 @@
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait};
+use uuid::Uuid;
+use crate::{auth::claims::Claims, error::AppError, models::users::{self, UserRole}};
+
+/// Admins manage and share the resources of any user
+pub fn is_admin(claims:&Claims) -> bool {
+    matches!(claims.role, UserRole::SuperAdmin | UserRole::Admin)
+}
+
+pub async fn get_user_department(db:&DatabaseConnection,user_id:Uuid) -> Result<Option<String>,DbErr> {
+    Ok(users::Entity::find_by_id(user_id)
+        .one(db)
+        .await?
+        .and_then(|user| user.department))
+}
+
+/// Department a resource is shared with when `department_scope` is set, users can only share with their own department
+pub fn sharing_department(claims:&Claims,department_scope:bool,requested:Option<String>,user_department:Option<String>) -> Result<Option<String>,AppError> {
+    if !department_scope {
+        return Ok(None);
+    }
+    let department = requested
+        .filter(|department| !department.trim().is_empty())
+        .or(user_department.clone())
+        .ok_or(AppError::missing_field("department"))?;
+    if !is_admin(claims) && Some(&department) != user_department.as_ref() {
+        return Err(AppError::PermissionDenied);
+    }
+    Ok(Some(department))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_share_only_with_their_own_department() {
+        let mut claims = Claims::default();
+        claims.role = UserRole::User;
+        let own = Some("sales".to_string());
+        assert_eq!(sharing_department(&claims, false, Some("hr".to_string()), own.clone()).unwrap(), None);
+        assert_eq!(sharing_department(&claims, true, None, own.clone()).unwrap(), own);
+        assert_eq!(sharing_department(&claims, true, Some(" ".to_string()), own.clone()).unwrap(), own);
+        assert!(matches!(sharing_department(&claims, true, Some("hr".to_string()), own.clone()), Err(AppError::PermissionDenied)));
+        assert!(sharing_department(&claims, true, None, None).is_err());
+        claims.role = UserRole::Admin;
+        assert_eq!(sharing_department(&claims, true, Some("hr".to_string()), own).unwrap(), Some("hr".to_string()));
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use axum::{Json, extract::{Path, State}};
+use chrono::Utc;
+use reqwest::StatusCode;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, DbErr, EntityTrait, FromQueryResult, IntoActiveModel, PaginatorTrait, QueryFilter, QuerySelect, Select, Statement, sea_query::Expr};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{
+    auth::{claims::Claims, error::{AuthError, AuthErrorResponse}},
+    dto::storage::{StorageQuotas, StorageReport, StorageUsage, UploadPolicy, UserStorageQuota, UserStorageUsage},
+    error::{AppError, ErrorResponse},
+    models::{files::{self, FileUploadStatus}, organizations, users::{self, UserRole}},
+    state::SharedState,
+};
+
+/// Key of the storage quota override in `users.metadata`
+pub const STORAGE_QUOTA_KEY:&str = "storageQuotaBytes";
+
+#[derive(FromQueryResult)]
+struct UserUsageRow {
+    #[sea_orm(from_alias = "userId")]
+    user_id:Uuid,
+    used_bytes:i64,
+    file_count:i64,
+}
+
+/// Sum of the sizes of the selected files
+async fn sum_sizes(db:&DatabaseConnection,select:Select<files::Entity>) -> Result<u64,DbErr> {
+    let used_bytes = select
+      .select_only()
+      .column_as(Expr::cust(r#"COALESCE(SUM("size"), 0)::bigint"#), "used_bytes")
+      .into_tuple::<i64>()
+      .one(db)
+      .await?
+      .unwrap_or(0);
+    Ok(used_bytes.max(0) as u64)
+}
+
+fn to_quota(bytes:Option<i64>) -> Option<u64> {
+    bytes.map(|bytes| bytes.max(0) as u64)
+}
+
+/// Quota of the user: their own override, otherwise the organization default
+fn user_quota(org:Option<&organizations::Model>,user:&users::Model) -> Option<u64> {
+    user.metadata
+      .as_ref()
+      .and_then(|json| json.get(STORAGE_QUOTA_KEY))
+      .and_then(|value| value.as_u64())
+      .or_else(|| to_quota(org.and_then(|org| org.user_storage_quota_bytes)))
+}
+
+async fn load_user_org(db:&DatabaseConnection,user_id:Uuid) -> Result<Option<(users::Model,Option<organizations::Model>)>,DbErr> {
+    let Some(user) = users::Entity::find_by_id(user_id)
+      .one(db)
+      .await? else {
+        return Ok(None);
+    };
+    // Single organization deployments may have users without org id
+    let org = match user.org_id {
+        Some(org_id) => organizations::Entity::find_by_id(org_id).one(db).await?,
+        None => organizations::Entity::find().one(db).await?,
+    };
+    Ok(Some((user, org)))
+}
+
+/// Room left under the tightest quota applying to the user, with that quota.
+/// Trashed files count until they are permanently deleted.
+pub async fn remaining_quota(db:&DatabaseConnection,user_id:Uuid) -> Result<Option<(u64,u64)>,DbErr> {
+    let Some((user, org)) = load_user_org(db, user_id).await? else {
+        return Ok(None);
+    };
+    let mut remaining:Option<(u64,u64)> = None;
+    if let Some(quota) = user_quota(org.as_ref(), &user) {
+        let used = sum_sizes(db, files::Entity::find().filter(files::Column::UserId.eq(user_id))).await?;
+        remaining = Some((quota.saturating_sub(used), quota));
+    }
+    if let Some(quota) = to_quota(org.as_ref().and_then(|org| org.storage_quota_bytes)) {
+        let used = sum_sizes(db, files::Entity::find()).await?;
+        let org_remaining = (quota.saturating_sub(used), quota);
+        remaining = Some(match remaining {
+            Some(user_remaining) if user_remaining.0 <= org_remaining.0 => user_remaining,
+            _ => org_remaining,
+        });
+    }
+    Ok(remaining)
+}
+
+/// Error of an upload of `size` bytes, `quota` being the room left and the quota it comes from
+pub fn limit_error(size:u64,max_bytes:u64,quota:Option<(u64,u64)>) -> Option<AppError> {
+    if size > max_bytes {
+        return Some(AppError::ValidationFileTooLarge { max_bytes });
+    }
+    match quota {
+        Some((remaining, quota_bytes)) if size > remaining => Some(AppError::StorageQuotaExceeded { quota_bytes }),
+        _ => None,
+    }
+}
+
+fn parse_upload_policy(value:&Option<serde_json::Value>) -> UploadPolicy {
+    value
+      .as_ref()
+      .and_then(|json| serde_json::from_value(json.clone()).ok())
+      .unwrap_or_default()
+}
+
+/// Upload policy of the organization, any type being accepted when none is set
+pub async fn load_upload_policy(db:&DatabaseConnection) -> Result<UploadPolicy,DbErr> {
+    let org = organizations::Entity::find().one(db).await?;
+    Ok(org.map(|org| parse_upload_policy(&org.upload_policy)).unwrap_or_default())
+}
+
+#[utoipa::path(
+    get,
+    path = "/files/usage",
+    tag = "files",
+    responses(
+        (status = 200, body = StorageUsage),
+        (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+        (status = 404, content_type = "application/json", body = ErrorResponse, description = "User not found (code=1001)"),
+        (status = 503, content_type = "application/json", body = ErrorResponse, description = "Database timeout/unavailable (code=5001/5000)"),
+    ),
+)]
+pub async fn get_storage_usage(
+   claims:Claims,
+   State(app_state):State<SharedState>,
+) -> Result<(StatusCode,Json<StorageUsage>),AppError>{
+    let db = &app_state.database;
+    let usage = async {
+        let Some((user, org)) = load_user_org(db, claims.user_id).await? else {
+            return Ok(None);
+        };
+        let user_files = files::Entity::find()
+          .filter(files::Column::UserId.eq(claims.user_id));
+        let usage = StorageUsage {
+            used_bytes:sum_sizes(db, user_files.clone()).await?,
+            deleted_bytes:sum_sizes(db, user_files.clone().filter(files::Column::Status.eq(FileUploadStatus::Deleted))).await?,
+            file_count:user_files.count(db).await?,
+            quota_bytes:user_quota(org.as_ref(), &user),
+        };
+        Ok::<_,DbErr>(Some(usage))
+    }
+    .await
+    .map_err(|e|{
+        eprintln!("db get one error: {e}");
+        AppError::DbTimeout
+    })?
+    .ok_or(AppError::ResourceNotFound)?;
+ Ok((StatusCode::OK,Json(usage)))
+}
+
+async fn build_storage_report(db:&DatabaseConnection) -> Result<StorageReport,DbErr> {
+    let org = organizations::Entity::find().one(db).await?;
+    // Deduplicated files share one stored content
+    let physical_bytes = db
+      .query_one(Statement::from_string(
+          DbBackend::Postgres,
+          r#"SELECT COALESCE(SUM("size"), 0)::bigint AS "physical_bytes" FROM (SELECT DISTINCT ON ("storageBackend", "storageKey") "size" FROM "files") AS "blobs""#,
+      ))
+      .await?
+      .map(|row| row.try_get::<i64>("", "physical_bytes"))
+      .transpose()?
+      .unwrap_or(0);
+    let rows = files::Entity::find()
+      .select_only()
+      .column(files::Column::UserId)
+      .column_as(Expr::cust(r#"COALESCE(SUM("size"), 0)::bigint"#), "used_bytes")
+      .column_as(Expr::cust("COUNT(*)"), "file_count")
+      .group_by(files::Column::UserId)
+      .into_model::<UserUsageRow>()
+      .all(db)
+      .await?;
+    let users = users::Entity::find()
+      .filter(users::Column::Id.is_in(rows.iter().map(|row| row.user_id)))
+      .all(db)
+      .await?
+      .into_iter()
+      .map(|user| (user.id, user))
+      .collect::<HashMap<Uuid,users::Model>>();
+    let mut user_usages = rows
+      .into_iter()
+      .map(|row| {
+          let user = users.get(&row.user_id);
+          UserStorageUsage {
+              user_id:row.user_id,
+              email:user.map(|user| user.email.clone()),
+              name:user.and_then(|user| user.name.clone()),
+              used_bytes:row.used_bytes.max(0) as u64,
+              file_count:row.file_count.max(0) as u64,
+              quota_bytes:user.and_then(|user| user_quota(org.as_ref(), user)),
+          }
+      })
+      .collect::<Vec<UserStorageUsage>>();
+    user_usages.sort_by_key(|usage| std::cmp::Reverse(usage.used_bytes));
+    Ok(StorageReport {
+        logical_bytes:user_usages.iter().map(|usage| usage.used_bytes).sum(),
+        physical_bytes:physical_bytes.max(0) as u64,
+        deleted_bytes:sum_sizes(db, files::Entity::find().filter(files::Column::Status.eq(FileUploadStatus::Deleted))).await?,
+        file_count:user_usages.iter().map(|usage| usage.file_count).sum(),
+        quotas:StorageQuotas {
+            storage_quota_bytes:to_quota(org.as_ref().and_then(|org| org.storage_quota_bytes)),
+            user_storage_quota_bytes:to_quota(org.as_ref().and_then(|org| org.user_storage_quota_bytes)),
+        },
+        users:user_usages,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/storage",
+    tag = "admin",
+    responses(
+       (status = 200, body = StorageReport),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_storage_report(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<StorageReport>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let report = build_storage_report(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("storage report error: {e}");
+         AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(report)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/storage/quotas",
+    tag = "admin",
+    request_body = StorageQuotas,
+    responses(
+       (status = 200, body = StorageQuotas),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "Organization not found (code=6301)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn update_storage_quotas(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(req): Json<StorageQuotas>
+) -> Result<(StatusCode,Json<StorageQuotas>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let org = organizations::Entity::find()
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::OrgDoesNotExist { org_id:claims.org_id })?;
+    let mut active_model = org.into_active_model();
+    active_model.storage_quota_bytes = Set(req.storage_quota_bytes.map(|bytes| bytes as i64));
+    active_model.user_storage_quota_bytes = Set(req.user_storage_quota_bytes.map(|bytes| bytes as i64));
+    active_model.updated_on = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+          eprintln!("update error: {e}");
+          AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(req)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/users/{user_id}/storage-quota",
+    tag = "admin",
+    request_body = UserStorageQuota,
+    responses(
+       (status = 200, body = UserStorageQuota),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = AuthErrorResponse, description = "Permission denied (code=6300)"),
+       (status = 404, content_type = "application/json", body = AuthErrorResponse, description = "User not found (code=6302)"),
+       (status = 503, content_type = "application/json", body = AuthErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn update_user_storage_quota(
+    claims: Claims,
+    Path(user_id): Path<Uuid>,
+    State(app_state): State<SharedState>,
+    Json(req): Json<UserStorageQuota>
+) -> Result<(StatusCode,Json<UserStorageQuota>), AuthError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AuthError::PermissionDenied),
+    }
+    let user = users::Entity::find_by_id(user_id)
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AuthError::DbTimeout
+      })?
+      .ok_or(AuthError::ResourceNotFound)?;
+    let mut metadata = user.metadata
+      .clone()
+      .filter(|json| json.is_object())
+      .unwrap_or_else(|| json!({}));
+    if let Some(json) = metadata.as_object_mut() {
+        match req.quota_bytes {
+            Some(quota_bytes) => {
+                json.insert(STORAGE_QUOTA_KEY.to_string(), json!(quota_bytes));
+            }
+            None => {
+                json.remove(STORAGE_QUOTA_KEY);
+            }
+        }
+    }
+    let mut active_model = user.into_active_model();
+    active_model.metadata = Set(Some(metadata));
+    active_model.updated_at = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+          eprintln!("update error: {e}");
+          AuthError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(req)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/storage/upload-policy",
+    tag = "admin",
+    responses(
+       (status = 200, body = UploadPolicy),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = ErrorResponse, description = "Permission denied (code=1002)"),
+       (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn get_upload_policy(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+) -> Result<(StatusCode,Json<UploadPolicy>), AppError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AppError::PermissionDenied),
+    }
+    let policy = load_upload_policy(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AppError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(policy)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/storage/upload-policy",
+    tag = "admin",
+    request_body = UploadPolicy,
+    responses(
+       (status = 200, body = UploadPolicy),
+       (status = 400, content_type = "application/json", body = ErrorResponse, description = "Content type that is not `type/subtype` or `type/*` (code=2003)"),
+       (status = 401, content_type = "application/json", body = AuthErrorResponse, description = "Invalid/expired token (code=6103)"),
+       (status = 403, content_type = "application/json", body = ErrorResponse, description = "Permission denied (code=1002)"),
+       (status = 404, content_type = "application/json", body = ErrorResponse, description = "Organization not found (code=1001)"),
+       (status = 503, content_type = "application/json", body = ErrorResponse, description = "DB timeout/unavailable (code=5001/5000)"),
+    )
+)]
+pub async fn update_upload_policy(
+    claims: Claims,
+    State(app_state): State<SharedState>,
+    Json(mut req): Json<UploadPolicy>
+) -> Result<(StatusCode,Json<UploadPolicy>), AppError> {
+    match claims.role {
+        UserRole::SuperAdmin | UserRole::Admin => {}
+        _ => return Err(AppError::PermissionDenied),
+    }
+    for rule in req.allowed_types.iter_mut() {
+        rule.content_type = rule.content_type.trim().to_ascii_lowercase();
+        let valid = rule.content_type
+          .split_once('/')
+          .is_some_and(|(family, subtype)| !family.is_empty() && family != "*" && !subtype.is_empty() && !subtype.contains('/'));
+        if !valid {
+            return Err(AppError::invalid_field("allowed_types"));
+        }
+    }
+    let org = organizations::Entity::find()
+      .one(&app_state.database)
+      .await
+      .map_err(|e| {
+         eprintln!("db get one error: {e}");
+         AppError::DbTimeout
+      })?
+      .ok_or(AppError::ResourceNotFound)?;
+    let mut active_model = org.into_active_model();
+    active_model.upload_policy = Set((!req.allowed_types.is_empty()).then(|| json!(req)));
+    active_model.updated_on = Set(Utc::now());
+    active_model
+      .update(&app_state.database)
+      .await
+      .map_err(|e| {
+          eprintln!("update error: {e}");
+          AppError::DbTimeout
+      })?;
+    Ok((StatusCode::OK,Json(req)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_error_checks_the_upload_size_before_the_quota() {
+        assert!(limit_error(10, 100, None).is_none());
+        assert!(limit_error(10, 100, Some((10, 50))).is_none());
+        assert!(matches!(limit_error(101, 100, Some((0, 50))), Some(AppError::ValidationFileTooLarge { max_bytes: 100 })));
+        assert!(matches!(limit_error(11, 100, Some((10, 50))), Some(AppError::StorageQuotaExceeded { quota_bytes: 50 })));
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+use anyhow::Error;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter};
+use serde_json::json;
+use crate::{dto::files::{FileScan, FileScanStatus}, models::files::{self, FileUploadStatus}, rag::retrieval::spawn_index_file, state::SharedState, storage::scan::ScanVerdict};
+
+/// Key of the malware scan verdict in `files.metadata`
+pub const SCAN_KEY:&str = "scan";
+pub const FILE_SCAN_RETRY_INTERVAL:Duration = Duration::from_secs(5 * 60);
+
+pub fn get_file_scan(metadata:&Option<serde_json::Value>) -> Option<FileScan> {
+   metadata
+     .as_ref()
+     .and_then(|json| json.get(SCAN_KEY))
+     .and_then(|value| serde_json::from_value::<FileScan>(value.clone()).ok())
+}
+
+pub fn scanned_status(scan:&FileScan) -> FileUploadStatus {
+   match scan.status {
+      FileScanStatus::Clean => FileUploadStatus::Clean,
+      FileScanStatus::Infected => FileUploadStatus::Infected,
+   }
+}
+
+/// Status of a file being stored or restored: its known verdict, otherwise
+/// scanning when a scanner is configured
+pub fn initial_status(app_state:&SharedState,scan:Option<&FileScan>) -> FileUploadStatus {
+   match scan {
+      Some(scan) => scanned_status(scan),
+      None if app_state.malware_scanner.is_some() => FileUploadStatus::Scanning,
+      None => FileUploadStatus::Uploaded,
+   }
+}
+
+/// Scans a file waiting for its verdict, or indexes an available one, in the background
+pub fn spawn_process_file(app_state:SharedState,file_model:files::Model) {
+   if file_model.status.is_available() {
+      spawn_index_file(app_state, file_model);
+   } else if file_model.status == FileUploadStatus::Scanning {
+      tokio::spawn(async move {
+         if let Err(e) = scan_file(&app_state, &file_model).await {
+            eprintln!("file {} scan error: {e}", file_model.id);
+         }
+      });
+   }
+}
+
+/// Records the verdict on the file, indexing it when clean. The file keeps
+/// the scanning status when the scanner fails, to be retried.
+pub async fn scan_file(app_state:&SharedState,file_model:&files::Model) -> Result<(),Error> {
+   let (status, scan) = match app_state.malware_scanner.as_ref() {
+      Some(scanner) => {
+         let bytes = app_state
+           .file_storage
+           .get(file_model.storage_backend, &file_model.storage_key)
+           .await?;
+         let verdict = scanner.scan(&bytes).await?;
+         let (status, signature) = match verdict {
+            ScanVerdict::Clean => (FileScanStatus::Clean, None),
+            ScanVerdict::Infected { signature } => (FileScanStatus::Infected, Some(signature)),
+         };
+         let scan = FileScan { status, signature, engine:scanner.engine().to_string(), scanned_at:Utc::now() };
+         (scanned_status(&scan), Some(scan))
+      }
+      // Scanning was turned off since the upload
+      None => (FileUploadStatus::Uploaded, None),
+   };
+   // The file may have been deleted while scanned
+   let Some(current) = files::Entity::find_by_id(file_model.id)
+     .filter(files::Column::Status.eq(FileUploadStatus::Scanning))
+     .one(&app_state.database)
+     .await? else {
+      return Ok(());
+   };
+   let mut metadata = current.metadata
+     .clone()
+     .filter(|json| json.is_object())
+     .unwrap_or_else(|| json!({}));
+   if let Some(scan) = &scan {
+      metadata[SCAN_KEY] = json!(scan);
+   }
+   let mut active_model = current.into_active_model();
+   active_model.status = Set(status);
+   active_model.metadata = Set(Some(metadata));
+   active_model.updated_at = Set(Utc::now());
+   let file_model = active_model
+     .update(&app_state.database)
+     .await?;
+   if let Some(signature) = scan.as_ref().and_then(|scan| scan.signature.as_ref()) {
+      println!("file {} quarantined, {signature} found", file_model.id);
+   }
+   spawn_process_file(app_state.clone(), file_model);
+   Ok(())
+}
+
+/// Periodically scans again the files left scanning, e.g. while clamd was unreachable or across restarts.
+pub async fn run_pending_file_scans(app_state:SharedState) {
+   let mut interval = tokio::time::interval(FILE_SCAN_RETRY_INTERVAL);
+   loop {
+      interval.tick().await;
+      if let Err(e) = scan_pending_files(&app_state).await {
+         eprintln!("pending file scans error: {e}");
+      }
+   }
+}
+
+async fn scan_pending_files(app_state:&SharedState) -> Result<(),Error> {
+   let retry_before = Utc::now() - FILE_SCAN_RETRY_INTERVAL;
+   let pending_files = files::Entity::find()
+     .filter(files::Column::Status.eq(FileUploadStatus::Scanning))
+     .filter(files::Column::UpdatedAt.lt(retry_before))
+     .all(&app_state.database)
+     .await?;
+   for file_model in pending_files {
+      if let Err(e) = scan_file(app_state, &file_model).await {
+         eprintln!("file {} scan error: {e}", file_model.id);
+      }
+   }
+   Ok(())
+}
@@ -0,0 +1,3 @@
+pub mod provider_files;
+
+pub mod file_scan;
\ No newline at end of file
@@ -0,0 +1,75 @@
+use std::time::Duration;
+use anyhow::Error;
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter, sea_query::Expr};
+use crate::{handlers::file::{PROVIDER_FILES_KEY, set_provider_file}, llm::provider::OpenaiApis, models::files::{self, FileUploadStatus}, state::SharedState};
+
+pub const PROVIDER_FILES_CLEANUP_INTERVAL:Duration = Duration::from_secs(10 * 60);
+
+/// Periodically removes provider-side copies (e.g. OpenAI file ids) of files deleted locally.
+pub async fn run_provider_files_cleanup(app_state:SharedState) {
+    let mut interval = tokio::time::interval(PROVIDER_FILES_CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = cleanup_deleted_provider_files(&app_state).await {
+            eprintln!("provider files cleanup error: {e}");
+        }
+    }
+}
+
+pub async fn cleanup_deleted_provider_files(app_state:&SharedState) -> Result<(),Error> {
+    let deleted_files = files::Entity::find()
+      .filter(files::Column::Status.eq(FileUploadStatus::Deleted))
+      .filter(Expr::cust(format!(r#""metadata" -> '{PROVIDER_FILES_KEY}' IS NOT NULL"#)))
+      .all(&app_state.database)
+      .await?;
+    for file_model in deleted_files {
+        let metadata = delete_provider_files(app_state, file_model.metadata.clone()).await;
+        if metadata != file_model.metadata {
+            let mut active_model = file_model.into_active_model();
+            active_model.metadata = Set(metadata);
+            active_model.updated_at = Set(Utc::now());
+            active_model
+              .update(&app_state.database)
+              .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes the provider-side copies listed in the file metadata, returning the
+/// metadata without the copies that are gone.
+pub async fn delete_provider_files(app_state:&SharedState,metadata:Option<serde_json::Value>) -> Option<serde_json::Value> {
+    let providers = metadata
+      .as_ref()
+      .and_then(|json| json.get(PROVIDER_FILES_KEY))
+      .and_then(|provider_files| provider_files.as_object())
+      .cloned()
+      .unwrap_or_default();
+    let mut metadata = metadata;
+    for (provider, provider_file) in providers {
+        let Some(file_id) = provider_file.get("fileId").and_then(|id| id.as_str()) else {
+            metadata = Some(set_provider_file(metadata, &provider, None));
+            continue;
+        };
+        let deleted = match provider.as_str() {
+            "openai" => {
+                let Some(openai_settings) = app_state.settings.openai.read().await.clone() else {
+                    continue;
+                };
+                app_state
+                  .req_client
+                  .openai_delete_file(&openai_settings, file_id)
+                  .await
+                  .map_err(|e| eprintln!("openai delete file {file_id} error: {e}"))
+                  .is_ok()
+            }
+            // No provider-side copy to remove for unknown providers
+            _ => true,
+        };
+        if deleted {
+            metadata = Some(set_provider_file(metadata, &provider, None));
+        }
+    }
+    metadata
+}
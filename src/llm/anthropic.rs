@@ -1,12 +1,11 @@
 use anyhow::{Error, anyhow};
 use async_trait::async_trait;
 use reqwest::{Client as ReqwestClient, RequestBuilder};
-use reqwest_eventsource::EventSource;
-use uuid::Uuid;
+use reqwest_eventsource::{EventSource, retry::Never};
 use crate::{
     config::setting::AnthropicSettings, dto::llm::anthropic::{
         AnthropicChatRequest, AnthropicChatResponse, AnthropicContentBlockResponse, AnthropicListModelsResponse, AnthropicMessage, AnthropicRole, AnthropicToolUnion, AnthropicWebSearchTool
-    }, handlers::file::get_file_binary, llm::{prompt::{Prompt, PromptTitleResponse}, provider::{AnthropicApis, AnthropicHeaders}}
+    }, llm::{http_policy::HttpPolicyExt, prompt::{Prompt, PromptTitleResponse}, provider::{AnthropicApis, AnthropicHeaders}}
 };
 
 pub const ANTHROPIC_API_URL: &str = "https://api.anthropic.com";
@@ -28,17 +27,9 @@ impl AnthropicApis for ReqwestClient {
         model_name: String,
         max_tokens: i32,
         temperature: Option<f32>,
-        mut prompts: Vec<Prompt>,
+        prompts: Vec<Prompt>,
         web_search: bool,
-        user_id:&Uuid,
     ) -> Result<EventSource, Error> {
-        for prompt in &mut prompts {
-           for file in &mut prompt.files {
-              if let Ok(attachment) = get_file_binary(&file, user_id){
-                 file.base64 = attachment.get_base64();
-              };
-           }
-        }
         let (messages, system_prompt) = AnthropicMessage::from_prompts(prompts);    
         let tools = if web_search {
             Some(vec![AnthropicToolUnion::WebSearchTool(
@@ -64,7 +55,10 @@ impl AnthropicApis for ReqwestClient {
             .add_anthropic_headers(anthropic_settings)
             .json(&body);
 
-        let es = EventSource::new(request)?;
+        anthropic_settings.http_policy().check_circuit()?;
+        let mut es = EventSource::new(request)?;
+        // Same as openai_chat_stream, retries reopen the stream from the chat handler
+        es.set_retry_policy(Box::new(Never));
         Ok(es)
     }
 
@@ -97,7 +91,9 @@ impl AnthropicApis for ReqwestClient {
             .add_anthropic_headers(anthropic_settings)
             .json(&body);
 
-        let es = EventSource::new(request)?;
+        anthropic_settings.http_policy().check_circuit()?;
+        let mut es = EventSource::new(request)?;
+        es.set_retry_policy(Box::new(Never));
         Ok(es)
     }
 
@@ -128,7 +124,7 @@ impl AnthropicApis for ReqwestClient {
             .post(format!("{ANTHROPIC_API_URL}/v1/messages"))
             .add_anthropic_headers(anthropic_settings)
             .json(&body)
-            .send()
+            .send_with_policy(&anthropic_settings.http_policy())
             .await?
             .error_for_status()?
             .json()
@@ -157,7 +153,7 @@ impl AnthropicApis for ReqwestClient {
         let models = self
             .get(format!("{ANTHROPIC_API_URL}/v1/models"))
             .add_anthropic_headers(anthropic_settings)
-            .send()
+            .send_with_policy(&anthropic_settings.http_policy())
             .await?
             .error_for_status()?
             .json::<AnthropicListModelsResponse>()
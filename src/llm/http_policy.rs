@@ -0,0 +1,263 @@
+use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+use anyhow::{Error, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{RequestBuilder, Response, StatusCode, header::{HeaderMap, RETRY_AFTER}};
+
+/// Connection establishment timeout shared by every provider call
+pub const HTTP_CONNECT_TIMEOUT:Duration = Duration::from_secs(10);
+/// Maximum idle time between two reads, keeps long streams alive while catching stalled ones
+pub const HTTP_READ_TIMEOUT:Duration = Duration::from_secs(120);
+pub const DEFAULT_TIMEOUT_MS:i32 = 60_000;
+pub const DEFAULT_MAX_RETRIES:i32 = 2;
+
+const BACKOFF_BASE:Duration = Duration::from_millis(500);
+const BACKOFF_MAX:Duration = Duration::from_secs(30);
+const CIRCUIT_BREAKER_THRESHOLD:u32 = 5;
+const CIRCUIT_BREAKER_COOLDOWN:Duration = Duration::from_secs(30);
+
+/// Timeout, retry and circuit breaker policy of one ai engine
+#[derive(Clone)]
+pub struct HttpPolicy {
+    pub timeout:Duration,
+    pub max_retries:u32,
+    pub circuit_breaker:Arc<CircuitBreaker>,
+}
+
+impl HttpPolicy {
+    pub fn new(timeout_ms:i32,max_retries:i32,circuit_breaker:Arc<CircuitBreaker>) -> Self {
+        Self {
+            timeout:Duration::from_millis(timeout_ms.max(0) as u64),
+            max_retries:max_retries.max(0) as u32,
+            circuit_breaker,
+        }
+    }
+
+    /// Delay before retrying after `attempt` failed attempts (0 based).
+    /// Honors the provider `retry-after` and otherwise backs off exponentially with full jitter,
+    /// returns `None` when no retry should be made.
+    pub fn backoff(&self,attempt:u32,retry_after:Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_retries {
+            return None;
+        }
+        if let Some(retry_after) = retry_after {
+            // Waiting longer than that is worse than failing over to another model
+            return (retry_after <= BACKOFF_MAX).then_some(retry_after);
+        }
+        let ceiling = BACKOFF_BASE
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(BACKOFF_MAX);
+        Some(Duration::from_millis(rand::random_range(0..=ceiling.as_millis() as u64)))
+    }
+
+    pub fn check_circuit(&self) -> Result<(),Error> {
+        if self.circuit_breaker.allow() {
+            Ok(())
+        } else {
+            Err(anyhow!("circuit breaker open for ai engine {}",self.circuit_breaker.engine_key))
+        }
+    }
+}
+
+pub fn is_retryable_status(status:StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads `retry-after-ms` (OpenAI) or `retry-after` in seconds or as an HTTP date
+pub fn parse_retry_after(headers:&HeaderMap) -> Option<Duration> {
+    if let Some(millis) = headers
+        .get("retry-after-ms")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_millis(millis.max(0.0) as u64));
+    }
+    let value = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - Utc::now()).to_std().unwrap_or_default())
+}
+
+/// Stops calling an ai engine after repeated failures, then lets a single trial call through after a cooldown
+pub struct CircuitBreaker {
+    pub engine_key:String,
+    state:Mutex<CircuitState>,
+}
+
+#[derive(Default)]
+struct CircuitState {
+    consecutive_failures:u32,
+    opened_at:Option<Instant>,
+    /// Start of the trial call let through once the cooldown is over (half open)
+    trial_started_at:Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new<S: Into<String>>(engine_key:S) -> Self {
+        Self { engine_key:engine_key.into(), state:Mutex::new(CircuitState::default()) }
+    }
+
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(opened_at) = state.opened_at else {
+            return true;
+        };
+        // A trial call that never reported back does not keep the breaker half open forever
+        let trial_pending = state
+            .trial_started_at
+            .is_some_and(|started_at| started_at.elapsed() < CIRCUIT_BREAKER_COOLDOWN);
+        if opened_at.elapsed() < CIRCUIT_BREAKER_COOLDOWN || trial_pending {
+            return false;
+        }
+        state.trial_started_at = Some(Instant::now());
+        true
+    }
+
+    pub fn record_success(&self) {
+        *self.state.lock().unwrap() = CircuitState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        // A failed trial call reopens the breaker right away
+        if state.consecutive_failures >= CIRCUIT_BREAKER_THRESHOLD || state.trial_started_at.is_some() {
+            state.trial_started_at = None;
+            // (Re)opening restarts the cooldown
+            if state.opened_at.is_none() {
+                eprintln!("circuit breaker opened for ai engine {}",self.engine_key);
+            }
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[async_trait]
+pub trait HttpPolicyExt {
+    /// Sends the request with the engine timeout, retrying on timeouts, connection errors, 429 and 5xx.
+    /// Requests with a non clonable body (multipart) are sent once.
+    async fn send_with_policy(self,policy:&HttpPolicy) -> Result<Response,Error>;
+}
+
+#[async_trait]
+impl HttpPolicyExt for RequestBuilder {
+    async fn send_with_policy(self,policy:&HttpPolicy) -> Result<Response,Error> {
+        let mut request = self.timeout(policy.timeout);
+        let mut attempt = 0;
+        loop {
+            policy.check_circuit()?;
+            let next_request = request.try_clone();
+            let result = request.send().await;
+            let retryable = match &result {
+                Ok(res) => is_retryable_status(res.status()),
+                Err(e) => e.is_timeout() || e.is_connect(),
+            };
+            if !retryable {
+                // Every outcome is recorded, an unrecorded trial call would keep the breaker half open
+                match &result {
+                    Ok(_) => policy.circuit_breaker.record_success(),
+                    Err(_) => policy.circuit_breaker.record_failure(),
+                }
+                return Ok(result?);
+            }
+            policy.circuit_breaker.record_failure();
+            let retry_after = result
+                .as_ref()
+                .ok()
+                .and_then(|res| parse_retry_after(res.headers()));
+            match (next_request, policy.backoff(attempt, retry_after)) {
+                (Some(next_request), Some(delay)) => {
+                    tokio::time::sleep(delay).await;
+                    request = next_request;
+                    attempt += 1;
+                }
+                _ => return Ok(result?),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn policy(max_retries:i32) -> HttpPolicy {
+        HttpPolicy::new(1_000,max_retries,Arc::new(CircuitBreaker::new("openai")))
+    }
+
+    #[test]
+    fn retry_after_seconds_and_millis() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(3)));
+        headers.insert("retry-after-ms", HeaderValue::from_static("250"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn backoff_stops_after_max_retries_and_long_retry_after() {
+        let policy = policy(2);
+        assert!(policy.backoff(0, None).unwrap() <= BACKOFF_BASE);
+        assert!(policy.backoff(1, None).unwrap() <= BACKOFF_BASE * 2);
+        assert_eq!(policy.backoff(2, None), None);
+        assert_eq!(policy.backoff(0, Some(Duration::from_secs(2))), Some(Duration::from_secs(2)));
+        assert_eq!(policy.backoff(0, Some(BACKOFF_MAX * 2)), None);
+    }
+
+    #[test]
+    fn circuit_breaker_opens_after_threshold() {
+        let breaker = CircuitBreaker::new("anthropic");
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD - 1 {
+            breaker.record_failure();
+        }
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert!(!breaker.allow());
+        breaker.record_success();
+        assert!(breaker.allow());
+    }
+
+    fn end_cooldown(breaker:&CircuitBreaker) {
+        breaker.state.lock().unwrap().opened_at = Instant::now().checked_sub(CIRCUIT_BREAKER_COOLDOWN);
+    }
+
+    #[test]
+    fn circuit_breaker_lets_a_single_trial_through_after_cooldown() {
+        let breaker = CircuitBreaker::new("openai");
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+            breaker.record_failure();
+        }
+        end_cooldown(&breaker);
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        // Failed trial reopens the breaker immediately
+        breaker.record_failure();
+        assert!(!breaker.allow());
+        end_cooldown(&breaker);
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert!(breaker.allow());
+        assert!(breaker.allow());
+    }
+
+    #[tokio::test]
+    async fn failed_trial_request_reopens_the_breaker() {
+        let policy = policy(0);
+        for _ in 0..CIRCUIT_BREAKER_THRESHOLD {
+            policy.circuit_breaker.record_failure();
+        }
+        end_cooldown(&policy.circuit_breaker);
+        // Refused before reaching the network, neither a timeout nor a connect error
+        let result = reqwest::Client::new().get("not a url").send_with_policy(&policy).await;
+        assert!(result.is_err());
+        let state = policy.circuit_breaker.state.lock().unwrap();
+        assert!(state.trial_started_at.is_none());
+        assert!(state.opened_at.is_some_and(|opened_at| opened_at.elapsed() < CIRCUIT_BREAKER_COOLDOWN));
+    }
+}
@@ -1,4 +1,6 @@
 pub mod provider;
 pub mod openai;
 pub mod anthropic;
-pub mod prompt;
\ No newline at end of file
+pub mod prompt;
+pub mod http_policy;
+pub mod pricing;
\ No newline at end of file
@@ -1,9 +1,8 @@
 use anyhow::{Error,anyhow};
 use async_trait::async_trait;
-use reqwest::{Client as ReqwestClient, RequestBuilder, multipart};
-use reqwest_eventsource::EventSource;
-use uuid::Uuid;
-use crate::{config::setting::OpenaiSettings, dto::{files::Attachment, llm::openai::{FileUploadResponse, OpenaiChatCompletionRequest, OpenaiChatCompletionResponse, OpenaiChatRequest, OpenaiListModelsResponse, OpenaiMessage, OpenaiModel, OpenaiTool}}, handlers::file::get_file_binary, llm::{prompt::{Prompt, PromptTitleResponse}, provider::{OpenaiApis, OpenaiHeaders}}};
+use reqwest::{Client as ReqwestClient, RequestBuilder, StatusCode, multipart};
+use reqwest_eventsource::{EventSource, retry::Never};
+use crate::{config::setting::OpenaiSettings, dto::{files::Attachment, llm::openai::{FileUploadResponse, OpenaiChatCompletionRequest, OpenaiChatCompletionResponse, OpenaiChatRequest, OpenaiEmbeddingRequest, OpenaiEmbeddingResponse, OpenaiImageRequest, OpenaiImageResponse, OpenaiListModelsResponse, OpenaiMessage, OpenaiModel, OpenaiSpeechRequest, OpenaiTool, OpenaiTranscriptionRequest, OpenaiTranscriptionResponse}}, llm::{http_policy::HttpPolicyExt, prompt::{Prompt, PromptTitleResponse}, provider::{OpenaiApis, OpenaiHeaders}}};
 
 pub const OPENAI_API_URL:&str = "https://api.openai.com";
 
@@ -22,7 +21,7 @@ impl OpenaiHeaders for RequestBuilder  {
 
 #[async_trait]
 impl OpenaiApis for ReqwestClient {
-    async fn openai_upload_file(&self,openai_settings:&OpenaiSettings,attachment:&Attachment) -> Result<String,Error>{
+    async fn openai_upload_file(&self,openai_settings:&OpenaiSettings,attachment:&Attachment) -> Result<FileUploadResponse,Error>{
       let bytes = attachment
         .file
         .as_ref()
@@ -39,25 +38,28 @@ impl OpenaiApis for ReqwestClient {
       let res = self.post(format!("{OPENAI_API_URL}/v1/files"))
         .add_openai_headers(openai_settings)
         .multipart(form)
-        .send()
+        .send_with_policy(&openai_settings.http_policy())
         .await?
         .error_for_status()?
         .json::<FileUploadResponse>()
         .await?;
-     Ok(res.id)
+     Ok(res)
     }
 
-   async fn openai_chat_stream(&self,openai_settings:&OpenaiSettings,model_name:String,temperature:Option<f32>,mut prompts:Vec<Prompt>,user_id:&Uuid,web_search:bool) -> Result<EventSource,Error>{
-       for prompt in &mut prompts {
-         for file in &mut prompt.files {
-            if let Ok(attachment) = get_file_binary(&file, user_id){
-               file.openai_id = self
-                 .openai_upload_file(openai_settings, &attachment)
-                 .await
-                 .ok()
-            }
-         }
-       }
+    async fn openai_delete_file(&self,openai_settings:&OpenaiSettings,file_id:&str) -> Result<(),Error>{
+      let res = self.delete(format!("{OPENAI_API_URL}/v1/files/{file_id}"))
+        .add_openai_headers(openai_settings)
+        .send_with_policy(&openai_settings.http_policy())
+        .await?;
+      // Already gone on the provider side, nothing left to clean up
+      if res.status() == StatusCode::NOT_FOUND {
+        return Ok(());
+      }
+      res.error_for_status()?;
+     Ok(())
+    }
+
+   async fn openai_chat_stream(&self,openai_settings:&OpenaiSettings,model_name:String,temperature:Option<f32>,prompts:Vec<Prompt>,web_search:bool) -> Result<EventSource,Error>{
        let tools = if web_search {
          Some(vec![OpenaiTool::web_search()])
        }else{
@@ -76,7 +78,10 @@ impl OpenaiApis for ReqwestClient {
             .post(format!("{OPENAI_API_URL}/v1/responses"))
             .add_openai_headers(openai_settings)
             .json(&body);
-     let es = EventSource::new(request)?;
+     openai_settings.http_policy().check_circuit()?;
+     let mut es = EventSource::new(request)?;
+     // Retries reopen the stream from the chat handler, a reconnect here would replay the request mid-answer
+     es.set_retry_policy(Box::new(Never));
      Ok(es)
    }
 
@@ -91,7 +96,9 @@ impl OpenaiApis for ReqwestClient {
             .post(format!("{OPENAI_API_URL}/v1/chat/completions"))
             .add_openai_headers(openai_sesstings)
             .json(&body);
-     let es = EventSource::new(request)?;
+     openai_sesstings.http_policy().check_circuit()?;
+     let mut es = EventSource::new(request)?;
+     es.set_retry_policy(Box::new(Never));
      Ok(es)
    }
 
@@ -107,7 +114,7 @@ impl OpenaiApis for ReqwestClient {
           .post(format!("{OPENAI_API_URL}/v1/chat/completions"))
           .add_openai_headers(&openai_settings)
           .json(&body)
-          .send()
+          .send_with_policy(&openai_settings.http_policy())
           .await?
           .json()
           .await?;
@@ -129,12 +136,84 @@ impl OpenaiApis for ReqwestClient {
         let res = self
             .get(format!("{OPENAI_API_URL}/v1/models"))
             .add_openai_headers(openai_settings)
-            .send()
+            .send_with_policy(&openai_settings.http_policy())
             .await?
             .error_for_status()?
             .json::<OpenaiListModelsResponse>()
             .await?;
         Ok(res.data)
     }
+
+    async fn openai_embeddings(&self,openai_settings:&OpenaiSettings,model_name:&str,input:Vec<String>) -> Result<Vec<Vec<f32>>,Error> {
+        let body = OpenaiEmbeddingRequest {
+            model:model_name.to_string(),
+            input,
+        };
+        let mut res = self
+            .post(format!("{OPENAI_API_URL}/v1/embeddings"))
+            .add_openai_headers(openai_settings)
+            .json(&body)
+            .send_with_policy(&openai_settings.http_policy())
+            .await?
+            .error_for_status()?
+            .json::<OpenaiEmbeddingResponse>()
+            .await?;
+        res.data.sort_by_key(|embedding| embedding.index);
+        Ok(res.data.into_iter().map(|embedding| embedding.embedding).collect())
+    }
+
+    async fn openai_transcribe(&self,openai_settings:&OpenaiSettings,request:&OpenaiTranscriptionRequest,audio:&Attachment) -> Result<OpenaiTranscriptionResponse,Error> {
+        let bytes = audio
+          .file
+          .clone()
+          .ok_or(anyhow!("audio attachment without content"))?;
+        let part = multipart::Part::bytes(bytes)
+          .file_name(audio.name.clone())
+          .mime_str(&audio.content_type)?;
+        let mut form = multipart::Form::new()
+          .text("model", request.model.clone())
+          .text("response_format", "json")
+          .part("file", part);
+        if let Some(language) = &request.language {
+            form = form.text("language", language.clone());
+        }
+        if let Some(prompt) = &request.prompt {
+            form = form.text("prompt", prompt.clone());
+        }
+        let res = self
+            .post(format!("{OPENAI_API_URL}/v1/audio/transcriptions"))
+            .add_openai_headers(openai_settings)
+            .multipart(form)
+            .send_with_policy(&openai_settings.http_policy())
+            .await?
+            .error_for_status()?
+            .json::<OpenaiTranscriptionResponse>()
+            .await?;
+        Ok(res)
+    }
+
+    async fn openai_speech(&self,openai_settings:&OpenaiSettings,request:&OpenaiSpeechRequest) -> Result<reqwest::Response,Error> {
+        let res = self
+            .post(format!("{OPENAI_API_URL}/v1/audio/speech"))
+            .add_openai_headers(openai_settings)
+            .json(request)
+            .send_with_policy(&openai_settings.http_policy())
+            .await?
+            .error_for_status()?;
+        Ok(res)
+    }
+
+    async fn openai_generate_images(&self,openai_settings:&OpenaiSettings,request:&OpenaiImageRequest) -> Result<OpenaiImageResponse,Error> {
+        let res = self
+            .post(format!("{OPENAI_API_URL}/v1/images/generations"))
+            .add_openai_headers(openai_settings)
+            .json(request)
+            .send_with_policy(&openai_settings.http_policy())
+            .await?
+            .error_for_status()?
+            .json::<OpenaiImageResponse>()
+            .await?;
+        Ok(res)
+    }
 }
 
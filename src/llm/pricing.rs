@@ -0,0 +1,79 @@
+use num_traits::FromPrimitive;
+use sea_orm::prelude::Decimal;
+
+/// Billed quantities of an audio request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioUsage {
+    Tokens { text_input:u32, audio_input:u32, output:u32 },
+    Seconds(f64),
+    Characters(usize),
+}
+
+/// Billed tokens of an image generation, the output tokens being the generated images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageGenerationUsage {
+    pub text_input:u32,
+    pub image_input:u32,
+    pub output:u32,
+}
+
+fn per_million(usd:Decimal,quantity:u64) -> Decimal {
+    usd * Decimal::from(quantity) / Decimal::from(1_000_000)
+}
+
+/// Costs are stored like message costs, to the micro dollar
+fn stored_cost(cost:Decimal) -> Decimal {
+    cost.round_dp(6)
+}
+
+/// Cost in USD at the OpenAI audio prices, zero when the usage does not match the model pricing
+pub fn audio_cost(model:&str,usage:AudioUsage) -> Decimal {
+    let cost = match (model, usage) {
+        // $0.006 per minute
+        ("whisper-1", AudioUsage::Seconds(seconds)) => Decimal::from_f64(seconds).unwrap_or_default() * Decimal::new(6, 3) / Decimal::from(60),
+        ("gpt-4o-transcribe", AudioUsage::Tokens { text_input, audio_input, output }) => {
+            per_million(Decimal::new(25, 1), text_input as u64) + per_million(Decimal::from(6), audio_input as u64) + per_million(Decimal::from(10), output as u64)
+        },
+        ("gpt-4o-mini-transcribe", AudioUsage::Tokens { text_input, audio_input, output }) => {
+            per_million(Decimal::new(125, 2), text_input as u64) + per_million(Decimal::from(3), audio_input as u64) + per_million(Decimal::from(5), output as u64)
+        },
+        ("tts-1", AudioUsage::Characters(characters)) => per_million(Decimal::from(15), characters as u64),
+        ("tts-1-hd", AudioUsage::Characters(characters)) => per_million(Decimal::from(30), characters as u64),
+        _ => Decimal::ZERO,
+    };
+    stored_cost(cost)
+}
+
+/// Cost in USD at the OpenAI image generation prices, zero for unpriced models
+pub fn image_generation_cost(model:&str,usage:ImageGenerationUsage) -> Decimal {
+    let (text_input, image_input, output) = match model {
+        "gpt-image-1" => (Decimal::from(5), Decimal::from(10), Decimal::from(40)),
+        "gpt-image-1-mini" => (Decimal::from(2), Decimal::new(25, 1), Decimal::from(8)),
+        _ => return Decimal::ZERO,
+    };
+    stored_cost(
+        per_million(text_input, usage.text_input as u64)
+          + per_million(image_input, usage.image_input as u64)
+          + per_million(output, usage.output as u64)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_cost_follows_the_model_pricing() {
+        assert_eq!(audio_cost("whisper-1", AudioUsage::Seconds(90.0)), Decimal::new(9, 3));
+        assert_eq!(audio_cost("gpt-4o-mini-transcribe", AudioUsage::Tokens { text_input:20, audio_input:1000, output:100 }), Decimal::new(3525, 6));
+        assert_eq!(audio_cost("tts-1-hd", AudioUsage::Characters(1000)), Decimal::new(3, 2));
+        assert_eq!(audio_cost("whisper-1", AudioUsage::Characters(1000)), Decimal::ZERO);
+    }
+
+    #[test]
+    fn image_generation_cost_prices_each_token_kind() {
+        let usage = ImageGenerationUsage { text_input:50, image_input:0, output:4160 };
+        assert_eq!(image_generation_cost("gpt-image-1", usage), Decimal::new(16665, 5));
+        assert_eq!(image_generation_cost("dall-e-2", usage), Decimal::ZERO);
+    }
+}
@@ -3,8 +3,7 @@ use async_trait::async_trait;
 use reqwest_eventsource::EventSource;
 use serde::{Deserialize, Serialize};
 use utoipa::{ToSchema};
-use uuid::Uuid;
-use crate::{config::setting::{AnthropicSettings, OpenaiSettings}, dto::{files::Attachment, llm::{anthropic::AnthropicListModelsResponse, openai::OpenaiModel}}, llm::prompt::{Prompt, PromptTitleResponse}};
+use crate::{config::setting::{AnthropicSettings, OpenaiSettings}, dto::{files::Attachment, llm::{anthropic::AnthropicListModelsResponse, openai::{FileUploadResponse, OpenaiImageRequest, OpenaiImageResponse, OpenaiModel, OpenaiSpeechRequest, OpenaiTranscriptionRequest, OpenaiTranscriptionResponse}}}, llm::prompt::{Prompt, PromptTitleResponse}};
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
@@ -25,11 +24,18 @@ pub fn get_title_generation_model(provider:&str) -> Option<&str>{
 
 #[async_trait]
 pub trait OpenaiApis {
-    async fn openai_chat_stream(&self,openai_settings:&OpenaiSettings,model_name:String,temperature:Option<f32>,mut prompts:Vec<Prompt>,user_id:&Uuid,web_search:bool) -> Result<EventSource,Error>;
+    async fn openai_chat_stream(&self,openai_settings:&OpenaiSettings,model_name:String,temperature:Option<f32>,prompts:Vec<Prompt>,web_search:bool) -> Result<EventSource,Error>;
     async fn openai_chat_stream_text(&self,openai_settings:&OpenaiSettings,model_name:String,temperature:Option<f32>,prompt:Vec<String>) -> Result<EventSource,Error>;
-    async fn openai_upload_file(&self,openai_settings:&OpenaiSettings,attachment:&Attachment) -> Result<String,Error>;
+    async fn openai_upload_file(&self,openai_settings:&OpenaiSettings,attachment:&Attachment) -> Result<FileUploadResponse,Error>;
+    async fn openai_delete_file(&self,openai_settings:&OpenaiSettings,file_id:&str) -> Result<(),Error>;
     async fn openai_get_title(&self,openai_settings:&OpenaiSettings,prompt:String) -> Result<PromptTitleResponse,Error>;
     async fn openai_list_models(&self,openai_settings: &OpenaiSettings) -> Result<Vec<OpenaiModel>, Error>;
+    /// One embedding per input, in input order
+    async fn openai_embeddings(&self,openai_settings:&OpenaiSettings,model_name:&str,input:Vec<String>) -> Result<Vec<Vec<f32>>,Error>;
+    async fn openai_transcribe(&self,openai_settings:&OpenaiSettings,request:&OpenaiTranscriptionRequest,audio:&Attachment) -> Result<OpenaiTranscriptionResponse,Error>;
+    /// Returns the response once its headers are received, the audio is streamed from its body
+    async fn openai_speech(&self,openai_settings:&OpenaiSettings,request:&OpenaiSpeechRequest) -> Result<reqwest::Response,Error>;
+    async fn openai_generate_images(&self,openai_settings:&OpenaiSettings,request:&OpenaiImageRequest) -> Result<OpenaiImageResponse,Error>;
 } 
 
 pub trait OpenaiHeaders: Send + Sync {
@@ -49,7 +55,6 @@ pub trait AnthropicApis {
         temperature: Option<f32>,
         prompts: Vec<Prompt>,
         web_search: bool,
-        user_id:&Uuid,
     ) -> Result<EventSource, Error>;
 
     async fn anthropic_chat_stream_text(
@@ -0,0 +1,10 @@
+use anyhow::Error;
+use async_trait::async_trait;
+
+pub mod smtp;
+
+/// Delivery of account emails, e.g. password invites and resets
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self,to:&str,subject:&str,body:&str) -> Result<(),Error>;
+}
@@ -0,0 +1,32 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor, message::{Mailbox, header::ContentType}};
+use crate::{config::setting::MailSettings, mail::Mailer};
+
+/// SMTP relay configured by an `smtp://` or `smtps://` url, credentials included
+pub struct SmtpMailer {
+    transport:AsyncSmtpTransport<Tokio1Executor>,
+    from:Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(settings:&MailSettings) -> Result<Self,Error> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(&settings.smtp_url)?.build();
+        let from = settings.from.parse::<Mailbox>()?;
+        Ok(Self { transport, from })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self,to:&str,subject:&str,body:&str) -> Result<(),Error> {
+        let message = Message::builder()
+          .from(self.from.clone())
+          .to(to.parse::<Mailbox>()?)
+          .subject(subject)
+          .header(ContentType::TEXT_PLAIN)
+          .body(body.to_string())?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
@@ -14,6 +14,10 @@ pub mod auth;
 pub mod handlers;
 pub mod database;
 pub mod llm;
+pub mod jobs;
+pub mod rag;
+pub mod storage;
+pub mod mail;
 
 #[tokio::main]
 async fn main() -> Result<(),Error> {
@@ -29,6 +29,10 @@ pub struct Model {
    pub whitelist_models:Vec<String>,
    pub default_model:String,
    pub api_key_validated_at:Option<DateTime<Utc>>,
+ #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub fallback_chains:Option<serde_json::Value>,
+   pub timeout_ms:i32,
+   pub max_retries:i32,
    pub created_at:DateTime<Utc>,
    pub updated_at:DateTime<Utc>
 }
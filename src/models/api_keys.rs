@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq, Hash,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+   /// GET requests
+   Read,
+   /// Requests changing data, chat completions included
+   Write,
+   /// `/admin` endpoints, for keys of admins only
+   Admin,
+}
+
+/// Personal access token of a user or service account
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "api_keys", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   pub user_id:Uuid,
+   pub name:String,
+   /// Start of the key shown in lists
+   pub prefix:String,
+   /// Hex SHA-256 of the key
+    #[sea_orm(unique, indexed)]
+   pub key_hash:String,
+   pub scopes:Vec<ApiKeyScope>,
+    #[sea_orm(nullable)]
+   pub expires_at:Option<DateTime<Utc>>,
+    #[sea_orm(nullable)]
+   pub last_used_at:Option<DateTime<Utc>>,
+   /// Admin who created the key of a service account
+    #[sea_orm(nullable)]
+   pub created_by:Option<Uuid>,
+   pub created_at:DateTime<Utc>,
+    #[sea_orm(nullable)]
+   pub revoked_at:Option<DateTime<Utc>>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::users::Entity",from = "Column::UserId",to = "super::users::Column::Id")]
+    Users,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
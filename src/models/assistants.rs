@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AssistantVisibility {
+   /// Only its creator
+   Private,
+   /// Users of the assistant department
+   Department,
+   /// Every user of the organization
+   Organization,
+}
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "assistants", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   // Creator, null once the user is deleted
+   pub user_id:Option<Uuid>,
+   pub name:String,
+   pub description:String,
+   pub model_provider: String,
+   pub model_name: String,
+   pub system_prompt:String,
+   pub temperature:Option<f32>,
+   // Tools the assistant may use, `web_search` included
+   pub tools:Vec<String>,
+   // Reference files of the creator attached to every conversation
+   pub file_ids:Vec<Uuid>,
+   pub visibility:AssistantVisibility,
+   pub department:Option<String>,
+   pub created_at:DateTime<Utc>,
+   pub updated_at: DateTime<Utc>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::users::Entity",from = "Column::UserId",to = "super::users::Column::Id")]
+    Users,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
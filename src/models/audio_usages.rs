@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AudioUsageKind {
+   Transcription,
+   Speech,
+}
+
+/// Usage of an audio request, recorded like the usage of chat messages
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "audio_usages", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   pub user_id:Uuid,
+   pub kind:AudioUsageKind,
+   pub model_provider: String,
+   pub model_name: String,
+   pub request_tokens:i32,
+   pub response_tokens:i32,
+   pub total_tokens: i32,
+   // Latency in milliseconds
+   pub latency:i32,
+   // Cost in USD
+   pub cost:Decimal,
+   // Conversation and message a transcript was posted to
+    #[sea_orm(nullable)]
+   pub conversation_id:Option<Uuid>,
+    #[sea_orm(nullable)]
+   pub message_id:Option<Uuid>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub metadata: Option<serde_json::Value>,
+   pub created_at:DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::users::Entity",from = "Column::UserId",to = "super::users::Column::Id")]
+    Users,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
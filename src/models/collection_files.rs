@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "collection_files", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   pub collection_id:Uuid,
+   pub file_id:Uuid,
+   pub created_at:DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::collections::Entity",from = "Column::CollectionId",to = "super::collections::Column::Id")]
+    Collections,
+    #[sea_orm(belongs_to = "super::files::Entity",from = "Column::FileId",to = "super::files::Column::Id")]
+    Files,
+}
+
+impl Related<super::collections::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Collections.def()
+    }
+}
+
+impl Related<super::files::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Files.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum CollectionScope {
+   /// Only its creator
+   User,
+   /// Users of the collection department
+   Department,
+   /// Every user of the organization
+   Organization,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "collections", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   // Creator, null once the user is deleted
+   pub user_id:Option<Uuid>,
+   pub name:String,
+   pub description:String,
+   pub scope:CollectionScope,
+   pub department:Option<String>,
+   // Roles allowed in the scope, every role when empty
+   pub roles:Vec<String>,
+   pub created_at:DateTime<Utc>,
+   pub updated_at: DateTime<Utc>,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::users::Entity",from = "Column::UserId",to = "super::users::Column::Id")]
+    Users,
+    #[sea_orm(has_many = "super::collection_files::Entity")]
+    CollectionFiles,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl Related<super::collection_files::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CollectionFiles.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -24,6 +24,12 @@ pub struct Model{
    pub total_cost: Decimal,
     #[sea_orm(column_type = "JsonBinary", nullable)]
    pub metadata: Option<serde_json::Value>,
+   // Last message of the active branch, the path to it is what the user sees
+    #[sea_orm(nullable)]
+   pub active_message_id: Option<Uuid>,
+   // Assistant the conversation was started from, its configuration applies to every turn
+    #[sea_orm(nullable)]
+   pub assistant_id: Option<Uuid>,
 }
 
 #[derive(Debug, FromQueryResult, Serialize, Deserialize)]
@@ -7,8 +7,30 @@ use utoipa::ToSchema;
 #[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]   
 pub enum FileUploadStatus {
+   /// Stored, not scanned when no malware scanner is configured
    Uploaded,
-   Deleted
+   Deleted,
+   /// Waiting for the malware scan, not readable yet
+   Scanning,
+   Infected,
+   Clean,
+}
+
+impl FileUploadStatus {
+   /// Statuses of files whose content can be downloaded, indexed or sent to providers
+   pub const AVAILABLE:[FileUploadStatus;2] = [FileUploadStatus::Uploaded, FileUploadStatus::Clean];
+
+   pub fn is_available(&self) -> bool {
+      Self::AVAILABLE.contains(self)
+   }
+}
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+   Local,
+   S3,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
@@ -21,7 +43,9 @@ pub struct Model{
    pub name:String,
    pub content_type:String,
    pub size:i64,
-   pub local_path:String,
+   /// Key of the file in its storage backend
+   pub storage_key:String,
+   pub storage_backend:StorageBackend,
    pub description:Option<String>,
    pub url:Option<String>,
    pub status:FileUploadStatus,
@@ -29,6 +53,8 @@ pub struct Model{
    pub created_at:DateTime<Utc>,
    pub updated_at: DateTime<Utc>,
    pub metadata: Option<serde_json::Value>,
+    #[sea_orm(column_type = "Text", nullable)]
+   pub extracted_text:Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
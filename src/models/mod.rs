@@ -6,4 +6,13 @@ pub mod prompt_templates;
 pub mod organizations;
 pub mod ai_engines;
 pub mod sso_providers;
-pub mod files;
\ No newline at end of file
+pub mod files;
+pub mod assistants;
+pub mod file_chunks;
+pub mod collections;
+pub mod collection_files;
+pub mod audio_usages;
+pub mod password_tokens;
+pub mod sessions;
+pub mod api_keys;
+pub mod user_identities;
\ No newline at end of file
@@ -17,6 +17,21 @@ pub struct Model {
    pub default_model:String,
    pub data_retention_days:i64,
    pub require_mfa: bool,
+   /// Mandatory instructions prepended to every chat of the organization
+   pub custom_instructions:Option<String>,
+   /// Instructions per department name, added after the organization ones
+   #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub department_instructions:Option<serde_json::Value>,
+   /// Total bytes of files of the organization, unlimited when None
+   pub storage_quota_bytes:Option<i64>,
+   /// Bytes of files per user, unless overridden on the user
+   pub user_storage_quota_bytes:Option<i64>,
+   /// Allowed upload types with their size limits, any type when None
+   #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub upload_policy:Option<serde_json::Value>,
+   /// Password rules and lockout of local accounts, defaults when None
+   #[sea_orm(column_type = "JsonBinary", nullable)]
+   pub password_policy:Option<serde_json::Value>,
    pub created_on:DateTime<Utc>,
    pub updated_on:DateTime<Utc>
 }
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordTokenPurpose {
+   /// Sent by an admin, sets the first password of the account
+   Invite,
+   /// Requested by the user who forgot their password
+   Reset,
+}
+
+/// Single use token setting the password of a user
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "password_tokens", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   pub user_id:Uuid,
+   pub purpose:PasswordTokenPurpose,
+   /// Hex SHA-256 of the token
+    #[sea_orm(unique, indexed)]
+   pub token_hash:String,
+   pub expires_at:DateTime<Utc>,
+    #[sea_orm(nullable)]
+   pub used_at:Option<DateTime<Utc>>,
+   /// Admin who sent the invite
+    #[sea_orm(nullable)]
+   pub created_by:Option<Uuid>,
+   pub created_at:DateTime<Utc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::users::Entity",from = "Column::UserId",to = "super::users::Column::Id")]
+    Users,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,6 +2,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sea_orm::entity::prelude::*;
 
+/// Values stored in the string flag columns
+pub const FLAG_SET:&str = "true";
+pub const FLAG_UNSET:&str = "false";
+
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "prompt_templates", rename_all="camelCase")]
 #[serde(rename_all = "camelCase")]
@@ -36,4 +40,14 @@ impl Related<super::users::Entity> for Entity {
         Relation::Users.def()
     }
 }
+impl Model {
+    pub fn is_public(&self) -> bool {
+        self.public_flag == FLAG_SET
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.system_flag_template == FLAG_SET
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
\ No newline at end of file
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sea_orm::entity::prelude::*;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone,Copy, PartialEq, Eq,EnumIter, DeriveActiveEnum, Serialize, Deserialize, ToSchema)]
+#[sea_orm(rs_type = "String",db_type = "String(StringLen::None)",rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SessionRevokeReason {
+   /// Signed out from this device
+   Logout,
+   /// Signed out from all devices
+   LogoutAll,
+   /// A rotated refresh token was presented again, the session may be stolen
+   Reuse,
+   /// Revoked by an admin
+   Admin,
+   /// The user was deactivated, suspended or deleted
+   UserStatus,
+   PasswordReset,
+   /// The password was changed from another session
+   PasswordChange,
+   /// The second factor was disabled or reset
+   MfaChange,
+}
+
+/// Signed in device, owning the refresh token family issued at its login
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "sessions", rename_all="camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct Model{
+   #[sea_orm(primary_key, unique, indexed)]
+   pub id: Uuid,
+   pub user_id:Uuid,
+   /// Id of the latest refresh token, the only one accepted
+   pub refresh_jti:Uuid,
+    #[sea_orm(nullable)]
+   pub user_agent:Option<String>,
+    #[sea_orm(nullable)]
+   pub ip_address:Option<String>,
+   pub created_at:DateTime<Utc>,
+   pub last_seen_at:DateTime<Utc>,
+   pub expires_at:DateTime<Utc>,
+    #[sea_orm(nullable)]
+   pub revoked_at:Option<DateTime<Utc>>,
+    #[sea_orm(nullable)]
+   pub revoked_reason:Option<SessionRevokeReason>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::users::Entity",from = "Column::UserId",to = "super::users::Column::Id")]
+    Users,
+}
+
+impl Related<super::users::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Users.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -39,16 +39,25 @@ pub struct Model {
   pub email_verified:bool,
   pub name:Option<String>,
   pub password:Option<String>,
-#[sea_orm(column_type = "Text", unique, indexed, nullable)]
-  pub google_id:Option<String>,
-#[sea_orm(column_type = "Text", unique, indexed, nullable)]
-  pub azure_id:Option<String>,
   pub mfa_enabled: bool,
+  /// TOTP secret in base32, encrypted with the app key
   pub mfa_secret:Option<String>,
+  /// Hex SHA-256 of the unused recovery codes
+  pub mfa_recovery_codes:Vec<String>,
+  /// Last TOTP time step accepted
+  pub mfa_last_step:Option<i64>,
+  /// Version of the access tokens still accepted, bumped on status and role changes
+  pub token_version:i32,
+  /// Admin managed account without login, used through its API keys only
+  pub is_service_account:bool,
   pub created_at:DateTime<Utc>,
   pub updated_at:DateTime<Utc>,
   pub last_login_at:DateTime<Utc>,
   pub password_changed_at:Option<DateTime<Utc>>,
+  /// Consecutive failed password logins, reset on success and on lockout
+  pub failed_login_attempts:i32,
+  /// Password logins are refused until then
+  pub locked_until:Option<DateTime<Utc>>,
   pub role:UserRole,
   pub hd: Option<String>, //hosted domain of user email/website
   pub department:Option<String>,
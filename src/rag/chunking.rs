@@ -0,0 +1,69 @@
+/// Characters per chunk, around 500 tokens of English text
+pub const CHUNK_SIZE:usize = 2_000;
+/// Characters repeated at the start of the next chunk so text cut at a boundary keeps its context
+pub const CHUNK_OVERLAP:usize = 200;
+
+/// Cut positions by preference: paragraph, line, sentence, then word
+const SEPARATORS:[&str; 4] = ["\n\n", "\n", ". ", " "];
+
+/// Splits the text in chunks of at most `size` characters, each starting `overlap` characters
+/// before the end of the previous one
+pub fn chunk_text(text:&str,size:usize,overlap:usize) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + size).min(chars.len());
+        if end < chars.len() {
+            end = start + boundary(&chars[start..end]).unwrap_or(end - start);
+        }
+        let chunk = chars[start..end].iter().collect::<String>();
+        let chunk = chunk.trim();
+        if !chunk.is_empty() {
+            chunks.push(chunk.to_string());
+        }
+        if end == chars.len() {
+            break;
+        }
+        // Always moves forward, even with an overlap larger than the chunk
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+    chunks
+}
+
+/// Position right after the last preferred separator found in the second half of the window
+fn boundary(window:&[char]) -> Option<usize> {
+    let half = window.len() / 2;
+    SEPARATORS.iter().find_map(|separator| {
+        let separator = separator.chars().collect::<Vec<char>>();
+        let last = window.len().checked_sub(separator.len())?;
+        (half..=last)
+          .rev()
+          .find(|&i| window[i..i + separator.len()] == separator[..])
+          .map(|i| i + separator.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_cut_at_boundaries_and_overlap() {
+        let text = "First paragraph about invoices.\n\nSecond paragraph about refunds. It has two sentences.";
+        let chunks = chunk_text(text, 60, 10);
+        assert_eq!(chunks[0], "First paragraph about invoices.");
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 60));
+        assert!(chunks.last().unwrap().ends_with("two sentences."));
+        assert_eq!(chunk_text("short", 60, 10), vec!["short".to_string()]);
+        assert!(chunk_text("  \n ", 60, 10).is_empty());
+    }
+
+    #[test]
+    fn chunking_progresses_without_separators() {
+        let text = "x".repeat(250);
+        let chunks = chunk_text(&text, 100, 150);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 100));
+        assert_eq!(chunks.last().unwrap().len(), 100);
+    }
+}
@@ -0,0 +1,362 @@
+use std::io::{Cursor, Read, Seek};
+use anyhow::{Error, anyhow};
+use calamine::Reader as _;
+use chrono::Utc;
+use quick_xml::{Reader, escape::resolve_predefined_entity, events::{BytesRef, Event}};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter};
+use serde_json::json;
+use uuid::Uuid;
+use zip::ZipArchive;
+use crate::{
+    dto::files::{DocumentFormat, DocumentStructure, ExtractionStatus, FileExtraction, SheetStructure},
+    llm::prompt::Prompt,
+    models::files::{self, FileUploadStatus},
+};
+
+pub const EXTRACTION_KEY:&str = "extraction";
+/// Characters of extracted text inlined in a prompt per file, longer documents go through retrieval
+pub const MAX_INLINE_CHARACTERS:usize = 100_000;
+/// Line width of the text rendered from HTML, wide enough to keep paragraphs on one line
+const HTML_TEXT_WIDTH:usize = 400;
+/// Uncompressed bytes read from the archive of a docx, pptx or xlsx, keeps zip bombs out of memory
+const MAX_UNZIPPED_BYTES:u64 = 128 * 1024 * 1024;
+
+/// Plain text and structure extracted from a file
+#[derive(Debug)]
+pub struct ExtractedDocument {
+    pub format:DocumentFormat,
+    pub text:String,
+    pub structure:DocumentStructure,
+}
+
+/// Extractor of the file, from its content type and then its extension
+pub fn document_format(content_type:&str,name:&str) -> Option<DocumentFormat> {
+    let content_type = content_type.to_lowercase();
+    let extension = name
+      .rsplit_once('.')
+      .map(|(_, extension)| extension.to_lowercase())
+      .unwrap_or_default();
+    let format = match (content_type.as_str(), extension.as_str()) {
+        ("application/pdf", _) | (_, "pdf") => DocumentFormat::Pdf,
+        ("application/vnd.openxmlformats-officedocument.wordprocessingml.document", _) | (_, "docx") => DocumentFormat::Docx,
+        ("application/vnd.openxmlformats-officedocument.presentationml.presentation", _) | (_, "pptx") => DocumentFormat::Pptx,
+        ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", _)
+        | ("application/vnd.ms-excel", _)
+        | ("application/vnd.oasis.opendocument.spreadsheet", _)
+        | (_, "xlsx" | "xlsm" | "xls" | "ods") => DocumentFormat::Spreadsheet,
+        ("text/csv", _) | (_, "csv") => DocumentFormat::Csv,
+        ("text/html", _) | (_, "html" | "htm") => DocumentFormat::Html,
+        ("application/json" | "application/xml" | "application/x-yaml", _) => DocumentFormat::Text,
+        (content_type, _) if content_type.starts_with("text/") => DocumentFormat::Text,
+        (_, "txt" | "md" | "json" | "xml" | "yaml" | "yml") => DocumentFormat::Text,
+        _ => return None,
+    };
+    Some(format)
+}
+
+pub fn extract_document(bytes:&[u8],format:DocumentFormat) -> Result<ExtractedDocument,Error> {
+    let mut structure = DocumentStructure::default();
+    let text = match format {
+        DocumentFormat::Pdf => {
+            let pages = pdf_extract::extract_text_from_mem_by_pages(bytes)?;
+            structure.pages = Some(pages.len());
+            pages.join("\n\n")
+        },
+        DocumentFormat::Docx => {
+            let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+            let mut budget = MAX_UNZIPPED_BYTES;
+            let xml = read_zip_entry(&mut archive, "word/document.xml", &mut budget)?;
+            let (text, tables) = ooxml_text(&xml)?;
+            structure.tables = Some(tables);
+            text
+        },
+        DocumentFormat::Pptx => {
+            let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+            let mut slides = archive
+              .file_names()
+              .filter_map(|name| name
+                .strip_prefix("ppt/slides/slide")
+                .and_then(|rest| rest.strip_suffix(".xml"))
+                .and_then(|number| number.parse::<usize>().ok()))
+              .collect::<Vec<usize>>();
+            slides.sort_unstable();
+            let mut text = String::new();
+            let mut tables = 0;
+            let mut budget = MAX_UNZIPPED_BYTES;
+            for number in &slides {
+                let xml = read_zip_entry(&mut archive, &format!("ppt/slides/slide{number}.xml"), &mut budget)?;
+                let (slide_text, slide_tables) = ooxml_text(&xml)?;
+                text.push_str(&format!("Slide {number}:\n{}\n\n", slide_text.trim()));
+                tables += slide_tables;
+            }
+            structure.slides = Some(slides.len());
+            structure.tables = Some(tables);
+            text
+        },
+        DocumentFormat::Spreadsheet => {
+            // xls and ods workbooks are not zip archives, xlsx ones are checked before calamine inflates them
+            if let Ok(mut archive) = ZipArchive::new(Cursor::new(bytes)) {
+                check_unzipped_size(&mut archive, MAX_UNZIPPED_BYTES)?;
+            }
+            let mut workbook = calamine::open_workbook_auto_from_rs(Cursor::new(bytes))?;
+            let mut text = String::new();
+            for name in workbook.sheet_names() {
+                let range = workbook.worksheet_range(&name)?;
+                let (rows, columns) = range.get_size();
+                text.push_str(&format!("Sheet {name}:\n"));
+                for row in range.rows() {
+                    let cells = row.iter().map(|cell| cell.to_string()).collect::<Vec<String>>();
+                    text.push_str(&cells.join(" | "));
+                    text.push('\n');
+                }
+                text.push('\n');
+                structure.sheets.push(SheetStructure { name, rows, columns });
+            }
+            text
+        },
+        DocumentFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+              .has_headers(false)
+              .flexible(true)
+              .from_reader(bytes);
+            let mut text = String::new();
+            let (mut rows, mut columns) = (0, 0);
+            for record in reader.records() {
+                let record = record?;
+                columns = columns.max(record.len());
+                rows += 1;
+                text.push_str(&record.iter().collect::<Vec<&str>>().join(" | "));
+                text.push('\n');
+            }
+            structure.sheets.push(SheetStructure { name:"csv".to_string(), rows, columns });
+            text
+        },
+        DocumentFormat::Html => html2text::from_read(bytes, HTML_TEXT_WIDTH)?,
+        DocumentFormat::Text => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    Ok(ExtractedDocument { format, text:text.trim().to_string(), structure })
+}
+
+/// Reads an entry as text, taking its uncompressed size from `budget` shared by the entries of the archive
+fn read_zip_entry<R:Read + Seek>(archive:&mut ZipArchive<R>,name:&str,budget:&mut u64) -> Result<String,Error> {
+    let entry = archive.by_name(name)?;
+    if entry.size() > *budget {
+        return Err(anyhow!("{name} is larger than {MAX_UNZIPPED_BYTES} bytes uncompressed"));
+    }
+    // The declared size can lie, reading one byte past the budget tells
+    let mut xml = String::new();
+    entry
+      .take(*budget + 1)
+      .read_to_string(&mut xml)?;
+    let size = xml.len() as u64;
+    if size > *budget {
+        return Err(anyhow!("{name} is larger than {MAX_UNZIPPED_BYTES} bytes uncompressed"));
+    }
+    *budget -= size;
+    Ok(xml)
+}
+
+/// Declared uncompressed size of all the entries against `limit`
+fn check_unzipped_size<R:Read + Seek>(archive:&mut ZipArchive<R>,limit:u64) -> Result<(),Error> {
+    let mut total = 0u64;
+    for index in 0..archive.len() {
+        total = total.saturating_add(archive.by_index_raw(index)?.size());
+    }
+    if total > limit {
+        return Err(anyhow!("archive is larger than {limit} bytes uncompressed"));
+    }
+    Ok(())
+}
+
+/// Text of a Word document or slide XML with its number of tables.
+/// Paragraphs are lines, table cells are separated by `|` and table rows are lines.
+fn ooxml_text(xml:&str) -> Result<(String,usize),Error> {
+    let mut reader = Reader::from_str(xml);
+    let mut text = String::new();
+    let mut tables = 0;
+    let mut in_text = false;
+    let mut cell_depth = 0usize;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"t" => in_text = true,
+                b"tbl" => tables += 1,
+                b"tc" => cell_depth += 1,
+                _ => {}
+            },
+            Event::Empty(element) => match element.local_name().as_ref() {
+                b"tab" => text.push('\t'),
+                b"br" => text.push('\n'),
+                _ => {}
+            },
+            Event::End(element) => match element.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => text.push(if cell_depth > 0 { ' ' } else { '\n' }),
+                b"tc" => {
+                    cell_depth = cell_depth.saturating_sub(1);
+                    text.truncate(text.trim_end_matches(' ').len());
+                    text.push_str(" | ");
+                },
+                b"tr" => {
+                    if let Some(row) = text.strip_suffix(" | ") {
+                        text.truncate(row.len());
+                    }
+                    text.push('\n');
+                },
+                _ => {}
+            },
+            Event::Text(content) if in_text => text.push_str(&content.decode()?),
+            Event::GeneralRef(reference) if in_text => text.push_str(&resolve_reference(&reference)?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((text, tables))
+}
+
+fn resolve_reference(reference:&BytesRef) -> Result<String,Error> {
+    if let Some(character) = reference.resolve_char_ref()? {
+        return Ok(character.to_string());
+    }
+    let name = reference.decode()?;
+    resolve_predefined_entity(&name)
+      .map(str::to_string)
+      .ok_or(anyhow!("unknown xml entity {name}"))
+}
+
+/// Extracts the file on a blocking thread, returns the text when extracted and the extraction record
+pub async fn extract_file(bytes:Vec<u8>,content_type:&str,name:&str) -> (Option<String>,FileExtraction) {
+    let mut extraction = FileExtraction {
+        status:ExtractionStatus::Unsupported,
+        format:document_format(content_type, name),
+        characters:0,
+        structure:DocumentStructure::default(),
+        extracted_at:Utc::now(),
+    };
+    let Some(format) = extraction.format else {
+        return (None, extraction);
+    };
+    // Parsers may panic on malformed documents, the join error is reported as a failure
+    match tokio::task::spawn_blocking(move || extract_document(&bytes, format)).await {
+        Ok(Ok(document)) => {
+            extraction.status = ExtractionStatus::Extracted;
+            extraction.characters = document.text.chars().count();
+            extraction.structure = document.structure;
+            (Some(document.text), extraction)
+        },
+        Ok(Err(e)) => {
+            eprintln!("file {name} text extraction error: {e}");
+            extraction.status = ExtractionStatus::Failed;
+            (None, extraction)
+        },
+        Err(e) => {
+            eprintln!("file {name} text extraction panicked: {e}");
+            extraction.status = ExtractionStatus::Failed;
+            (None, extraction)
+        },
+    }
+}
+
+pub fn get_file_extraction(metadata:&Option<serde_json::Value>) -> Option<FileExtraction> {
+    metadata
+      .as_ref()
+      .and_then(|json| json.get(EXTRACTION_KEY))
+      .and_then(|value| serde_json::from_value::<FileExtraction>(value.clone()).ok())
+}
+
+/// Whether the model reads the file as is
+fn is_native(content_type:&str,supports_pdf_native:bool) -> bool {
+    content_type.starts_with("image/") || (content_type == "application/pdf" && supports_pdf_native)
+}
+
+/// Replaces the files the model cannot read natively by their extracted text, appended to the prompt text.
+/// Files without extracted text are left attached.
+pub async fn inline_extracted_files(db:&DatabaseConnection,user_id:Uuid,supports_pdf_native:bool,prompts:&mut [Prompt]) -> Result<(),DbErr> {
+    let file_ids = prompts
+      .iter()
+      .flat_map(|prompt| prompt.files.iter())
+      .filter(|file| !is_native(&file.content_type, supports_pdf_native))
+      .map(|file| file.id)
+      .collect::<Vec<Uuid>>();
+    if file_ids.is_empty() {
+        return Ok(());
+    }
+    let file_models = files::Entity::find()
+      .filter(files::Column::Id.is_in(file_ids))
+      .filter(files::Column::Status.is_in(FileUploadStatus::AVAILABLE))
+      .all(db)
+      .await?;
+    for prompt in prompts.iter_mut() {
+        let mut kept = Vec::new();
+        for file in std::mem::take(&mut prompt.files) {
+            let owner_id = file.owner_id.unwrap_or(user_id);
+            let extracted_text = file_models
+              .iter()
+              .find(|file_model| file_model.id == file.id && file_model.user_id == owner_id)
+              .and_then(|file_model| file_model.extracted_text.as_ref())
+              .filter(|_| !is_native(&file.content_type, supports_pdf_native));
+            match extracted_text {
+                Some(extracted_text) => {
+                    let mut text = extracted_text.chars().take(MAX_INLINE_CHARACTERS).collect::<String>();
+                    if text.len() < extracted_text.len() {
+                        text.push_str("\n[truncated]");
+                    }
+                    prompt.text.push_str(&format!("\n\n<file name=\"{}\">\n{}\n</file>", file.name, text));
+                },
+                None => kept.push(file),
+            }
+        }
+        prompt.files = kept;
+    }
+    Ok(())
+}
+
+/// Metadata of a new file with its extraction record
+pub fn extraction_metadata(extraction:&FileExtraction) -> serde_json::Value {
+    json!({ EXTRACTION_KEY:extraction })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ooxml_paragraphs_and_tables() {
+        let xml = r#"<w:document xmlns:w="w"><w:body>
+            <w:p><w:r><w:t>Refund policy</w:t></w:r></w:p>
+            <w:p><w:r><w:t xml:space="preserve">Terms &amp; </w:t></w:r><w:r><w:t>conditions</w:t></w:r></w:p>
+            <w:tbl><w:tr><w:tc><w:p><w:r><w:t>Plan</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Days</w:t></w:r></w:p></w:tc></w:tr></w:tbl>
+        </w:body></w:document>"#;
+        let (text, tables) = ooxml_text(xml).unwrap();
+        assert_eq!(tables, 1);
+        assert_eq!(text, "Refund policy\nTerms & conditions\nPlan | Days\n");
+    }
+
+    #[test]
+    fn csv_rows_and_format_detection() {
+        let document = extract_document(b"name,amount\nalice,3\nbob,4,extra\n", DocumentFormat::Csv).unwrap();
+        assert_eq!(document.text, "name | amount\nalice | 3\nbob | 4 | extra");
+        assert_eq!(document.structure.sheets[0].rows, 3);
+        assert_eq!(document.structure.sheets[0].columns, 3);
+        assert_eq!(document_format("application/octet-stream", "Report.DOCX"), Some(DocumentFormat::Docx));
+        assert_eq!(document_format("text/markdown", "notes.md"), Some(DocumentFormat::Text));
+        assert_eq!(document_format("image/png", "logo.png"), None);
+    }
+
+    #[test]
+    fn oversized_zip_entries_are_refused() {
+        use std::io::Write;
+        use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("word/document.xml", options).unwrap();
+        writer.write_all(&[b' '; 2048]).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert!(read_zip_entry(&mut archive, "word/document.xml", &mut 1024).is_err());
+        assert!(check_unzipped_size(&mut archive, 1024).is_err());
+        let mut budget = 4096;
+        assert_eq!(read_zip_entry(&mut archive, "word/document.xml", &mut budget).unwrap().len(), 2048);
+        assert_eq!(budget, 2048);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod extraction;
+pub mod chunking;
+pub mod retrieval;
+pub mod vector_store;
@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{Error, anyhow};
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IntoActiveModel, QueryFilter};
+use serde_json::json;
+use uuid::Uuid;
+use crate::{
+    dto::{chat::Citation, files::{File, FileIndex, FileIndexStatus}},
+    llm::{prompt::Prompt, provider::OpenaiApis},
+    models::{file_chunks, files::{self, FileUploadStatus}, messages::ChatRole},
+    rag::{chunking::{CHUNK_OVERLAP, CHUNK_SIZE, chunk_text}, extraction::{MAX_INLINE_CHARACTERS, extract_file, get_file_extraction}},
+    state::SharedState,
+};
+
+pub const FILE_INDEX_KEY:&str = "fileIndex";
+pub const EMBEDDING_MODEL:&str = "text-embedding-3-small";
+/// Chunks injected in the prompt per answer
+pub const RETRIEVAL_TOP_K:usize = 8;
+const EMBEDDING_BATCH_SIZE:usize = 64;
+
+pub fn get_file_index(metadata:&Option<serde_json::Value>) -> Option<FileIndex> {
+   metadata
+     .as_ref()
+     .and_then(|json| json.get(FILE_INDEX_KEY))
+     .and_then(|value| serde_json::from_value::<FileIndex>(value.clone()).ok())
+}
+
+/// Text extracted from the file, extracted now for files uploaded before extraction existed
+async fn read_text(app_state:&SharedState,file_model:&files::Model) -> Option<String> {
+   if file_model.extracted_text.is_some() || get_file_extraction(&file_model.metadata).is_some() {
+      return file_model.extracted_text.clone();
+   }
+   let buff = app_state
+     .file_storage
+     .get(file_model.storage_backend, &file_model.storage_key)
+     .await
+     .map_err(|e| eprintln!("file storage error : {e}"))
+     .ok()?;
+   extract_file(buff, &file_model.content_type, &file_model.name)
+     .await
+     .0
+}
+
+/// Embeds the texts with the configured OpenAI engine, in input order
+async fn embed(app_state:&SharedState,texts:Vec<String>) -> Result<Vec<Vec<f32>>,Error> {
+   let settings = app_state
+     .settings
+     .openai
+     .read()
+     .await
+     .clone()
+     .filter(|settings| settings.is_enabled)
+     .ok_or(anyhow!("no ai engine configured for embeddings"))?;
+   let mut embeddings = Vec::with_capacity(texts.len());
+   for batch in texts.chunks(EMBEDDING_BATCH_SIZE) {
+      let batch_embeddings = app_state
+        .req_client
+        .openai_embeddings(&settings, EMBEDDING_MODEL, batch.to_vec())
+        .await?;
+      if batch_embeddings.len() != batch.len() {
+         return Err(anyhow!("expected {} embeddings, got {}", batch.len(), batch_embeddings.len()));
+      }
+      embeddings.extend(batch_embeddings);
+   }
+ Ok(embeddings)
+}
+
+/// Chunks and embeds the file, replacing its previous chunks, and records the index on the file row.
+/// Failures are not recorded so the file is indexed again on its next use.
+pub async fn index_file(app_state:&SharedState,file_model:&files::Model) -> Result<FileIndex,Error> {
+   let chunks = read_text(app_state, file_model)
+     .await
+     .map(|text| chunk_text(&text, CHUNK_SIZE, CHUNK_OVERLAP))
+     .unwrap_or_default();
+   let file_index = if chunks.is_empty() {
+      FileIndex { status:FileIndexStatus::Unsupported, chunk_count:0, embedding_model:None, indexed_at:Utc::now() }
+   } else {
+      let embeddings = embed(app_state, chunks.clone()).await?;
+      let chunk_models = chunks
+        .into_iter()
+        .zip(embeddings)
+        .enumerate()
+        .map(|(chunk_index, (content, embedding))| file_chunks::Model {
+           id:Uuid::new_v4(),
+           file_id:file_model.id,
+           user_id:file_model.user_id,
+           chunk_index:chunk_index as i32,
+           content,
+           embedding,
+           embedding_model:EMBEDDING_MODEL.to_string(),
+           created_at:Utc::now(),
+        })
+        .collect::<Vec<file_chunks::Model>>();
+      let chunk_count = chunk_models.len();
+      app_state.vector_store.replace_file(file_model.id, chunk_models).await?;
+      FileIndex { status:FileIndexStatus::Ready, chunk_count, embedding_model:Some(EMBEDDING_MODEL.to_string()), indexed_at:Utc::now() }
+   };
+   // Reloaded, the provider files cache may have changed while embedding
+   let current = files::Entity::find_by_id(file_model.id)
+     .one(&app_state.database)
+     .await?
+     .ok_or(anyhow!("file {} not found", file_model.id))?;
+   let mut metadata = current.metadata
+     .clone()
+     .filter(|json| json.is_object())
+     .unwrap_or_else(|| json!({}));
+   metadata[FILE_INDEX_KEY] = json!(file_index);
+   let mut active_model = current.into_active_model();
+   active_model.metadata = Set(Some(metadata));
+   active_model
+     .update(&app_state.database)
+     .await?;
+ Ok(file_index)
+}
+
+/// Indexes a newly uploaded file in the background
+pub fn spawn_index_file(app_state:SharedState,file_model:files::Model) {
+   tokio::spawn(async move {
+      if let Err(e) = index_file(&app_state, &file_model).await {
+         eprintln!("file {} indexing error: {e}", file_model.id);
+      }
+   });
+}
+
+/// Whether an attached file is too long to be sent whole, files without extracted text are judged by their size
+fn needs_retrieval(file_model:&files::Model) -> bool {
+   match &file_model.extracted_text {
+      Some(text) => text.chars().count() > MAX_INLINE_CHARACTERS,
+      None => file_model.size > MAX_INLINE_CHARACTERS as i64,
+   }
+}
+
+/// Whether the chunks of the file can be searched with the current embedding model
+pub fn is_searchable(file_index:&FileIndex) -> bool {
+   file_index.status == FileIndexStatus::Ready
+     && file_index.chunk_count > 0
+     && file_index.embedding_model.as_deref() == Some(EMBEDDING_MODEL)
+}
+
+/// Replaces the indexed files of the prompts too long to be sent whole by their chunks most relevant to
+/// the last user message, added as a system prompt. Files that cannot be indexed stay attached and are sent whole.
+/// `sources` are only searched, the ones still being indexed are skipped.
+/// Returns the citations of the injected chunks, none when retrieval is unavailable.
+pub async fn retrieve_context(app_state:&SharedState,user_id:Uuid,prompts:&mut Vec<Prompt>,sources:Vec<File>) -> Vec<Citation> {
+   match try_retrieve_context(app_state, user_id, prompts, sources).await {
+      Ok(citations) => citations,
+      Err(e) => {
+         eprintln!("retrieval error: {e}");
+         Vec::new()
+      }
+   }
+}
+
+async fn try_retrieve_context(app_state:&SharedState,user_id:Uuid,prompts:&mut Vec<Prompt>,sources:Vec<File>) -> Result<Vec<Citation>,Error> {
+   let attached = prompts
+     .iter()
+     .flat_map(|prompt| prompt.files.iter())
+     .map(|file| file.id)
+     .collect::<HashSet<Uuid>>();
+   let owners = prompts
+     .iter()
+     .flat_map(|prompt| prompt.files.iter())
+     .chain(sources.iter())
+     .map(|file| (file.id, file.owner_id.unwrap_or(user_id)))
+     .collect::<HashMap<Uuid,Uuid>>();
+   let Some(query) = prompts
+     .iter()
+     .rev()
+     .find(|prompt| prompt.role == ChatRole::User)
+     .map(|prompt| prompt.text.trim().to_string())
+     .filter(|text| !text.is_empty()) else {
+      return Ok(Vec::new());
+   };
+   if owners.is_empty() {
+      return Ok(Vec::new());
+   }
+   let file_models = files::Entity::find()
+     .filter(files::Column::Id.is_in(owners.keys().copied()))
+     .filter(files::Column::Status.is_in(FileUploadStatus::AVAILABLE))
+     .all(&app_state.database)
+     .await?
+     .into_iter()
+     .filter(|file_model| owners.get(&file_model.id) == Some(&file_model.user_id))
+     .collect::<Vec<files::Model>>();
+   let mut file_names = HashMap::new();
+   for file_model in file_models {
+      // Short attachments are sent whole, the model reads their tables and layout
+      if attached.contains(&file_model.id) && !needs_retrieval(&file_model) {
+         continue;
+      }
+      // Files uploaded before retrieval existed, or whose indexing failed, are indexed on first use
+      let file_index = match get_file_index(&file_model.metadata) {
+         Some(file_index) if is_searchable(&file_index) || file_index.status == FileIndexStatus::Unsupported => file_index,
+         _ if !attached.contains(&file_model.id) => continue,
+         _ => index_file(app_state, &file_model).await?,
+      };
+      if is_searchable(&file_index) {
+         file_names.insert(file_model.id, file_model.name);
+      }
+   }
+   if file_names.is_empty() {
+      return Ok(Vec::new());
+   }
+   let query_embedding = embed(app_state, vec![query])
+     .await?
+     .pop()
+     .ok_or(anyhow!("no embedding returned for the query"))?;
+   let file_ids = file_names.keys().copied().collect::<Vec<Uuid>>();
+   let scored_chunks = app_state
+     .vector_store
+     .search(&query_embedding, EMBEDDING_MODEL, &file_ids, RETRIEVAL_TOP_K)
+     .await?;
+   for prompt in prompts.iter_mut() {
+      prompt.files.retain(|file| !file_names.contains_key(&file.id));
+   }
+   if scored_chunks.is_empty() {
+      return Ok(Vec::new());
+   }
+   let mut context = String::from("Excerpts of the files and collections attached to the conversation, most relevant first. Use them to answer and cite them by number, e.g. [1].");
+   let mut citations = Vec::new();
+   for (position, scored_chunk) in scored_chunks.into_iter().enumerate() {
+      let file_name = file_names.get(&scored_chunk.chunk.file_id).cloned().unwrap_or_default();
+      context.push_str(&format!("\n\n[{}] {} (part {})\n{}", position + 1, file_name, scored_chunk.chunk.chunk_index + 1, scored_chunk.chunk.content));
+      citations.push(Citation {
+         index:position + 1,
+         chunk_id:scored_chunk.chunk.id,
+         file_id:scored_chunk.chunk.file_id,
+         file_name,
+         chunk_index:scored_chunk.chunk.chunk_index,
+         score:scored_chunk.score,
+      });
+   }
+   let position = prompts
+     .iter()
+     .take_while(|prompt| prompt.role == ChatRole::System)
+     .count();
+   prompts.insert(position, Prompt { text:context, role:ChatRole::System, files:Vec::new() });
+ Ok(citations)
+}
+
+/// Adds the citations to the metadata of an answer
+pub fn with_citations(metadata:Option<serde_json::Value>,citations:&[Citation]) -> Option<serde_json::Value> {
+   if citations.is_empty() {
+      return metadata;
+   }
+   let mut metadata = metadata
+     .filter(|json| json.is_object())
+     .unwrap_or_else(|| json!({}));
+   metadata["citations"] = json!(citations);
+   Some(metadata)
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use anyhow::Error;
+use async_trait::async_trait;
+use sea_orm::{ColumnTrait, ConnectionTrait, DatabaseConnection, DbBackend, EntityTrait, FromQueryResult, QueryFilter, Statement, TransactionTrait};
+use uuid::Uuid;
+use crate::models::file_chunks;
+
+/// Chunk returned by a similarity search, `score` is the cosine similarity with the query
+#[derive(Debug, Clone)]
+pub struct ScoredChunk {
+    pub chunk:file_chunks::Model,
+    pub score:f32,
+}
+
+/// Storage and similarity search of the embedded file chunks
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Replaces the chunks of the file at once, concurrent indexing of a file leaves a single set of chunks
+    async fn replace_file(&self,file_id:Uuid,chunks:Vec<file_chunks::Model>) -> Result<(),Error>;
+    /// Chunks of the files most similar to the embedding, best first. Only chunks embedded
+    /// with `embedding_model` are compared, vectors of different models are not comparable.
+    async fn search(&self,embedding:&[f32],embedding_model:&str,file_ids:&[Uuid],top_k:usize) -> Result<Vec<ScoredChunk>,Error>;
+    async fn delete_file(&self,file_id:Uuid) -> Result<(),Error>;
+}
+
+pub fn cosine_similarity(a:&[f32],b:&[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Ranks the chunks in process, used when the database cannot compare vectors
+pub fn rank_chunks(chunks:Vec<file_chunks::Model>,embedding:&[f32],top_k:usize) -> Vec<ScoredChunk> {
+    let mut scored = chunks
+      .into_iter()
+      .map(|chunk| ScoredChunk { score:cosine_similarity(&chunk.embedding, embedding), chunk })
+      .collect::<Vec<ScoredChunk>>();
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+    scored.truncate(top_k);
+    scored
+}
+
+#[derive(FromQueryResult)]
+struct ChunkScore {
+    id:Uuid,
+    score:f64,
+}
+
+/// Chunks stored in `file_chunks`. Searches run in Postgres when the pgvector extension is
+/// installed (`embeddingVector` column), otherwise the chunks are ranked in process.
+pub struct PgVectorStore {
+    database:DatabaseConnection,
+    pgvector:bool,
+}
+
+impl PgVectorStore {
+    pub async fn new(database:DatabaseConnection) -> Self {
+        let statement = Statement::from_string(
+            DbBackend::Postgres,
+            r#"SELECT EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = 'file_chunks' AND column_name = 'embeddingVector') AS "pgvector""#,
+        );
+        let pgvector = match database.query_one(statement).await {
+            Ok(Some(row)) => row.try_get::<bool>("", "pgvector").unwrap_or(false),
+            Ok(None) => false,
+            Err(e) => {
+                eprintln!("pgvector detection error: {e}");
+                false
+            }
+        };
+        if !pgvector {
+            println!("pgvector not installed, file chunks are ranked in process");
+        }
+        Self { database, pgvector }
+    }
+}
+
+#[async_trait]
+impl VectorStore for PgVectorStore {
+    async fn replace_file(&self,file_id:Uuid,chunks:Vec<file_chunks::Model>) -> Result<(),Error> {
+        let transaction = self.database.begin().await?;
+        // Locks the file row, a concurrent replacement waits and then deletes the chunks inserted here
+        transaction
+          .execute(Statement::from_sql_and_values(
+              DbBackend::Postgres,
+              r#"SELECT 1 FROM "files" WHERE "id" = $1 FOR UPDATE"#,
+              [file_id.into()],
+          ))
+          .await?;
+        file_chunks::Entity::delete_many()
+          .filter(file_chunks::Column::FileId.eq(file_id))
+          .exec(&transaction)
+          .await?;
+        if !chunks.is_empty() {
+            file_chunks::Entity::insert_many(chunks.into_iter().map(file_chunks::ActiveModel::from))
+              .exec(&transaction)
+              .await?;
+            if self.pgvector {
+                transaction
+                  .execute(Statement::from_sql_and_values(
+                      DbBackend::Postgres,
+                      r#"UPDATE "file_chunks" SET "embeddingVector" = "embedding"::vector WHERE "fileId" = $1 AND "embeddingVector" IS NULL"#,
+                      [file_id.into()],
+                  ))
+                  .await?;
+            }
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn search(&self,embedding:&[f32],embedding_model:&str,file_ids:&[Uuid],top_k:usize) -> Result<Vec<ScoredChunk>,Error> {
+        if file_ids.is_empty() || top_k == 0 {
+            return Ok(Vec::new());
+        }
+        if !self.pgvector {
+            let chunks = file_chunks::Entity::find()
+              .filter(file_chunks::Column::FileId.is_in(file_ids.to_vec()))
+              .filter(file_chunks::Column::EmbeddingModel.eq(embedding_model))
+              .all(&self.database)
+              .await?;
+            return Ok(rank_chunks(chunks, embedding, top_k));
+        }
+        let vector = format!("[{}]", embedding.iter().map(f32::to_string).collect::<Vec<String>>().join(","));
+        let scores = ChunkScore::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            r#"SELECT "id", 1 - ("embeddingVector" <=> $1::vector) AS "score" FROM "file_chunks"
+               WHERE "fileId" = ANY($2) AND "embeddingModel" = $3
+               ORDER BY "embeddingVector" <=> $1::vector LIMIT $4"#,
+            [vector.into(), file_ids.to_vec().into(), embedding_model.into(), (top_k as i64).into()],
+          ))
+          .all(&self.database)
+          .await?;
+        let mut chunks = file_chunks::Entity::find()
+          .filter(file_chunks::Column::Id.is_in(scores.iter().map(|score| score.id)))
+          .all(&self.database)
+          .await?
+          .into_iter()
+          .map(|chunk| (chunk.id, chunk))
+          .collect::<HashMap<Uuid,file_chunks::Model>>();
+        Ok(scores
+          .into_iter()
+          .filter_map(|score| chunks.remove(&score.id).map(|chunk| ScoredChunk { chunk, score:score.score as f32 }))
+          .collect())
+    }
+
+    async fn delete_file(&self,file_id:Uuid) -> Result<(),Error> {
+        file_chunks::Entity::delete_many()
+          .filter(file_chunks::Column::FileId.eq(file_id))
+          .exec(&self.database)
+          .await?;
+        Ok(())
+    }
+}
+
+/// Chunks kept in memory, stands in for the database in tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryVectorStore {
+    chunks:std::sync::Mutex<Vec<file_chunks::Model>>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl VectorStore for MemoryVectorStore {
+    async fn replace_file(&self,file_id:Uuid,chunks:Vec<file_chunks::Model>) -> Result<(),Error> {
+        let mut stored = self.chunks.lock().unwrap();
+        stored.retain(|chunk| chunk.file_id != file_id);
+        stored.extend(chunks);
+        Ok(())
+    }
+
+    async fn search(&self,embedding:&[f32],embedding_model:&str,file_ids:&[Uuid],top_k:usize) -> Result<Vec<ScoredChunk>,Error> {
+        let chunks = self.chunks
+          .lock()
+          .unwrap()
+          .iter()
+          .filter(|chunk| file_ids.contains(&chunk.file_id) && chunk.embedding_model == embedding_model)
+          .cloned()
+          .collect();
+        Ok(rank_chunks(chunks, embedding, top_k))
+    }
+
+    async fn delete_file(&self,file_id:Uuid) -> Result<(),Error> {
+        self.chunks.lock().unwrap().retain(|chunk| chunk.file_id != file_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn chunk(file_id:Uuid,chunk_index:i32,embedding:Vec<f32>) -> file_chunks::Model {
+        file_chunks::Model {
+            id:Uuid::new_v4(),
+            file_id,
+            user_id:Uuid::nil(),
+            chunk_index,
+            content:format!("chunk {chunk_index}"),
+            embedding,
+            embedding_model:"test-embedding".into(),
+            created_at:Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn search_ranks_chunks_of_requested_files() {
+        let store = MemoryVectorStore::default();
+        let (file_id, other_file_id) = (Uuid::new_v4(), Uuid::new_v4());
+        store.replace_file(file_id, vec![chunk(file_id, 0, vec![1.0, 0.0])]).await.unwrap();
+        store.replace_file(file_id, vec![
+            chunk(file_id, 0, vec![1.0, 0.0]),
+            chunk(file_id, 1, vec![0.6, 0.8]),
+        ]).await.unwrap();
+        store.replace_file(other_file_id, vec![chunk(other_file_id, 0, vec![1.0, 0.0])]).await.unwrap();
+        assert_eq!(store.search(&[1.0, 0.0], "test-embedding", &[file_id], 5).await.unwrap().len(), 2);
+        let results = store.search(&[0.0, 1.0], "test-embedding", &[file_id], 1).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk.chunk_index, 1);
+        assert!((results[0].score - 0.8).abs() < 1e-6);
+        assert!(store.search(&[0.0, 1.0], "other-embedding", &[file_id], 5).await.unwrap().is_empty());
+        store.delete_file(file_id).await.unwrap();
+        assert!(store.search(&[0.0, 1.0], "test-embedding", &[file_id], 5).await.unwrap().is_empty());
+    }
+}
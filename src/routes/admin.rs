@@ -1,18 +1,33 @@
 use axum::{Router, routing::{delete, get, patch, post, put}};
-use crate::{handlers::{admin_ai::{delete_ai_engines_api_key_key, get_ai_engine_models_by_key, get_ai_engines, get_ai_engines_by_key, update_ai_engines_by_key, validate_ai_engines_by_key}, admin_department::get_departments, admin_org::{get_org, update_org}, admin_sso_provider::{delete_sso_provider_by_id, get_sso_provider_by_id, get_sso_providers, update_sso_provider_by_id}, admin_users::{add_new_user, delete_user, get_user_by_id, get_users, patch_user_status, update_user}}, state::SharedState};
+use crate::{handlers::{admin_ai::{delete_ai_engines_api_key_key, get_ai_engine_models_by_key, get_ai_engines, get_ai_engines_by_key, update_ai_engines_by_key, validate_ai_engines_by_key}, admin_department::get_departments, assistants::get_all_assistants, collections::get_all_collections, instructions::{get_org_instructions, update_org_instructions}, storage::{get_storage_report, get_upload_policy, update_storage_quotas, update_upload_policy, update_user_storage_quota}, admin_org::{get_org, update_org}, admin_sso_provider::{add_sso_provider, delete_sso_provider_by_id, get_sso_provider_by_id, get_sso_providers, update_sso_provider_by_id}, admin_users::{add_new_user, delete_user, get_user_by_id, get_users, patch_user_status, update_user}, mfa::reset_user_mfa, sessions::{get_user_sessions, revoke_user_sessions_admin}, api_keys::{add_service_account, add_service_account_api_key, delete_service_account_api_key, get_service_account_api_keys, get_service_accounts}, password::{get_password_policy, invite_user_password, unlock_user, update_password_policy}}, state::SharedState};
 
 pub fn admin_routes() -> Router<SharedState> {
    Router::new()
      .route("/admin/users", get(get_users).post(add_new_user))
      .route("/admin/users/{user_id}",put(update_user).delete(delete_user).get(get_user_by_id))
      .route("/admin/users/{user_id}/status", patch(patch_user_status))
+     .route("/admin/users/{user_id}/storage-quota", put(update_user_storage_quota))
+     .route("/admin/users/{user_id}/password-invite", post(invite_user_password))
+     .route("/admin/users/{user_id}/lockout", delete(unlock_user))
+     .route("/admin/users/{user_id}/mfa", delete(reset_user_mfa))
+     .route("/admin/users/{user_id}/sessions", get(get_user_sessions).delete(revoke_user_sessions_admin))
+     .route("/admin/service-accounts", get(get_service_accounts).post(add_service_account))
+     .route("/admin/service-accounts/{user_id}/api-keys", get(get_service_account_api_keys).post(add_service_account_api_key))
+     .route("/admin/service-accounts/{user_id}/api-keys/{key_id}", delete(delete_service_account_api_key))
+     .route("/admin/auth/password-policy", get(get_password_policy).put(update_password_policy))
+     .route("/admin/storage", get(get_storage_report))
+     .route("/admin/storage/quotas", put(update_storage_quotas))
+     .route("/admin/storage/upload-policy", get(get_upload_policy).put(update_upload_policy))
      .route("/admin/organization", get(get_org).put(update_org))
      .route("/admin/ai-engines", get(get_ai_engines))
      .route("/admin/ai-engines/{engine_key}", put(update_ai_engines_by_key).get(get_ai_engines_by_key))
      .route("/admin/departments", get(get_departments))
+     .route("/admin/assistants", get(get_all_assistants))
+     .route("/admin/collections", get(get_all_collections))
+     .route("/admin/instructions", get(get_org_instructions).put(update_org_instructions))
      .route("/admin/ai-engines/{engine-key}/validate",post(validate_ai_engines_by_key))
      .route("/admin/ai-engines/{engine-key}/api-key",delete(delete_ai_engines_api_key_key))
      .route("/admin/ai-engines/{engine-key}/models",get(get_ai_engine_models_by_key))
-     .route("/admin/sso-providers",get(get_sso_providers))
+     .route("/admin/sso-providers",get(get_sso_providers).post(add_sso_provider))
      .route("/admin/sso-providers/{provider_id}", put(update_sso_provider_by_id).delete(delete_sso_provider_by_id).get(get_sso_provider_by_id))
 }
\ No newline at end of file
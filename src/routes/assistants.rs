@@ -0,0 +1,10 @@
+use axum::{Router, middleware::from_extractor_with_state, routing::{get, post}};
+use crate::{auth::claims::Claims, handlers::assistants::{add_assistant, delete_assistant_by_id, get_assistant_by_id, get_assistants, start_assistant_chat, update_assistant_by_id}, state::SharedState};
+
+pub fn assistants_routes(app_state:SharedState) -> Router<SharedState> {
+   Router::new()
+    .route("/assistants", get(get_assistants).post(add_assistant))
+    .route("/assistants/{assistant_id}", get(get_assistant_by_id).put(update_assistant_by_id).delete(delete_assistant_by_id))
+    .route("/assistants/{assistant_id}/chat", post(start_assistant_chat))
+    .route_layer(from_extractor_with_state::<Claims, _>(app_state))
+}
\ No newline at end of file
@@ -0,0 +1,9 @@
+use axum::{Router, extract::DefaultBodyLimit, routing::post};
+use crate::{handlers::audio::{synthesize_speech, transcribe_audio}, state::SharedState};
+
+pub fn audio_routes() -> Router<SharedState> {
+   Router::new()
+    // The recording size is checked while reading the form
+    .route("/audio/transcriptions", post(transcribe_audio).layer(DefaultBodyLimit::disable()))
+    .route("/audio/speech", post(synthesize_speech))
+}
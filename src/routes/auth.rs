@@ -1,7 +1,22 @@
-use axum::{Router, routing::post};
-use crate::{handlers::auth::handle_refresh_token, state::SharedState};
+use axum::{Router, routing::{delete, get, post}};
+use crate::{handlers::{auth::handle_refresh_token, mfa::{disable_mfa, enable_mfa, regenerate_recovery_codes, setup_mfa, verify_mfa}, sessions::{delete_session, get_sessions, logout, logout_all}, api_keys::{add_api_key, delete_api_key, get_api_keys}, password::{change_password, forgot_password, password_login, reset_password}}, state::SharedState};
 
 pub fn auth_routes() -> Router<SharedState> {
    Router::new()
     .route("/auth/refresh",post(handle_refresh_token))
+    .route("/auth/login", post(password_login))
+    .route("/auth/password/change", post(change_password))
+    .route("/auth/password/forgot", post(forgot_password))
+    .route("/auth/password/reset", post(reset_password))
+    .route("/auth/mfa/setup", post(setup_mfa))
+    .route("/auth/mfa/enable", post(enable_mfa))
+    .route("/auth/mfa/verify", post(verify_mfa))
+    .route("/auth/mfa/disable", post(disable_mfa))
+    .route("/auth/mfa/recovery-codes", post(regenerate_recovery_codes))
+    .route("/auth/logout", post(logout))
+    .route("/auth/logout/all", post(logout_all))
+    .route("/auth/sessions", get(get_sessions))
+    .route("/auth/sessions/{session_id}", delete(delete_session))
+    .route("/auth/api-keys", get(get_api_keys).post(add_api_key))
+    .route("/auth/api-keys/{key_id}", delete(delete_api_key))
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
-use axum::{Router, middleware::from_extractor, routing::{delete, get, post}};
-use crate::{auth::claims::Claims, handlers::{chat::{delete_chat_by_id, get_chat_by_id, get_chats, update_chat_by_id}, chat_stream::handle_chat_stream}, state::SharedState};
+use axum::{Router, middleware::from_extractor_with_state, routing::{delete, get, post}};
+use crate::{auth::claims::Claims, handlers::{chat::{delete_chat_by_id, get_chat_by_id, get_chats, update_chat_by_id}, chat_stream::{handle_chat_compare, handle_chat_stream}, images::generate_chat_images}, state::SharedState};
 
-pub fn chat_routes() -> Router<SharedState> {
+pub fn chat_routes(app_state:SharedState) -> Router<SharedState> {
    Router::new()
     .route("/chat/stream",post(handle_chat_stream))
     .route("/chat/stream/{chat_id}", post(handle_chat_stream))
+    .route("/chat/compare", post(handle_chat_compare))
+    .route("/chat/{chat_id}/images", post(generate_chat_images))
     .route("/chat",get(get_chats))
     .route("/chat/{chat_id}", delete(delete_chat_by_id).get(get_chat_by_id).put(update_chat_by_id))
-    .route_layer(from_extractor::<Claims>())
+    .route_layer(from_extractor_with_state::<Claims, _>(app_state))
 }
\ No newline at end of file
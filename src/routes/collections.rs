@@ -0,0 +1,12 @@
+use axum::{Router, middleware::from_extractor_with_state, routing::{delete, get, post}};
+use crate::{auth::claims::Claims, handlers::collections::{add_collection, add_collection_files, delete_collection_by_id, delete_collection_file, get_collection_by_id, get_collections, ingest_collection, update_collection_by_id}, state::SharedState};
+
+pub fn collections_routes(app_state:SharedState) -> Router<SharedState> {
+   Router::new()
+    .route("/collections", get(get_collections).post(add_collection))
+    .route("/collections/{collection_id}", get(get_collection_by_id).put(update_collection_by_id).delete(delete_collection_by_id))
+    .route("/collections/{collection_id}/files", post(add_collection_files))
+    .route("/collections/{collection_id}/files/{file_id}", delete(delete_collection_file))
+    .route("/collections/{collection_id}/ingest", post(ingest_collection))
+    .route_layer(from_extractor_with_state::<Claims, _>(app_state))
+}
@@ -1,9 +1,14 @@
-use axum::{Router, routing::{get, post}};
-use crate::{handlers::file::{download_file, get_file_by_id, get_files, upload_file}, state::SharedState};
+use axum::{Router, extract::DefaultBodyLimit, routing::{get, post}};
+use crate::{handlers::{file::{delete_file_by_id, download_file, get_file_by_id, get_file_thumbnail, get_files, restore_file, update_file, upload_file, upload_file_multipart}, storage::get_storage_usage}, state::SharedState};
 
 pub fn files_routes() -> Router<SharedState> {
    Router::new()
     .route("/files",post(upload_file).get(get_files))
-    .route("/files/{file_id}", get(get_file_by_id))
+    // The size limit is enforced while streaming, from the storage settings
+    .route("/files/upload",post(upload_file_multipart).layer(DefaultBodyLimit::disable()))
+    .route("/files/usage", get(get_storage_usage))
+    .route("/files/{file_id}", get(get_file_by_id).patch(update_file).delete(delete_file_by_id))
+    .route("/files/{file_id}/restore", post(restore_file))
     .route("/files/{file_id}/download", get(download_file))
+    .route("/files/{file_id}/thumbnail", get(get_file_thumbnail))
 }
\ No newline at end of file
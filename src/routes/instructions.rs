@@ -0,0 +1,9 @@
+use axum::{Router, middleware::from_extractor_with_state, routing::get};
+use crate::{auth::claims::Claims, handlers::instructions::{get_personal_instructions, preview_instructions, update_personal_instructions}, state::SharedState};
+
+pub fn instructions_routes(app_state:SharedState) -> Router<SharedState> {
+   Router::new()
+    .route("/instructions", get(get_personal_instructions).put(update_personal_instructions))
+    .route("/instructions/preview", get(preview_instructions))
+    .route_layer(from_extractor_with_state::<Claims, _>(app_state))
+}
\ No newline at end of file
@@ -1,8 +1,11 @@
-use axum::{Router, routing::{delete, patch}};
-use crate::{handlers::message::{delete_chat_message_by_id, edit_chat_message_by_id_and_stream}, state::SharedState};
+use axum::{Router, routing::{delete, get, patch, post, put}};
+use crate::{handlers::message::{delete_chat_message_by_id, edit_chat_message_by_id_and_stream, get_chat_message_siblings, regenerate_chat_message_by_id, select_chat_message_branch}, state::SharedState};
 
 pub fn message_routes() -> Router<SharedState> {
    Router::new()
     .route("/chat/{chat_id}/message/{message_id}",delete(delete_chat_message_by_id))
     .route("/chat/{chat_id}/message/{message_id}/stream", patch(edit_chat_message_by_id_and_stream))
+    .route("/chat/{chat_id}/message/{message_id}/regenerate", post(regenerate_chat_message_by_id))
+    .route("/chat/{chat_id}/message/{message_id}/siblings", get(get_chat_message_siblings))
+    .route("/chat/{chat_id}/message/{message_id}/active", put(select_chat_message_branch))
 }
\ No newline at end of file
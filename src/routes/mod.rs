@@ -6,4 +6,9 @@ pub mod message;
 pub mod admin;
 pub mod models;
 pub mod auth;
-pub mod open_error;
\ No newline at end of file
+pub mod open_error;
+pub mod prompt_templates;
+pub mod instructions;
+pub mod assistants;
+pub mod collections;
+pub mod audio;
\ No newline at end of file
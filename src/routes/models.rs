@@ -1,8 +1,8 @@
-use axum::{Router, middleware::from_extractor, routing::get};
+use axum::{Router, middleware::from_extractor_with_state, routing::get};
 use crate::{auth::claims::Claims, handlers::models::get_list_models, state::SharedState};
 
-pub fn models_routes() -> Router<SharedState> {
+pub fn models_routes(app_state:SharedState) -> Router<SharedState> {
     Router::new()
         .route("/models", get(get_list_models))
-        .route_layer(from_extractor::<Claims>())
+        .route_layer(from_extractor_with_state::<Claims, _>(app_state))
 }
\ No newline at end of file
@@ -0,0 +1,10 @@
+use axum::{Router, middleware::from_extractor_with_state, routing::get};
+use crate::{auth::claims::Claims, handlers::prompt_templates::{add_prompt_template, delete_prompt_template_by_id, get_prompt_template_by_id, get_prompt_template_categories, get_prompt_templates, update_prompt_template_by_id}, state::SharedState};
+
+pub fn prompt_templates_routes(app_state:SharedState) -> Router<SharedState> {
+   Router::new()
+    .route("/prompt-templates", get(get_prompt_templates).post(add_prompt_template))
+    .route("/prompt-templates/categories", get(get_prompt_template_categories))
+    .route("/prompt-templates/{template_id}", get(get_prompt_template_by_id).put(update_prompt_template_by_id).delete(delete_prompt_template_by_id))
+    .route_layer(from_extractor_with_state::<Claims, _>(app_state))
+}
\ No newline at end of file
@@ -1,22 +1,31 @@
-use anyhow::Error;
 use sea_orm::{Database, DatabaseConnection};
 use tokio::sync::RwLock;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 use reqwest::Client as ReqwestClient;
-use crate::{auth::{azure::build_azure_client, encryption::decrypt_key, google::build_google_client}, config::setting::{ConfigError, OidcClient, Settings}, dto::oauth::AuthProvider, models::users};
+use crate::{auth::{user_access::{USER_ACCESS_TTL, UserAccessCache}, discovery::build_oidc_client, encryption::decrypt_key}, config::setting::{ConfigError, OidcClient, Settings, SsoProviderSettings}, dto::oauth::AuthProvider, llm::http_policy::{HTTP_CONNECT_TIMEOUT, HTTP_READ_TIMEOUT}, rag::vector_store::{PgVectorStore, VectorStore}, storage::{FileStorage, clamd::ClamdScanner, scan::MalwareScanner}, mail::{Mailer, smtp::SmtpMailer}};
 
 pub struct AppState {
     pub database:DatabaseConnection,
-    pub google_client:RwLock<Option<OidcClient>>,
-    pub azure_client:RwLock<Option<OidcClient>>,
+    /// Clients of the configured SSO providers by provider key
+    pub oidc_clients:RwLock<HashMap<String,OidcClient>>,
     pub req_client:ReqwestClient,
     pub settings:Settings,
+    pub vector_store:Arc<dyn VectorStore>,
+    pub file_storage:FileStorage,
+    /// Uploads stay unavailable until scanned when set
+    pub malware_scanner:Option<Arc<dyn MalwareScanner>>,
+    /// Account emails are not sent when unset, admins share invite links themselves
+    pub mailer:Option<Arc<dyn Mailer>>,
+    /// Status, role and token version checked on every authenticated request
+    pub user_access:UserAccessCache,
 }
 
 impl AppState {
     pub async fn from_settings(mut settings:Settings) -> Result<SharedState,ConfigError> {
          let req_client =  reqwest::ClientBuilder::new()
             .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(HTTP_CONNECT_TIMEOUT)
+            .read_timeout(HTTP_READ_TIMEOUT)
             .build()
             .map_err(|e| ConfigError::ReqwestClientBuildError(e.to_string()))?;
          let database = Database::connect(&settings.auth.database_url)
@@ -30,81 +39,59 @@ impl AppState {
            .load_sso_providers_from_db(&database)
            .await
            .map_err(|e|eprintln!("Loading sso providers from db error: {e}"));
+         let vector_store = Arc::new(PgVectorStore::new(database.clone()).await);
+         let file_storage = FileStorage::from_settings(&settings.storage).await?;
+         let malware_scanner = settings
+           .storage
+           .clamd
+           .as_ref()
+           .map(|clamd| Arc::new(ClamdScanner::new(clamd)) as Arc<dyn MalwareScanner>);
+         let mailer = settings
+           .mail
+           .as_ref()
+           .map(|mail| SmtpMailer::new(mail).map(|mailer| Arc::new(mailer) as Arc<dyn Mailer>))
+           .transpose()
+           .map_err(|e| ConfigError::Custom(e.to_string()))?;
          let state =  Self { 
             database,
-            google_client:RwLock::new(None),
-            azure_client:RwLock::new(None),
-            req_client,settings
+            oidc_clients:RwLock::new(HashMap::new()),
+            req_client,settings,
+            vector_store,
+            file_storage,
+            malware_scanner,
+            mailer,
+            user_access:UserAccessCache::new(USER_ACCESS_TTL),
          };
-         state.refresh_azure_client()
-          .await?;
-         state.refresh_google_client()
-          .await?;
+         state.refresh_oidc_clients()
+          .await;
         Ok(Arc::new(state))
     }
 
+    pub async fn sso_provider_settings(&self,provider:&AuthProvider) -> Option<SsoProviderSettings> {
+        self.settings
+           .sso_providers
+           .read()
+           .await
+           .get(&provider.to_lowercase())
+           .cloned()
+    }
+
     pub async fn check_sso_provider_is_enabled(&self,provider:&AuthProvider) -> Option<bool> {
-           match provider.to_lowercase().as_str() {
-            "azure" => {
-                let is_enabled = self
-                   .settings
-                   .google
-                   .read()
-                   .await
-                   .as_ref()
-                   .map(|setting| setting.is_enabled);
-                is_enabled
-            },
-            "google" => {
-                let is_enabled = self
-                   .settings
-                   .google
-                   .read()
-                   .await
-                   .as_ref()
-                   .map(|setting| setting.is_enabled);
-                is_enabled
-            },
-            _ => None
-        }
+        self.sso_provider_settings(provider)
+           .await
+           .map(|setting| setting.is_enabled)
     }
 
     pub async fn is_email_domain_allowed(&self,email:&str,provider:&AuthProvider) -> (bool,Option<String>) {
      if let Some((_, domain)) = email.split_once('@') {
-         match provider.to_lowercase().as_str() {
-            "azure" => {
-                let allowed_domains = self
-                   .settings
-                   .azure
-                   .read()
-                   .await
-                   .as_ref()
-                   .map(|setting| setting.allowed_domains.clone())
-                   .unwrap_or(Vec::new());
-                if allowed_domains.is_empty(){
-                    return (true,None);
-                }else {
-                    return (allowed_domains.contains(&domain.to_string()),Some(domain.to_string()));
-                }
-            },
-            "google" => {
-                let allowed_domains = self
-                   .settings
-                   .google
-                   .read()
-                   .await
-                   .as_ref()
-                   .map(|setting| setting.allowed_domains.clone())
-                   .unwrap_or(Vec::new());
-                if allowed_domains.is_empty(){
-                    return (true,None);
-                }else {
-                    return (allowed_domains.contains(&domain.to_string()),Some(domain.to_string()));
-                }
-            },
-            _ => return (false,Some(domain.to_string())),
+         let Some(setting) = self.sso_provider_settings(provider).await else {
+            return (false,Some(domain.to_string()));
+         };
+         if setting.allowed_domains.is_empty(){
+            return (true,None);
          }
-      } 
+         return (setting.allowed_domains.contains(&domain.to_string()),Some(domain.to_string()));
+      }
       (false,None)
     }
 
@@ -134,67 +121,66 @@ impl AppState {
         }
     }
 
-    pub async fn get_oidc_client_and_column_and_redirect_uri(&self, provider: &AuthProvider) -> Result<(&RwLock<Option<OidcClient>>, users::Column,Option<String>), ConfigError> {
-        match provider.to_lowercase().as_str() {
-            "azure" => {
-                let redirect_url = self
-                   .settings
-                   .azure
-                   .read()
-                   .await
-                   .as_ref()
-                   .map(|setting| setting.redirect_url.clone());
-                return Ok((&self.azure_client, users::Column::AzureId,redirect_url))
-            },
-            "google" => {
-                let redirect_url = self
-                   .settings
-                   .google
-                   .read()
-                   .await
-                   .as_ref()
-                   .map(|setting| setting.redirect_url.clone());
-                return Ok((&self.google_client, users::Column::GoogleId,redirect_url))
-            },
-            _ => Err(ConfigError::InvalidSSoProvider(provider.into())),
+    /// Client of the provider, built now when its last build failed, e.g. the issuer was down at startup
+    pub async fn get_oidc_client(&self, provider: &AuthProvider) -> Option<OidcClient> {
+        let client = self.oidc_clients
+           .read()
+           .await
+           .get(&provider.to_lowercase())
+           .cloned();
+        if client.is_some() {
+            return client;
         }
+        self.sso_provider_settings(provider).await?;
+        if let Err(e) = self.refresh_oidc_client(provider).await {
+            eprintln!("oidc client refresh error: {e}");
+        }
+        self.oidc_clients
+           .read()
+           .await
+           .get(&provider.to_lowercase())
+           .cloned()
     }
 
-    pub async fn refresh_oidc_client(&self, provider: &AuthProvider) -> Result<(), Error> {
-        match provider.to_lowercase().as_str() {
-            "azure" => self.refresh_azure_client().await?,
-            "google" => self.refresh_google_client().await?,
-            _ => return Err(anyhow::anyhow!("Unknown provider: {}", provider)),
-        }
+    /// Rebuilds the client of a provider from its settings, e.g. after an update or a signing key rotation.
+    /// The previous client is kept when the build fails.
+    pub async fn refresh_oidc_client(&self, provider: &AuthProvider) -> Result<(), ConfigError> {
+        let provider = provider.to_lowercase();
+        let Some(setting) = self.sso_provider_settings(&provider).await else {
+            self.oidc_clients.write().await.remove(&provider);
+            return Err(ConfigError::InvalidSSoProvider(provider));
+        };
+        let client = build_oidc_client(&self.req_client, &setting)
+           .await
+           .map_err(|e| ConfigError::SsoClientBuildError(format!("{provider} client build error: {e}")));
+        self.oidc_clients.write().await.insert(provider, client?);
         Ok(())
-    } 
- 
-    async fn refresh_google_client(&self) -> Result<(),ConfigError> {
-          let google = self
-             .settings
-             .google
-             .read()
-             .await
-             .clone()
-             .ok_or(ConfigError::NotConfigured("google settings not configured in App State"))?;
-           let google_client = build_google_client(&self.req_client,google.client_id,google.client_secret,google.redirect_url)
-             .await;
-           *self.google_client.write().await = google_client.ok();
-         Ok(())
     }
 
-    async fn refresh_azure_client(&self) -> Result<(),ConfigError> {
-          let azure = self
-             .settings
-             .azure
-             .read()
-             .await
-             .clone()
-             .ok_or(ConfigError::NotConfigured("Azure settings not configured in App State"))?;
-           let azure_client = build_azure_client(&self.req_client,azure.client_id,azure.client_secret,azure.redirect_url,azure.tenant_id)
-             .await;
-           *self.azure_client.write().await = azure_client.ok();
-         Ok(())
+    async fn refresh_oidc_clients(&self) {
+        let providers:Vec<String> = self.settings
+           .sso_providers
+           .read()
+           .await
+           .keys()
+           .cloned()
+           .collect();
+        for provider in providers {
+            if let Err(e) = self.refresh_oidc_client(&provider).await {
+                eprintln!("Loading oidc client error: {e}");
+            }
+        }
+    }
+
+    /// Drops the settings and client of a provider
+    pub async fn remove_oidc_client(&self, provider: &AuthProvider) {
+        self.settings
+           .remove_sso_provider_from_state(provider)
+           .await;
+        self.oidc_clients
+           .write()
+           .await
+           .remove(&provider.to_lowercase());
     }
 
     pub fn get_decrypted_api_key_preview(&self,api_key:&Option<String>) -> Option<String> {
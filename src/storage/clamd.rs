@@ -0,0 +1,129 @@
+use std::time::Duration;
+use anyhow::{Error, anyhow};
+use async_trait::async_trait;
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}, net::{TcpStream, UnixStream}};
+use crate::{config::setting::{ClamdAddress, ClamdSettings}, storage::scan::{MalwareScanner, ScanVerdict}};
+
+/// Bytes sent per INSTREAM chunk, well under the clamd StreamMaxLength
+const CHUNK_LEN:usize = 64 * 1024;
+const MAX_RESPONSE_LEN:usize = 4 * 1024;
+
+/// ClamAV daemon, reached over TCP or a Unix socket with the INSTREAM command
+pub struct ClamdScanner {
+    address:ClamdAddress,
+    timeout:Duration,
+}
+
+impl ClamdScanner {
+    pub fn new(settings:&ClamdSettings) -> Self {
+        Self { address:settings.address.clone(), timeout:settings.timeout }
+    }
+}
+
+#[async_trait]
+impl MalwareScanner for ClamdScanner {
+    fn engine(&self) -> &'static str {
+        "clamav"
+    }
+
+    async fn scan(&self,bytes:&[u8]) -> Result<ScanVerdict,Error> {
+        let scan = async {
+            match &self.address {
+                ClamdAddress::Tcp(address) => instream(TcpStream::connect(address).await?, bytes).await,
+                ClamdAddress::Unix(path) => instream(UnixStream::connect(path).await?, bytes).await,
+            }
+        };
+        tokio::time::timeout(self.timeout, scan)
+          .await
+          .map_err(|_| anyhow!("clamd scan timed out after {:?}", self.timeout))?
+    }
+}
+
+/// Sends the content as length prefixed chunks ended by an empty one, then reads
+/// the null terminated reply
+async fn instream<S:AsyncRead + AsyncWrite + Unpin>(mut stream:S,bytes:&[u8]) -> Result<ScanVerdict,Error> {
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in bytes.chunks(CHUNK_LEN) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+    stream.flush().await?;
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 512];
+    while !response.contains(&0) && response.len() < MAX_RESPONSE_LEN {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        response.extend_from_slice(&buffer[..read]);
+    }
+    parse_response(&String::from_utf8_lossy(&response))
+}
+
+/// `stream: OK`, `stream: <signature> FOUND` or `<message> ERROR`
+fn parse_response(response:&str) -> Result<ScanVerdict,Error> {
+    let response = response.trim_end_matches('\0').trim();
+    let result = response
+      .strip_prefix("stream:")
+      .map(str::trim)
+      .unwrap_or(response);
+    if result == "OK" {
+        return Ok(ScanVerdict::Clean);
+    }
+    match result.strip_suffix("FOUND") {
+        Some(signature) => Ok(ScanVerdict::Infected { signature:signature.trim().to_string() }),
+        None => Err(anyhow!("clamd error: {response}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    const EICAR:&[u8] = br"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+    /// Answers one INSTREAM command like clamd, flagging the EICAR test string
+    async fn fake_clamd(listener:TcpListener) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut command = [0u8; 10];
+        stream.read_exact(&mut command).await.unwrap();
+        assert_eq!(&command, b"zINSTREAM\0");
+        let mut content = Vec::new();
+        loop {
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len).await.unwrap();
+            let len = u32::from_be_bytes(len) as usize;
+            if len == 0 {
+                break;
+            }
+            let mut chunk = vec![0u8; len];
+            stream.read_exact(&mut chunk).await.unwrap();
+            content.extend_from_slice(&chunk);
+        }
+        let reply:&[u8] = if content.windows(EICAR.len()).any(|window| window == EICAR) {
+            b"stream: Eicar-Test-Signature FOUND\0"
+        } else {
+            b"stream: OK\0"
+        };
+        stream.write_all(reply).await.unwrap();
+    }
+
+    async fn scan_with_fake(bytes:&[u8]) -> ScanVerdict {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(fake_clamd(listener));
+        let scanner = ClamdScanner::new(&ClamdSettings { address:ClamdAddress::Tcp(address), timeout:Duration::from_secs(5) });
+        scanner.scan(bytes).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn scan_streams_the_content_to_clamd() {
+        let mut infected = vec![b'a'; CHUNK_LEN];
+        infected.extend_from_slice(EICAR);
+        assert_eq!(scan_with_fake(&infected).await, ScanVerdict::Infected { signature:"Eicar-Test-Signature".to_string() });
+        assert_eq!(scan_with_fake(b"quarterly report").await, ScanVerdict::Clean);
+        assert!(parse_response("INSTREAM size limit exceeded. ERROR\0").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::Cursor;
+use anyhow::Error;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, codecs::jpeg::JpegEncoder, imageops::FilterType};
+
+/// Long edge of the thumbnails shown by the UI
+pub const THUMBNAIL_DIMENSION:u32 = 256;
+const JPEG_QUALITY:u8 = 85;
+/// Memory a decoded upload may take, against decompression bombs
+const MAX_DECODED_BYTES:u64 = 512 * 1024 * 1024;
+
+/// Image types decoded at upload to be normalized
+pub fn is_normalized_type(content_type:&str) -> bool {
+    matches!(content_type, "image/jpeg" | "image/png" | "image/gif" | "image/webp")
+}
+
+/// Image re-encoded without its metadata
+pub struct EncodedImage {
+    pub bytes:Vec<u8>,
+    pub content_type:&'static str,
+    pub width:u32,
+    pub height:u32,
+}
+
+pub struct NormalizedImage {
+    /// Size of the upright uploaded image
+    pub width:u32,
+    pub height:u32,
+    /// One per distinct size, largest first
+    pub variants:Vec<EncodedImage>,
+    pub thumbnail:EncodedImage,
+}
+
+/// Size of an image fitting `max_dimension` on its long edge, never upscaled
+pub fn fit_within(width:u32,height:u32,max_dimension:u32) -> (u32,u32) {
+    let long_edge = width.max(height);
+    if long_edge <= max_dimension {
+        return (width, height);
+    }
+    let scale = |edge:u32| ((edge as u64 * max_dimension as u64).div_ceil(long_edge as u64) as u32).clamp(1, max_dimension);
+    (scale(width), scale(height))
+}
+
+/// File name of a variant, with the extension of its re-encoded type
+pub fn variant_file_name(name:&str,content_type:&str) -> String {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let extension = if content_type == "image/png" { "png" } else { "jpg" };
+    format!("{stem}.{extension}")
+}
+
+fn decode(bytes:&[u8]) -> Result<DynamicImage,Error> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_DECODED_BYTES);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    // Phone photos are stored sideways, the orientation is lost with the metadata
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Transparent images stay PNG, others become JPEG
+fn encode(image:&DynamicImage) -> Result<EncodedImage,Error> {
+    let mut bytes = Vec::new();
+    let content_type = if image.color().has_alpha() {
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)?;
+        "image/png"
+    } else {
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY).encode_image(&image.to_rgb8())?;
+        "image/jpeg"
+    };
+    Ok(EncodedImage { bytes, content_type, width:image.width(), height:image.height() })
+}
+
+fn resize(image:&DynamicImage,max_dimension:u32,filter:FilterType) -> DynamicImage {
+    let (width, height) = fit_within(image.width(), image.height(), max_dimension);
+    if (width, height) == (image.width(), image.height()) {
+        return image.clone();
+    }
+    image.resize_exact(width, height, filter)
+}
+
+/// Re-encodes the upload upright within each of the maximum dimensions, and
+/// as a thumbnail. Only pixels are kept: EXIF, GPS and other metadata are dropped.
+pub fn normalize_image(bytes:&[u8],max_dimensions:&[u32]) -> Result<NormalizedImage,Error> {
+    let image = decode(bytes)?;
+    let mut max_dimensions = max_dimensions.to_vec();
+    max_dimensions.sort_by_key(|max_dimension| std::cmp::Reverse(*max_dimension));
+    let mut variants:Vec<EncodedImage> = Vec::new();
+    for max_dimension in max_dimensions {
+        let size = fit_within(image.width(), image.height(), max_dimension);
+        if variants.iter().any(|variant| (variant.width, variant.height) == size) {
+            continue;
+        }
+        variants.push(encode(&resize(&image, max_dimension, FilterType::Lanczos3))?);
+    }
+    let thumbnail = encode(&resize(&image, THUMBNAIL_DIMENSION, FilterType::Triangle))?;
+    Ok(NormalizedImage { width:image.width(), height:image.height(), variants, thumbnail })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn fit_within_keeps_the_aspect_ratio() {
+        assert_eq!(fit_within(4032, 3024, 2048), (2048, 1536));
+        assert_eq!(fit_within(3024, 4032, 1568), (1176, 1568));
+        assert_eq!(fit_within(800, 600, 2048), (800, 600));
+        assert_eq!(fit_within(10000, 1, 256), (256, 1));
+    }
+
+    #[test]
+    fn normalize_image_reencodes_each_size() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(3000, 1500, Rgb([10, 20, 30])))
+          .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+          .unwrap();
+        let normalized = normalize_image(&png, &[1568, 2048, 2048]).unwrap();
+        assert_eq!((normalized.width, normalized.height), (3000, 1500));
+        let sizes = normalized.variants.iter().map(|variant| (variant.width, variant.height)).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![(2048, 1024), (1568, 784)]);
+        assert!(normalized.variants.iter().all(|variant| variant.content_type == "image/jpeg"));
+        assert_eq!((normalized.thumbnail.width, normalized.thumbnail.height), (256, 128));
+
+        let mut transparent = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 50, Rgba([0, 0, 0, 0])))
+          .write_to(&mut Cursor::new(&mut transparent), ImageFormat::Png)
+          .unwrap();
+        let normalized = normalize_image(&transparent, &[1568, 2048]).unwrap();
+        assert_eq!(normalized.variants.len(), 1);
+        assert_eq!(normalized.variants[0].content_type, "image/png");
+        assert_eq!(variant_file_name("photo.heic.webp", normalized.variants[0].content_type), "photo.heic.png");
+    }
+}
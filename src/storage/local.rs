@@ -0,0 +1,109 @@
+use std::{io::ErrorKind, path::{Component, Path, PathBuf}};
+use anyhow::{Error, anyhow};
+use async_trait::async_trait;
+use tokio::{fs::File, io::AsyncWriteExt};
+use crate::storage::{FileStore, FileWriter};
+
+/// Files under a root folder of the local filesystem, a mounted volume in containers
+pub struct LocalFileStore {
+    root:PathBuf,
+}
+
+impl LocalFileStore {
+    pub fn new(root:&str) -> Self {
+        Self { root:PathBuf::from(root) }
+    }
+
+    fn path(&self,key:&str) -> Result<PathBuf,Error> {
+        // Keys must not escape the root
+        if Path::new(key).components().any(|component| !matches!(component, Component::Normal(_))) {
+            return Err(anyhow!("invalid storage key {key}"));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl FileStore for LocalFileStore {
+    async fn put(&self,key:&str,bytes:Vec<u8>,_content_type:&str) -> Result<(),Error> {
+        let path = self.path(key)?;
+        if let Some(folder) = path.parent() {
+            tokio::fs::create_dir_all(folder).await?;
+        }
+        tokio::fs::write(&path, bytes).await?;
+        Ok(())
+    }
+
+    async fn get(&self,key:&str) -> Result<Vec<u8>,Error> {
+        Ok(tokio::fs::read(self.path(key)?).await?)
+    }
+
+    async fn delete(&self,key:&str) -> Result<(),Error> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn writer(&self,key:&str,_content_type:&str) -> Result<Box<dyn FileWriter>,Error> {
+        let path = self.path(key)?;
+        if let Some(folder) = path.parent() {
+            tokio::fs::create_dir_all(folder).await?;
+        }
+        // Written aside then renamed, readers never see a partial file
+        let part_path = PathBuf::from(format!("{}.part", path.display()));
+        let file = File::create(&part_path).await?;
+        Ok(Box::new(LocalFileWriter { file, part_path, path }))
+    }
+}
+
+struct LocalFileWriter {
+    file:File,
+    part_path:PathBuf,
+    path:PathBuf,
+}
+
+#[async_trait]
+impl FileWriter for LocalFileWriter {
+    async fn write(&mut self,chunk:&[u8]) -> Result<(),Error> {
+        self.file.write_all(chunk).await?;
+        Ok(())
+    }
+
+    async fn finish(mut self:Box<Self>) -> Result<(),Error> {
+        self.file.flush().await?;
+        tokio::fs::rename(&self.part_path, &self.path).await?;
+        Ok(())
+    }
+
+    async fn abort(self:Box<Self>) -> Result<(),Error> {
+        drop(self.file);
+        tokio::fs::remove_file(&self.part_path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn stores_reads_and_deletes_files() {
+        let root = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let store = LocalFileStore::new(root.to_str().unwrap());
+        store.put("user/file/id/notes.txt", b"hello".to_vec(), "text/plain").await.unwrap();
+        assert_eq!(store.get("user/file/id/notes.txt").await.unwrap(), b"hello");
+        store.delete("user/file/id/notes.txt").await.unwrap();
+        assert!(store.get("user/file/id/notes.txt").await.is_err());
+        store.delete("user/file/id/notes.txt").await.unwrap();
+        let mut writer = store.writer("user/file/id/report.csv", "text/csv").await.unwrap();
+        writer.write(b"a,b\n").await.unwrap();
+        writer.write(b"1,2\n").await.unwrap();
+        assert!(store.get("user/file/id/report.csv").await.is_err());
+        writer.finish().await.unwrap();
+        assert_eq!(store.get("user/file/id/report.csv").await.unwrap(), b"a,b\n1,2\n");
+        assert!(store.put("../escape.txt", Vec::new(), "text/plain").await.is_err());
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use anyhow::{Error, anyhow};
+use async_trait::async_trait;
+use uuid::Uuid;
+use crate::{config::setting::{ConfigError, StorageSettings}, models::files::StorageBackend, storage::{local::LocalFileStore, s3::S3FileStore}};
+
+pub mod clamd;
+pub mod images;
+pub mod local;
+pub mod s3;
+pub mod scan;
+pub mod upload;
+
+/// Blob storage of the uploaded files, addressed by key
+#[async_trait]
+pub trait FileStore: Send + Sync {
+    async fn put(&self,key:&str,bytes:Vec<u8>,content_type:&str) -> Result<(),Error>;
+    async fn get(&self,key:&str) -> Result<Vec<u8>,Error>;
+    /// Deleting a missing key succeeds
+    async fn delete(&self,key:&str) -> Result<(),Error>;
+    /// Streams a file to the key, without holding it in memory
+    async fn writer(&self,key:&str,content_type:&str) -> Result<Box<dyn FileWriter>,Error>;
+}
+
+/// Streamed write of one file, nothing is readable at the key before `finish`
+#[async_trait]
+pub trait FileWriter: Send {
+    async fn write(&mut self,chunk:&[u8]) -> Result<(),Error>;
+    async fn finish(self:Box<Self>) -> Result<(),Error>;
+    /// Discards the chunks written so far
+    async fn abort(self:Box<Self>) -> Result<(),Error>;
+}
+
+/// Key of an uploaded file, the same in every backend. Client names are kept
+/// out of keys, they are only display names.
+pub fn file_key(user_id:&Uuid,file_id:&Uuid) -> String {
+    format!("{user_id}/file/{file_id}")
+}
+
+/// Key of a content derived from a stored file, e.g. a resized image
+pub fn variant_key(storage_key:&str,variant:&str) -> String {
+    format!("{storage_key}.{variant}")
+}
+
+/// Stores of every configured backend. New files go to the configured backend,
+/// existing files are read from the backend recorded on their row.
+pub struct FileStorage {
+    backend:StorageBackend,
+    local:Arc<dyn FileStore>,
+    s3:Option<Arc<dyn FileStore>>,
+}
+
+impl FileStorage {
+    pub async fn from_settings(settings:&StorageSettings) -> Result<Self,ConfigError> {
+        let s3 = match &settings.s3 {
+            Some(s3_settings) => Some(Arc::new(S3FileStore::new(s3_settings).await) as Arc<dyn FileStore>),
+            None => None,
+        };
+        if settings.backend == StorageBackend::S3 && s3.is_none() {
+            return Err(ConfigError::Missing("S3_BUCKET"));
+        }
+        Ok(Self {
+            backend:settings.backend,
+            local:Arc::new(LocalFileStore::new(&settings.local_root)),
+            s3,
+        })
+    }
+
+    /// Backend new files are written to
+    pub fn backend(&self) -> StorageBackend {
+        self.backend
+    }
+
+    fn store(&self,backend:StorageBackend) -> Result<&Arc<dyn FileStore>,Error> {
+        match backend {
+            StorageBackend::Local => Ok(&self.local),
+            StorageBackend::S3 => self.s3.as_ref().ok_or(anyhow!("s3 storage not configured")),
+        }
+    }
+
+    /// Writes to the configured backend, returning it to be recorded with the key
+    pub async fn put(&self,key:&str,bytes:Vec<u8>,content_type:&str) -> Result<StorageBackend,Error> {
+        self.store(self.backend)?
+            .put(key, bytes, content_type)
+            .await?;
+        Ok(self.backend)
+    }
+
+    /// Streams to the configured backend, returning it to be recorded with the key
+    pub async fn writer(&self,key:&str,content_type:&str) -> Result<(Box<dyn FileWriter>,StorageBackend),Error> {
+        let writer = self.store(self.backend)?
+            .writer(key, content_type)
+            .await?;
+        Ok((writer, self.backend))
+    }
+
+    pub async fn get(&self,backend:StorageBackend,key:&str) -> Result<Vec<u8>,Error> {
+        self.store(backend)?
+            .get(key)
+            .await
+    }
+
+    pub async fn delete(&self,backend:StorageBackend,key:&str) -> Result<(),Error> {
+        self.store(backend)?
+            .delete(key)
+            .await
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{Error, anyhow};
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::{Client, config::{Builder, Credentials}, primitives::ByteStream, types::{CompletedMultipartUpload, CompletedPart}};
+use crate::{config::setting::S3Settings, storage::{FileStore, FileWriter}};
+
+/// Size of the multipart upload parts, S3 requires at least 5 MiB but for the last one
+const PART_SIZE:usize = 8 * 1024 * 1024;
+
+/// Files in an S3 compatible bucket: AWS S3, MinIO, R2...
+pub struct S3FileStore {
+    client:Client,
+    bucket:String,
+}
+
+impl S3FileStore {
+    /// Static keys when configured, the default AWS credentials chain otherwise
+    pub async fn new(settings:&S3Settings) -> Self {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest())
+            .region(Region::new(settings.region.clone()));
+        if let (Some(access_key_id), Some(secret_access_key)) = (&settings.access_key_id, &settings.secret_access_key) {
+            loader = loader.credentials_provider(Credentials::new(access_key_id, secret_access_key, None, None, "env"));
+        }
+        let sdk_config = loader.load().await;
+        let mut config = Builder::from(&sdk_config)
+            .force_path_style(settings.force_path_style);
+        if let Some(endpoint) = &settings.endpoint {
+            config = config.endpoint_url(endpoint);
+        }
+        Self {
+            client:Client::from_conf(config.build()),
+            bucket:settings.bucket.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl FileStore for S3FileStore {
+    async fn put(&self,key:&str,bytes:Vec<u8>,content_type:&str) -> Result<(),Error> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self,key:&str) -> Result<Vec<u8>,Error> {
+        let object = self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await?;
+        Ok(object.body.collect().await?.to_vec())
+    }
+
+    async fn delete(&self,key:&str) -> Result<(),Error> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn writer(&self,key:&str,content_type:&str) -> Result<Box<dyn FileWriter>,Error> {
+        Ok(Box::new(S3FileWriter {
+            client:self.client.clone(),
+            bucket:self.bucket.clone(),
+            key:key.to_string(),
+            content_type:content_type.to_string(),
+            buffer:Vec::new(),
+            upload_id:None,
+            parts:Vec::new(),
+        }))
+    }
+}
+
+/// Multipart upload started once a first part is full, smaller files are sent in one request
+struct S3FileWriter {
+    client:Client,
+    bucket:String,
+    key:String,
+    content_type:String,
+    buffer:Vec<u8>,
+    upload_id:Option<String>,
+    parts:Vec<CompletedPart>,
+}
+
+impl S3FileWriter {
+    async fn upload_part(&mut self,bytes:Vec<u8>) -> Result<(),Error> {
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let upload = self.client
+                    .create_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(&self.key)
+                    .content_type(&self.content_type)
+                    .send()
+                    .await?;
+                let upload_id = upload.upload_id.ok_or(anyhow!("no upload id for {}", self.key))?;
+                self.upload_id = Some(upload_id.clone());
+                upload_id
+            },
+        };
+        let part_number = self.parts.len() as i32 + 1;
+        let part = self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+        self.parts.push(CompletedPart::builder()
+            .set_e_tag(part.e_tag)
+            .part_number(part_number)
+            .build());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FileWriter for S3FileWriter {
+    async fn write(&mut self,chunk:&[u8]) -> Result<(),Error> {
+        self.buffer.extend_from_slice(chunk);
+        while self.buffer.len() >= PART_SIZE {
+            let part = self.buffer.drain(..PART_SIZE).collect::<Vec<u8>>();
+            self.upload_part(part).await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self:Box<Self>) -> Result<(),Error> {
+        let Some(upload_id) = self.upload_id.clone() else {
+            return self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(&self.key)
+                .content_type(&self.content_type)
+                .body(ByteStream::from(std::mem::take(&mut self.buffer)))
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(Error::from);
+        };
+        if !self.buffer.is_empty() {
+            let part = std::mem::take(&mut self.buffer);
+            self.upload_part(part).await?;
+        }
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder()
+                .set_parts(Some(std::mem::take(&mut self.parts)))
+                .build())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn abort(self:Box<Self>) -> Result<(),Error> {
+        if let Some(upload_id) = &self.upload_id {
+            self.client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(&self.key)
+                .upload_id(upload_id)
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use anyhow::Error;
+use async_trait::async_trait;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    Infected { signature:String },
+}
+
+/// Malware scanner run on uploads before their content is served or sent to providers
+#[async_trait]
+pub trait MalwareScanner: Send + Sync {
+    /// Name recorded with the verdicts
+    fn engine(&self) -> &'static str;
+    async fn scan(&self,bytes:&[u8]) -> Result<ScanVerdict,Error>;
+}
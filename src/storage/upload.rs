@@ -0,0 +1,173 @@
+use crate::{dto::storage::UploadPolicy, error::AppError};
+
+/// Bytes of an upload inspected to detect its type
+pub const SNIFF_LEN:usize = 8 * 1024;
+const MAX_FILE_NAME_CHARS:usize = 255;
+const MAX_EXTENSION_CHARS:usize = 16;
+
+/// Executable content, refused whatever the upload policy
+const BLOCKED_TYPES:&[&str] = &[
+    "application/x-executable",
+    "application/vnd.microsoft.portable-executable",
+    "application/x-mach-binary",
+    "application/vnd.android.dex",
+    "application/vnd.android.dey",
+    "application/x-msdownload",
+    "application/x-sh",
+    "text/x-shellscript",
+];
+
+/// Formats stored in a zip container, only known from their name when their
+/// entries are past the sniffed bytes
+const ZIP_BASED_TYPES:&[&str] = &[
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.presentation",
+    "application/epub+zip",
+];
+
+/// Bidirectional overrides, used to disguise an extension (`exe.pdf` shown as `fdp.exe`)
+fn is_bidi_control(c:char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' | '\u{200E}' | '\u{200F}')
+}
+
+/// Display name of an upload: its last path segment without control characters.
+/// The name never reaches the storage, files are stored under generated keys.
+pub fn sanitize_file_name(name:&str) -> Option<String> {
+    let name = name
+      .rsplit(['/', '\\'])
+      .next()
+      .unwrap_or_default()
+      .chars()
+      .filter(|c| !c.is_control() && !is_bidi_control(*c))
+      .collect::<String>();
+    let name = name.trim();
+    if name.chars().all(|c| c == '.') {
+        return None;
+    }
+    if name.chars().count() <= MAX_FILE_NAME_CHARS {
+        return Some(name.to_string());
+    }
+    // Long names are shortened, keeping the extension
+    let extension = name
+      .rsplit_once('.')
+      .map(|(_, extension)| extension)
+      .filter(|extension| !extension.is_empty() && extension.chars().count() <= MAX_EXTENSION_CHARS);
+    Some(match extension {
+        Some(extension) => {
+            let stem = name.chars().take(MAX_FILE_NAME_CHARS - extension.chars().count() - 1).collect::<String>();
+            format!("{stem}.{extension}")
+        }
+        None => name.chars().take(MAX_FILE_NAME_CHARS).collect(),
+    })
+}
+
+fn is_text_type(content_type:&str) -> bool {
+    content_type.starts_with("text/")
+      || content_type.ends_with("+xml")
+      || content_type.ends_with("+json")
+      || matches!(content_type, "application/json" | "application/xml" | "application/javascript" | "application/x-yaml" | "application/sql")
+}
+
+fn is_utf8_text(head:&[u8]) -> bool {
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The sniffed bytes may end in the middle of a character
+        Err(e) => e.error_len().is_none() && head.len() >= SNIFF_LEN,
+    }
+}
+
+/// Type of an upload from its first bytes. The name only refines what the
+/// content allows: a zip container, an xml dialect or a text format.
+pub fn sniff_content_type(head:&[u8],name:&str) -> String {
+    let head = &head[..head.len().min(SNIFF_LEN)];
+    let guessed = mime_guess::from_path(name)
+      .first()
+      .map(|mime| mime.essence_str().to_string());
+    if let Some(kind) = infer::get(head) {
+        let detected = kind.mime_type();
+        return match guessed {
+            Some(guessed) if detected == "application/zip" && ZIP_BASED_TYPES.contains(&guessed.as_str()) => guessed,
+            Some(guessed) if detected == "text/xml" && guessed.ends_with("+xml") => guessed,
+            _ => detected.to_string(),
+        };
+    }
+    if !is_utf8_text(head) {
+        return "application/octet-stream".to_string();
+    }
+    guessed
+      .filter(|guessed| is_text_type(guessed))
+      .unwrap_or("text/plain".to_string())
+}
+
+fn matches_type(pattern:&str,content_type:&str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(family) => content_type.split('/').next() == Some(family),
+        None => pattern.eq_ignore_ascii_case(content_type),
+    }
+}
+
+/// Maximum size of an upload of the type, when the type is accepted
+pub fn allowed_max_bytes(policy:&UploadPolicy,content_type:&str,max_bytes:u64) -> Result<u64,AppError> {
+    let not_allowed = || AppError::ValidationFileTypeNotAllowed { content_type:content_type.to_string() };
+    if BLOCKED_TYPES.contains(&content_type) {
+        return Err(not_allowed());
+    }
+    if policy.allowed_types.is_empty() {
+        return Ok(max_bytes);
+    }
+    // The most specific rule applies, exact types before families
+    let rule = policy.allowed_types
+      .iter()
+      .filter(|rule| matches_type(&rule.content_type, content_type))
+      .min_by_key(|rule| rule.content_type.ends_with("/*"))
+      .ok_or_else(not_allowed)?;
+    Ok(rule.max_bytes.map_or(max_bytes, |rule_max| rule_max.min(max_bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::storage::UploadRule;
+
+    #[test]
+    fn sanitize_file_name_keeps_the_last_segment() {
+        assert_eq!(sanitize_file_name("../../etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(sanitize_file_name("C:\\Users\\me\\report.pdf").as_deref(), Some("report.pdf"));
+        assert_eq!(sanitize_file_name(" in\u{202E}fdp.exe\n").as_deref(), Some("infdp.exe"));
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name("dir/"), None);
+        let long = format!("{}.pdf", "a".repeat(300));
+        let sanitized = sanitize_file_name(&long).unwrap();
+        assert_eq!(sanitized.chars().count(), MAX_FILE_NAME_CHARS);
+        assert!(sanitized.ends_with(".pdf"));
+    }
+
+    #[test]
+    fn sniff_content_type_trusts_the_content_over_the_name() {
+        assert_eq!(sniff_content_type(b"%PDF-1.7\n", "notes.txt"), "application/pdf");
+        assert_eq!(sniff_content_type(b"MZ\x90\x00\x03\x00\x00\x00", "invoice.pdf"), "application/vnd.microsoft.portable-executable");
+        assert_eq!(sniff_content_type(b"a,b\n1,2\n", "data.csv"), "text/csv");
+        assert_eq!(sniff_content_type(b"plain words", "archive.zip"), "text/plain");
+        assert_eq!(sniff_content_type(&[0xff, 0x00, 0xfe, 0x01], "data.bin"), "application/octet-stream");
+        assert_eq!(sniff_content_type(b"PK\x03\x04\x14\x00\x00\x00", "report.docx"), "application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+    }
+
+    #[test]
+    fn allowed_max_bytes_applies_the_most_specific_rule() {
+        let policy = UploadPolicy { allowed_types:vec![
+            UploadRule { content_type:"image/*".to_string(), max_bytes:Some(10) },
+            UploadRule { content_type:"image/png".to_string(), max_bytes:Some(20) },
+            UploadRule { content_type:"application/pdf".to_string(), max_bytes:None },
+        ] };
+        assert_eq!(allowed_max_bytes(&policy, "image/png", 100).unwrap(), 20);
+        assert_eq!(allowed_max_bytes(&policy, "image/jpeg", 100).unwrap(), 10);
+        assert_eq!(allowed_max_bytes(&policy, "application/pdf", 100).unwrap(), 100);
+        assert!(allowed_max_bytes(&policy, "text/plain", 100).is_err());
+        assert!(allowed_max_bytes(&UploadPolicy::default(), "text/plain", 100).is_ok());
+        assert!(allowed_max_bytes(&UploadPolicy::default(), "application/x-executable", 100).is_err());
+    }
+}